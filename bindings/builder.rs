@@ -0,0 +1,31 @@
+// The bindgen configuration for the PVCam headers. It is include!d by build.rs and by
+// tools/generate-bindings so that bundled bindings are generated exactly like build time ones.
+// Only what bindings/allowlist.txt names is generated; deprecated functions are added when
+// `deprecated` is set.
+fn bindings_builder(include_path: &str, deprecated: bool) -> bindgen::Builder {
+    let mut builder = bindgen::Builder::default()
+        // generate bindings for these headers
+        .header(format!("{}/master.h", include_path))
+        .header(format!("{}/pvcam.h", include_path));
+
+    for (line_nr, line) in include_str!("allowlist.txt").lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        builder = match (parts.next(), parts.next(), parts.next()) {
+            (Some("function"), Some(pattern), None) => builder.whitelist_function(pattern),
+            (Some("type"), Some(pattern), None) => builder.whitelist_type(pattern),
+            (Some("var"), Some(pattern), None) => builder.whitelist_var(pattern),
+            (Some("deprecated-function"), Some(pattern), None) => match deprecated {
+                true => builder.whitelist_function(pattern),
+                false => builder,
+            },
+            _ => panic!("bindings/allowlist.txt:{}: cannot parse {:?}", line_nr + 1, line),
+        };
+    }
+
+    builder
+}
@@ -1,69 +1,362 @@
 extern crate bindgen;
 
 use std::env;
+use std::fs;
 use std::path::PathBuf;
 
+include!("bindings/builder.rs");
+
 const SDK_PATH_KEY: &str = "PVCAM_SDK_PATH";
 
-fn sdk_path() -> String {
-    // if this is not set the build will panic
-    let path = match env::var(SDK_PATH_KEY) {
-        Ok(v) => v,
-        Err(_) => panic!("{} must be set", SDK_PATH_KEY),
+// version feature => SDK version of the bundled bindings it selects
+const BUNDLED_VERSIONS: &[(&str, &str)] = &[
+    ("CARGO_FEATURE_PVCAM_3_8_4_3", "3.8.4.3"),
+    ("CARGO_FEATURE_PVCAM_3_9_4_0", "3.9.4.0"),
+];
+
+// SDK releases the crate gates APIs on; cfg(pvcam_<major>_<minor>) is set for each one the
+// headers are at least
+const SDK_RELEASES: &[(u16, u16)] = &[(3, 7), (3, 8), (3, 9)];
+// names the release of headers without version macros, e.g. 3.8.4.3
+const SDK_VERSION_KEY: &str = "PVCAM_SDK_VERSION";
+// what a release added to the headers, for telling apart ones without version macros; newest first
+const RELEASE_MARKERS: &[((u16, u16), &[&str])] =
+    &[((3, 9), &["pl_exp_trigger", "EXT_TRIG_SOFTWARE_FIRST"])];
+
+// where the PVCam installer puts the SDK and the libraries
+const DEFAULT_SDK_PATH: &str = "/opt/pvcam/sdk";
+const DEFAULT_LIBRARY_PATH: &str = "/opt/pvcam/library";
+// the installer exports PVCAM_SDK_PATH from a script in here, which only shells started after a
+// login have seen
+const PROFILE_D: &str = "/etc/profile.d";
+
+// The value of an `export PVCAM_SDK_PATH=...` line, if it is one and holds a plain path
+fn parse_sdk_export(line: &str) -> Option<PathBuf> {
+    let line = line.trim();
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let value = line.strip_prefix(SDK_PATH_KEY)?.strip_prefix('=')?;
+    let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+
+    match value.is_empty() || value.contains('$') {
+        true => None,
+        false => Some(PathBuf::from(value)),
+    }
+}
+
+fn profile_d_sdk_paths() -> Vec<PathBuf> {
+    let mut scripts: Vec<PathBuf> = match fs::read_dir(PROFILE_D) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return vec![],
     };
+    scripts.retain(|path| match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name.starts_with("pvcam") && name.ends_with(".sh"),
+        None => false,
+    });
+    scripts.sort();
+
+    scripts
+        .iter()
+        .filter_map(|script| fs::read_to_string(script).ok())
+        .flat_map(|text| {
+            text.lines()
+                .filter_map(parse_sdk_export)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// PVCAM_SDK_PATH, then what the installer exported, then the default install location
+fn sdk_candidates() -> Vec<PathBuf> {
+    let mut candidates = vec![];
+    if let Ok(path) = env::var(SDK_PATH_KEY) {
+        candidates.push(PathBuf::from(path));
+    }
+    candidates.extend(profile_d_sdk_paths());
+    candidates.push(PathBuf::from(DEFAULT_SDK_PATH));
+
+    let mut unique = vec![];
+    for path in candidates {
+        if !unique.contains(&path) {
+            unique.push(path);
+        }
+    }
+
+    unique
+}
+
+fn sdk_path() -> Option<PathBuf> {
+    sdk_candidates()
+        .into_iter()
+        .find(|path| path.join("include").join("pvcam.h").is_file())
+}
 
-    path
+fn not_found(what: &str, tried: &[PathBuf]) -> ! {
+    panic!(
+        "{} not found, tried:\n{}\nSet {} to the PVCam SDK directory (the one with include/pvcam.h \
+         and library/<arch>), or see the bundled-bindings and dynamic-loading features to build \
+         without it.",
+        what,
+        tried
+            .iter()
+            .map(|path| format!("  {}", path.display()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        SDK_PATH_KEY
+    )
 }
 
-/**
- * TODO: expand the logic here to incorporate other OSes and architectures
- * see: https://doc.rust-lang.org/reference/conditional-compilation.html#conditional-compilation
- */
-#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-fn libpvcam_search_path() -> String {
-    format!("{}/library/x86_64", sdk_path())
+// the directory name PVCam uses for the target's libraries
+fn library_arch() -> &'static str {
+    let os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    match (os.as_str(), arch.as_str()) {
+        ("linux", "x86_64") => "x86_64",
+        ("linux", "aarch64") => "aarch64",
+        ("linux", "x86") => "i686",
+        _ => panic!(
+            "PVCam has no library for {} {}, only for x86_64, aarch64 and i686 Linux; the \
+             dynamic-loading feature builds without linking it",
+            arch, os
+        ),
+    }
+}
+
+// The directory with libpvcam.so for the target, or every directory looked in
+fn libpvcam_search_path() -> Result<PathBuf, Vec<PathBuf>> {
+    let arch = library_arch();
+    let mut candidates: Vec<PathBuf> = sdk_candidates()
+        .iter()
+        .map(|sdk| sdk.join("library").join(arch))
+        .collect();
+    candidates.push(PathBuf::from(DEFAULT_LIBRARY_PATH).join(arch));
+
+    match candidates
+        .iter()
+        .find(|dir| dir.join("libpvcam.so").exists())
+    {
+        Some(dir) => Ok(dir.clone()),
+        None => Err(candidates),
+    }
 }
 
 fn header_include_path() -> String {
-    format!("{}/include", sdk_path())
+    match sdk_path() {
+        Some(path) => path.join("include").display().to_string(),
+        None => not_found(
+            "PVCam headers",
+            &sdk_candidates()
+                .iter()
+                .map(|sdk| sdk.join("include").join("pvcam.h"))
+                .collect::<Vec<_>>(),
+        ),
+    }
 }
 
-fn main() {
-    // tell cargo to tell rust c to link to pvcam
-    println!("cargo:rustc-link-lib=pvcam");
+// with the dynamic-loading feature libpvcam is opened at runtime, see src/pvcam/internal/dynamic.rs
+fn dynamic_loading() -> bool {
+    env::var("CARGO_FEATURE_DYNAMIC_LOADING").is_ok()
+}
+
+// with the deprecated-api feature the deprecated pl_* families in bindings/allowlist.txt are
+// generated too, and reachable through pvcam::sys
+fn deprecated_api() -> bool {
+    env::var("CARGO_FEATURE_DEPRECATED_API").is_ok()
+}
+
+// The SDK version to take checked in bindings for. That is with the bundled-bindings feature, and
+// with dynamic-loading, which needs neither the headers nor libclang either; the newest version
+// bundled is taken if it names none.
+fn bundled_version() -> Option<&'static str> {
+    let bundled = env::var("CARGO_FEATURE_BUNDLED_BINDINGS").is_ok();
+    if !bundled && !dynamic_loading() {
+        return None;
+    }
+
+    let selected: Vec<&str> = BUNDLED_VERSIONS
+        .iter()
+        .filter(|(feature, _)| env::var(feature).is_ok())
+        .map(|(_, version)| *version)
+        .collect();
+
+    match selected.as_slice() {
+        [version] => Some(version),
+        [] if !bundled => BUNDLED_VERSIONS.last().map(|(_, version)| *version),
+        _ => panic!(
+            "bundled-bindings needs exactly one SDK version feature, one of: {}",
+            BUNDLED_VERSIONS
+                .iter()
+                .map(|(_, version)| format!("pvcam-{}", version.replace('.', "-")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+// Drops the extern "C" blocks bindgen emits for functions, leaving types and constants. Used
+// for dynamic-loading, which supplies the functions itself.
+fn strip_functions(bindings: &str) -> String {
+    let mut out = String::with_capacity(bindings.len());
+    let mut in_extern = false;
+    for line in bindings.lines() {
+        if line.starts_with("extern \"C\" {") {
+            in_extern = true;
+        } else if in_extern {
+            in_extern = line != "}";
+        } else {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+// The version the PVCAM_VERSION_MAJOR/MINOR/BUILD macros give, looked for in every header
+fn header_version(include_path: &str) -> Option<(u16, u16, u16)> {
+    let mut parts = [None, None, None];
+    for header in ["master.h", "pvcam.h"].iter() {
+        let text = fs::read_to_string(format!("{}/{}", include_path, header)).unwrap_or_default();
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("#define") {
+                continue;
+            }
+            let slot = match words.next() {
+                Some("PVCAM_VERSION_MAJOR") => 0,
+                Some("PVCAM_VERSION_MINOR") => 1,
+                Some("PVCAM_VERSION_BUILD") => 2,
+                _ => continue,
+            };
+            parts[slot] = words
+                .next()
+                .and_then(|v| v.trim_matches(|c| c == '(' || c == ')').parse().ok());
+        }
+    }
 
-    // tell cargo to tell rust c where to search for pvcam
-    println!("cargo:rustc-link-search={}", libpvcam_search_path());
+    match parts {
+        [Some(major), Some(minor), build] => Some((major, minor, build.unwrap_or(0))),
+        _ => None,
+    }
+}
+
+fn header_identifiers(include_path: &str) -> Vec<String> {
+    let mut identifiers = vec![];
+    for header in ["master.h", "pvcam.h"].iter() {
+        let text = fs::read_to_string(format!("{}/{}", include_path, header)).unwrap_or_default();
+        identifiers.extend(
+            text.split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .filter(|word| !word.is_empty())
+                .map(str::to_owned),
+        );
+    }
+
+    identifiers
+}
+
+// The release of headers without version macros: the one PVCAM_SDK_VERSION names, else the
+// newest whose additions they have. Older releases cannot be told apart, so there is no guess.
+fn header_release(include_path: &str) -> (u16, u16, u16) {
+    if let Ok(version) = env::var(SDK_VERSION_KEY) {
+        return parse_version(&version);
+    }
+
+    let identifiers = header_identifiers(include_path);
+    let found = RELEASE_MARKERS.iter().find(|(_, markers)| {
+        markers
+            .iter()
+            .any(|marker| identifiers.iter().any(|i| i == marker))
+    });
+    match found {
+        Some(((major, minor), _)) => (*major, *minor, 0),
+        None => panic!(
+            "the PVCam headers in {} have no PVCAM_VERSION macros and nothing newer than 3.8 in \
+             them; set {} to the SDK's version, e.g. 3.8.4.3",
+            include_path, SDK_VERSION_KEY
+        ),
+    }
+}
+
+// Whether the SDK is the one tools/fake-pvcam lays out, which marks its pvcam.h; tests that need
+// the fake library check cfg(pvcam_fake)
+fn fake_sdk() -> bool {
+    let header = match sdk_path() {
+        Some(path) => path.join("include").join("pvcam.h"),
+        None => return false,
+    };
+    fs::read_to_string(header)
+        .unwrap_or_default()
+        .lines()
+        .any(|line| {
+            line.split_whitespace()
+                .eq(["#define", "PVCAM_FAKE", "1"].iter().copied())
+        })
+}
+
+// "3.9.4.0" => (3, 9, 4); "3.9" => (3, 9, 0)
+fn parse_version(version: &str) -> (u16, u16, u16) {
+    let parts: Option<Vec<u16>> = version.split('.').map(|v| v.trim().parse().ok()).collect();
+    match parts.as_deref() {
+        Some([major, minor]) => (*major, *minor, 0),
+        Some([major, minor, build, ..]) => (*major, *minor, *build),
+        _ => panic!("{:?} is not a PVCam version such as 3.9.4.0", version),
+    }
+}
+
+// Sets the pvcam_<major>_<minor> cfgs and writes $OUT_DIR/version.rs with SDK_HEADER_VERSION
+fn write_version(version: (u16, u16, u16)) {
+    let (major, minor, build) = version;
+    for (release_major, release_minor) in SDK_RELEASES.iter() {
+        let cfg = format!("pvcam_{}_{}", release_major, release_minor);
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+        if (major, minor) >= (*release_major, *release_minor) {
+            println!("cargo:rustc-cfg={}", cfg);
+        }
+    }
+
+    let out_file = PathBuf::from(env::var("OUT_DIR").unwrap()).join("version.rs");
+    fs::write(
+        out_file,
+        format!(
+            "pub const SDK_HEADER_VERSION: Version = Version {{ major: {}, minor: {}, build: {} }};\n",
+            major, minor, build
+        ),
+    )
+    .expect("Couldn't write version!");
+}
+
+fn write_bundled_bindings(version: &str, out_file: PathBuf) {
+    let bundled = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+        .join("bindings")
+        .join(format!("pvcam-{}.rs", version));
+    println!("cargo:rerun-if-changed={}", bundled.display());
 
+    let bindings = match fs::read_to_string(&bundled) {
+        Ok(v) => v,
+        Err(e) => panic!(
+            "{} could not be read ({}). It is generated on a machine with the PVCam {} SDK, \
+             from bindings/allowlist.txt as checked in:\n  PVCAM_SDK_PATH=/opt/pvcam/sdk cargo run \
+             -p generate-bindings -- {}\nand committed; without it neither bundled-bindings nor \
+             dynamic-loading can build this version.",
+            bundled.display(),
+            e,
+            version,
+            version
+        ),
+    };
+    let bindings = match dynamic_loading() {
+        true => strip_functions(&bindings),
+        false => bindings,
+    };
+
+    fs::write(out_file, bindings).expect("Couldn't write bindings!");
+}
+
+fn generate_bindings(out_file: PathBuf) {
     // Tell cargo to invalidate the build if the header files change
     println!("cargo:rerun-if-changed={}/master.h", header_include_path());
     println!("cargo:rerun-if-changed={}/pvcam.h", header_include_path());
 
-    let bindings = bindgen::Builder::default()
-        // generate bindings for these headers
-        .header(format!("{}/master.h", header_include_path()))
-        .header(format!("{}/pvcam.h", header_include_path()))
-        // TODO: work out how to populate this from
-        //  - https://www.photometrics.com/docs/pvcam-sdk/group__grp__pm__deprecated__functions.xhtml
-        //  - https://www.photometrics.com/docs/pvcam-sdk/group__grp__pm__deprecated__typedefs.xhtml
-        // BLACKLIST: pl_cam_open; REASON: camera_name variable incorrectly marked as mutable
-        .blacklist_function("pl_cam_open")
-        // BLACKLIST: pl_dd_*; REASON: deprecated upstream
-        .blacklist_function("pl_dd_.*")
-        // BLACKLIST: pl_ccd_*; REASON: deprecated upstream
-        .blacklist_function("pl_ccd_.*")
-        // BLACKLIST: pl_spdtab_*; REASON: deprecated upstream
-        .blacklist_function("pl_spdtab_.*")
-        // BLACKLIST: pl_shtr_*; REASON: deprecated upstream
-        .blacklist_function("pl_shtr_.*")
-        // BLACKLIST: pl_ccs_*; REASON: deprecated upstream
-        .blacklist_function("pl_ccs_.*")
-        // BLACKLIST: pl_exp_[sg]et_time_seq; REASON: deprecated upstream
-        .blacklist_function("pl_exp_[sg]et_time_seq")
-        // BLACKLIST: pl_exp_check_progress; REASON: deprecated upstream
-        .blacklist_function("pl_exp_check_progress")
-        // BLACKLIST: pl_exp_set_cont_mode; REASON: deprecated upstream
-        .blacklist_function("pl_exp_set_cont_mode")
+    let bindings = bindings_builder(&header_include_path(), deprecated_api())
         // Tell cargo to invalidate the built crate whenever any of the
         // included header files changed.
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
@@ -72,9 +365,54 @@ fn main() {
         // Unwrap the Result and panic on failure.
         .expect("Unable to generate bindings");
 
-    // Write the bindings to the $OUT_DIR/bindings.rs file.
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
     bindings
-        .write_to_file(out_path.join("bindings.rs"))
+        .write_to_file(out_file)
         .expect("Couldn't write bindings!");
 }
+
+fn main() {
+    println!("cargo:rerun-if-env-changed={}", SDK_PATH_KEY);
+    println!("cargo:rerun-if-env-changed={}", SDK_VERSION_KEY);
+    let bundled = bundled_version();
+
+    if !dynamic_loading() {
+        // tell cargo to tell rust c to link to pvcam
+        println!("cargo:rustc-link-lib=pvcam");
+
+        // tell cargo to tell rust c where to search for pvcam
+        match (libpvcam_search_path(), bundled) {
+            (Ok(path), _) => println!("cargo:rustc-link-search={}", path.display()),
+            // bundled builds may instead rely on the linker finding a system wide install
+            (Err(_), Some(_)) => {}
+            (Err(tried), None) => not_found("libpvcam.so", &tried),
+        }
+    }
+
+    println!("cargo:rustc-check-cfg=cfg(pvcam_fake)");
+    // dynamic-loading builds do not look for the SDK at all
+    if !dynamic_loading() && fake_sdk() {
+        println!("cargo:rustc-cfg=pvcam_fake");
+        // the fake library is not installed anywhere the loader looks
+        if let Ok(path) = libpvcam_search_path() {
+            println!("cargo:rustc-link-arg-tests=-Wl,-rpath,{}", path.display());
+        }
+    }
+
+    // Write the bindings to the $OUT_DIR/bindings.rs file.
+    let out_file = PathBuf::from(env::var("OUT_DIR").unwrap()).join("bindings.rs");
+    match bundled {
+        Some(version) => {
+            write_bundled_bindings(version, out_file);
+            write_version(parse_version(version));
+        }
+        None => {
+            let include_path = header_include_path();
+            let version = match header_version(&include_path) {
+                Some(version) => version,
+                None => header_release(&include_path),
+            };
+            generate_bindings(out_file);
+            write_version(version);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Writes a camera's parameter space as JSON, for SimulatedCamera::from_description to play back
+// in tests and CI without the camera:
+//
+//   cargo run --features describe --bin pvcam-describe > prime-bsi.json
+//   cargo run --features describe --bin pvcam-describe -- PMPCIECam00 prime-bsi.json
+//
+// The first camera is described unless one is named. Every port and speed is selected in turn
+// while it runs, so do not run it on a camera that is acquiring.
+
+use std::env;
+use std::process;
+
+use libpvcam_sys::pvcam::{self, Camera, CameraDescription};
+
+fn describe(name: Option<&str>) -> pvcam::Result<CameraDescription> {
+    let name = match name {
+        Some(name) => name.to_owned(),
+        None => pvcam::cam_get_name(0)?,
+    };
+    let mut camera = Camera::open(&name)?;
+
+    camera.describe()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (name, output) = match args.as_slice() {
+        [] => (None, None),
+        [name] => (Some(name.as_str()), None),
+        [name, output] => (Some(name.as_str()), Some(output.as_str())),
+        _ => {
+            eprintln!("usage: pvcam-describe [<camera> [<output.json>]]");
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = pvcam::init() {
+        eprintln!("pvcam-describe: {}", e);
+        process::exit(1);
+    }
+    let result = describe(name).and_then(|description| match output {
+        Some(output) => description.save(output),
+        None => description.to_json().map(|json| println!("{}", json)),
+    });
+    let _ = pvcam::uninit();
+
+    if let Err(e) = result {
+        eprintln!("pvcam-describe: {}", e);
+        process::exit(1);
+    }
+}
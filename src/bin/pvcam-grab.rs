@@ -0,0 +1,369 @@
+// Acquires a sequence and writes it to disk, for verifying a camera without writing code:
+//
+//   cargo run --features cli --bin pvcam-grab -- --frames 100 --exposure 10 grab.raw
+//   cargo run --features cli --bin pvcam-grab -- --profile assay.toml --roi 0,511,0,511 \
+//       --binning 2 --trigger ExtTrigEdgeRising --timeout 30 grab.raw
+//
+// The settings are a Profile, from --profile if given, with the flags on top; all of them are
+// checked against the camera before any is written. The frames go to <output> as little endian
+// u16, one after the other, or by its extension as a multi-page TIFF (.tif), OME-TIFF
+// (.ome.tif), FITS (.fits), NumPy array (.npy, .npz) or OME-Zarr store (.zarr), and their
+// regions, numbers, timestamps and exposures to <output>.json. Progress goes to stderr, and a summary with the achieved frame
+// rate and the frames the camera dropped once done. Any failure exits 1 with PVCam's error text.
+
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+
+use libpvcam_sys::pvcam::io::fits::FitsWriter;
+use libpvcam_sys::pvcam::io::npy;
+use libpvcam_sys::pvcam::io::ome_tiff::OmeTiffWriter;
+use libpvcam_sys::pvcam::io::tiff::TiffWriter;
+use libpvcam_sys::pvcam::io::zarr::{BloscCodec, Compression, ZarrWriter};
+use libpvcam_sys::pvcam::{
+    self, io, Binning, Camera, CaptureStatus, Error, ErrorKind, ExposureMode, Profile, Region, Roi,
+    Sequence,
+};
+
+const USAGE: &str = "usage: pvcam-grab [options] <output>
+  --camera <name>        the camera to use, the first one by default
+  --profile <file>       settings to start from, see pvcam::Profile
+  --frames <n>           frames to acquire, 1 by default
+  --exposure <ms>        exposure of every frame, 10 by default
+  --roi <s1,s2,p1,p2>    a region in sensor pixels, inclusive; repeat for more
+  --binning <n>|<s>x<p>  binning of every region
+  --trigger <mode>       an ExposureMode, e.g. Timed or ExtTrigEdgeRising
+  --port <name>          readout port
+  --speed <index>        readout speed, a PARAM_SPDTAB_INDEX
+  --gain <index>         a PARAM_GAIN_INDEX
+  --timeout <s>          give up when no frame arrives for this long";
+
+struct Options {
+    camera: Option<String>,
+    profile: Profile,
+    frames: u16,
+    timeout: Option<Duration>,
+    output: String,
+}
+
+fn usage(problem: &str) -> ! {
+    eprintln!("pvcam-grab: {}\n{}", problem, USAGE);
+    process::exit(2);
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> T {
+    match value.parse() {
+        Ok(v) => v,
+        Err(_) => usage(&format!("{} takes a number, not {:?}", flag, value)),
+    }
+}
+
+fn roi(value: &str) -> Roi {
+    let bounds: Vec<u16> = value.split(',').map(|v| number("--roi", v)).collect();
+    match bounds.as_slice() {
+        [s1, s2, p1, p2] => Roi {
+            s1: *s1,
+            s2: *s2,
+            p1: *p1,
+            p2: *p2,
+        },
+        _ => usage(&format!("--roi takes s1,s2,p1,p2, not {:?}", value)),
+    }
+}
+
+fn binning(value: &str) -> Binning {
+    let (serial, parallel) = match value.split_once('x') {
+        Some((serial, parallel)) => (serial, parallel),
+        None => (value, value),
+    };
+    Binning {
+        serial: number("--binning", serial),
+        parallel: number("--binning", parallel),
+    }
+}
+
+// by the name of the ExposureMode variant, as a profile has it
+fn trigger(value: &str) -> ExposureMode {
+    match serde_json::from_value(serde_json::Value::String(value.to_owned())) {
+        Ok(mode) => mode,
+        Err(_) => usage(&format!("{:?} is not an ExposureMode", value)),
+    }
+}
+
+fn parse_args() -> Options {
+    let mut args = env::args().skip(1);
+    let mut camera = None;
+    let mut profile = None;
+    let mut overrides = Profile::default();
+    let mut frames = 1;
+    let mut exposure_ms = None;
+    let mut timeout = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || match args.next() {
+            Some(value) => value,
+            None => usage(&format!("{} needs a value", arg)),
+        };
+        match arg.as_str() {
+            "--camera" => camera = Some(value()),
+            "--profile" => profile = Some(value()),
+            "--frames" => frames = number(&arg, &value()),
+            "--exposure" => exposure_ms = Some(number(&arg, &value())),
+            "--roi" => overrides.roi.push(roi(&value())),
+            "--binning" => overrides.binning = Some(binning(&value())),
+            "--trigger" => overrides.trigger = Some(trigger(&value())),
+            "--port" => overrides.readout_port = Some(value()),
+            "--speed" => overrides.speed = Some(number(&arg, &value())),
+            "--gain" => overrides.gain = Some(number(&arg, &value())),
+            "--timeout" => {
+                timeout = Some(Duration::from_secs_f64(
+                    number::<f64>(&arg, &value()).max(0.0),
+                ))
+            }
+            flag if flag.starts_with("--") => usage(&format!("unknown option {}", flag)),
+            path if output.is_none() => output = Some(path.to_owned()),
+            _ => usage("only one output can be given"),
+        }
+    }
+
+    let mut profile = match profile {
+        Some(path) => Profile::load(&path).unwrap_or_else(|e| fail(e)),
+        None => Profile::default(),
+    };
+    if !overrides.roi.is_empty() {
+        profile.roi = overrides.roi;
+    }
+    profile.binning = overrides.binning.or(profile.binning);
+    profile.trigger = overrides.trigger.or(profile.trigger);
+    profile.readout_port = overrides.readout_port.or(profile.readout_port);
+    profile.speed = overrides.speed.or(profile.speed);
+    profile.gain = overrides.gain.or(profile.gain);
+    profile.exposure_ms = exposure_ms.or(profile.exposure_ms).or(Some(10));
+    if frames == 0 {
+        usage("--frames has to be at least 1");
+    }
+
+    Options {
+        camera,
+        profile,
+        frames,
+        timeout,
+        output: output.unwrap_or_else(|| usage("no output given")),
+    }
+}
+
+fn fail(e: Error) -> ! {
+    eprintln!("pvcam-grab: {}", e);
+    let _ = pvcam::uninit();
+    process::exit(1);
+}
+
+fn binding_error(message: String) -> Error {
+    Error::new(ErrorKind::Binding, message)
+}
+
+// Runs the sequence, reporting every frame as its bytes arrive; returns when it started too
+fn acquire(
+    camera: &mut Camera,
+    options: &Options,
+) -> pvcam::Result<(Sequence, SystemTime, Duration)> {
+    let config = camera.sequence_config(options.frames)?;
+    if config.exposure_mode().is_software_trigger() {
+        return Err(binding_error(format!(
+            "{:?} needs a program to fire the triggers",
+            config.exposure_mode()
+        )));
+    }
+    let frame_bytes: usize = config
+        .regions()
+        .iter()
+        .map(|r| r.width() * r.height() * 2)
+        .sum();
+
+    camera.setup_seq(&config)?;
+    let started = Instant::now();
+    let start_time = SystemTime::now();
+    camera.start_seq()?;
+    let mut arrived = 0;
+    let mut last_progress = Instant::now();
+    loop {
+        let (status, bytes) = camera.exp_check_status()?;
+        let frames = bytes as usize / frame_bytes.max(1);
+        if frames != arrived {
+            arrived = frames;
+            last_progress = Instant::now();
+            eprint!("\rframe {}/{}", arrived, options.frames);
+        }
+        match status {
+            CaptureStatus::ReadoutComplete => break,
+            CaptureStatus::ReadoutFailed => {
+                camera.exp_abort()?;
+                return Err(binding_error("readout failed".to_owned()));
+            }
+            _ => {}
+        }
+        if let Some(timeout) = options.timeout {
+            if last_progress.elapsed() > timeout {
+                camera.exp_abort()?;
+                return Err(binding_error(format!(
+                    "no frame arrived for {:?}, {} of {} read out",
+                    timeout, arrived, options.frames
+                )));
+            }
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    let elapsed = started.elapsed();
+    eprintln!("\rframe {}/{}", options.frames, options.frames);
+
+    Ok((camera.finish_seq()?, start_time, elapsed))
+}
+
+#[derive(Serialize)]
+struct FrameMetadata {
+    number: u32,
+    timestamp: Option<i64>,
+    exposure_ms: u128,
+}
+
+#[derive(Serialize)]
+struct Metadata<'a> {
+    camera: &'a str,
+    profile: &'a Profile,
+    regions: &'a [Region],
+    frames: Vec<FrameMetadata>,
+}
+
+fn write_raw(path: &str, sequence: &Sequence) -> pvcam::Result<()> {
+    let io_error = |e: std::io::Error| binding_error(format!("unable to write {}: {}", path, e));
+
+    let mut raw = BufWriter::new(File::create(path).map_err(io_error)?);
+    for frame in sequence.frames.iter() {
+        for pixel in frame.data.iter() {
+            raw.write_all(&pixel.to_le_bytes()).map_err(io_error)?;
+        }
+    }
+    raw.flush().map_err(io_error)
+}
+
+fn write(
+    options: &Options,
+    camera: &str,
+    camera_metadata: io::Metadata,
+    start_time: SystemTime,
+    sequence: &Sequence,
+) -> pvcam::Result<()> {
+    let io_error = |path: &str, e: std::io::Error| -> Error {
+        binding_error(format!("unable to write {}: {}", path, e))
+    };
+
+    let output = options.output.to_lowercase();
+    if output.ends_with(".npy") {
+        npy::save(&options.output, sequence)?;
+    } else if output.ends_with(".npz") {
+        npy::save_npz(&options.output, sequence)?;
+    } else if output.ends_with(".fits") || output.ends_with(".fit") {
+        let mut fits = FitsWriter::create(&options.output, camera_metadata)?.start_time(start_time);
+        fits.write_sequence(sequence)?;
+        fits.finish()?;
+    } else if output.ends_with(".zarr") {
+        let mut zarr =
+            ZarrWriter::create(&options.output, camera_metadata)?.compression(Compression::Blosc {
+                codec: BloscCodec::Lz4,
+                shuffle: true,
+            });
+        zarr.write_sequence(sequence)?;
+        zarr.finish()?;
+    } else if output.ends_with(".ome.tif") || output.ends_with(".ome.tiff") {
+        let mut tiff = OmeTiffWriter::create(&options.output, camera_metadata)?;
+        tiff.write_sequence(sequence)?;
+        tiff.finish()?;
+    } else if output.ends_with(".tif") || output.ends_with(".tiff") {
+        let mut tiff = TiffWriter::create(&options.output, camera_metadata)?;
+        tiff.write_sequence(sequence)?;
+        tiff.finish()?;
+    } else {
+        write_raw(&options.output, sequence)?;
+    }
+
+    let metadata = Metadata {
+        camera,
+        profile: &options.profile,
+        regions: &sequence.regions,
+        frames: sequence
+            .frames
+            .iter()
+            .map(|frame| FrameMetadata {
+                number: frame.number,
+                timestamp: frame.timestamp,
+                exposure_ms: frame.exposure.as_millis(),
+            })
+            .collect(),
+    };
+    let path = format!("{}.json", options.output);
+    let json = serde_json::to_string_pretty(&metadata)
+        .map_err(|e| binding_error(format!("unable to write {}: {}", path, e)))?;
+    std::fs::write(&path, json + "\n").map_err(|e| io_error(&path, e))
+}
+
+// Frames the camera skipped, from gaps in their numbers, and frames the end-of-frame callback
+// never reported
+fn dropped(sequence: &Sequence) -> usize {
+    let mut dropped = 0;
+    let mut previous = 0;
+    for frame in sequence.frames.iter() {
+        if frame.timestamp.is_none() {
+            dropped += 1;
+            continue;
+        }
+        dropped += frame.number.saturating_sub(previous + 1) as usize;
+        previous = frame.number;
+    }
+
+    dropped
+}
+
+fn main() {
+    let options = parse_args();
+
+    if let Err(e) = pvcam::init() {
+        fail(e);
+    }
+    let name = match &options.camera {
+        Some(name) => name.clone(),
+        None => pvcam::cam_get_name(0).unwrap_or_else(|e| fail(e)),
+    };
+    let mut camera = Camera::open(&name).unwrap_or_else(|e| fail(e));
+    for change in camera
+        .apply_profile(&options.profile)
+        .unwrap_or_else(|e| fail(e))
+    {
+        eprintln!("{}", change);
+    }
+
+    let (sequence, start_time, elapsed) =
+        acquire(&mut camera, &options).unwrap_or_else(|e| fail(e));
+    let metadata = io::Metadata::read(&camera).unwrap_or_else(|e| fail(e));
+    drop(camera);
+    let _ = pvcam::uninit();
+
+    if let Err(e) = write(&options, &name, metadata, start_time, &sequence) {
+        eprintln!("pvcam-grab: {}", e);
+        process::exit(1);
+    }
+    let seconds = elapsed.as_secs_f64();
+    eprintln!(
+        "{} frames in {:.3} s, {:.1} fps, {} dropped, written to {}",
+        sequence.frames.len(),
+        seconds,
+        sequence.frames.len() as f64 / seconds.max(f64::EPSILON),
+        dropped(&sequence),
+        options.output
+    );
+}
@@ -0,0 +1,230 @@
+// Lists the cameras PVCam sees and dumps their parameters, for checking a rig without writing
+// code:
+//
+//   cargo run --features cli --bin pvcam-info
+//   cargo run --features cli --bin pvcam-info -- --list
+//   cargo run --features cli --bin pvcam-info -- --format json PMPCIECam00
+//
+// Every camera is dumped unless some are named. --list leaves out the parameters. The output is a
+// table, or with --format the same data as JSON or YAML.
+
+use std::env;
+use std::process;
+
+use serde::Serialize;
+
+use libpvcam_sys::pvcam::{self, Camera, ParamDescription, Parameter, ParameterValue, PARAMETERS};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Table,
+    Json,
+    Yaml,
+}
+
+#[derive(Serialize)]
+struct Info {
+    // the libpvcam in use and the headers this was built against
+    library: String,
+    headers: String,
+    cameras: Vec<CameraInfo>,
+}
+
+#[derive(Serialize)]
+struct CameraInfo {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    product: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    serial: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    firmware: Option<String>,
+    // the available ones, in PARAMETERS order; empty with --list
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    params: Vec<ParamDescription>,
+}
+
+fn usage() -> ! {
+    eprintln!("usage: pvcam-info [--list] [--format table|json|yaml] [<camera>...]");
+    process::exit(2);
+}
+
+fn fail(e: pvcam::Error) -> ! {
+    eprintln!("pvcam-info: {}", e);
+    let _ = pvcam::uninit();
+    process::exit(1);
+}
+
+fn current(camera: &Camera, parameter: Parameter) -> pvcam::Result<Option<ParameterValue>> {
+    Ok(camera.describe_param(parameter)?.current)
+}
+
+fn camera_info(name: &str, list: bool) -> pvcam::Result<CameraInfo> {
+    let camera = Camera::open(name)?;
+    let string = |parameter| -> pvcam::Result<Option<String>> {
+        Ok(current(&camera, parameter)?.map(|v| v.to_string()))
+    };
+    // PARAM_CAM_FW_VERSION is major in the high byte, minor in the low one
+    let firmware = match current(&camera, Parameter::FirmwareVersion)? {
+        Some(ParameterValue::Int(v)) => Some(format!("{}.{}", (v >> 8) & 0xff, v & 0xff)),
+        _ => None,
+    };
+    let mut params = vec![];
+    if !list {
+        for parameter in PARAMETERS.iter() {
+            let param = camera.describe_param(*parameter)?;
+            if param.available {
+                params.push(param);
+            }
+        }
+    }
+
+    Ok(CameraInfo {
+        name: name.to_owned(),
+        product: string(Parameter::ProductName)?,
+        serial: string(Parameter::CameraSerial)?,
+        firmware,
+        params,
+    })
+}
+
+fn value(value: &Option<ParameterValue>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => String::new(),
+    }
+}
+
+// the range of a number, the options of an enum
+fn range(param: &ParamDescription) -> String {
+    if !param.options.is_empty() {
+        return param
+            .options
+            .iter()
+            .map(|e| format!("{} ({})", e.name, e.value))
+            .collect::<Vec<_>>()
+            .join(", ");
+    }
+    match (&param.min, &param.max) {
+        (Some(min), Some(max)) => format!("{}..={}", min, max),
+        _ => String::new(),
+    }
+}
+
+fn print_table(info: &Info) {
+    println!("PVCam {} (built against {})", info.library, info.headers);
+    if info.cameras.is_empty() {
+        println!("no cameras");
+    }
+
+    for camera in info.cameras.iter() {
+        let details: Vec<String> = [
+            camera.product.clone(),
+            camera.serial.as_ref().map(|s| format!("serial {}", s)),
+            camera.firmware.as_ref().map(|f| format!("firmware {}", f)),
+        ]
+        .iter()
+        .flatten()
+        .cloned()
+        .collect();
+        println!();
+        println!("{}: {}", camera.name, details.join(", "));
+        if camera.params.is_empty() {
+            continue;
+        }
+
+        let header = ["parameter", "access", "type", "current", "range"].map(String::from);
+        let rows: Vec<[String; 5]> = camera
+            .params
+            .iter()
+            .map(|param| {
+                [
+                    format!("{:?}", param.parameter),
+                    param.access.map(|a| format!("{:?}", a)).unwrap_or_default(),
+                    param.kind.clone().unwrap_or_default(),
+                    value(&param.current),
+                    range(param),
+                ]
+            })
+            .collect();
+        let mut widths = [0; 5];
+        for row in std::iter::once(&header).chain(rows.iter()) {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        for row in std::iter::once(&header).chain(rows.iter()) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            println!("  {}", line.join("  ").trim_end());
+        }
+    }
+}
+
+fn main() {
+    let mut format = Format::Table;
+    let mut list = false;
+    let mut names = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list" => list = true,
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("table") => Format::Table,
+                    Some("json") => Format::Json,
+                    Some("yaml") => Format::Yaml,
+                    _ => usage(),
+                }
+            }
+            flag if flag.starts_with("--") => usage(),
+            name => names.push(name.to_owned()),
+        }
+    }
+
+    if let Err(e) = pvcam::init() {
+        fail(e);
+    }
+    let library = match pvcam::library_version() {
+        Ok(version) => version.to_string(),
+        Err(e) => fail(e),
+    };
+    if names.is_empty() {
+        let total = pvcam::cam_get_total().unwrap_or_else(|e| fail(e));
+        for i in 0..total {
+            names.push(pvcam::cam_get_name(i).unwrap_or_else(|e| fail(e)));
+        }
+    }
+    let cameras = names
+        .iter()
+        .map(|name| camera_info(name, list))
+        .collect::<pvcam::Result<Vec<_>>>()
+        .unwrap_or_else(|e| fail(e));
+    let _ = pvcam::uninit();
+
+    let info = Info {
+        library,
+        headers: pvcam::SDK_HEADER_VERSION.to_string(),
+        cameras,
+    };
+    match format {
+        Format::Table => print_table(&info),
+        Format::Json => match serde_json::to_string_pretty(&info) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("pvcam-info: {}", e);
+                process::exit(1);
+            }
+        },
+        Format::Yaml => match serde_yaml::to_string(&info) {
+            Ok(yaml) => print!("{}", yaml),
+            Err(e) => {
+                eprintln!("pvcam-info: {}", e);
+                process::exit(1);
+            }
+        },
+    }
+}
@@ -6,6 +6,12 @@ pub mod pvcam {
         #![allow(dead_code)]
         include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+        #[cfg(feature = "dynamic-loading")]
+        mod dynamic;
+        #[cfg(feature = "dynamic-loading")]
+        pub use self::dynamic::*;
+
+        #[cfg(not(feature = "dynamic-loading"))]
         extern "C" {
             // blacklisted from bindgen because it incorrectly identifies camera_name as *mut std::os::raw::c_char
             // this may not be the correct strategy here; it may be the case that
@@ -20,18 +26,79 @@ pub mod pvcam {
         }
     }
 
+    // The raw bindgen output, deprecated functions included, for rigs still built on them
+    #[cfg(feature = "deprecated-api")]
+    pub mod sys {
+        pub use super::internal::*;
+    }
+
+    #[cfg(feature = "ndarray")]
+    mod array;
+    mod backend;
+    mod camera;
+    mod continuous;
+    #[cfg(feature = "describe")]
+    mod describe;
+    pub mod io;
+    #[cfg(feature = "profile")]
+    mod profile;
+    mod sensor;
+    mod sequence;
+    mod simulated;
+    mod state;
+    mod trace;
+    pub use backend::{CameraBackend, EofCallback, FrameInfo, PvcamBackend};
+    pub use camera::Camera;
+    pub use continuous::ContinuousConfig;
+    #[cfg(feature = "describe")]
+    pub use describe::{CameraDescription, ParamDescription, PortDescription, SpeedDescription};
+    #[cfg(feature = "profile")]
+    pub use profile::{Binning, Change, Profile, Roi};
+    pub use sensor::{SensorConditions, SensorModel};
+    pub use sequence::{Frame, Sequence, SequenceConfig};
+    pub use simulated::{SimParam, SimulatedCamera};
+    pub use state::{CameraState, PostProcessingValue, RestoreReport};
+    pub use trace::{Call, RecordingBackend, ReplayBackend, Trace};
+
     use std::ffi;
     use std::fmt;
     use std::os::raw as c_types;
 
     pub type Result<T> = std::result::Result<T, Error>;
 
+    // Pvcam errors come from pl_error_code, everything else is raised by this binding
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ErrorKind {
+        Pvcam,
+        Binding,
+        // pl_exp_trigger reported PL_SW_TRIG_STATUS_IGNORED
+        TriggerNotAccepted,
+        // the call requires an acquisition armed in a suitable ExposureMode
+        NotArmed,
+        // libpvcam could not be loaded at runtime, see the dynamic-loading feature
+        LibraryNotAvailable,
+    }
+
+    // non_exhaustive so fields can be added without breaking callers; build one with Error::new
     #[derive(Debug, Clone)]
+    #[non_exhaustive]
     pub struct Error {
+        pub kind: ErrorKind,
         pub code: i16,
         pub message: String,
     }
 
+    impl Error {
+        // An error raised outside PVCam, which has no pl_error_code
+        pub fn new(kind: ErrorKind, message: String) -> Self {
+            Error {
+                kind,
+                code: -1,
+                message,
+            }
+        }
+    }
+
     impl fmt::Display for Error {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             write!(f, "{} (code: {})", self.message, self.code)
@@ -45,6 +112,7 @@ pub mod pvcam {
         fn from(error: ffi::IntoStringError) -> Self {
             Error {
                 code: -1,
+                kind: ErrorKind::Binding,
                 message: format!("{:?} caused error", error.into_cstring()),
             }
         }
@@ -71,7 +139,25 @@ pub mod pvcam {
         PVResult::Err
     }
 
+    #[cfg(feature = "dynamic-loading")]
+    fn library_error() -> Option<Error> {
+        self::internal::load_error().map(|message| Error {
+            kind: ErrorKind::LibraryNotAvailable,
+            code: -1,
+            message,
+        })
+    }
+
+    #[cfg(not(feature = "dynamic-loading"))]
+    fn library_error() -> Option<Error> {
+        None
+    }
+
     fn pvcam_error() -> Error {
+        if let Some(e) = library_error() {
+            return e;
+        }
+
         let code = unsafe { self::internal::pl_error_code() };
         let message = unsafe {
             let buf =
@@ -88,10 +174,60 @@ pub mod pvcam {
             }
         };
 
-        Error { code, message }
+        Error {
+            kind: ErrorKind::Pvcam,
+            code,
+            message,
+        }
+    }
+
+    // A PVCam release as major.minor.build
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Version {
+        pub major: u16,
+        pub minor: u16,
+        pub build: u16,
+    }
+
+    impl Version {
+        // headers and library agree on the API within a major.minor release
+        pub fn same_release(self, other: Version) -> bool {
+            (self.major, self.minor) == (other.major, other.minor)
+        }
+    }
+
+    impl fmt::Display for Version {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}.{}.{}", self.major, self.minor, self.build)
+        }
+    }
+
+    // SDK_HEADER_VERSION, the headers the bindings were generated from; build.rs reads it from
+    // their version macros or, with bundled-bindings, the selected version feature
+    include!(concat!(env!("OUT_DIR"), "/version.rs"));
+
+    // The version of the libpvcam in use, from pl_pvcam_get_ver. One that is not the
+    // same_release as SDK_HEADER_VERSION may not match the bindings.
+    pub fn library_version() -> Result<Version> {
+        let mut version: u16 = 0;
+
+        match check_call(unsafe { self::internal::pl_pvcam_get_ver(&mut version) }) {
+            // 0xMMmt: major, minor and trivial version
+            PVResult::Ok => Ok(Version {
+                major: version >> 8,
+                minor: (version >> 4) & 0xf,
+                build: version & 0xf,
+            }),
+            PVResult::Err => Err(pvcam_error()),
+        }
     }
 
     pub fn init() -> Result<()> {
+        if let Some(e) = library_error() {
+            return Err(e);
+        }
+
         match check_call(unsafe { self::internal::pl_pvcam_init() }) {
             PVResult::Ok => Ok(()),
             PVResult::Err => Err(pvcam_error()),
@@ -127,6 +263,13 @@ pub mod pvcam {
         }
     }
 
+    pub fn cam_close(cam_handle: i16) -> Result<()> {
+        match check_call(unsafe { self::internal::pl_cam_close(cam_handle) }) {
+            PVResult::Ok => Ok(()),
+            PVResult::Err => Err(pvcam_error()),
+        }
+    }
+
     pub fn cam_open(cam_name: &str) -> Result<i16> {
         unsafe {
             let cam_name = match ffi::CString::new(cam_name) {
@@ -134,6 +277,7 @@ pub mod pvcam {
                 Err(_) => {
                     return Err(Error {
                         code: -1,
+                        kind: ErrorKind::Binding,
                         message: "Unable to create ptr".to_owned(),
                     });
                 }
@@ -152,19 +296,130 @@ pub mod pvcam {
     }
 
     #[repr(u32)]
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum Parameter {
+        AdcOffset = self::internal::PARAM_ADC_OFFSET,
+        BinningParallel = self::internal::PARAM_BINNING_PAR,
+        BinningSerial = self::internal::PARAM_BINNING_SER,
+        BitDepth = self::internal::PARAM_BIT_DEPTH,
+        CameraPartNumber = self::internal::PARAM_CAMERA_PART_NUMBER,
         CameraSerial = self::internal::PARAM_HEAD_SER_NUM_ALPHA,
+        CamInterfaceMode = self::internal::PARAM_CAM_INTERFACE_MODE,
+        CamInterfaceType = self::internal::PARAM_CAM_INTERFACE_TYPE,
+        CentroidsCount = self::internal::PARAM_CENTROIDS_COUNT,
+        CentroidsEnabled = self::internal::PARAM_CENTROIDS_ENABLED,
+        CentroidsRadius = self::internal::PARAM_CENTROIDS_RADIUS,
+        ChipName = self::internal::PARAM_CHIP_NAME,
+        CircBuffer = self::internal::PARAM_CIRC_BUFFER,
+        CleanWhileExposing = self::internal::PARAM_CLN_WHILE_EXPO,
+        ClearCycles = self::internal::PARAM_CLEAR_CYCLES,
+        ClearMode = self::internal::PARAM_CLEAR_MODE,
+        ClearingTime = self::internal::PARAM_CLEARING_TIME,
+        CoolingMode = self::internal::PARAM_COOLING_MODE,
         ExposureMode = self::internal::PARAM_EXPOSURE_MODE,
         ExposeOutMode = self::internal::PARAM_EXPOSE_OUT_MODE,
+        ExposureResolution = self::internal::PARAM_EXP_RES,
+        ExposureResolutionIndex = self::internal::PARAM_EXP_RES_INDEX,
+        ExposureTime = self::internal::PARAM_EXP_TIME,
+        FanSpeedSetpoint = self::internal::PARAM_FAN_SPEED_SETPOINT,
         FirmwareVersion = self::internal::PARAM_CAM_FW_VERSION,
+        FrameCapable = self::internal::PARAM_FRAME_CAPABLE,
         GainIndex = self::internal::PARAM_GAIN_INDEX,
+        GainMultEnable = self::internal::PARAM_GAIN_MULT_ENABLE,
+        GainMultFactor = self::internal::PARAM_GAIN_MULT_FACTOR,
+        GainName = self::internal::PARAM_GAIN_NAME,
+        LastMuxedSignal = self::internal::PARAM_LAST_MUXED_SIGNAL,
+        MetadataEnabled = self::internal::PARAM_METADATA_ENABLED,
+        PixelParallelSize = self::internal::PARAM_PIX_PAR_SIZE,
+        PixelSerialSize = self::internal::PARAM_PIX_SER_SIZE,
+        PixelTime = self::internal::PARAM_PIX_TIME,
+        PMode = self::internal::PARAM_PMODE,
+        ProductName = self::internal::PARAM_PRODUCT_NAME,
         ReadoutPort = self::internal::PARAM_READOUT_PORT,
+        ReadoutTime = self::internal::PARAM_READOUT_TIME,
+        RoiCount = self::internal::PARAM_ROI_COUNT,
+        ScanDirection = self::internal::PARAM_SCAN_DIRECTION,
+        ScanDirectionReset = self::internal::PARAM_SCAN_DIRECTION_RESET,
+        ScanLineDelay = self::internal::PARAM_SCAN_LINE_DELAY,
+        ScanLineTime = self::internal::PARAM_SCAN_LINE_TIME,
+        ScanMode = self::internal::PARAM_SCAN_MODE,
+        ScanWidth = self::internal::PARAM_SCAN_WIDTH,
         SensorParallelSize = self::internal::PARAM_PAR_SIZE,
         SensorSerialSize = self::internal::PARAM_SER_SIZE,
+        ShutterCloseDelay = self::internal::PARAM_SHTR_CLOSE_DELAY,
+        ShutterOpenDelay = self::internal::PARAM_SHTR_OPEN_DELAY,
+        ShutterOpenMode = self::internal::PARAM_SHTR_OPEN_MODE,
+        SmartStreamModeEnabled = self::internal::PARAM_SMART_STREAM_MODE_ENABLED,
         SpeedTableIndex = self::internal::PARAM_SPDTAB_INDEX,
+        Temperature = self::internal::PARAM_TEMP,
+        TemperatureSetpoint = self::internal::PARAM_TEMP_SETPOINT,
+        TriggerTableSignal = self::internal::PARAM_TRIGTAB_SIGNAL,
+        VendorName = self::internal::PARAM_VENDOR_NAME,
     }
 
+    // Every Parameter, ordered so that writing them in turn respects their dependencies: the
+    // readout port decides the speed table, the speed decides the gains, and so on.
+    pub const PARAMETERS: &[Parameter] = &[
+        Parameter::ReadoutPort,
+        Parameter::SpeedTableIndex,
+        Parameter::GainIndex,
+        Parameter::GainMultEnable,
+        Parameter::GainMultFactor,
+        Parameter::AdcOffset,
+        Parameter::PMode,
+        Parameter::ClearMode,
+        Parameter::ClearCycles,
+        Parameter::CleanWhileExposing,
+        Parameter::ShutterOpenMode,
+        Parameter::ShutterOpenDelay,
+        Parameter::ShutterCloseDelay,
+        Parameter::ExposureResolution,
+        Parameter::ExposureResolutionIndex,
+        Parameter::ExposureMode,
+        Parameter::ExposeOutMode,
+        Parameter::ExposureTime,
+        Parameter::ScanMode,
+        Parameter::ScanDirection,
+        Parameter::ScanDirectionReset,
+        Parameter::ScanLineDelay,
+        Parameter::ScanWidth,
+        Parameter::BinningSerial,
+        Parameter::BinningParallel,
+        Parameter::MetadataEnabled,
+        Parameter::CentroidsEnabled,
+        Parameter::CentroidsRadius,
+        Parameter::CentroidsCount,
+        Parameter::SmartStreamModeEnabled,
+        Parameter::CircBuffer,
+        Parameter::TriggerTableSignal,
+        Parameter::LastMuxedSignal,
+        Parameter::TemperatureSetpoint,
+        Parameter::FanSpeedSetpoint,
+        Parameter::BitDepth,
+        Parameter::GainName,
+        Parameter::PixelTime,
+        Parameter::ReadoutTime,
+        Parameter::ClearingTime,
+        Parameter::ScanLineTime,
+        Parameter::RoiCount,
+        Parameter::FrameCapable,
+        Parameter::CoolingMode,
+        Parameter::Temperature,
+        Parameter::SensorParallelSize,
+        Parameter::SensorSerialSize,
+        Parameter::PixelParallelSize,
+        Parameter::PixelSerialSize,
+        Parameter::ChipName,
+        Parameter::CameraSerial,
+        Parameter::CameraPartNumber,
+        Parameter::ProductName,
+        Parameter::VendorName,
+        Parameter::FirmwareVersion,
+        Parameter::CamInterfaceType,
+        Parameter::CamInterfaceMode,
+    ];
+
     impl fmt::Display for Parameter {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             write!(f, "{:?}=>{}", self, *self as u32)
@@ -172,6 +427,7 @@ pub mod pvcam {
     }
 
     #[repr(i16)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
     pub enum ParamAttrKind {
         Current = self::internal::PL_PARAM_ATTRIBUTES_ATTR_CURRENT as i16,
         Count = self::internal::PL_PARAM_ATTRIBUTES_ATTR_COUNT as i16,
@@ -184,71 +440,149 @@ pub mod pvcam {
         Available = self::internal::PL_PARAM_ATTRIBUTES_ATTR_AVAIL as i16,
     }
 
-    fn is_param_avail(cam_handle: i16, param_id: u32) -> Result<bool> {
-        unsafe {
-            // assume false
-            let mut avail: c_types::c_ushort = 0;
-            let mut_ptr = &mut avail as *mut c_types::c_ushort as *mut c_types::c_void;
+    fn unknown_param(param_id: u32) -> Error {
+        Error {
+            code: -1,
+            kind: ErrorKind::Binding,
+            message: format!("parameter {} is unknown", param_id),
+        }
+    }
 
-            match check_call(self::internal::pl_get_param(
-                cam_handle,
-                param_id,
-                ParamAttrKind::Available as i16,
-                mut_ptr,
-            )) {
-                PVResult::Ok => {
-                    Ok(*(mut_ptr as *const c_types::c_ushort) as u32 == self::internal::PV_OK)
-                }
-                PVResult::Err => Err(pvcam_error()),
-            }
+    fn unexpected_value(param_id: u32, value: &ParameterValue) -> Error {
+        Error {
+            code: -1,
+            kind: ErrorKind::Binding,
+            message: format!("unexpected {} for parameter {}", value, param_id),
+        }
+    }
+
+    fn is_available(backend: &dyn CameraBackend, param_id: u32) -> Result<bool> {
+        match backend.get_param(param_id, ParamAttrKind::Available)? {
+            ParameterValue::Bool(v) => Ok(v),
+            other => Err(unexpected_value(param_id, &other)),
+        }
+    }
+
+    // Reads a parameter through the backend, refusing ones the camera does not have
+    fn read_param(
+        backend: &dyn CameraBackend,
+        param_id: u32,
+        param_attr: ParamAttrKind,
+    ) -> Result<ParameterValue> {
+        // the availability check can succeed with a false value
+        if !is_available(backend, param_id)? {
+            return Err(unknown_param(param_id));
+        }
+
+        backend.get_param(param_id, param_attr)
+    }
+
+    // Int and Long values alike, e.g. for Count whose width is not the parameter's
+    fn read_int(
+        backend: &dyn CameraBackend,
+        param_id: u32,
+        param_attr: ParamAttrKind,
+    ) -> Result<i64> {
+        match read_param(backend, param_id, param_attr)? {
+            ParameterValue::Int(v) => Ok(v as i64),
+            ParameterValue::Long(v) => Ok(v),
+            other => Err(unexpected_value(param_id, &other)),
         }
     }
 
+    fn read_string(
+        backend: &dyn CameraBackend,
+        param_id: u32,
+        param_attr: ParamAttrKind,
+    ) -> Result<String> {
+        match read_param(backend, param_id, param_attr)? {
+            ParameterValue::String(v) => Ok(v),
+            other => Err(unexpected_value(param_id, &other)),
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
     enum ParamType {
         Enum,
+        Bool,
+        Int8,
+        UInt8,
         Int16,
+        UInt16,
         Int32,
+        UInt32,
+        Int64,
+        UInt64,
+        Float64,
         String,
     }
 
     fn get_param_type(cam_handle: i16, param_id: u32) -> Result<ParamType> {
-        let kind: u32 = unsafe {
-            let mut t: c_types::c_uint = 0;
-            let mut_ptr = &mut t as *mut c_types::c_uint as *mut c_types::c_void;
-            match check_call(self::internal::pl_get_param(
-                cam_handle,
-                param_id,
-                ParamAttrKind::AttrType as i16,
-                mut_ptr,
-            )) {
-                PVResult::Ok => *(mut_ptr as *const c_types::c_uint),
-                PVResult::Err => {
-                    return Err(pvcam_error());
-                }
-            }
-        };
+        // ATTR_TYPE is an uns16 whatever the parameter's own type
+        let kind = get_param_as::<u16>(cam_handle, param_id, ParamAttrKind::AttrType)? as u32;
 
         match kind {
+            self::internal::TYPE_ENUM => Ok(ParamType::Enum),
+            self::internal::TYPE_BOOLEAN => Ok(ParamType::Bool),
+            self::internal::TYPE_INT8 => Ok(ParamType::Int8),
+            self::internal::TYPE_UNS8 => Ok(ParamType::UInt8),
             self::internal::TYPE_INT16 => Ok(ParamType::Int16),
-            self::internal::TYPE_UNS16 => Ok(ParamType::Int32), // is this a leaky abstraction?
+            self::internal::TYPE_UNS16 => Ok(ParamType::UInt16),
+            self::internal::TYPE_INT32 => Ok(ParamType::Int32),
+            self::internal::TYPE_UNS32 => Ok(ParamType::UInt32),
+            self::internal::TYPE_INT64 => Ok(ParamType::Int64),
+            self::internal::TYPE_UNS64 => Ok(ParamType::UInt64),
+            self::internal::TYPE_FLT64 => Ok(ParamType::Float64),
             self::internal::TYPE_CHAR_PTR => Ok(ParamType::String),
-            self::internal::TYPE_ENUM => Ok(ParamType::Enum),
             _ => Err(Error {
                 code: -1,
+                kind: ErrorKind::Binding,
                 message: format!("{:#X} unknown parameter type", kind),
             }),
         }
     }
 
+    // reads an attribute into a T; T must have the width of the type PVCam reports for it
+    fn get_param_as<T: Copy + Default>(
+        cam_handle: i16,
+        param_id: u32,
+        param_attr: ParamAttrKind,
+    ) -> Result<T> {
+        unsafe {
+            let mut value = T::default();
+            let mut_ptr = &mut value as *mut T as *mut c_types::c_void;
+            match check_call(self::internal::pl_get_param(
+                cam_handle,
+                param_id,
+                param_attr as i16,
+                mut_ptr,
+            )) {
+                PVResult::Ok => Ok(value),
+                PVResult::Err => Err(pvcam_error()),
+            }
+        }
+    }
+
+    fn set_param_as<T: Copy>(cam_handle: i16, param_id: u32, value: T) -> Result<()> {
+        unsafe {
+            let mut value = value;
+            let mut_ptr = &mut value as *mut T as *mut c_types::c_void;
+            match check_call(self::internal::pl_set_param(cam_handle, param_id, mut_ptr)) {
+                PVResult::Ok => Ok(()),
+                PVResult::Err => Err(pvcam_error()),
+            }
+        }
+    }
+
     fn get_param_as_string(
         cam_handle: i16,
         param_id: u32,
         param_attr: ParamAttrKind,
     ) -> Result<String> {
+        // ATTR_COUNT of a string is the buffer it needs, terminator included
+        let len = get_param_as::<u32>(cam_handle, param_id, ParamAttrKind::Count)?.max(1);
         unsafe {
-            let buf =
-                ffi::CString::from_vec_unchecked(vec![0; self::internal::MAX_PP_NAME_LEN as usize])
-                    .into_raw();
+            let buf = ffi::CString::from_vec_unchecked(vec![0; len as usize]).into_raw();
 
             match check_call(self::internal::pl_get_param(
                 cam_handle,
@@ -332,8 +666,22 @@ pub mod pvcam {
         }
     }
 
+    fn set_int_param_u16(cam_handle: i16, param_id: u32, value: u16) -> Result<()> {
+        unsafe {
+            let mut value = value;
+            let mut_ptr = &mut value as *mut c_types::c_ushort as *mut c_types::c_void;
+            match check_call(self::internal::pl_set_param(cam_handle, param_id, mut_ptr)) {
+                PVResult::Ok => Ok(()),
+                PVResult::Err => Err(pvcam_error()),
+            }
+        }
+    }
+
+    // serialized by name and value only; idx is where the camera happens to list it
     #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct PVEnum {
+        #[cfg_attr(feature = "serde", serde(skip))]
         pub idx: u32,
         pub value: i32,
         pub name: String,
@@ -401,6 +749,7 @@ pub mod pvcam {
             Some(idx) => Ok((idx as u32, enums)),
             None => Err(Error {
                 code: -1,
+                kind: ErrorKind::Binding,
                 message: format!("could not find {} in enum with values {:?} ", value, enums),
             }),
         }
@@ -418,59 +767,190 @@ pub mod pvcam {
     }
 
     #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum ParameterValue {
+        // serialized as the selected option's name, see enum_by_name
+        #[cfg_attr(
+            feature = "serde",
+            serde(
+                serialize_with = "enum_by_name::serialize",
+                deserialize_with = "enum_by_name::deserialize"
+            )
+        )]
         Enum(u32, Vec<PVEnum>),
         Int(i32),
+        // uns32, int64 and uns64 parameters
+        Long(i64),
+        Float(f64),
+        Bool(bool),
         String(String),
     }
 
-    pub fn set_param(cam_handle: i16, parameter: Parameter, value: ParameterValue) -> Result<()> {
-        let param_id = parameter as u32;
-        // is_param_avail can succeed with a false value
-        if !is_param_avail(cam_handle, param_id)? {
-            return Err(Error {
+    impl fmt::Display for ParameterValue {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ParameterValue::Enum(idx, enums) => match enums.get(*idx as usize) {
+                    Some(e) => write!(f, "{}", e),
+                    None => write!(f, "{}", idx),
+                },
+                ParameterValue::Int(v) => write!(f, "{}", v),
+                ParameterValue::Long(v) => write!(f, "{}", v),
+                ParameterValue::Float(v) => write!(f, "{}", v),
+                ParameterValue::Bool(v) => write!(f, "{}", v),
+                ParameterValue::String(v) => write!(f, "{}", v),
+            }
+        }
+    }
+
+    // Enum indices and values can be reordered between firmware versions, so a ParameterValue::Enum
+    // is stored as the name of the selected option. It comes back as a single entry list, which
+    // set_param resolves against the camera's options by name.
+    #[cfg(feature = "serde")]
+    mod enum_by_name {
+        use super::PVEnum;
+        use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            idx: &u32,
+            enums: &[PVEnum],
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            match enums.get(*idx as usize) {
+                Some(e) => serializer.serialize_str(&e.name),
+                None => Err(ser::Error::custom(format!(
+                    "enum index {} is not in {:?}",
+                    idx, enums
+                ))),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<(u32, Vec<PVEnum>), D::Error> {
+            let name = String::deserialize(deserializer)?;
+            if name.is_empty() {
+                return Err(de::Error::custom("enum name cannot be empty"));
+            }
+
+            Ok((
+                0,
+                vec![PVEnum {
+                    idx: 0,
+                    value: -1,
+                    name,
+                }],
+            ))
+        }
+    }
+
+    // writes v with the width of the parameter's type, refusing values which do not fit
+    fn set_int_param(cam_handle: i16, param_id: u32, v: i64) -> Result<()> {
+        use std::convert::TryFrom;
+        let too_big = |kind: &str| Error {
+            code: -1,
+            kind: ErrorKind::Binding,
+            message: format!(
+                "{} cannot fit in {}, which is what parameter {} is",
+                v, kind, param_id
+            ),
+        };
+
+        match get_param_type(cam_handle, param_id)? {
+            ParamType::Int8 => set_param_as(
+                cam_handle,
+                param_id,
+                i8::try_from(v).map_err(|_| too_big("i8"))?,
+            ),
+            ParamType::UInt8 => set_param_as(
+                cam_handle,
+                param_id,
+                u8::try_from(v).map_err(|_| too_big("u8"))?,
+            ),
+            ParamType::Int16 => set_int_param_i16(
+                cam_handle,
+                param_id,
+                i16::try_from(v).map_err(|_| too_big("i16"))?,
+            ),
+            ParamType::UInt16 => set_int_param_u16(
+                cam_handle,
+                param_id,
+                u16::try_from(v).map_err(|_| too_big("u16"))?,
+            ),
+            ParamType::Int32 => set_int_param_i32(
+                cam_handle,
+                param_id,
+                i32::try_from(v).map_err(|_| too_big("i32"))?,
+            ),
+            ParamType::UInt32 => set_param_as(
+                cam_handle,
+                param_id,
+                u32::try_from(v).map_err(|_| too_big("u32"))?,
+            ),
+            ParamType::Int64 => set_param_as(cam_handle, param_id, v),
+            ParamType::UInt64 => set_param_as(
+                cam_handle,
+                param_id,
+                u64::try_from(v).map_err(|_| too_big("u64"))?,
+            ),
+            _ => Err(Error {
                 code: -1,
-                message: format!("parameter {} is unknown", param_id),
-            });
+                kind: ErrorKind::Binding,
+                message: "unexpected number type".to_string(),
+            }),
         }
+    }
+
+    // Writes a parameter with the width PVCam reports for it. An Enum is written as the value
+    // of the option its index selects, PVCam wants the value not the index.
+    fn set_param_value(cam_handle: i16, param_id: u32, value: ParameterValue) -> Result<()> {
         // TODO: check if the parameter can be read or if it is write only or exist check only
         // INFO: the PL_PARAM_ACCESS enum governs whether a parameter is r, w, rw or can only be checked for existence
-        use std::convert::TryFrom;
         match value {
-            ParameterValue::Int(v) => match get_param_type(cam_handle, param_id)? {
-                ParamType::Int16 => match i16::try_from(v) {
-                    Ok(v) => set_int_param_i16(cam_handle, param_id, v as i16)?,
-                    Err(_) => {
-                        return Err(Error {
-                            code: -1,
-                            message: format!(
-                                "{} cannot fit in i16, which is what {} is",
-                                v, parameter
-                            ),
-                        })
-                    }
-                },
-                ParamType::Int32 => set_int_param_i32(cam_handle, param_id, v)?,
+            ParameterValue::Int(v) => set_int_param(cam_handle, param_id, v as i64)?,
+            ParameterValue::Long(v) => set_int_param(cam_handle, param_id, v)?,
+            ParameterValue::Float(v) => match get_param_type(cam_handle, param_id)? {
+                ParamType::Float64 => set_param_as(cam_handle, param_id, v)?,
+                _ => {
+                    return Err(Error {
+                        code: -1,
+                        kind: ErrorKind::Binding,
+                        message: "unexpected float type".to_string(),
+                    });
+                }
+            },
+            ParameterValue::Bool(v) => match get_param_type(cam_handle, param_id)? {
+                ParamType::Bool => {
+                    set_param_as(cam_handle, param_id, v as self::internal::rs_bool)?
+                }
                 _ => {
                     return Err(Error {
                         code: -1,
-                        message: "unexpected number type".to_string(),
+                        kind: ErrorKind::Binding,
+                        message: "unexpected bool type".to_string(),
                     });
                 }
             },
-            ParameterValue::Enum(v, _) => match get_param_type(cam_handle, param_id)? {
-                ParamType::Enum => set_enum_param(cam_handle, param_id, v)?,
+            ParameterValue::Enum(idx, enums) => match get_param_type(cam_handle, param_id)? {
+                ParamType::Enum => {
+                    let value = match enums.get(idx as usize) {
+                        Some(e) => e.value as u32,
+                        None => idx,
+                    };
+                    set_enum_param(cam_handle, param_id, value)?
+                }
                 _ => {
                     return Err(Error {
                         code: -1,
+                        kind: ErrorKind::Binding,
                         message: "unexpected enum type".to_string(),
                     });
                 }
             },
-            _ => {
+            ParameterValue::String(_) => {
                 return Err(Error {
                     code: -1,
-                    message: format!("have not implemented this yet"),
+                    kind: ErrorKind::Binding,
+                    message: "have not implemented this yet".to_string(),
                 });
             }
         }
@@ -478,8 +958,55 @@ pub mod pvcam {
         Ok(())
     }
 
+    // The index of an Enum is into the list it carries; the option is looked up on the camera by
+    // name since that list may be stale or deserialized, which leaves a name and no value. The
+    // backends do this in set_param so such a value is never written as it is.
+    fn resolve_enum(
+        backend: &dyn CameraBackend,
+        param_id: u32,
+        value: ParameterValue,
+    ) -> Result<ParameterValue> {
+        let name = match &value {
+            ParameterValue::Enum(idx, enums) => match enums.get(*idx as usize) {
+                Some(e) => e.name.clone(),
+                None => return Ok(value),
+            },
+            _ => return Ok(value),
+        };
+
+        let current = backend.get_enums(param_id)?;
+        match current.iter().position(|option| option.name == name) {
+            Some(i) => Ok(ParameterValue::Enum(i as u32, current)),
+            None => Err(Error {
+                code: -1,
+                kind: ErrorKind::Binding,
+                message: format!("parameter {} has no option named {:?}", param_id, name),
+            }),
+        }
+    }
+
+    // Writes a parameter through the backend, refusing ones the camera does not have
+    fn write_param(
+        backend: &dyn CameraBackend,
+        param_id: u32,
+        value: ParameterValue,
+    ) -> Result<()> {
+        // the availability check can succeed with a false value
+        if !is_available(backend, param_id)? {
+            return Err(unknown_param(param_id));
+        }
+
+        let value = resolve_enum(backend, param_id, value)?;
+        backend.set_param(param_id, value)
+    }
+
+    pub fn set_param(cam_handle: i16, parameter: Parameter, value: ParameterValue) -> Result<()> {
+        write_param(&PvcamBackend::borrowed(cam_handle), parameter as u32, value)
+    }
+
     #[repr(u32)]
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum ParameterAccess {
         ReadOnly = self::internal::PL_PARAM_ACCESS_ACC_READ_ONLY,
         ReadWrite = self::internal::PL_PARAM_ACCESS_ACC_READ_WRITE,
@@ -487,8 +1014,8 @@ pub mod pvcam {
         WriteOnly = self::internal::PL_PARAM_ACCESS_ACC_WRITE_ONLY,
     }
 
-    pub fn get_param_access(cam_handle: i16, parameter: Parameter) -> Result<ParameterAccess> {
-        let access = get_int_param_u16(cam_handle, parameter as u32, ParamAttrKind::Access)?;
+    fn read_access(backend: &dyn CameraBackend, param_id: u32) -> Result<ParameterAccess> {
+        let access = read_int(backend, param_id, ParamAttrKind::Access)?;
         match access as u32 {
             self::internal::PL_PARAM_ACCESS_ACC_READ_ONLY => Ok(ParameterAccess::ReadOnly),
             self::internal::PL_PARAM_ACCESS_ACC_READ_WRITE => Ok(ParameterAccess::ReadWrite),
@@ -496,25 +1023,60 @@ pub mod pvcam {
             self::internal::PL_PARAM_ACCESS_ACC_WRITE_ONLY => Ok(ParameterAccess::WriteOnly),
             _ => Err(Error {
                 code: -1,
-                message: "got {} from access check, not expected".to_owned(),
+                kind: ErrorKind::Binding,
+                message: format!("got {} from access check, not expected", access),
             }),
         }
     }
 
+    pub fn get_param_access(cam_handle: i16, parameter: Parameter) -> Result<ParameterAccess> {
+        read_access(&PvcamBackend::borrowed(cam_handle), parameter as u32)
+    }
+
     pub fn get_param(
         cam_handle: i16,
         parameter: Parameter,
         param_attr: ParamAttrKind,
     ) -> Result<ParameterValue> {
-        let param_id = parameter as u32;
-        // is_param_avail can succeed with a false value
-        if !is_param_avail(cam_handle, param_id)? {
-            return Err(Error {
-                code: -1,
-                message: format!("parameter {} is unknown", param_id),
-            });
+        read_param(
+            &PvcamBackend::borrowed(cam_handle),
+            parameter as u32,
+            param_attr,
+        )
+    }
+
+    // Reads an attribute as pl_get_param would. Available, Access, Count and AttrType have the
+    // same type whatever the parameter's is (rs_bool, uns16, uns32 and uns16); the others have the
+    // parameter's type.
+    fn get_param_value(
+        cam_handle: i16,
+        param_id: u32,
+        param_attr: ParamAttrKind,
+    ) -> Result<ParameterValue> {
+        match param_attr {
+            ParamAttrKind::Available => Ok(ParameterValue::Bool(
+                get_param_as::<self::internal::rs_bool>(cam_handle, param_id, param_attr)? != 0,
+            )),
+            ParamAttrKind::Access => Ok(ParameterValue::Int(get_int_param_u16(
+                cam_handle, param_id, param_attr,
+            )? as i32)),
+            ParamAttrKind::AttrType => Ok(ParameterValue::Long(get_int_param_u16(
+                cam_handle, param_id, param_attr,
+            )? as i64)),
+            ParamAttrKind::Count => Ok(ParameterValue::Long(get_param_as::<u32>(
+                cam_handle, param_id, param_attr,
+            )? as i64)),
+            ParamAttrKind::Current | ParamAttrKind::Min | ParamAttrKind::Max => {
+                get_typed_param(cam_handle, param_id, param_attr)
+            }
         }
+    }
 
+    fn get_typed_param(
+        cam_handle: i16,
+        param_id: u32,
+        param_attr: ParamAttrKind,
+    ) -> Result<ParameterValue> {
         // TODO: check if the parameter can be read or if it is write only or exist check only
         // INFO: the PL_PARAM_ACCESS enum governs whether a parameter is r, w, rw or can only be checked for existence
         match get_param_type(cam_handle, param_id)? {
@@ -522,12 +1084,36 @@ pub mod pvcam {
                 let (idx, enums) = get_param_as_enum(cam_handle, param_id, param_attr)?;
                 Ok(ParameterValue::Enum(idx, enums))
             }
+            ParamType::Bool => Ok(ParameterValue::Bool(
+                get_param_as::<self::internal::rs_bool>(cam_handle, param_id, param_attr)? != 0,
+            )),
+            ParamType::Int8 => Ok(ParameterValue::Int(get_param_as::<i8>(
+                cam_handle, param_id, param_attr,
+            )? as i32)),
+            ParamType::UInt8 => Ok(ParameterValue::Int(get_param_as::<u8>(
+                cam_handle, param_id, param_attr,
+            )? as i32)),
             ParamType::Int16 => Ok(ParameterValue::Int(get_int_param_i16(
                 cam_handle, param_id, param_attr,
             )? as i32)),
+            ParamType::UInt16 => Ok(ParameterValue::Int(get_int_param_u16(
+                cam_handle, param_id, param_attr,
+            )? as i32)),
             ParamType::Int32 => Ok(ParameterValue::Int(get_int_param_i32(
                 cam_handle, param_id, param_attr,
             )?)),
+            ParamType::UInt32 => Ok(ParameterValue::Long(get_param_as::<u32>(
+                cam_handle, param_id, param_attr,
+            )? as i64)),
+            ParamType::Int64 => Ok(ParameterValue::Long(get_param_as::<i64>(
+                cam_handle, param_id, param_attr,
+            )?)),
+            ParamType::UInt64 => Ok(ParameterValue::Long(get_param_as::<u64>(
+                cam_handle, param_id, param_attr,
+            )? as i64)),
+            ParamType::Float64 => Ok(ParameterValue::Float(get_param_as::<f64>(
+                cam_handle, param_id, param_attr,
+            )?)),
             ParamType::String => Ok(ParameterValue::String(get_param_as_string(
                 cam_handle, param_id, param_attr,
             )?)),
@@ -536,6 +1122,9 @@ pub mod pvcam {
 
     // disable the dead_code check here because this is just a rusty export of rgn_type
     #[allow(dead_code)]
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Region {
         s1: u16,
         s2: u16,
@@ -547,15 +1136,79 @@ pub mod pvcam {
 
     use std::ops::Range;
     impl Region {
+        // Panics if a binning is 0 or a range ends before it starts
         pub fn new(x_config: (u16, Range<u16>), y_config: (u16, Range<u16>)) -> Self {
-            return Region {
+            assert!(
+                x_config.0 > 0 && y_config.0 > 0,
+                "region binning {}x{} is not at least 1x1",
+                x_config.0,
+                y_config.0
+            );
+            assert!(
+                x_config.1.start <= x_config.1.end && y_config.1.start <= y_config.1.end,
+                "region {:?} x {:?} ends before it starts",
+                x_config.1,
+                y_config.1
+            );
+            Region {
                 s1: x_config.1.start,
                 s2: x_config.1.end,
                 sbin: x_config.0,
                 p1: y_config.1.start,
                 p2: y_config.1.end,
                 pbin: y_config.0,
-            };
+            }
+        }
+
+        // pixels per row once binned; s1..s2 is inclusive in rgn_type. A region new would refuse,
+        // deserialized or read from a file, has none.
+        pub fn width(&self) -> usize {
+            binned(self.s1, self.s2, self.sbin)
+        }
+
+        // rows once binned; p1..p2 is inclusive in rgn_type
+        pub fn height(&self) -> usize {
+            binned(self.p1, self.p2, self.pbin)
+        }
+    }
+
+    fn binned(start: u16, end: u16, bin: u16) -> usize {
+        end.checked_sub(start)
+            .and_then(|len| (len as usize + 1).checked_div(bin as usize))
+            .unwrap_or(0)
+    }
+
+    #[repr(i16)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum ExposureMode {
+        Timed = self::internal::PL_EXPOSURE_MODES_TIMED_MODE as i16,
+        Strobed = self::internal::PL_EXPOSURE_MODES_STROBED_MODE as i16,
+        Bulb = self::internal::PL_EXPOSURE_MODES_BULB_MODE as i16,
+        TriggerFirst = self::internal::PL_EXPOSURE_MODES_TRIGGER_FIRST_MODE as i16,
+        Flash = self::internal::PL_EXPOSURE_MODES_FLASH_MODE as i16,
+        VariableTimed = self::internal::PL_EXPOSURE_MODES_VARIABLE_TIMED_MODE as i16,
+        IntStrobe = self::internal::PL_EXPOSURE_MODES_INT_STROBE_MODE as i16,
+        ExtTrigInternal = self::internal::PL_EXPOSURE_MODES_EXT_TRIG_INTERNAL as i16,
+        ExtTrigTrigFirst = self::internal::PL_EXPOSURE_MODES_EXT_TRIG_TRIG_FIRST as i16,
+        ExtTrigEdgeRising = self::internal::PL_EXPOSURE_MODES_EXT_TRIG_EDGE_RISING as i16,
+        ExtTrigLevel = self::internal::PL_EXPOSURE_MODES_EXT_TRIG_LEVEL as i16,
+        #[cfg(pvcam_3_9)]
+        ExtTrigSoftwareFirst = self::internal::PL_EXPOSURE_MODES_EXT_TRIG_SOFTWARE_FIRST as i16,
+        #[cfg(pvcam_3_9)]
+        ExtTrigSoftwareEdge = self::internal::PL_EXPOSURE_MODES_EXT_TRIG_SOFTWARE_EDGE as i16,
+        ExtTrigLevelOverlap = self::internal::PL_EXPOSURE_MODES_EXT_TRIG_LEVEL_OVERLAP as i16,
+        ExtTrigLevelPulsed = self::internal::PL_EXPOSURE_MODES_EXT_TRIG_LEVEL_PULSED as i16,
+    }
+
+    impl ExposureMode {
+        // only these modes wait on pl_exp_trigger to start a frame
+        pub fn is_software_trigger(self) -> bool {
+            match self {
+                #[cfg(pvcam_3_9)]
+                Self::ExtTrigSoftwareFirst | Self::ExtTrigSoftwareEdge => true,
+                _ => false,
+            }
         }
     }
 
@@ -600,6 +1253,7 @@ pub mod pvcam {
 
     #[repr(i16)]
     #[derive(Debug, Clone, Copy)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum CaptureStatus {
         ReadoutNotActive = self::internal::PL_IMAGE_STATUSES_READOUT_NOT_ACTIVE as i16,
         ExposureInProgress = self::internal::PL_IMAGE_STATUSES_EXPOSURE_IN_PROGRESS as i16,
@@ -636,4 +1290,140 @@ pub mod pvcam {
             }
         }
     }
+
+    pub fn exp_abort(cam_handle: i16) -> Result<()> {
+        match check_call(unsafe {
+            self::internal::pl_exp_abort(
+                cam_handle,
+                self::internal::PL_CCS_ABORT_MODES_CCS_HALT as i16,
+            )
+        }) {
+            PVResult::Ok => Ok(()),
+            PVResult::Err => Err(pvcam_error()),
+        }
+    }
+
+    // Sets up a continuous acquisition into a CIRC_OVERWRITE buffer, returning the bytes of a frame
+    pub fn exp_setup_cont(
+        cam_handle: i16,
+        regions: Vec<Region>,
+        exp_mode: i16,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        unsafe {
+            let mut frame_size: u32 = 0;
+            let region_total = regions.len() as u16;
+            let regions = regions.as_ptr() as *const self::internal::rgn_type;
+
+            match check_call(self::internal::pl_exp_setup_cont(
+                cam_handle,
+                region_total,
+                regions,
+                exp_mode,
+                exposure_ms,
+                &mut frame_size,
+                self::internal::PL_CIRC_MODES_CIRC_OVERWRITE as i16,
+            )) {
+                PVResult::Ok => Ok(frame_size),
+                PVResult::Err => Err(pvcam_error()),
+            }
+        }
+    }
+
+    // buf_size is in bytes and should be a multiple of the frame size exp_setup_cont returned
+    pub fn exp_start_cont(cam_handle: i16, buf_ptr: *mut u16, buf_size: u32) -> Result<()> {
+        unsafe {
+            match check_call(self::internal::pl_exp_start_cont(
+                cam_handle,
+                buf_ptr as *mut c_types::c_void,
+                buf_size,
+            )) {
+                PVResult::Ok => Ok(()),
+                PVResult::Err => Err(pvcam_error()),
+            }
+        }
+    }
+
+    // (status, bytes arrived, times the buffer has been filled)
+    pub fn exp_check_cont_status(cam_handle: i16) -> Result<(CaptureStatus, u32, u32)> {
+        unsafe {
+            let mut status: i16 = -1;
+            let mut bytes_read: u32 = 0;
+            let mut buffer_cnt: u32 = 0;
+            match check_call(self::internal::pl_exp_check_cont_status(
+                cam_handle,
+                &mut status,
+                &mut bytes_read,
+                &mut buffer_cnt,
+            )) {
+                PVResult::Ok => Ok((CaptureStatus::from_i16(status), bytes_read, buffer_cnt)),
+                PVResult::Err => Err(pvcam_error()),
+            }
+        }
+    }
+
+    // Where in the continuous buffer the newest frame starts, along with its FRAME_INFO
+    pub fn exp_get_latest_frame(cam_handle: i16) -> Result<(*mut u16, FrameInfo)> {
+        unsafe {
+            // created by PVCam so the structure carries the version it expects
+            let mut info: *mut self::internal::FRAME_INFO = std::ptr::null_mut();
+            if let PVResult::Err =
+                check_call(self::internal::pl_create_frame_info_struct(&mut info))
+            {
+                return Err(pvcam_error());
+            }
+
+            let mut frame: *mut c_types::c_void = std::ptr::null_mut();
+            let result = match check_call(self::internal::pl_exp_get_latest_frame_ex(
+                cam_handle, &mut frame, info,
+            )) {
+                PVResult::Ok => Ok((
+                    frame as *mut u16,
+                    FrameInfo {
+                        number: (*info).FrameNr as u32,
+                        timestamp: (*info).TimeStamp,
+                    },
+                )),
+                PVResult::Err => Err(pvcam_error()),
+            };
+            self::internal::pl_release_frame_info_struct(info);
+
+            result
+        }
+    }
+
+    pub fn exp_stop_cont(cam_handle: i16) -> Result<()> {
+        match check_call(unsafe {
+            self::internal::pl_exp_stop_cont(
+                cam_handle,
+                self::internal::PL_CCS_ABORT_MODES_CCS_HALT as i16,
+            )
+        }) {
+            PVResult::Ok => Ok(()),
+            PVResult::Err => Err(pvcam_error()),
+        }
+    }
+
+    // the flags pl_exp_trigger wrote back, or TriggerNotAccepted if they say it was ignored
+    #[cfg(pvcam_3_9)]
+    fn trigger_flags(flags: u32) -> Result<u32> {
+        match flags {
+            self::internal::PL_SW_TRIG_STATUSES_PL_SW_TRIG_STATUS_IGNORED => Err(Error {
+                kind: ErrorKind::TriggerNotAccepted,
+                code: -1,
+                message: "software trigger was not accepted by the camera".to_owned(),
+            }),
+            _ => Ok(flags),
+        }
+    }
+
+    // not public: Camera::trigger guards this behind an armed software-trigger acquisition
+    #[cfg(pvcam_3_9)]
+    fn exp_trigger(cam_handle: i16, flags: u32, value: u32) -> Result<u32> {
+        let mut flags = flags;
+        match check_call(unsafe { self::internal::pl_exp_trigger(cam_handle, &mut flags, value) }) {
+            PVResult::Ok => trigger_flags(flags),
+            PVResult::Err => Err(pvcam_error()),
+        }
+    }
 }
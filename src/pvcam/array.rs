@@ -0,0 +1,84 @@
+// Frames and sequences as ndarray arrays of (rows, columns), binned pixels along both, so
+// frames go straight into analysis code:
+//
+//   let sequence = camera.acquire_seq(&config)?;
+//   let stack = sequence.as_array3();   // (frames, rows, columns)
+//   let frame = &sequence.frames[0];
+//   let mean = frame.view().mapv(f64::from).mean();
+//   for (region, view) in frame.regions.iter().zip(frame.region_views()) { ... }
+//
+// Frame views borrow the frame's data. A sequence's frames are separate buffers, so its arrays
+// are copies.
+
+use ndarray::{Array3, ArrayView2};
+
+use super::{Frame, Region, Sequence};
+
+// Where each region's pixels start in a frame's data, and how many there are
+fn spans(regions: &[Region]) -> Vec<(usize, usize)> {
+    let mut start = 0;
+    regions
+        .iter()
+        .map(|region| {
+            let len = region.width() * region.height();
+            start += len;
+            (start - len, len)
+        })
+        .collect()
+}
+
+impl Frame {
+    // The first region; the only one for most acquisitions. Panics if the data is shorter than
+    // the regions say, which it is not for frames from a Camera.
+    pub fn view(&self) -> ArrayView2<'_, u16> {
+        match self.region_view(0) {
+            Some(view) => view,
+            None => panic!(
+                "frame {} has {} pixels, fewer than its first region",
+                self.number,
+                self.data.len()
+            ),
+        }
+    }
+
+    // The index'th region, or None if there is no such region or the data is too short for it
+    pub fn region_view(&self, index: usize) -> Option<ArrayView2<'_, u16>> {
+        let region = self.regions.get(index)?;
+        let (start, len) = spans(&self.regions)[index];
+        let pixels = self.data.get(start..start + len)?;
+
+        ArrayView2::from_shape((region.height(), region.width()), pixels).ok()
+    }
+
+    // A view per region, in the order of the regions, stopping at the first the data is too
+    // short for
+    pub fn region_views(&self) -> Vec<ArrayView2<'_, u16>> {
+        (0..self.regions.len())
+            .map_while(|index| self.region_view(index))
+            .collect()
+    }
+}
+
+impl Sequence {
+    // The first region of every frame as (frames, rows, columns). Panics if a frame's data is
+    // shorter than the regions say, which it is not for sequences from a Camera.
+    pub fn as_array3(&self) -> Array3<u16> {
+        match self.region_array3(0) {
+            Some(array) => array,
+            None => panic!("the sequence has no regions, or a frame has fewer pixels than them"),
+        }
+    }
+
+    // The index'th region of every frame as (frames, rows, columns), or None if there is no
+    // such region or a frame's data is too short for it
+    pub fn region_array3(&self, index: usize) -> Option<Array3<u16>> {
+        let region = self.regions.get(index)?;
+        let (start, len) = spans(&self.regions)[index];
+        let mut pixels = Vec::with_capacity(self.frames.len() * len);
+        for frame in self.frames.iter() {
+            pixels.extend_from_slice(frame.data.get(start..start + len)?);
+        }
+
+        Array3::from_shape_vec((self.frames.len(), region.height(), region.width()), pixels).ok()
+    }
+}
@@ -0,0 +1,278 @@
+use std::os::raw as c_types;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard};
+
+#[cfg(pvcam_3_9)]
+use super::exp_trigger;
+use super::{
+    cam_close, cam_open, check_call, exp_abort, exp_check_cont_status, exp_check_status,
+    exp_get_latest_frame, exp_setup_cont, exp_setup_seq, exp_start_cont, exp_start_seq,
+    exp_stop_cont, get_enums, get_param_value, internal, pvcam_error, resolve_enum,
+    set_param_value, CaptureStatus, Error, ErrorKind, ExposureMode, PVEnum, PVResult,
+    ParamAttrKind, ParameterValue, Region, Result,
+};
+
+// What PVCam's FRAME_INFO says about a frame as it arrives
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameInfo {
+    // FrameNr, 1 based
+    pub number: u32,
+    pub timestamp: i64,
+}
+
+// Runs after every frame on the backend's thread, which is not the one that started the
+// acquisition. The backend is passed back in for the calls allowed from there, such as setting
+// PARAM_EXP_TIME in VARIABLE_TIMED_MODE. The info is None if the frame came without any.
+pub type EofCallback = Box<dyn FnMut(Option<FrameInfo>, &dyn CameraBackend) + Send>;
+
+// The calls the safe layer makes on a camera, close to one per pl_* function. PvcamBackend
+// passes them on to libpvcam and SimulatedCamera answers them itself, so everything built on
+// Camera runs the same against either.
+#[allow(clippy::missing_safety_doc)]
+pub trait CameraBackend: Send {
+    // the PVCam handle, if there is one
+    fn handle(&self) -> Option<i16> {
+        None
+    }
+
+    // pl_get_param. Current, Min and Max have the parameter's type; Available is a Bool,
+    // Access an Int holding a PL_PARAM_ACCESS, and Count and AttrType are Longs.
+    fn get_param(&self, param_id: u32, attr: ParamAttrKind) -> Result<ParameterValue>;
+    // pl_set_param; an Enum is written as the value of the camera's option with the name of the
+    // one its index selects
+    fn set_param(&self, param_id: u32, value: ParameterValue) -> Result<()>;
+    // pl_get_enum_param for every option of the parameter
+    fn get_enums(&self, param_id: u32) -> Result<Vec<PVEnum>>;
+
+    // pl_exp_setup_seq, returning the bytes the exp_start_seq buffer must hold
+    fn exp_setup_seq(
+        &self,
+        exp_total: u16,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32>;
+    // pl_exp_start_seq. The buffer is written to until the sequence completes or is aborted,
+    // so it must stay valid until then.
+    unsafe fn exp_start_seq(&self, buffer: *mut u16) -> Result<()>;
+    fn exp_check_status(&self) -> Result<(CaptureStatus, u32)>;
+    // pl_exp_abort with CCS_HALT
+    fn exp_abort(&self) -> Result<()>;
+    // pl_exp_trigger; a trigger the camera ignores is ErrorKind::TriggerNotAccepted
+    fn exp_trigger(&self, _flags: u32, _value: u32) -> Result<u32> {
+        Err(Error {
+            kind: ErrorKind::Binding,
+            code: -1,
+            message: "software triggers need the PVCam 3.9 headers".to_owned(),
+        })
+    }
+
+    // pl_exp_setup_cont with a CIRC_OVERWRITE buffer, returning the bytes of one frame
+    fn exp_setup_cont(
+        &self,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32>;
+    // pl_exp_start_cont. Frames are written round the `size` bytes of the buffer until
+    // exp_stop_cont, so it must stay valid until then.
+    unsafe fn exp_start_cont(&self, buffer: *mut u16, size: u32) -> Result<()>;
+    // (status, bytes arrived, times the buffer has been filled)
+    fn exp_check_cont_status(&self) -> Result<(CaptureStatus, u32, u32)>;
+    // pl_exp_get_latest_frame_ex: where the newest frame starts in the continuous buffer
+    fn exp_get_latest_frame(&self) -> Result<(*const u16, Option<FrameInfo>)>;
+    // pl_exp_stop_cont with CCS_HALT
+    fn exp_stop_cont(&self) -> Result<()>;
+
+    // pl_cam_register_callback_ex3 for PL_CALLBACK_EOF, replacing any callback registered before
+    fn register_eof(&self, callback: EofCallback) -> Result<()>;
+    fn deregister_eof(&self) -> Result<()>;
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+// What PVCam hands back to on_eof; boxed so its address is stable while registered
+struct EofRegistration {
+    handle: i16,
+    callback: EofCallback,
+}
+
+// Runs on a PVCam thread after every frame; must not panic across the FFI boundary
+extern "C" fn on_eof(frame_info: *mut internal::FRAME_INFO, context: *mut c_types::c_void) {
+    let registration = unsafe { &mut *(context as *mut EofRegistration) };
+    let info = unsafe { frame_info.as_ref() }.map(|info| FrameInfo {
+        number: info.FrameNr as u32,
+        timestamp: info.TimeStamp,
+    });
+    let backend = PvcamBackend::borrowed(registration.handle);
+
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| (registration.callback)(info, &backend)));
+}
+
+// A camera opened through libpvcam; closed again when dropped
+pub struct PvcamBackend {
+    handle: i16,
+    // false for the short lived ones wrapping a handle someone else closes
+    owned: bool,
+    eof: Mutex<Option<Box<EofRegistration>>>,
+}
+
+impl PvcamBackend {
+    pub fn open(cam_name: &str) -> Result<Self> {
+        Ok(PvcamBackend {
+            handle: cam_open(cam_name)?,
+            owned: true,
+            eof: Mutex::new(None),
+        })
+    }
+
+    // for the free functions that take a handle
+    pub(super) fn borrowed(cam_handle: i16) -> Self {
+        PvcamBackend {
+            handle: cam_handle,
+            owned: false,
+            eof: Mutex::new(None),
+        }
+    }
+}
+
+impl CameraBackend for PvcamBackend {
+    fn handle(&self) -> Option<i16> {
+        Some(self.handle)
+    }
+
+    fn get_param(&self, param_id: u32, attr: ParamAttrKind) -> Result<ParameterValue> {
+        get_param_value(self.handle, param_id, attr)
+    }
+
+    fn set_param(&self, param_id: u32, value: ParameterValue) -> Result<()> {
+        set_param_value(self.handle, param_id, resolve_enum(self, param_id, value)?)
+    }
+
+    fn get_enums(&self, param_id: u32) -> Result<Vec<PVEnum>> {
+        get_enums(self.handle, param_id)
+    }
+
+    fn exp_setup_seq(
+        &self,
+        exp_total: u16,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        exp_setup_seq(
+            self.handle,
+            exp_total,
+            regions.to_vec(),
+            exp_mode as i16,
+            exposure_ms,
+        )
+    }
+
+    unsafe fn exp_start_seq(&self, buffer: *mut u16) -> Result<()> {
+        exp_start_seq(self.handle, buffer)
+    }
+
+    fn exp_check_status(&self) -> Result<(CaptureStatus, u32)> {
+        exp_check_status(self.handle)
+    }
+
+    fn exp_abort(&self) -> Result<()> {
+        exp_abort(self.handle)
+    }
+
+    #[cfg(pvcam_3_9)]
+    fn exp_trigger(&self, flags: u32, value: u32) -> Result<u32> {
+        exp_trigger(self.handle, flags, value)
+    }
+
+    fn exp_setup_cont(
+        &self,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        exp_setup_cont(self.handle, regions.to_vec(), exp_mode as i16, exposure_ms)
+    }
+
+    unsafe fn exp_start_cont(&self, buffer: *mut u16, size: u32) -> Result<()> {
+        exp_start_cont(self.handle, buffer, size)
+    }
+
+    fn exp_check_cont_status(&self) -> Result<(CaptureStatus, u32, u32)> {
+        exp_check_cont_status(self.handle)
+    }
+
+    fn exp_get_latest_frame(&self) -> Result<(*const u16, Option<FrameInfo>)> {
+        let (frame, info) = exp_get_latest_frame(self.handle)?;
+        Ok((frame as *const u16, Some(info)))
+    }
+
+    fn exp_stop_cont(&self) -> Result<()> {
+        exp_stop_cont(self.handle)
+    }
+
+    fn register_eof(&self, callback: EofCallback) -> Result<()> {
+        // the old registration may only be freed once PVCam no longer calls it
+        self.deregister_eof()?;
+
+        let mut eof = lock(&self.eof);
+        let mut registration = Box::new(EofRegistration {
+            handle: self.handle,
+            callback,
+        });
+        let callback = on_eof as extern "C" fn(*mut internal::FRAME_INFO, *mut c_types::c_void);
+        match check_call(unsafe {
+            internal::pl_cam_register_callback_ex3(
+                self.handle,
+                internal::PL_CALLBACK_EVENT_PL_CALLBACK_EOF as i32,
+                callback as *mut c_types::c_void,
+                &mut *registration as *mut EofRegistration as *mut c_types::c_void,
+            )
+        }) {
+            PVResult::Ok => {
+                *eof = Some(registration);
+                Ok(())
+            }
+            PVResult::Err => Err(pvcam_error()),
+        }
+    }
+
+    fn deregister_eof(&self) -> Result<()> {
+        let mut eof = lock(&self.eof);
+        if eof.is_none() {
+            return Ok(());
+        }
+
+        match check_call(unsafe {
+            internal::pl_cam_deregister_callback(
+                self.handle,
+                internal::PL_CALLBACK_EVENT_PL_CALLBACK_EOF as i32,
+            )
+        }) {
+            PVResult::Ok => {
+                *eof = None;
+                Ok(())
+            }
+            PVResult::Err => Err(pvcam_error()),
+        }
+    }
+}
+
+impl Drop for PvcamBackend {
+    fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+
+        // nothing useful can be done with a failure this late
+        let _ = self.deregister_eof();
+        let _ = cam_close(self.handle);
+    }
+}
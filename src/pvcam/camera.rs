@@ -0,0 +1,306 @@
+use std::thread;
+use std::time::Duration;
+
+use super::backend::{CameraBackend, PvcamBackend};
+use super::continuous::Continuous;
+#[cfg(feature = "describe")]
+use super::describe::{self, CameraDescription, ParamDescription};
+#[cfg(feature = "profile")]
+use super::profile::{self, Change, Profile};
+use super::sequence::Acquisition;
+use super::state;
+#[cfg(feature = "describe")]
+use super::Parameter;
+use super::{
+    CameraState, CaptureStatus, ContinuousConfig, Error, ErrorKind, ExposureMode, Frame, Region,
+    RestoreReport, Result, Sequence, SequenceConfig,
+};
+
+// Owns a camera backend and tracks the acquisition it has armed, so calls that are only
+// valid mid-acquisition (e.g. trigger) can be refused up front rather than by the SDK.
+pub struct Camera {
+    // dropped before the acquisitions, whose buffers it may still be writing into
+    backend: Box<dyn CameraBackend>,
+    exp_mode: Option<ExposureMode>,
+    armed: bool,
+    acquisition: Option<Acquisition>,
+    continuous: Option<Continuous>,
+    // the last one applied, whose roi, binning, exposure and trigger sequence_config uses
+    #[cfg(feature = "profile")]
+    profile: Option<Profile>,
+}
+
+impl Camera {
+    pub fn open(cam_name: &str) -> Result<Self> {
+        Ok(Self::new(PvcamBackend::open(cam_name)?))
+    }
+
+    // A camera on any backend, e.g. a SimulatedCamera
+    pub fn new<B: CameraBackend + 'static>(backend: B) -> Self {
+        Camera {
+            backend: Box::new(backend),
+            exp_mode: None,
+            armed: false,
+            acquisition: None,
+            continuous: None,
+            #[cfg(feature = "profile")]
+            profile: None,
+        }
+    }
+
+    // None unless the camera was opened through PVCam
+    pub fn handle(&self) -> Option<i16> {
+        self.backend.handle()
+    }
+
+    pub fn backend(&self) -> &dyn CameraBackend {
+        &*self.backend
+    }
+
+    // Stops whatever is still writing into the acquisition buffers before they are dropped
+    fn release(&mut self) {
+        if self.continuous.is_some() {
+            let _ = self.backend.exp_stop_cont();
+            self.continuous = None;
+        }
+        if self.acquisition.is_some() {
+            if self.armed {
+                let _ = self.backend.exp_abort();
+            }
+            let _ = self.backend.deregister_eof();
+            self.acquisition = None;
+        }
+        self.armed = false;
+    }
+
+    pub fn exp_setup_seq(
+        &mut self,
+        exp_total: u16,
+        regions: Vec<Region>,
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        let buf_size = self
+            .backend
+            .exp_setup_seq(exp_total, &regions, exp_mode, exposure_ms)?;
+        self.exp_mode = Some(exp_mode);
+        self.armed = false;
+
+        Ok(buf_size)
+    }
+
+    // start_seq starts into a buffer of its own and is the one to use.
+    //
+    // Safety: `buf_ptr` must point to at least the number of bytes exp_setup_seq returned, and
+    // stay valid and otherwise untouched until the sequence completes or is aborted, as the
+    // camera writes whole frames through it from another thread.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn exp_start_seq(&mut self, buf_ptr: *mut u16) -> Result<()> {
+        self.backend.exp_start_seq(buf_ptr)?;
+        self.armed = true;
+
+        Ok(())
+    }
+
+    // Reads every available, readable Parameter along with the post-processing values; check
+    // is_complete, a parameter that cannot be read does not stop the others
+    pub fn snapshot(&self) -> CameraState {
+        state::snapshot(&*self.backend)
+    }
+
+    // Writes a snapshot back in dependency order, skipping read only parameters; check the
+    // report, a parameter that cannot be written does not stop the others
+    pub fn restore(&mut self, state: &CameraState) -> RestoreReport {
+        state::restore(&*self.backend, state)
+    }
+
+    // Validates every setting in the profile against this camera before writing any of them,
+    // then returns the settings that changed. Nothing is written if one of them does not fit,
+    // and the ones already written are put back if a write fails. A speed or gain behind a port
+    // or speed the profile selects is only checked once that is selected, and puts it back if it
+    // does not fit. The acquisition settings are kept for sequence_config.
+    #[cfg(feature = "profile")]
+    pub fn apply_profile(&mut self, profile: &Profile) -> Result<Vec<Change>> {
+        let changes = profile::apply(&*self.backend, profile)?;
+        self.profile = Some(profile.clone());
+
+        Ok(changes)
+    }
+
+    // A sequence of frames with the roi, binning, exposure and trigger of the last applied profile
+    #[cfg(feature = "profile")]
+    pub fn sequence_config(&self, frames: u16) -> Result<SequenceConfig> {
+        match &self.profile {
+            Some(profile) => profile.sequence_config(self, frames),
+            None => Err(Error {
+                kind: ErrorKind::Binding,
+                code: -1,
+                message: "no profile has been applied".to_owned(),
+            }),
+        }
+    }
+
+    // Reads every Parameter with its access, type, range and options, and the speed table. Every
+    // port and speed is selected in turn for that; the port, speed and gain are put back after.
+    #[cfg(feature = "describe")]
+    pub fn describe(&mut self) -> Result<CameraDescription> {
+        describe::describe(&*self.backend)
+    }
+
+    // One parameter as describe reads it, without touching the port or speed
+    #[cfg(feature = "describe")]
+    pub fn describe_param(&self, parameter: Parameter) -> Result<ParamDescription> {
+        describe::describe_param(&*self.backend, parameter)
+    }
+
+    // Sets up a sequence into a buffer owned by the camera; pair with start_seq and finish_seq,
+    // or use acquire_seq to do all three
+    pub fn setup_seq(&mut self, config: &SequenceConfig) -> Result<()> {
+        // release any previous acquisition first so its callback is deregistered
+        self.release();
+        let acquisition = Acquisition::setup(&*self.backend, config)?;
+        self.exp_mode = Some(acquisition.exp_mode());
+        self.armed = false;
+        self.acquisition = Some(acquisition);
+
+        Ok(())
+    }
+
+    pub fn start_seq(&mut self) -> Result<()> {
+        let buf_ptr = match self.acquisition.as_mut() {
+            Some(acquisition) => acquisition.buffer_ptr(),
+            None => {
+                return Err(Error {
+                    kind: ErrorKind::NotArmed,
+                    code: -1,
+                    message: "start_seq called before setup_seq".to_owned(),
+                })
+            }
+        };
+
+        // the acquisition holds a buffer of the size setup_seq asked for, and release stops the
+        // camera before the buffer is dropped
+        unsafe { self.exp_start_seq(buf_ptr) }
+    }
+
+    pub fn finish_seq(&mut self) -> Result<Sequence> {
+        self.armed = false;
+        match self.acquisition.take() {
+            Some(acquisition) => {
+                self.backend.deregister_eof()?;
+                Ok(acquisition.finish())
+            }
+            None => Err(Error {
+                kind: ErrorKind::NotArmed,
+                code: -1,
+                message: "finish_seq called before setup_seq".to_owned(),
+            }),
+        }
+    }
+
+    // Blocks until every frame of the sequence has been read out. Software trigger modes need
+    // the setup_seq/start_seq/trigger/finish_seq steps instead.
+    pub fn acquire_seq(&mut self, config: &SequenceConfig) -> Result<Sequence> {
+        self.setup_seq(config)?;
+        self.start_seq()?;
+        loop {
+            match self.exp_check_status()?.0 {
+                CaptureStatus::ReadoutComplete => break,
+                CaptureStatus::ReadoutFailed => {
+                    self.release();
+                    return Err(Error {
+                        kind: ErrorKind::Binding,
+                        code: -1,
+                        message: "sequence readout failed".to_owned(),
+                    });
+                }
+                _ => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+
+        self.finish_seq()
+    }
+
+    pub fn exp_check_status(&mut self) -> Result<(CaptureStatus, u32)> {
+        let (status, bytes_read) = self.backend.exp_check_status()?;
+        match status {
+            CaptureStatus::ReadoutComplete
+            | CaptureStatus::ReadoutFailed
+            | CaptureStatus::ReadoutNotActive => self.armed = false,
+            _ => {}
+        }
+
+        Ok((status, bytes_read))
+    }
+
+    pub fn exp_abort(&mut self) -> Result<()> {
+        self.armed = false;
+        self.backend.exp_abort()
+    }
+
+    // Starts a continuous acquisition into a buffer owned by the camera; poll latest_frame for
+    // frames and end it with stop_cont
+    pub fn start_cont(&mut self, config: &ContinuousConfig) -> Result<()> {
+        self.release();
+        let mut continuous = Continuous::setup(&*self.backend, config)?;
+        continuous.start(&*self.backend)?;
+        self.exp_mode = Some(config.exposure_mode());
+        self.armed = true;
+        self.continuous = Some(continuous);
+
+        Ok(())
+    }
+
+    // The newest frame of the continuous acquisition, or None if there is none since the last
+    // call. Frames in between are overwritten, not queued.
+    pub fn latest_frame(&mut self) -> Result<Option<Frame>> {
+        match self.continuous.as_mut() {
+            Some(continuous) => continuous.latest_frame(&*self.backend),
+            None => Err(Error {
+                kind: ErrorKind::NotArmed,
+                code: -1,
+                message: "latest_frame called before start_cont".to_owned(),
+            }),
+        }
+    }
+
+    pub fn stop_cont(&mut self) -> Result<()> {
+        self.armed = false;
+        if self.continuous.is_none() {
+            return Ok(());
+        }
+
+        // the buffer goes only once the camera has stopped writing to it
+        let result = self.backend.exp_stop_cont();
+        self.continuous = None;
+
+        result
+    }
+
+    // Fires one frame of an acquisition started in a software trigger ExposureMode.
+    // `flags` and `value` are passed to pl_exp_trigger as is and the flags it writes back are
+    // returned; a trigger the camera ignores is reported as ErrorKind::TriggerNotAccepted.
+    #[cfg(pvcam_3_9)]
+    pub fn trigger(&mut self, flags: u32, value: u32) -> Result<u32> {
+        match self.exp_mode {
+            Some(mode) if self.armed && mode.is_software_trigger() => {
+                self.backend.exp_trigger(flags, value)
+            }
+            _ => Err(Error {
+                kind: ErrorKind::NotArmed,
+                code: -1,
+                message: format!(
+                    "trigger requires a started acquisition in a software trigger mode, have {:?}",
+                    self.exp_mode
+                ),
+            }),
+        }
+    }
+}
+
+impl Drop for Camera {
+    fn drop(&mut self) {
+        // the backend closes the camera itself
+        self.release();
+    }
+}
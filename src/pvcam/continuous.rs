@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use super::backend::CameraBackend;
+use super::{CaptureStatus, Error, ErrorKind, ExposureMode, Frame, Region, Result};
+
+const DEFAULT_BUFFER_FRAMES: usize = 16;
+
+// Describes a continuous acquisition: frames are read out round a circular buffer until the
+// camera is told to stop, and Camera::latest_frame copies out the newest.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContinuousConfig {
+    regions: Vec<Region>,
+    exp_mode: ExposureMode,
+    exposure: Duration,
+    buffer_frames: usize,
+}
+
+impl ContinuousConfig {
+    pub fn new(regions: Vec<Region>, exposure: Duration) -> Self {
+        ContinuousConfig {
+            regions,
+            exp_mode: ExposureMode::Timed,
+            exposure,
+            buffer_frames: DEFAULT_BUFFER_FRAMES,
+        }
+    }
+
+    pub fn exp_mode(mut self, exp_mode: ExposureMode) -> Self {
+        self.exp_mode = exp_mode;
+        self
+    }
+
+    // how many frames the circular buffer holds
+    pub fn buffer_frames(mut self, buffer_frames: usize) -> Self {
+        self.buffer_frames = buffer_frames;
+        self
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn exposure_mode(&self) -> ExposureMode {
+        self.exp_mode
+    }
+
+    pub fn exposure(&self) -> Duration {
+        self.exposure
+    }
+
+    pub fn buffer_len(&self) -> usize {
+        self.buffer_frames
+    }
+}
+
+// A continuous acquisition set up on a camera along with its circular buffer
+pub(super) struct Continuous {
+    config: ContinuousConfig,
+    // pixels per frame
+    frame_len: usize,
+    buffer: Vec<u16>,
+    // number of the frame latest_frame returned last
+    last: Option<u32>,
+}
+
+impl Continuous {
+    pub(super) fn setup(backend: &dyn CameraBackend, config: &ContinuousConfig) -> Result<Self> {
+        if config.buffer_frames == 0 {
+            return Err(Error {
+                kind: ErrorKind::Binding,
+                code: -1,
+                message: "a continuous acquisition needs room for at least one frame".to_owned(),
+            });
+        }
+
+        let frame_size = backend.exp_setup_cont(
+            &config.regions,
+            config.exp_mode,
+            config.exposure.as_millis() as u32,
+        )?;
+        let frame_len = frame_size as usize / 2;
+
+        Ok(Continuous {
+            config: config.clone(),
+            frame_len,
+            buffer: vec![0; frame_len * config.buffer_frames],
+            last: None,
+        })
+    }
+
+    pub(super) fn start(&mut self, backend: &dyn CameraBackend) -> Result<()> {
+        let size = (self.buffer.len() * 2) as u32;
+        unsafe { backend.exp_start_cont(self.buffer.as_mut_ptr(), size) }
+    }
+
+    // Copies out the newest frame, or None if none arrived since the last call
+    pub(super) fn latest_frame(&mut self, backend: &dyn CameraBackend) -> Result<Option<Frame>> {
+        match backend.exp_check_cont_status()?.0 {
+            // FRAME_AVAILABLE in continuous mode
+            CaptureStatus::ReadoutComplete => {}
+            CaptureStatus::ReadoutFailed => {
+                return Err(Error {
+                    kind: ErrorKind::Binding,
+                    code: -1,
+                    message: "continuous readout failed".to_owned(),
+                })
+            }
+            _ => return Ok(None),
+        }
+
+        let (frame, info) = backend.exp_get_latest_frame()?;
+        let number = match info {
+            Some(info) => info.number,
+            None => self.last.map_or(1, |last| last + 1),
+        };
+        if self.last == Some(number) {
+            return Ok(None);
+        }
+
+        // the frame must lie within our buffer before it is read
+        let start = self.buffer.as_ptr() as usize;
+        let offset = (frame as usize).wrapping_sub(start) / 2;
+        if (frame as usize) < start || offset + self.frame_len > self.buffer.len() {
+            return Err(Error {
+                kind: ErrorKind::Binding,
+                code: -1,
+                message: "latest frame is outside the continuous buffer".to_owned(),
+            });
+        }
+        self.last = Some(number);
+
+        // copied through the pointer as the backend keeps writing the rest of the buffer
+        let mut data = vec![0; self.frame_len];
+        unsafe { std::ptr::copy_nonoverlapping(frame, data.as_mut_ptr(), self.frame_len) };
+
+        Ok(Some(Frame {
+            number,
+            exposure: self.config.exposure,
+            timestamp: info.map(|info| info.timestamp),
+            regions: self.config.regions.clone(),
+            data,
+        }))
+    }
+}
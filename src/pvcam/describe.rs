@@ -0,0 +1,463 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::backend::CameraBackend;
+use super::simulated::SimSpeed;
+use super::state::{is_readable, is_writable};
+use super::{
+    internal, is_available, read_access, read_int, read_param, write_param, Error, ErrorKind,
+    PVEnum, ParamAttrKind, Parameter, ParameterAccess, ParameterValue, Result, SimParam,
+    SimulatedCamera, PARAMETERS,
+};
+
+// ATTR_TYPE codes by the name a description gives them
+const TYPES: &[(&str, u32)] = &[
+    ("int8", internal::TYPE_INT8),
+    ("uns8", internal::TYPE_UNS8),
+    ("int16", internal::TYPE_INT16),
+    ("uns16", internal::TYPE_UNS16),
+    ("int32", internal::TYPE_INT32),
+    ("uns32", internal::TYPE_UNS32),
+    ("int64", internal::TYPE_INT64),
+    ("uns64", internal::TYPE_UNS64),
+    ("flt64", internal::TYPE_FLT64),
+    ("boolean", internal::TYPE_BOOLEAN),
+    ("enum", internal::TYPE_ENUM),
+    ("char_ptr", internal::TYPE_CHAR_PTR),
+];
+
+// One Parameter as a camera has it. An unavailable one has nothing but `available`; the range is
+// only there for numbers and the options only for enums.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParamDescription {
+    pub parameter: Parameter,
+    pub available: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<ParameterAccess>,
+    // the ATTR_TYPE, named as in TYPES, e.g. "uns16"
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    // None when the parameter cannot be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current: Option<ParameterValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<ParameterValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<ParameterValue>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<PVEnum>,
+}
+
+// What one PARAM_SPDTAB_INDEX of a readout port sets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpeedDescription {
+    pub index: i32,
+    pub bit_depth: i32,
+    // PARAM_PIX_TIME in nanoseconds, if the camera has it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pixel_time: Option<i32>,
+    // the PARAM_GAIN_INDEX values it offers
+    #[serde(default)]
+    pub gains: Vec<i32>,
+}
+
+// The speeds of one PARAM_READOUT_PORT option
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortDescription {
+    pub name: String,
+    pub value: i32,
+    pub speeds: Vec<SpeedDescription>,
+}
+
+// The parameter space of a camera model, as Camera::describe reads it and
+// SimulatedCamera::from_description plays it back. Usually kept as JSON:
+//
+//   {
+//     "sensor_size": [2048, 2048],
+//     "params": [
+//       { "parameter": "ReadoutPort", "available": true, "access": "ReadWrite", "type": "enum",
+//         "current": { "Enum": "Sensitivity" },
+//         "options": [{ "value": 0, "name": "Sensitivity" }, { "value": 1, "name": "Speed" }] },
+//       { "parameter": "GainMultFactor", "available": false },
+//       ...
+//     ],
+//     "speed_table": [
+//       { "name": "Sensitivity", "value": 0, "speeds": [
+//         { "index": 0, "bit_depth": 16, "pixel_time": 10, "gains": [1, 2] } ] },
+//       ...
+//     ]
+//   }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    // (serial, parallel); wins over the size parameters when loaded, so a description can be
+    // shrunk to keep simulated frames small
+    pub sensor_size: (u16, u16),
+    // every Parameter, available or not, in PARAMETERS order; one left out counts as unavailable
+    pub params: Vec<ParamDescription>,
+    // empty when the camera has no port and speed to select
+    #[serde(default)]
+    pub speed_table: Vec<PortDescription>,
+}
+
+fn binding_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message,
+    }
+}
+
+impl CameraDescription {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                return Err(binding_error(format!(
+                    "unable to read {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        match serde_json::from_str(text) {
+            Ok(description) => Ok(description),
+            Err(e) => Err(binding_error(format!("invalid camera description: {}", e))),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => Ok(json),
+            Err(e) => Err(binding_error(format!(
+                "unable to write camera description: {}",
+                e
+            ))),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        match fs::write(path, self.to_json()? + "\n") {
+            Ok(()) => Ok(()),
+            Err(e) => Err(binding_error(format!(
+                "unable to write {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    pub fn get(&self, parameter: Parameter) -> Option<&ParamDescription> {
+        self.params.iter().find(|p| p.parameter == parameter)
+    }
+}
+
+fn type_name(parameter: Parameter, code: u32) -> Result<&'static str> {
+    match TYPES.iter().find(|(_, c)| *c == code) {
+        Some((name, _)) => Ok(name),
+        None => Err(binding_error(format!(
+            "{} has the unknown type {:#X}",
+            parameter, code
+        ))),
+    }
+}
+
+fn type_code(parameter: Parameter, name: &str) -> Result<u32> {
+    match TYPES.iter().find(|(n, _)| *n == name) {
+        Some((_, code)) => Ok(*code),
+        None => Err(binding_error(format!(
+            "{} has the unknown type {:?}",
+            parameter, name
+        ))),
+    }
+}
+
+pub(super) fn describe_param(
+    backend: &dyn CameraBackend,
+    parameter: Parameter,
+) -> Result<ParamDescription> {
+    let param_id = parameter as u32;
+    let mut description = ParamDescription {
+        parameter,
+        available: false,
+        access: None,
+        kind: None,
+        current: None,
+        min: None,
+        max: None,
+        options: vec![],
+    };
+    if !is_available(backend, param_id)? {
+        return Ok(description);
+    }
+
+    let access = read_access(backend, param_id)?;
+    let kind = type_name(
+        parameter,
+        read_int(backend, param_id, ParamAttrKind::AttrType)? as u32,
+    )?;
+    description.available = true;
+    description.access = Some(access);
+    description.kind = Some(kind.to_owned());
+    if is_readable(access) {
+        description.current = Some(read_param(backend, param_id, ParamAttrKind::Current)?);
+    }
+    match kind {
+        "enum" => description.options = backend.get_enums(param_id)?,
+        "boolean" | "char_ptr" => {}
+        _ if access != ParameterAccess::CheckOnly => {
+            description.min = Some(read_param(backend, param_id, ParamAttrKind::Min)?);
+            description.max = Some(read_param(backend, param_id, ParamAttrKind::Max)?);
+        }
+        _ => {}
+    }
+
+    Ok(description)
+}
+
+fn optional_int(backend: &dyn CameraBackend, parameter: Parameter) -> Result<Option<i64>> {
+    match is_available(backend, parameter as u32)? {
+        true => Ok(Some(read_int(
+            backend,
+            parameter as u32,
+            ParamAttrKind::Current,
+        )?)),
+        false => Ok(None),
+    }
+}
+
+fn describe_speed(backend: &dyn CameraBackend, index: i64) -> Result<SpeedDescription> {
+    let gain_id = Parameter::GainIndex as u32;
+    let gains = match is_available(backend, gain_id)? {
+        true => (read_int(backend, gain_id, ParamAttrKind::Min)?
+            ..=read_int(backend, gain_id, ParamAttrKind::Max)?)
+            .map(|gain| gain as i32)
+            .collect(),
+        false => vec![],
+    };
+
+    Ok(SpeedDescription {
+        index: index as i32,
+        bit_depth: read_int(backend, Parameter::BitDepth as u32, ParamAttrKind::Current)? as i32,
+        pixel_time: optional_int(backend, Parameter::PixelTime)?.map(|t| t as i32),
+        gains,
+    })
+}
+
+// Selects every port and speed in turn to read what each sets. The port, speed and gain are put
+// back after.
+fn describe_speed_table(backend: &dyn CameraBackend) -> Result<Vec<PortDescription>> {
+    let port_id = Parameter::ReadoutPort as u32;
+    let speed_id = Parameter::SpeedTableIndex as u32;
+    let gain_id = Parameter::GainIndex as u32;
+    for param_id in [port_id, speed_id].iter() {
+        if !is_available(backend, *param_id)? || !is_writable(read_access(backend, *param_id)?) {
+            return Ok(vec![]);
+        }
+    }
+
+    let port = read_param(backend, port_id, ParamAttrKind::Current)?;
+    let speed = read_param(backend, speed_id, ParamAttrKind::Current)?;
+    let gain = match is_available(backend, gain_id)? {
+        true => Some(read_param(backend, gain_id, ParamAttrKind::Current)?),
+        false => None,
+    };
+
+    let walk = || -> Result<Vec<PortDescription>> {
+        let options = backend.get_enums(port_id)?;
+        let mut ports = vec![];
+        for (i, option) in options.iter().enumerate() {
+            write_param(
+                backend,
+                port_id,
+                ParameterValue::Enum(i as u32, options.clone()),
+            )?;
+            let mut speeds = vec![];
+            for index in read_int(backend, speed_id, ParamAttrKind::Min)?
+                ..=read_int(backend, speed_id, ParamAttrKind::Max)?
+            {
+                write_param(backend, speed_id, ParameterValue::Int(index as i32))?;
+                speeds.push(describe_speed(backend, index)?);
+            }
+            ports.push(PortDescription {
+                name: option.name.clone(),
+                value: option.value,
+                speeds,
+            });
+        }
+
+        Ok(ports)
+    };
+    let result = walk();
+
+    write_param(backend, port_id, port)?;
+    write_param(backend, speed_id, speed)?;
+    if let Some(gain) = gain {
+        write_param(backend, gain_id, gain)?;
+    }
+
+    result
+}
+
+pub(super) fn describe(backend: &dyn CameraBackend) -> Result<CameraDescription> {
+    let params = PARAMETERS
+        .iter()
+        .map(|parameter| describe_param(backend, *parameter))
+        .collect::<Result<Vec<_>>>()?;
+    let size = |parameter: Parameter| -> Result<u16> {
+        Ok(read_int(backend, parameter as u32, ParamAttrKind::Current)? as u16)
+    };
+
+    Ok(CameraDescription {
+        sensor_size: (
+            size(Parameter::SensorSerialSize)?,
+            size(Parameter::SensorParallelSize)?,
+        ),
+        params,
+        speed_table: describe_speed_table(backend)?,
+    })
+}
+
+fn int_value(value: &Option<ParameterValue>) -> Option<i64> {
+    match value {
+        Some(ParameterValue::Int(v)) => Some(*v as i64),
+        Some(ParameterValue::Long(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+fn float_value(value: &Option<ParameterValue>) -> Option<f64> {
+    match value {
+        Some(ParameterValue::Float(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+// The described parameter as the simulator has it, None if the camera does not have it
+fn sim_param(param: &ParamDescription) -> Result<Option<SimParam>> {
+    if !param.available {
+        return Ok(None);
+    }
+    let incomplete = |what: &str| {
+        binding_error(format!(
+            "{} is described as available but without {}",
+            param.parameter, what
+        ))
+    };
+    let kind = param.kind.as_deref().ok_or_else(|| incomplete("a type"))?;
+    let access = param.access.ok_or_else(|| incomplete("an access"))?;
+    let code = type_code(param.parameter, kind)?;
+
+    let sim = match kind {
+        "enum" => {
+            if param.options.is_empty() {
+                return Err(incomplete("options"));
+            }
+            let options: Vec<(i32, &str)> = param
+                .options
+                .iter()
+                .map(|e| (e.value, e.name.as_str()))
+                .collect();
+            // the current option is stored by name
+            let selected = match &param.current {
+                Some(ParameterValue::Enum(idx, enums)) => enums.get(*idx as usize),
+                _ => None,
+            };
+            let value = selected
+                .and_then(|selected| param.options.iter().find(|e| e.name == selected.name))
+                .unwrap_or(&param.options[0])
+                .value;
+            SimParam::enumeration(&options, value)
+        }
+        "boolean" => SimParam::boolean(matches!(param.current, Some(ParameterValue::Bool(true)))),
+        "char_ptr" => match &param.current {
+            Some(ParameterValue::String(v)) => SimParam::string(v),
+            _ => SimParam::string(""),
+        },
+        "flt64" => {
+            let min = float_value(&param.min).ok_or_else(|| incomplete("a min"))?;
+            let max = float_value(&param.max).ok_or_else(|| incomplete("a max"))?;
+            SimParam::float(float_value(&param.current).unwrap_or(min), min, max)
+        }
+        _ => {
+            let min = int_value(&param.min).ok_or_else(|| incomplete("a min"))?;
+            let max = int_value(&param.max).ok_or_else(|| incomplete("a max"))?;
+            let current = int_value(&param.current).unwrap_or(min);
+            // the widths get_param reports as a Long
+            match code {
+                internal::TYPE_UNS32 | internal::TYPE_INT64 | internal::TYPE_UNS64 => {
+                    SimParam::long(current, min, max)
+                }
+                _ => SimParam::int(current as i32, min as i32, max as i32),
+            }
+        }
+    };
+
+    Ok(Some(sim.attr_type(code).access(access)))
+}
+
+pub(super) fn simulate(description: &CameraDescription) -> Result<SimulatedCamera> {
+    let mut camera = PARAMETERS
+        .iter()
+        .fold(SimulatedCamera::new(), |camera, parameter| {
+            camera.without_param(*parameter as u32)
+        });
+    for param in description.params.iter() {
+        if let Some(sim) = sim_param(param)? {
+            camera = camera.param(param.parameter as u32, sim);
+        }
+    }
+
+    let (serial, parallel) = description.sensor_size;
+    for (parameter, size) in [
+        (Parameter::SensorSerialSize, serial),
+        (Parameter::SensorParallelSize, parallel),
+    ]
+    .iter()
+    {
+        let code = match description.get(*parameter).and_then(|p| p.kind.as_deref()) {
+            Some(kind) => type_code(*parameter, kind)?,
+            None => internal::TYPE_UNS16,
+        };
+        let size = *size as i32;
+        camera = camera.param(
+            *parameter as u32,
+            SimParam::int(size, size, size).read_only().attr_type(code),
+        );
+    }
+
+    let speed_table: BTreeMap<i32, Vec<SimSpeed>> = description
+        .speed_table
+        .iter()
+        .map(|port| {
+            let speeds = port
+                .speeds
+                .iter()
+                .map(|speed| SimSpeed {
+                    bit_depth: Some(speed.bit_depth as i64),
+                    pixel_time: speed.pixel_time.map(|t| t as i64),
+                    gains: match (speed.gains.iter().min(), speed.gains.iter().max()) {
+                        (Some(min), Some(max)) => Some((*min as i64, *max as i64)),
+                        _ => None,
+                    },
+                })
+                .collect();
+            (port.value, speeds)
+        })
+        .collect();
+
+    Ok(camera.speed_table(speed_table))
+}
@@ -0,0 +1,217 @@
+// Resolves the pl_* functions from libpvcam at runtime rather than linking against it, so the
+// crate builds and runs on machines without PVCam. Each function keeps the signature bindgen
+// would have generated; while the library is missing they fail like the SDK would, returning
+// PV_FAIL, and load_error says why.
+//
+// The functions only newer releases have are optional: they are looked up on their first call,
+// so an older libpvcam loads and only a call to one it lacks fails.
+
+use std::cell::Cell;
+use std::os::raw::{c_char, c_void};
+
+use once_cell::sync::Lazy;
+
+use super::{int16, int32, rgn_type, rs_bool, uns16, uns32, FRAME_INFO};
+
+// PVCAM_LIBRARY names a specific file, otherwise the loader's search path is used
+const LIBRARY_KEY: &str = "PVCAM_LIBRARY";
+const LIBRARY_NAMES: &[&str] = &["libpvcam.so", "libpvcam.so.2"];
+
+thread_local! {
+    // the optional function this thread last called that the library does not have
+    static MISSING: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+macro_rules! pvcam_functions {
+    (
+        required {
+            $($(#[$meta:meta])* $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty = $fail:expr;)*
+        }
+        optional {
+            $($(#[$opt_meta:meta])* $opt_name:ident($($opt_arg:ident: $opt_ty:ty),*) -> $opt_ret:ty = $opt_fail:expr;)*
+        }
+    ) => {
+        struct Functions {
+            $($(#[$meta])* $name: unsafe extern "C" fn($($ty),*) -> $ret,)*
+            $($(#[$opt_meta])* $opt_name: once_cell::sync::OnceCell<Option<unsafe extern "C" fn($($opt_ty),*) -> $opt_ret>>,)*
+            name: String,
+            // keeps the symbols above valid
+            library: libloading::Library,
+        }
+
+        impl Functions {
+            unsafe fn load(
+                name: &str,
+                library: libloading::Library,
+            ) -> Result<Self, libloading::Error> {
+                Ok(Functions {
+                    $($(#[$meta])* $name: *library.get::<unsafe extern "C" fn($($ty),*) -> $ret>(
+                        concat!(stringify!($name), "\0").as_bytes(),
+                    )?,)*
+                    $($(#[$opt_meta])* $opt_name: once_cell::sync::OnceCell::new(),)*
+                    name: name.to_owned(),
+                    library,
+                })
+            }
+        }
+
+        $(
+            $(#[$meta])*
+            // as unsafe as the extern declaration it stands in for
+            #[allow(clippy::missing_safety_doc)]
+            pub unsafe fn $name($($arg: $ty),*) -> $ret {
+                match FUNCTIONS.as_ref() {
+                    Ok(functions) => (functions.$name)($($arg),*),
+                    Err(_) => $fail,
+                }
+            }
+        )*
+
+        $(
+            $(#[$opt_meta])*
+            #[allow(clippy::missing_safety_doc)]
+            pub unsafe fn $opt_name($($opt_arg: $opt_ty),*) -> $opt_ret {
+                let functions = match FUNCTIONS.as_ref() {
+                    Ok(functions) => functions,
+                    Err(_) => return $opt_fail,
+                };
+                let function = functions.$opt_name.get_or_init(|| {
+                    functions
+                        .library
+                        .get::<unsafe extern "C" fn($($opt_ty),*) -> $opt_ret>(
+                            concat!(stringify!($opt_name), "\0").as_bytes(),
+                        )
+                        .ok()
+                        .map(|symbol| *symbol)
+                });
+                match function {
+                    Some(function) => function($($opt_arg),*),
+                    None => {
+                        MISSING.with(|missing| missing.set(Some(stringify!($opt_name))));
+                        $opt_fail
+                    }
+                }
+            }
+        )*
+    };
+}
+
+const FAIL: rs_bool = super::PV_FAIL as rs_bool;
+
+pvcam_functions! {
+    required {
+        pl_pvcam_init() -> rs_bool = FAIL;
+        pl_pvcam_uninit() -> rs_bool = FAIL;
+        pl_pvcam_get_ver(pvcam_version: *mut uns16) -> rs_bool = FAIL;
+        pl_error_code() -> int16 = -1;
+        pl_error_message(err_code: int16, msg: *mut c_char) -> rs_bool = FAIL;
+        pl_cam_get_total(totl_cams: *mut int16) -> rs_bool = FAIL;
+        pl_cam_get_name(cam_num: int16, camera_name: *mut c_char) -> rs_bool = FAIL;
+        pl_cam_open(camera_name: *const c_char, hcam: *mut int16, o_mode: int16) -> rs_bool = FAIL;
+        pl_cam_close(hcam: int16) -> rs_bool = FAIL;
+        pl_cam_register_callback_ex3(
+            hcam: int16,
+            callback_event: int32,
+            callback: *mut c_void,
+            context: *mut c_void
+        ) -> rs_bool = FAIL;
+        pl_cam_deregister_callback(hcam: int16, callback_event: int32) -> rs_bool = FAIL;
+        pl_get_param(
+            hcam: int16,
+            param_id: uns32,
+            param_attribute: int16,
+            param_value: *mut c_void
+        ) -> rs_bool = FAIL;
+        pl_set_param(hcam: int16, param_id: uns32, param_value: *mut c_void) -> rs_bool = FAIL;
+        pl_get_enum_param(
+            hcam: int16,
+            param_id: uns32,
+            index: uns32,
+            value: *mut int32,
+            desc: *mut c_char,
+            length: uns32
+        ) -> rs_bool = FAIL;
+        pl_enum_str_length(hcam: int16, param_id: uns32, index: uns32, length: *mut uns32) -> rs_bool = FAIL;
+        pl_exp_setup_seq(
+            hcam: int16,
+            exp_total: uns16,
+            rgn_total: uns16,
+            rgn_array: *const rgn_type,
+            exp_mode: int16,
+            exposure_time: uns32,
+            exp_bytes: *mut uns32
+        ) -> rs_bool = FAIL;
+        pl_exp_start_seq(hcam: int16, pixel_stream: *mut c_void) -> rs_bool = FAIL;
+        pl_exp_check_status(hcam: int16, status: *mut int16, bytes_arrived: *mut uns32) -> rs_bool = FAIL;
+        pl_exp_abort(hcam: int16, cam_state: int16) -> rs_bool = FAIL;
+        pl_exp_setup_cont(
+            hcam: int16,
+            rgn_total: uns16,
+            rgn_array: *const rgn_type,
+            exp_mode: int16,
+            exposure_time: uns32,
+            exp_bytes: *mut uns32,
+            buffer_mode: int16
+        ) -> rs_bool = FAIL;
+        pl_exp_start_cont(hcam: int16, pixel_stream: *mut c_void, size: uns32) -> rs_bool = FAIL;
+        pl_exp_check_cont_status(
+            hcam: int16,
+            status: *mut int16,
+            bytes_arrived: *mut uns32,
+            buffer_cnt: *mut uns32
+        ) -> rs_bool = FAIL;
+        pl_exp_get_latest_frame_ex(
+            hcam: int16,
+            frame: *mut *mut c_void,
+            frame_info: *mut FRAME_INFO
+        ) -> rs_bool = FAIL;
+        pl_exp_stop_cont(hcam: int16, cam_state: int16) -> rs_bool = FAIL;
+        pl_create_frame_info_struct(new_frame: *mut *mut FRAME_INFO) -> rs_bool = FAIL;
+        pl_release_frame_info_struct(frame_to_delete: *mut FRAME_INFO) -> rs_bool = FAIL;
+    }
+
+    optional {
+        // only in the 3.9 headers and libraries
+        #[cfg(pvcam_3_9)]
+        pl_exp_trigger(hcam: int16, flags: *mut uns32, value: uns32) -> rs_bool = FAIL;
+    }
+}
+
+static FUNCTIONS: Lazy<Result<Functions, String>> = Lazy::new(|| {
+    let names: Vec<String> = match std::env::var(LIBRARY_KEY) {
+        Ok(path) => vec![path],
+        Err(_) => LIBRARY_NAMES.iter().map(|n| n.to_string()).collect(),
+    };
+
+    let mut errors = vec![];
+    for name in names.iter() {
+        match unsafe { libloading::Library::new(name) } {
+            Ok(library) => {
+                return unsafe { Functions::load(name, library) }
+                    .map_err(|e| format!("{} is missing a PVCam function: {}", name, e))
+            }
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+
+    Err(format!(
+        "libpvcam is not available (set {} to its path): {}",
+        LIBRARY_KEY,
+        errors.join("; ")
+    ))
+});
+
+// Why libpvcam could not be loaded, or, once it has, which function the last failed call on
+// this thread could not find in it; None otherwise
+pub fn load_error() -> Option<String> {
+    let functions = match FUNCTIONS.as_ref() {
+        Ok(functions) => functions,
+        Err(e) => return Some(e.clone()),
+    };
+    MISSING.with(|missing| missing.take()).map(|function| {
+        format!(
+            "{} has no {}; it is older than the PVCam headers this was built with",
+            functions.name, function
+        )
+    })
+}
@@ -0,0 +1,134 @@
+// Writers for acquired frames. Each takes the frames as Camera hands them out, along with the
+// Metadata read from the camera, and streams them to disk as they come.
+
+use std::io;
+use std::path::Path;
+
+use super::backend::CameraBackend;
+use super::state::is_readable;
+use super::{
+    is_available, read_access, read_param, Camera, Error, ErrorKind, ParamAttrKind, Parameter,
+    ParameterValue, Result,
+};
+
+pub mod fits;
+pub mod npy;
+pub mod ome_tiff;
+pub mod stream;
+pub mod tiff;
+#[cfg(feature = "zarr")]
+pub mod zarr;
+
+// What the writers record about the camera besides each frame's own exposure, timestamp and
+// regions. Every field is None on cameras without the parameter.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metadata {
+    // PARAM_VENDOR_NAME and PARAM_PRODUCT_NAME
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    // PARAM_CHIP_NAME
+    pub chip: Option<String>,
+    // nanometers, serial and parallel; PARAM_PIX_SER_SIZE and PARAM_PIX_PAR_SIZE
+    pub pixel_size: Option<(u16, u16)>,
+    pub readout_port: Option<String>,
+    // PARAM_SPDTAB_INDEX and PARAM_GAIN_INDEX
+    pub speed: Option<i32>,
+    pub gain: Option<i32>,
+    pub bit_depth: Option<u16>,
+    // degrees Celsius; PARAM_TEMP is in hundredths of one
+    pub temperature: Option<f64>,
+}
+
+fn optional_param(
+    backend: &dyn CameraBackend,
+    parameter: Parameter,
+) -> Result<Option<ParameterValue>> {
+    let param_id = parameter as u32;
+    if !is_available(backend, param_id)? || !is_readable(read_access(backend, param_id)?) {
+        return Ok(None);
+    }
+
+    Ok(Some(read_param(backend, param_id, ParamAttrKind::Current)?))
+}
+
+fn optional_int(backend: &dyn CameraBackend, parameter: Parameter) -> Result<Option<i64>> {
+    Ok(match optional_param(backend, parameter)? {
+        Some(ParameterValue::Int(v)) => Some(v as i64),
+        Some(ParameterValue::Long(v)) => Some(v),
+        _ => None,
+    })
+}
+
+fn optional_string(backend: &dyn CameraBackend, parameter: Parameter) -> Result<Option<String>> {
+    Ok(optional_param(backend, parameter)?.map(|v| v.to_string()))
+}
+
+impl Metadata {
+    // Reads the camera's current settings; read it while they are the ones frames are taken with
+    pub fn read(camera: &Camera) -> Result<Self> {
+        let backend = camera.backend();
+
+        Ok(Metadata {
+            vendor: optional_string(backend, Parameter::VendorName)?,
+            model: optional_string(backend, Parameter::ProductName)?,
+            serial: optional_string(backend, Parameter::CameraSerial)?,
+            chip: optional_string(backend, Parameter::ChipName)?,
+            pixel_size: match (
+                optional_int(backend, Parameter::PixelSerialSize)?,
+                optional_int(backend, Parameter::PixelParallelSize)?,
+            ) {
+                (Some(serial), Some(parallel)) => Some((serial as u16, parallel as u16)),
+                _ => None,
+            },
+            readout_port: optional_string(backend, Parameter::ReadoutPort)?,
+            speed: optional_int(backend, Parameter::SpeedTableIndex)?.map(|v| v as i32),
+            gain: optional_int(backend, Parameter::GainIndex)?.map(|v| v as i32),
+            bit_depth: optional_int(backend, Parameter::BitDepth)?.map(|v| v as u16),
+            temperature: optional_int(backend, Parameter::Temperature)?.map(|v| v as f64 / 100.0),
+        })
+    }
+}
+
+fn io_error(path: Option<&Path>, e: io::Error) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message: match path {
+            Some(path) => format!("unable to write {}: {}", path.display(), e),
+            None => format!("unable to write: {}", e),
+        },
+    }
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+// CRC-32 as zip has it, continuing from the CRC of what came before `bytes`, 0 for nothing
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in bytes.iter() {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
@@ -0,0 +1,462 @@
+// FITS: an empty primary HDU describing the camera, then an IMAGE extension per region of every
+// frame, written as the frame comes:
+//
+//   let mut fits = FitsWriter::create("spectra.fits", Metadata::read(&camera)?)?;
+//   fits.write_sequence(&camera.acquire_seq(&config)?)?;
+//   fits.finish()?;
+//
+// Pixels are BITPIX 16 with BZERO 32768, FITS' way of storing u16. Each extension has the
+// standard keywords, EXPTIME, DATE-OBS, CCD-TEMP, XBINNING, YBINNING, GAIN and INSTRUME, and
+// the frame number, the region's origin on the sensor and the raw timestamp.
+//
+// DATE-OBS is the time of the first frame plus how much later the frame's FRAME_INFO timestamp
+// is. The first frame's time is when the writer was made unless set with start_time, so set it
+// when the writer is made well before or after the acquisition.
+//
+// read() loads such files back, and any other of 16 bit images.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{io_error, Metadata};
+use crate::pvcam::{Error, ErrorKind, Frame, Region, Result, Sequence};
+
+const BLOCK: usize = 2880;
+const CARD: usize = 80;
+
+// A header value, formatted as FITS wants it
+enum Value {
+    Logical(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+struct Card {
+    keyword: &'static str,
+    value: Value,
+    comment: &'static str,
+}
+
+fn card(keyword: &'static str, value: Value, comment: &'static str) -> Card {
+    Card {
+        keyword,
+        value,
+        comment,
+    }
+}
+
+fn fits_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message,
+    }
+}
+
+impl Card {
+    fn format(&self) -> String {
+        let value = match &self.value {
+            Value::Logical(v) => format!("{:>20}", if *v { "T" } else { "F" }),
+            Value::Int(v) => format!("{:>20}", v),
+            // Debug keeps the decimal point and writes exponents, which FITS wants in upper case
+            Value::Float(v) => format!("{:>20}", format!("{:?}", v).to_uppercase()),
+            Value::Text(v) => {
+                // printable ASCII, as much as fits in a card
+                let text: String = v
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii() && !c.is_ascii_control() {
+                            c
+                        } else {
+                            '?'
+                        }
+                    })
+                    .take(68)
+                    .collect();
+                format!("'{:<8}'", text.replace('\'', "''"))
+            }
+        };
+        let mut card = format!("{:<8}= {}", self.keyword, value);
+        if !self.comment.is_empty() {
+            card = format!("{} / {}", card, self.comment);
+        }
+        card.truncate(CARD);
+
+        format!("{:<80}", card)
+    }
+}
+
+// YYYY-MM-DDThh:mm:ss.ssssss in UTC; None before 1970
+fn date(time: SystemTime) -> Option<String> {
+    let since = time.duration_since(UNIX_EPOCH).ok()?;
+    let (days, seconds) = (since.as_secs() / 86400, since.as_secs() % 86400);
+
+    // the proleptic Gregorian date of a day since 1970-01-01
+    let z = days as i64 + 719_468;
+    let (era, day_of_era) = (z / 146_097, z % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        since.subsec_micros()
+    ))
+}
+
+pub struct FitsWriter<W: Write> {
+    out: W,
+    // for error messages
+    path: Option<PathBuf>,
+    metadata: Metadata,
+    start_time: SystemTime,
+    timestamp_resolution: Duration,
+    first_timestamp: Option<i64>,
+}
+
+impl FitsWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, metadata: Metadata) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| io_error(Some(path), e))?;
+        let mut writer = Self::new(BufWriter::with_capacity(1 << 20, file), metadata)?;
+        writer.path = Some(path.to_owned());
+
+        Ok(writer)
+    }
+}
+
+impl<W: Write> FitsWriter<W> {
+    // Writes the primary HDU
+    pub fn new(out: W, metadata: Metadata) -> Result<Self> {
+        let mut writer = FitsWriter {
+            out,
+            path: None,
+            metadata,
+            start_time: SystemTime::now(),
+            timestamp_resolution: Duration::from_micros(1),
+            first_timestamp: None,
+        };
+        let mut cards = vec![
+            card("SIMPLE", Value::Logical(true), "conforms to FITS"),
+            card("BITPIX", Value::Int(16), ""),
+            card("NAXIS", Value::Int(0), "the frames are in extensions"),
+            card("EXTEND", Value::Logical(true), ""),
+        ];
+        cards.extend(writer.camera_cards());
+        writer.write_hdu(&cards, &[])?;
+
+        Ok(writer)
+    }
+
+    // When the first frame was taken, for DATE-OBS
+    pub fn start_time(mut self, time: SystemTime) -> Self {
+        self.start_time = time;
+        self
+    }
+
+    // The unit of FRAME_INFO.TimeStamp; a microsecond by default
+    pub fn timestamp_resolution(mut self, resolution: Duration) -> Self {
+        self.timestamp_resolution = resolution;
+        self
+    }
+
+    fn camera_cards(&self) -> Vec<Card> {
+        let metadata = &self.metadata;
+        let text = |v: &Option<String>| v.clone().map(Value::Text);
+        vec![
+            ("INSTRUME", text(&metadata.model), "camera"),
+            ("ORIGIN", text(&metadata.vendor), ""),
+            ("SERIALNO", text(&metadata.serial), "camera serial number"),
+            ("DETECTOR", text(&metadata.chip), "sensor"),
+            (
+                "READPORT",
+                text(&metadata.readout_port),
+                "PARAM_READOUT_PORT",
+            ),
+            (
+                "RDSPEED",
+                metadata.speed.map(|v| Value::Int(v as i64)),
+                "PARAM_SPDTAB_INDEX",
+            ),
+            (
+                "GAIN",
+                metadata.gain.map(|v| Value::Int(v as i64)),
+                "PARAM_GAIN_INDEX",
+            ),
+            (
+                "CCD-TEMP",
+                metadata.temperature.map(Value::Float),
+                "[C] sensor temperature",
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(keyword, value, comment)| value.map(|v| card(keyword, v, comment)))
+        .collect()
+    }
+
+    // The header and data of an HDU, each padded to whole blocks
+    fn write_hdu(&mut self, cards: &[Card], data: &[u8]) -> Result<()> {
+        let mut header: String = cards.iter().map(Card::format).collect();
+        header.push_str(&format!("{:<80}", "END"));
+        let mut bytes = header.into_bytes();
+        bytes.resize(bytes.len().div_ceil(BLOCK) * BLOCK, b' ');
+        bytes.extend_from_slice(data);
+        bytes.resize(bytes.len().div_ceil(BLOCK) * BLOCK, 0);
+
+        self.out
+            .write_all(&bytes)
+            .map_err(|e| io_error(self.path.as_deref(), e))
+    }
+
+    fn frame_cards(&self, frame: &Frame, index: usize, region: &Region) -> Vec<Card> {
+        let mut cards = vec![
+            card(
+                "XTENSION",
+                Value::Text("IMAGE".to_owned()),
+                "image extension",
+            ),
+            card("BITPIX", Value::Int(16), ""),
+            card("NAXIS", Value::Int(2), ""),
+            card("NAXIS1", Value::Int(region.width() as i64), ""),
+            card("NAXIS2", Value::Int(region.height() as i64), ""),
+            card("PCOUNT", Value::Int(0), ""),
+            card("GCOUNT", Value::Int(1), ""),
+            card("BZERO", Value::Float(32768.0), "unsigned 16 bit pixels"),
+            card("BSCALE", Value::Float(1.0), ""),
+            card("EXTNAME", Value::Text(format!("FRAME{}", frame.number)), ""),
+            card(
+                "FRAMENUM",
+                Value::Int(frame.number as i64),
+                "FRAME_INFO.FrameNr",
+            ),
+        ];
+        if frame.regions.len() > 1 {
+            cards.push(card("EXTVER", Value::Int(index as i64 + 1), "region"));
+        }
+        cards.push(card(
+            "EXPTIME",
+            Value::Float(frame.exposure.as_secs_f64()),
+            "[s] exposure time",
+        ));
+        if let (Some(timestamp), Some(first)) = (frame.timestamp, self.first_timestamp) {
+            let ticks = (timestamp - first).max(0) as u128;
+            let since = Duration::from_nanos((ticks * self.timestamp_resolution.as_nanos()) as u64);
+            if let Some(date) = date(self.start_time + since) {
+                cards.push(card("DATE-OBS", Value::Text(date), "UTC"));
+            }
+            cards.push(card(
+                "TIMESTMP",
+                Value::Int(timestamp),
+                "FRAME_INFO.TimeStamp",
+            ));
+        }
+        cards.extend(vec![
+            card("XBINNING", Value::Int(region.sbin as i64), "serial binning"),
+            card(
+                "YBINNING",
+                Value::Int(region.pbin as i64),
+                "parallel binning",
+            ),
+            card("XORGSUBF", Value::Int(region.s1 as i64), "region origin"),
+            card("YORGSUBF", Value::Int(region.p1 as i64), "region origin"),
+        ]);
+        cards.extend(self.camera_cards());
+
+        cards
+    }
+
+    // Writes every region of the frame as an extension of its own
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        if self.first_timestamp.is_none() {
+            self.first_timestamp = frame.timestamp;
+        }
+
+        let mut start = 0;
+        for (index, region) in frame.regions.iter().enumerate() {
+            let len = region.width() * region.height();
+            let pixels = match frame.data.get(start..start + len) {
+                Some(pixels) => pixels,
+                None => {
+                    return Err(fits_error(format!(
+                        "frame {} has {} pixels, fewer than its regions",
+                        frame.number,
+                        frame.data.len()
+                    )))
+                }
+            };
+            start += len;
+
+            // big endian, less BZERO
+            let mut data = Vec::with_capacity(len * 2);
+            for pixel in pixels.iter() {
+                data.extend_from_slice(&(pixel ^ 0x8000).to_be_bytes());
+            }
+            let cards = self.frame_cards(frame, index, region);
+            self.write_hdu(&cards, &data)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_sequence(&mut self, sequence: &Sequence) -> Result<()> {
+        for frame in sequence.frames.iter() {
+            self.write_frame(frame)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        if let Err(e) = self.out.flush() {
+            return Err(io_error(self.path.as_deref(), e));
+        }
+
+        Ok(self.out)
+    }
+}
+
+// An HDU as read back; the primary one of a FitsWriter's file has no pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hdu {
+    // keyword and value of every card with one, in order; strings without their quotes
+    pub header: Vec<(String, String)>,
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u16>,
+}
+
+impl Hdu {
+    pub fn get(&self, keyword: &str) -> Option<&str> {
+        self.header
+            .iter()
+            .find(|(k, _)| k == keyword)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn number(&self, keyword: &str) -> Result<Option<f64>> {
+        match self.get(keyword) {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(fits_error(format!(
+                    "{} is not a number: {}",
+                    keyword, value
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+// The keyword and value of a card, None for ones without a value
+fn parse_card(card: &str) -> Option<(String, String)> {
+    if card.len() < 10 || &card[8..10] != "= " {
+        return None;
+    }
+    let keyword = card[..8].trim_end().to_owned();
+    let rest = card[10..].trim_start();
+    let value = match rest.strip_prefix('\'') {
+        Some(text) => {
+            // up to the first quote that is not doubled
+            let mut value = String::new();
+            let mut chars = text.chars().peekable();
+            while let Some(c) = chars.next() {
+                if c == '\'' {
+                    if chars.peek() != Some(&'\'') {
+                        break;
+                    }
+                    chars.next();
+                }
+                value.push(c);
+            }
+            value.trim_end().to_owned()
+        }
+        None => rest.split('/').next().unwrap_or("").trim().to_owned(),
+    };
+
+    Some((keyword, value))
+}
+
+// Every HDU of a file of 16 bit images or none
+pub fn read<R: Read>(mut input: R) -> Result<Vec<Hdu>> {
+    let mut bytes = vec![];
+    input
+        .read_to_end(&mut bytes)
+        .map_err(|e| fits_error(format!("unable to read FITS: {}", e)))?;
+
+    let mut hdus = vec![];
+    let mut at = 0;
+    while at < bytes.len() {
+        let mut header = vec![];
+        loop {
+            let card = match bytes.get(at..at + CARD) {
+                Some(card) => String::from_utf8_lossy(card).into_owned(),
+                None => return Err(fits_error("a FITS header has no END".to_owned())),
+            };
+            at += CARD;
+            if card.trim_end() == "END" {
+                break;
+            }
+            header.extend(parse_card(&card));
+        }
+        at = at.div_ceil(BLOCK) * BLOCK;
+
+        let mut hdu = Hdu {
+            header,
+            width: 0,
+            height: 0,
+            data: vec![],
+        };
+        let axes = hdu.number("NAXIS")?.unwrap_or(0.0) as usize;
+        if axes > 0 {
+            if hdu.get("BITPIX") != Some("16") || axes != 2 {
+                return Err(fits_error(format!(
+                    "only 16 bit images can be read, not BITPIX {:?} with {} axes",
+                    hdu.get("BITPIX"),
+                    axes
+                )));
+            }
+            hdu.width = hdu.number("NAXIS1")?.unwrap_or(0.0) as usize;
+            hdu.height = hdu.number("NAXIS2")?.unwrap_or(0.0) as usize;
+            let zero = hdu.number("BZERO")?.unwrap_or(0.0);
+            let scale = hdu.number("BSCALE")?.unwrap_or(1.0);
+
+            let len = hdu.width * hdu.height * 2;
+            let data = match bytes.get(at..at + len) {
+                Some(data) => data,
+                None => return Err(fits_error("a FITS image is cut short".to_owned())),
+            };
+            hdu.data = data
+                .chunks(2)
+                .map(|b| {
+                    let raw = i16::from_be_bytes([b[0], b[1]]) as f64;
+                    (raw * scale + zero).round().clamp(0.0, u16::MAX as f64) as u16
+                })
+                .collect();
+            at += len.div_ceil(BLOCK) * BLOCK;
+        }
+        hdus.push(hdu);
+    }
+
+    Ok(hdus)
+}
+
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Vec<Hdu>> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .map_err(|e| fits_error(format!("unable to read {}: {}", path.display(), e)))?;
+
+    read(BufReader::new(file))
+}
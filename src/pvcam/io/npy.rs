@@ -0,0 +1,373 @@
+// NumPy's .npy and .npz, for handing sequences to Python:
+//
+//   npy::save("frames.npy", &sequence)?;
+//   npy::save_npz("frames.npz", &sequence)?;
+//
+//   frames = numpy.load("frames.npy")            # (frames, height, width), uint16
+//   with numpy.load("frames.npz") as npz:
+//       frames, timestamps = npz["frames"], npz["timestamps"]
+//
+// An .npy holds one region of every frame; write_region picks which when there are several.
+// An .npz has them all, as "frames" or "frames_0", "frames_1", ... with several regions, along
+// with "numbers", the frames' FRAME_INFO.FrameNr as uint32, and "timestamps", their
+// FRAME_INFO.TimeStamp as int64, -1 for frames the callback did not report.
+//
+// Headers are laid out as numpy.save lays them out, and an .npz is an uncompressed zip in the
+// Zip64 format, so its arrays may pass 4 GB.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::{crc32, io_error};
+use crate::pvcam::{Error, ErrorKind, Result, Sequence};
+
+const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+// numpy aligns the data this far into the file
+const ALIGN: usize = 64;
+// numpy leaves room in the header for this many digits of the first dimension
+const GROWTH_DIGITS: usize = 21;
+
+fn npy_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message,
+    }
+}
+
+// The header of an array of `descr`, e.g. "<u2", in C order
+fn header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let first_digits = shape.first().map_or(0, |v| v.to_string().len());
+    let shape = match shape {
+        [length] => format!("({},)", length),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+    dict.push_str(&" ".repeat(GROWTH_DIGITS.saturating_sub(first_digits)));
+    // numpy pads a whole ALIGN when it is aligned already
+    let unpadded = MAGIC.len() + 2 + dict.len() + 1;
+    let padding = ALIGN - unpadded % ALIGN;
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&((dict.len() + padding + 1) as u16).to_le_bytes());
+    bytes.extend(dict.into_bytes());
+    bytes.extend(" ".repeat(padding).into_bytes());
+    bytes.push(b'\n');
+
+    bytes
+}
+
+// The .npy of one region of every frame, as (frames, height, width) "<u2"
+fn write_frames(
+    out: &mut dyn Write,
+    sequence: &Sequence,
+    index: usize,
+    path: Option<&Path>,
+) -> Result<()> {
+    let region = match sequence.regions.get(index) {
+        Some(region) => region,
+        None => {
+            return Err(npy_error(format!(
+                "the sequence has {} regions, not {}",
+                sequence.regions.len(),
+                index + 1
+            )))
+        }
+    };
+    let start: usize = sequence.regions[..index]
+        .iter()
+        .map(|r| r.width() * r.height())
+        .sum();
+    let len = region.width() * region.height();
+
+    let header = header(
+        "<u2",
+        &[sequence.frames.len(), region.height(), region.width()],
+    );
+    out.write_all(&header).map_err(|e| io_error(path, e))?;
+    for frame in sequence.frames.iter() {
+        let pixels = match frame.data.get(start..start + len) {
+            Some(pixels) => pixels,
+            None => {
+                return Err(npy_error(format!(
+                    "frame {} has {} pixels, fewer than its regions",
+                    frame.number,
+                    frame.data.len()
+                )))
+            }
+        };
+        let mut bytes = Vec::with_capacity(len * 2);
+        for pixel in pixels.iter() {
+            bytes.extend_from_slice(&pixel.to_le_bytes());
+        }
+        out.write_all(&bytes).map_err(|e| io_error(path, e))?;
+    }
+
+    Ok(())
+}
+
+// One region of every frame as an .npy
+pub fn write_region<W: Write>(mut out: W, sequence: &Sequence, region: usize) -> Result<()> {
+    write_frames(&mut out, sequence, region, None)?;
+    out.flush().map_err(|e| io_error(None, e))
+}
+
+fn only_region(sequence: &Sequence) -> Result<()> {
+    match sequence.regions.len() {
+        1 => Ok(()),
+        n => Err(npy_error(format!(
+            "an .npy holds one region, the sequence has {}; see write_region",
+            n
+        ))),
+    }
+}
+
+// The frames of a sequence of one region as an .npy
+pub fn write<W: Write>(out: W, sequence: &Sequence) -> Result<()> {
+    only_region(sequence)?;
+    write_region(out, sequence, 0)
+}
+
+// Counts and checksums what goes through it
+struct Checksummed<W> {
+    out: W,
+    crc: u32,
+    len: u64,
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let written = self.out.write(bytes)?;
+        self.crc = crc32(self.crc, &bytes[..written]);
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// An entry of the zip as the central directory has it
+struct Entry {
+    name: String,
+    crc: u32,
+    len: u64,
+    offset: u64,
+}
+
+// An uncompressed Zip64 archive, each entry's size and CRC following its data
+struct Zip<'a, W: Write> {
+    out: Checksummed<W>,
+    entries: Vec<Entry>,
+    // for error messages
+    path: Option<&'a Path>,
+}
+
+// version 4.5, for Zip64; sizes after the data; 1980-01-01 00:00
+const ZIP_VERSION: u16 = 45;
+const ZIP_FLAGS: u16 = 0x0008;
+const ZIP_DATE: u16 = 0x0021;
+
+fn le16(bytes: &mut Vec<u8>, v: u16) {
+    bytes.extend_from_slice(&v.to_le_bytes());
+}
+
+fn le32(bytes: &mut Vec<u8>, v: u32) {
+    bytes.extend_from_slice(&v.to_le_bytes());
+}
+
+fn le64(bytes: &mut Vec<u8>, v: u64) {
+    bytes.extend_from_slice(&v.to_le_bytes());
+}
+
+impl<W: Write> Zip<'_, W> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.out
+            .write_all(bytes)
+            .map_err(|e| io_error(self.path, e))
+    }
+
+    fn add<F>(&mut self, name: &str, write: F) -> Result<()>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<()>,
+    {
+        let offset = self.out.len;
+        let mut local = vec![];
+        le32(&mut local, 0x0403_4b50);
+        le16(&mut local, ZIP_VERSION);
+        le16(&mut local, ZIP_FLAGS);
+        // stored, at midnight
+        le16(&mut local, 0);
+        le16(&mut local, 0);
+        le16(&mut local, ZIP_DATE);
+        // CRC and sizes are in the descriptor after the data
+        le32(&mut local, 0);
+        le32(&mut local, u32::MAX);
+        le32(&mut local, u32::MAX);
+        le16(&mut local, name.len() as u16);
+        le16(&mut local, 20);
+        local.extend_from_slice(name.as_bytes());
+        le16(&mut local, 0x0001);
+        le16(&mut local, 16);
+        le64(&mut local, 0);
+        le64(&mut local, 0);
+        self.write_bytes(&local)?;
+
+        let mut data = Checksummed {
+            out: &mut self.out,
+            crc: 0,
+            len: 0,
+        };
+        write(&mut data)?;
+        let (crc, len) = (data.crc, data.len);
+
+        let mut descriptor = vec![];
+        le32(&mut descriptor, 0x0807_4b50);
+        le32(&mut descriptor, crc);
+        le64(&mut descriptor, len);
+        le64(&mut descriptor, len);
+        self.write_bytes(&descriptor)?;
+
+        self.entries.push(Entry {
+            name: name.to_owned(),
+            crc,
+            len,
+            offset,
+        });
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W> {
+        let start = self.out.len;
+        let mut directory = vec![];
+        for entry in self.entries.iter() {
+            le32(&mut directory, 0x0201_4b50);
+            le16(&mut directory, ZIP_VERSION);
+            le16(&mut directory, ZIP_VERSION);
+            le16(&mut directory, ZIP_FLAGS);
+            le16(&mut directory, 0);
+            le16(&mut directory, 0);
+            le16(&mut directory, ZIP_DATE);
+            le32(&mut directory, entry.crc);
+            // sizes and offset are in the Zip64 field
+            le32(&mut directory, u32::MAX);
+            le32(&mut directory, u32::MAX);
+            le16(&mut directory, entry.name.len() as u16);
+            le16(&mut directory, 28);
+            // no comment, disk 0, no attributes
+            le16(&mut directory, 0);
+            le16(&mut directory, 0);
+            le16(&mut directory, 0);
+            le32(&mut directory, 0);
+            le32(&mut directory, u32::MAX);
+            directory.extend_from_slice(entry.name.as_bytes());
+            le16(&mut directory, 0x0001);
+            le16(&mut directory, 24);
+            le64(&mut directory, entry.len);
+            le64(&mut directory, entry.len);
+            le64(&mut directory, entry.offset);
+        }
+        let end = start + directory.len() as u64;
+        let entries = self.entries.len() as u64;
+
+        // Zip64 end of central directory, its locator, then the classic end pointing at them
+        le32(&mut directory, 0x0606_4b50);
+        le64(&mut directory, 44);
+        le16(&mut directory, ZIP_VERSION);
+        le16(&mut directory, ZIP_VERSION);
+        le32(&mut directory, 0);
+        le32(&mut directory, 0);
+        le64(&mut directory, entries);
+        le64(&mut directory, entries);
+        le64(&mut directory, end - start);
+        le64(&mut directory, start);
+        le32(&mut directory, 0x0706_4b50);
+        le32(&mut directory, 0);
+        le64(&mut directory, end);
+        le32(&mut directory, 1);
+        le32(&mut directory, 0x0605_4b50);
+        le16(&mut directory, 0);
+        le16(&mut directory, 0);
+        le16(&mut directory, entries.min(u16::MAX as u64) as u16);
+        le16(&mut directory, entries.min(u16::MAX as u64) as u16);
+        le32(&mut directory, u32::MAX);
+        le32(&mut directory, u32::MAX);
+        le16(&mut directory, 0);
+        self.write_bytes(&directory)?;
+
+        self.out.flush().map_err(|e| io_error(self.path, e))?;
+        Ok(self.out.out)
+    }
+}
+
+fn zip_npz<W: Write>(out: W, sequence: &Sequence, path: Option<&Path>) -> Result<W> {
+    let mut zip = Zip {
+        out: Checksummed {
+            out,
+            crc: 0,
+            len: 0,
+        },
+        entries: vec![],
+        path,
+    };
+
+    for index in 0..sequence.regions.len() {
+        let name = match sequence.regions.len() {
+            1 => "frames.npy".to_owned(),
+            _ => format!("frames_{}.npy", index),
+        };
+        zip.add(&name, |out| write_frames(out, sequence, index, path))?;
+    }
+
+    let frames = sequence.frames.len();
+    let mut numbers = header("<u4", &[frames]);
+    let mut timestamps = header("<i8", &[frames]);
+    for frame in sequence.frames.iter() {
+        numbers.extend_from_slice(&frame.number.to_le_bytes());
+        timestamps.extend_from_slice(&frame.timestamp.unwrap_or(-1).to_le_bytes());
+    }
+    zip.add("numbers.npy", |out| {
+        out.write_all(&numbers).map_err(|e| io_error(path, e))
+    })?;
+    zip.add("timestamps.npy", |out| {
+        out.write_all(&timestamps).map_err(|e| io_error(path, e))
+    })?;
+
+    zip.finish()
+}
+
+// Every region of every frame, the frame numbers and the timestamps as an .npz
+pub fn write_npz<W: Write>(out: W, sequence: &Sequence) -> Result<W> {
+    zip_npz(out, sequence, None)
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>> {
+    let file = File::create(path).map_err(|e| io_error(Some(path), e))?;
+    Ok(BufWriter::with_capacity(1 << 20, file))
+}
+
+pub fn save<P: AsRef<Path>>(path: P, sequence: &Sequence) -> Result<()> {
+    let path = path.as_ref();
+    only_region(sequence)?;
+    let mut out = create(path)?;
+    write_frames(&mut out, sequence, 0, Some(path))?;
+    out.flush().map_err(|e| io_error(Some(path), e))
+}
+
+pub fn save_npz<P: AsRef<Path>>(path: P, sequence: &Sequence) -> Result<()> {
+    let path = path.as_ref();
+    zip_npz(create(path)?, sequence, Some(path))?;
+    Ok(())
+}
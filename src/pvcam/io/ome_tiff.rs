@@ -0,0 +1,308 @@
+// OME-TIFF, a TiffWriter whose first page describes the acquisition as OME-XML, which is what
+// Fiji and Bio-Formats read:
+//
+//   let mut tiff = OmeTiffWriter::create("stack.ome.tif", Metadata::read(&camera)?)?;
+//   tiff.write_sequence(&camera.acquire_seq(&config)?)?;
+//   tiff.finish()?;
+//
+// Frames are the T dimension. Regions of the same size and binning are channels of one image,
+// C; regions that differ are images of their own, as an image's planes all share one size. The
+// physical pixel size is PARAM_PIX_SER_SIZE and PARAM_PIX_PAR_SIZE times the binning. The
+// camera is the image's detector, the rest of the Metadata is a map annotation on every image.
+//
+// The pages are the same as a TiffWriter's, one per region of every frame, so the C of a plane
+// varies fastest and the XML is in DimensionOrder XYCZT. Every frame has to have the regions of
+// the first.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use super::tiff::TiffWriter;
+use super::Metadata;
+use crate::pvcam::{Error, ErrorKind, Frame, Region, Result, Sequence};
+
+const NAMESPACE: &str = "http://www.openmicroscopy.org/Schemas/OME/2016-06";
+
+// What a plane says about its frame
+struct Plane {
+    timestamp: Option<i64>,
+    exposure: Duration,
+}
+
+pub struct OmeTiffWriter<W: Write + Seek> {
+    tiff: TiffWriter<W>,
+    metadata: Metadata,
+    // of the first frame
+    regions: Vec<Region>,
+    planes: Vec<Plane>,
+    timestamp_resolution: Duration,
+}
+
+fn ome_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message,
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// OME only names the square binnings up to 8x8
+fn binning(region: &Region) -> &'static str {
+    match (region.sbin, region.pbin) {
+        (1, 1) => "1x1",
+        (2, 2) => "2x2",
+        (4, 4) => "4x4",
+        (8, 8) => "8x8",
+        _ => "Other",
+    }
+}
+
+impl OmeTiffWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, metadata: Metadata) -> Result<Self> {
+        let tiff = TiffWriter::create(path, metadata.clone())?;
+
+        Ok(OmeTiffWriter::with_tiff(tiff, metadata))
+    }
+}
+
+impl<W: Write + Seek> OmeTiffWriter<W> {
+    pub fn new(out: W, metadata: Metadata) -> Result<Self> {
+        let tiff = TiffWriter::new(out, metadata.clone())?;
+
+        Ok(OmeTiffWriter::with_tiff(tiff, metadata))
+    }
+
+    fn with_tiff(tiff: TiffWriter<W>, metadata: Metadata) -> Self {
+        OmeTiffWriter {
+            tiff,
+            metadata,
+            regions: vec![],
+            planes: vec![],
+            timestamp_resolution: Duration::from_micros(1),
+        }
+    }
+
+    pub fn big_tiff(mut self, big: bool) -> Self {
+        self.tiff = self.tiff.big_tiff(big);
+        self
+    }
+
+    // The unit of FRAME_INFO.TimeStamp, for the planes' DeltaT; a microsecond by default
+    pub fn timestamp_resolution(mut self, resolution: Duration) -> Self {
+        self.timestamp_resolution = resolution;
+        self
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        if self.planes.is_empty() {
+            self.regions = frame.regions.clone();
+        } else if frame.regions != self.regions {
+            return Err(ome_error(format!(
+                "frame {} has regions other than the first frame's",
+                frame.number
+            )));
+        }
+
+        self.tiff.write_frame(frame)?;
+        self.planes.push(Plane {
+            timestamp: frame.timestamp,
+            exposure: frame.exposure,
+        });
+
+        Ok(())
+    }
+
+    pub fn write_sequence(&mut self, sequence: &Sequence) -> Result<()> {
+        for frame in sequence.frames.iter() {
+            self.write_frame(frame)?;
+        }
+
+        Ok(())
+    }
+
+    // The regions of each image: all of them if they are alike, otherwise one each
+    fn images(&self) -> Vec<Vec<usize>> {
+        let first = &self.regions[0];
+        let alike = self.regions.iter().all(|r| {
+            r.width() == first.width()
+                && r.height() == first.height()
+                && (r.sbin, r.pbin) == (first.sbin, first.pbin)
+        });
+        match alike {
+            true => vec![(0..self.regions.len()).collect()],
+            false => (0..self.regions.len()).map(|r| vec![r]).collect(),
+        }
+    }
+
+    // The metadata the OME model has no place for
+    fn annotations(&self) -> Vec<(&'static str, String)> {
+        let metadata = &self.metadata;
+        vec![
+            ("chip", metadata.chip.clone()),
+            ("readout_port", metadata.readout_port.clone()),
+            ("speed", metadata.speed.map(|v| v.to_string())),
+            ("gain", metadata.gain.map(|v| v.to_string())),
+            ("temperature", metadata.temperature.map(|v| v.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|v| (key, v)))
+        .collect()
+    }
+
+    // The OME-XML finish writes for the frames so far. It is ASCII, as TIFF wants it; the
+    // physical sizes are in OME's default unit, micrometers.
+    pub fn xml(&self) -> String {
+        let metadata = &self.metadata;
+        let annotations = self.annotations();
+        let first_timestamp = self.planes.iter().find_map(|p| p.timestamp);
+        let attribute = |name: &str, value: &Option<String>| match value {
+            Some(value) => format!(" {}=\"{}\"", name, escape(value)),
+            None => String::new(),
+        };
+
+        // writing to a String does not fail
+        let mut xml = String::new();
+        let _ = write!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <OME xmlns=\"{ns}\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+             xsi:schemaLocation=\"{ns} {ns}/ome.xsd\" Creator=\"libpvcam-sys {version}\">\n",
+            ns = NAMESPACE,
+            version = env!("CARGO_PKG_VERSION")
+        );
+        let _ = writeln!(
+            xml,
+            "  <Instrument ID=\"Instrument:0\">\n    <Detector ID=\"Detector:0\"{}{}{}/>\n  </Instrument>",
+            attribute("Manufacturer", &metadata.vendor),
+            attribute("Model", &metadata.model),
+            attribute("SerialNumber", &metadata.serial)
+        );
+
+        let images = self.images();
+        for (image, regions) in images.iter().enumerate() {
+            let region = &self.regions[regions[0]];
+            let name = match images.len() {
+                1 => String::new(),
+                _ => format!(" Name=\"region {}\"", regions[0]),
+            };
+            let _ = writeln!(
+                xml,
+                "  <Image ID=\"Image:{}\"{}>\n    <InstrumentRef ID=\"Instrument:0\"/>",
+                image, name
+            );
+
+            let _ = write!(
+                xml,
+                "    <Pixels ID=\"Pixels:{}\" DimensionOrder=\"XYCZT\" Type=\"uint16\" \
+                 SizeX=\"{}\" SizeY=\"{}\" SizeZ=\"1\" SizeC=\"{}\" SizeT=\"{}\"",
+                image,
+                region.width(),
+                region.height(),
+                regions.len(),
+                self.planes.len()
+            );
+            if let Some(bit_depth) = metadata.bit_depth.filter(|b| *b > 0) {
+                let _ = write!(xml, " SignificantBits=\"{}\"", bit_depth);
+            }
+            if let Some((serial, parallel)) = metadata.pixel_size.filter(|(s, p)| *s > 0 && *p > 0)
+            {
+                let _ = write!(
+                    xml,
+                    " PhysicalSizeX=\"{}\" PhysicalSizeY=\"{}\"",
+                    serial as f64 * region.sbin as f64 / 1000.0,
+                    parallel as f64 * region.pbin as f64 / 1000.0
+                );
+            }
+            xml.push_str(">\n");
+
+            for (channel, index) in regions.iter().enumerate() {
+                let region = &self.regions[*index];
+                let _ = writeln!(
+                    xml,
+                    "      <Channel ID=\"Channel:{}:{}\" Name=\"region {}: {},{},{},{}\" \
+                     SamplesPerPixel=\"1\">\n        \
+                     <DetectorSettings ID=\"Detector:0\" Binning=\"{}\"/>\n      </Channel>",
+                    image,
+                    channel,
+                    index,
+                    region.s1,
+                    region.s2,
+                    region.p1,
+                    region.p2,
+                    binning(region)
+                );
+            }
+            for t in 0..self.planes.len() {
+                for (c, index) in regions.iter().enumerate() {
+                    let _ = writeln!(
+                        xml,
+                        "      <TiffData IFD=\"{}\" FirstC=\"{}\" FirstT=\"{}\" FirstZ=\"0\" \
+                         PlaneCount=\"1\"/>",
+                        t * self.regions.len() + index,
+                        c,
+                        t
+                    );
+                }
+            }
+            for (t, plane) in self.planes.iter().enumerate() {
+                let delta = match (plane.timestamp, first_timestamp) {
+                    (Some(timestamp), Some(first)) => format!(
+                        " DeltaT=\"{}\" DeltaTUnit=\"s\"",
+                        (timestamp - first) as f64 * self.timestamp_resolution.as_secs_f64()
+                    ),
+                    _ => String::new(),
+                };
+                for c in 0..regions.len() {
+                    let _ = writeln!(
+                        xml,
+                        "      <Plane TheZ=\"0\" TheT=\"{}\" TheC=\"{}\"{} \
+                         ExposureTime=\"{}\" ExposureTimeUnit=\"ms\"/>",
+                        t,
+                        c,
+                        delta,
+                        plane.exposure.as_secs_f64() * 1000.0
+                    );
+                }
+            }
+            xml.push_str("    </Pixels>\n");
+            if !annotations.is_empty() {
+                xml.push_str("    <AnnotationRef ID=\"Annotation:0\"/>\n");
+            }
+            xml.push_str("  </Image>\n");
+        }
+
+        if !annotations.is_empty() {
+            xml.push_str(
+                "  <StructuredAnnotations>\n    <MapAnnotation ID=\"Annotation:0\" \
+                 Namespace=\"libpvcam-sys\">\n      <Value>\n",
+            );
+            for (key, value) in annotations.iter() {
+                let _ = writeln!(xml, "        <M K=\"{}\">{}</M>", key, escape(value));
+            }
+            xml.push_str("      </Value>\n    </MapAnnotation>\n  </StructuredAnnotations>\n");
+        }
+        xml.push_str("</OME>\n");
+
+        xml
+    }
+
+    pub fn finish(self) -> Result<W> {
+        if self.planes.is_empty() {
+            return Err(ome_error("an OME-TIFF needs at least one frame".to_owned()));
+        }
+
+        let xml = self.xml();
+        self.tiff.finish_with_description(xml)
+    }
+}
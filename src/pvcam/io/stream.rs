@@ -0,0 +1,816 @@
+// Raw recordings at the camera's full rate: frames are handed to a writer thread, which packs
+// them into large aligned writes to a preallocated file, with an index of where each landed
+// written beside it on finish:
+//
+//   let mut recorder = StreamRecorder::create("run.raw", &StreamConfig::new().direct(true))?;
+//   camera.start_cont(&config)?;
+//   while running() {
+//       if let Some(frame) = camera.latest_frame()? {
+//           if recorder.record(frame)?.dropped > 0 {
+//               eprintln!("the disk is falling behind");
+//           }
+//       }
+//   }
+//   camera.stop_cont()?;
+//   recorder.finish()?;
+//
+//   let frames = StreamReader::open("run.raw")?.read_sequence()?;
+//
+// record never waits for the disk unless the config says to: frames queue up to the config's
+// queue_frames, and once that many wait, the ones after are dropped and counted in the Backlog
+// record returns.
+//
+// The recording describes itself, so what reached the disk before a crash or power loss can be
+// read without the index. It starts with the magic "PVCAMREC", a version and the regions. Each
+// frame follows as a 48 byte header and then the pixels of every region, little endian u16. The
+// header holds the magic "PVFR", the pixels' length in bytes, the frame's sequence number in the
+// recording, its FRAME_INFO.FrameNr, timestamp and exposure, and a CRC-32 of the header and
+// pixels. The index, <path>.idx, is the magic "PVCAMIDX", a version, the regions and an entry
+// per frame with its sequence number, FrameNr, timestamp, exposure and offset in the recording.
+// It is written on finish; recover rebuilds it from the recording alone:
+//
+//   let recovery = stream::recover("run.raw")?;
+//   eprintln!("{} frames survived, {:?} did not", recovery.entries.len(), recovery.missing);
+//   let frames = StreamReader::open("run.raw")?.read_sequence()?;
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{crc32, io_error};
+use crate::pvcam::{Error, ErrorKind, Frame, Region, Result, Sequence};
+
+const MAGIC: &[u8] = b"PVCAMREC";
+const VERSION: u32 = 1;
+const FRAME_MAGIC: &[u8] = b"PVFR";
+const FRAME_HEADER_LEN: usize = 48;
+const INDEX_MAGIC: &[u8] = b"PVCAMIDX";
+const INDEX_VERSION: u32 = 2;
+const INDEX_ENTRY_LEN: usize = 40;
+// of the writes and their buffer, enough for O_DIRECT on disks of 512 and 4096 byte sectors
+const ALIGN: usize = 4096;
+const DEFAULT_WRITE_SIZE: usize = 8 << 20;
+const DEFAULT_QUEUE_FRAMES: usize = 64;
+// an entry's timestamp for a frame without one
+const NO_TIMESTAMP: i64 = i64::MIN;
+
+// How a StreamRecorder writes
+#[derive(Debug, Clone)]
+pub struct StreamConfig {
+    preallocate: u64,
+    direct: bool,
+    write_size: usize,
+    queue_frames: usize,
+    block: bool,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig::new()
+    }
+}
+
+impl StreamConfig {
+    pub fn new() -> Self {
+        StreamConfig {
+            preallocate: 0,
+            direct: false,
+            write_size: DEFAULT_WRITE_SIZE,
+            queue_frames: DEFAULT_QUEUE_FRAMES,
+            block: false,
+        }
+    }
+
+    // Bytes to allocate on disk up front, for the expected length of the recording; what is not
+    // used is cut off again on finish
+    pub fn preallocate(mut self, bytes: u64) -> Self {
+        self.preallocate = bytes;
+        self
+    }
+
+    // Writes past the page cache with O_DIRECT, on Linux only. Not every file system takes it.
+    pub fn direct(mut self, direct: bool) -> Self {
+        self.direct = direct;
+        self
+    }
+
+    // Bytes per write, rounded up to a multiple of 4096; 8 MB by default
+    pub fn write_size(mut self, bytes: usize) -> Self {
+        self.write_size = bytes.max(1).div_ceil(ALIGN) * ALIGN;
+        self
+    }
+
+    // Frames that may wait for the writer thread; 64 by default
+    pub fn queue_frames(mut self, frames: usize) -> Self {
+        self.queue_frames = frames;
+        self
+    }
+
+    // Makes record wait for room in the queue rather than drop the frame
+    pub fn block(mut self, block: bool) -> Self {
+        self.block = block;
+        self
+    }
+}
+
+// How far the writer thread is behind, as of a frame handed to record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backlog {
+    // frames waiting for the writer thread
+    pub queued: usize,
+    pub capacity: usize,
+    // frames dropped so far as the queue was full
+    pub dropped: u64,
+}
+
+// What finish reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recorded {
+    pub frames: u64,
+    pub dropped: u64,
+    pub bytes: u64,
+}
+
+// A frame of the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    // 0 based, counting the frames the writer thread got, not the ones dropped
+    pub sequence: u64,
+    pub number: u32,
+    pub timestamp: Option<i64>,
+    pub exposure: Duration,
+    // bytes into the recording of the frame's header
+    pub offset: u64,
+}
+
+// What recover found in a recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    // the frames whose header and pixels are intact
+    pub entries: Vec<IndexEntry>,
+    // sequence numbers up to the last intact frame's that are not among them
+    pub missing: Vec<u64>,
+    // offsets of the frames that failed their checksum
+    pub damaged: Vec<u64>,
+    // bytes past the last frame, a frame cut short or what was preallocated and never written
+    pub trailing: u64,
+}
+
+// What the writer thread hands back
+struct Written {
+    entries: Vec<IndexEntry>,
+    bytes: u64,
+}
+
+pub struct StreamRecorder {
+    path: PathBuf,
+    sender: Option<SyncSender<Frame>>,
+    writer: Option<JoinHandle<Result<Written>>>,
+    queued: Arc<AtomicUsize>,
+    capacity: usize,
+    block: bool,
+    dropped: u64,
+    // of the first frame
+    regions: Option<Vec<Region>>,
+}
+
+fn stream_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message,
+    }
+}
+
+fn read_error(path: &Path, e: std::io::Error) -> Error {
+    stream_error(format!("unable to read {}: {}", path.display(), e))
+}
+
+// The index beside a recording
+pub fn index_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut index = path.as_ref().as_os_str().to_owned();
+    index.push(".idx");
+    PathBuf::from(index)
+}
+
+#[cfg(target_os = "linux")]
+fn open(path: &Path, config: &StreamConfig) -> Result<File> {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    if config.direct {
+        options.custom_flags(libc::O_DIRECT);
+    }
+    let file = options.open(path).map_err(|e| io_error(Some(path), e))?;
+    if config.preallocate > 0 {
+        // unlike set_len, takes the blocks now rather than on the first write to them
+        let len = config.preallocate as libc::off_t;
+        match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len) } {
+            0 => {}
+            errno => {
+                let e = std::io::Error::from_raw_os_error(errno);
+                return Err(io_error(Some(path), e));
+            }
+        }
+    }
+
+    Ok(file)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open(path: &Path, config: &StreamConfig) -> Result<File> {
+    if config.direct {
+        return Err(stream_error(
+            "O_DIRECT is only supported on Linux".to_owned(),
+        ));
+    }
+    let file = File::create(path).map_err(|e| io_error(Some(path), e))?;
+    file.set_len(config.preallocate)
+        .map_err(|e| io_error(Some(path), e))?;
+
+    Ok(file)
+}
+
+// write_size bytes aligned to ALIGN, as O_DIRECT wants them
+struct AlignedBuffer {
+    storage: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuffer {
+    fn new(size: usize) -> Self {
+        let storage = vec![0; size + ALIGN];
+        let start = storage.as_ptr().align_offset(ALIGN);
+        AlignedBuffer {
+            storage,
+            start,
+            len: 0,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.storage.len() - ALIGN
+    }
+
+    fn bytes(&mut self) -> &mut [u8] {
+        let (start, capacity) = (self.start, self.capacity());
+        &mut self.storage[start..start + capacity]
+    }
+
+    // Copies bytes in, writing the buffer each time it fills
+    fn append(&mut self, mut bytes: &[u8], file: &mut File, path: &Path) -> Result<()> {
+        while !bytes.is_empty() {
+            let (at, room) = (self.len, self.capacity() - self.len);
+            let (now, later) = bytes.split_at(room.min(bytes.len()));
+            self.bytes()[at..at + now.len()].copy_from_slice(now);
+            self.len += now.len();
+            if self.len == self.capacity() {
+                self.write_to(file, self.len, path)?;
+            }
+            bytes = later;
+        }
+        Ok(())
+    }
+
+    // Writes the first len bytes and empties the buffer
+    fn write_to(&mut self, file: &mut File, len: usize, path: &Path) -> Result<()> {
+        file.write_all(&self.bytes()[..len])
+            .map_err(|e| io_error(Some(path), e))?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn le_u16(bytes: &mut Vec<u8>, v: u16) {
+    bytes.extend_from_slice(&v.to_le_bytes());
+}
+
+// The recording's header: magic, version and regions
+fn recording_header(regions: &[Region]) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&(regions.len() as u32).to_le_bytes());
+    for region in regions.iter() {
+        for v in [
+            region.s1,
+            region.s2,
+            region.sbin,
+            region.p1,
+            region.p2,
+            region.pbin,
+        ] {
+            le_u16(&mut header, v);
+        }
+    }
+    header
+}
+
+// A frame's header and pixels as they are recorded
+fn frame_record(sequence: u64, frame: &Frame) -> Vec<u8> {
+    let len = frame.data.len() * 2;
+    let mut record = Vec::with_capacity(FRAME_HEADER_LEN + len);
+    record.extend_from_slice(FRAME_MAGIC);
+    record.extend_from_slice(&(len as u32).to_le_bytes());
+    record.extend_from_slice(&sequence.to_le_bytes());
+    record.extend_from_slice(&frame.number.to_le_bytes());
+    record.extend_from_slice(&[0; 4]);
+    let timestamp = frame.timestamp.unwrap_or(NO_TIMESTAMP);
+    record.extend_from_slice(&timestamp.to_le_bytes());
+    record.extend_from_slice(&(frame.exposure.as_nanos() as u64).to_le_bytes());
+    // the CRC and 4 bytes to spare
+    record.extend_from_slice(&[0; 8]);
+    for pixel in frame.data.iter() {
+        le_u16(&mut record, *pixel);
+    }
+
+    let crc = crc32(crc32(0, &record[..40]), &record[FRAME_HEADER_LEN..]);
+    record[40..44].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+// The entry of a frame's header and pixels, None unless they are intact
+fn check_record(offset: u64, header: &[u8], pixels: &[u8]) -> Option<IndexEntry> {
+    let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+    if &header[..4] != FRAME_MAGIC || u32_at(4) as usize != pixels.len() {
+        return None;
+    }
+    if crc32(crc32(0, &header[..40]), pixels) != u32_at(40) {
+        return None;
+    }
+
+    Some(IndexEntry {
+        sequence: u64_at(8),
+        number: u32_at(16),
+        timestamp: match u64_at(24) as i64 {
+            NO_TIMESTAMP => None,
+            timestamp => Some(timestamp),
+        },
+        exposure: Duration::from_nanos(u64_at(32)),
+        offset,
+    })
+}
+
+// The writer thread: packs the recording's header, then each frame's, into the buffer and
+// writes it whenever it fills, then the rest padded to ALIGN, cutting the file back to the
+// frames' end
+fn run(
+    mut file: File,
+    path: PathBuf,
+    write_size: usize,
+    frames: Receiver<Frame>,
+    queued: Arc<AtomicUsize>,
+) -> Result<Written> {
+    let mut buffer = AlignedBuffer::new(write_size);
+    let mut entries = vec![];
+    let mut end = 0u64;
+
+    for frame in frames {
+        queued.fetch_sub(1, Ordering::SeqCst);
+        if end == 0 {
+            let header = recording_header(&frame.regions);
+            buffer.append(&header, &mut file, &path)?;
+            end = header.len() as u64;
+        }
+
+        let sequence = entries.len() as u64;
+        entries.push(IndexEntry {
+            sequence,
+            number: frame.number,
+            timestamp: frame.timestamp,
+            exposure: frame.exposure,
+            offset: end,
+        });
+        let record = frame_record(sequence, &frame);
+        buffer.append(&record, &mut file, &path)?;
+        end += record.len() as u64;
+    }
+
+    let len = buffer.len;
+    if len > 0 {
+        let padded = len.div_ceil(ALIGN) * ALIGN;
+        buffer.bytes()[len..padded].fill(0);
+        buffer.write_to(&mut file, padded, &path)?;
+    }
+    file.set_len(end).map_err(|e| io_error(Some(&path), e))?;
+    file.sync_all().map_err(|e| io_error(Some(&path), e))?;
+
+    Ok(Written {
+        entries,
+        bytes: end,
+    })
+}
+
+impl StreamRecorder {
+    // Creates the recording and starts its writer thread
+    pub fn create<P: AsRef<Path>>(path: P, config: &StreamConfig) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let file = open(&path, config)?;
+        let (sender, receiver) = mpsc::sync_channel(config.queue_frames);
+        let queued = Arc::new(AtomicUsize::new(0));
+
+        let writer = {
+            let (path, queued) = (path.clone(), queued.clone());
+            let write_size = config.write_size;
+            thread::Builder::new()
+                .name("pvcam-recorder".to_owned())
+                .spawn(move || run(file, path, write_size, receiver, queued))
+                .map_err(|e| stream_error(format!("unable to start the writer thread: {}", e)))?
+        };
+
+        Ok(StreamRecorder {
+            path,
+            sender: Some(sender),
+            writer: Some(writer),
+            queued,
+            capacity: config.queue_frames,
+            block: config.block,
+            dropped: 0,
+            regions: None,
+        })
+    }
+
+    pub fn backlog(&self) -> Backlog {
+        Backlog {
+            queued: self.queued.load(Ordering::SeqCst),
+            capacity: self.capacity,
+            dropped: self.dropped,
+        }
+    }
+
+    // The writer thread's error once it has stopped
+    fn writer_error(&mut self) -> Error {
+        self.sender = None;
+        match self.writer.take().map(|writer| writer.join()) {
+            Some(Ok(Err(e))) => e,
+            Some(Err(_)) => stream_error("the writer thread panicked".to_owned()),
+            _ => stream_error("the writer thread has stopped".to_owned()),
+        }
+    }
+
+    // Queues a frame for the writer thread, or drops it if the queue is full and the config
+    // does not block. Every frame has to have the regions of the first.
+    pub fn record(&mut self, frame: Frame) -> Result<Backlog> {
+        match &self.regions {
+            None => self.regions = Some(frame.regions.clone()),
+            Some(regions) if *regions != frame.regions => {
+                return Err(stream_error(format!(
+                    "frame {} has regions other than the first frame's",
+                    frame.number
+                )))
+            }
+            Some(_) => {}
+        }
+        let len: usize = frame.regions.iter().map(|r| r.width() * r.height()).sum();
+        if frame.data.len() != len {
+            return Err(stream_error(format!(
+                "frame {} has {} pixels, its regions {}",
+                frame.number,
+                frame.data.len(),
+                len
+            )));
+        }
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return Err(self.writer_error()),
+        };
+
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        let sent = match self.block {
+            true => sender.send(frame).map_err(|_| ()),
+            false => match sender.try_send(frame) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(_)) => {
+                    self.queued.fetch_sub(1, Ordering::SeqCst);
+                    self.dropped += 1;
+                    Ok(())
+                }
+                Err(TrySendError::Disconnected(_)) => Err(()),
+            },
+        };
+        if sent.is_err() {
+            return Err(self.writer_error());
+        }
+
+        Ok(self.backlog())
+    }
+
+    // Waits for the writer thread to write every queued frame, then writes the index
+    pub fn finish(mut self) -> Result<Recorded> {
+        self.sender = None;
+        let written = match self.writer.take().map(|writer| writer.join()) {
+            Some(Ok(written)) => written?,
+            _ => return Err(stream_error("the writer thread panicked".to_owned())),
+        };
+
+        let regions = self.regions.take().unwrap_or_default();
+        write_index(&index_path(&self.path), &regions, &written.entries)?;
+
+        Ok(Recorded {
+            frames: written.entries.len() as u64,
+            dropped: self.dropped,
+            bytes: written.bytes,
+        })
+    }
+}
+
+impl Drop for StreamRecorder {
+    // lets the writer thread write what is queued; without finish there is no index
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn write_index(path: &Path, regions: &[Region], entries: &[IndexEntry]) -> Result<()> {
+    let file = File::create(path).map_err(|e| io_error(Some(path), e))?;
+    let mut out = BufWriter::new(file);
+
+    // the recording's header but for the magic and version
+    let mut header = INDEX_MAGIC.to_vec();
+    header.extend_from_slice(&INDEX_VERSION.to_le_bytes());
+    header.extend_from_slice(&recording_header(regions)[12..]);
+    header.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    out.write_all(&header)
+        .map_err(|e| io_error(Some(path), e))?;
+
+    for entry in entries.iter() {
+        let mut bytes = [0; INDEX_ENTRY_LEN];
+        bytes[..8].copy_from_slice(&entry.sequence.to_le_bytes());
+        bytes[8..12].copy_from_slice(&entry.number.to_le_bytes());
+        let timestamp = entry.timestamp.unwrap_or(NO_TIMESTAMP);
+        bytes[16..24].copy_from_slice(&timestamp.to_le_bytes());
+        bytes[24..32].copy_from_slice(&(entry.exposure.as_nanos() as u64).to_le_bytes());
+        bytes[32..].copy_from_slice(&entry.offset.to_le_bytes());
+        out.write_all(&bytes).map_err(|e| io_error(Some(path), e))?;
+    }
+
+    out.flush().map_err(|e| io_error(Some(path), e))
+}
+
+// The regions of a recording's or index's header, from its region count on, and where they end
+fn read_regions(bytes: &[u8]) -> Option<(Vec<Region>, usize)> {
+    let count = u32::from_le_bytes(bytes.get(12..16)?.try_into().ok()?) as usize;
+    let mut regions = vec![];
+    let mut at = 16;
+    for _ in 0..count {
+        let v: Vec<u16> = bytes
+            .get(at..at + 12)?
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        // a zero binning or a range ending before it starts is no region a camera acquired
+        if v[2] == 0 || v[5] == 0 || v[1] < v[0] || v[4] < v[3] {
+            return None;
+        }
+        regions.push(Region {
+            s1: v[0],
+            s2: v[1],
+            sbin: v[2],
+            p1: v[3],
+            p2: v[4],
+            pbin: v[5],
+        });
+        at += 12;
+    }
+
+    Some((regions, at))
+}
+
+// Checks the magic and version of a recording's or index's header
+fn check_header(bytes: &[u8], magic: &[u8], version: u32, path: &Path) -> Result<()> {
+    if !bytes.starts_with(magic) {
+        return Err(stream_error(format!(
+            "{} is not a recording or its index",
+            path.display()
+        )));
+    }
+    match bytes.get(8..12) {
+        Some(v) if u32::from_le_bytes(v.try_into().unwrap()) == version => Ok(()),
+        _ => Err(stream_error(format!(
+            "{} is of another version",
+            path.display()
+        ))),
+    }
+}
+
+fn read_index(path: &Path) -> Result<(Vec<Region>, Vec<IndexEntry>)> {
+    let mut bytes = vec![];
+    File::open(path)
+        .and_then(|file| BufReader::new(file).read_to_end(&mut bytes))
+        .map_err(|e| read_error(path, e))?;
+    check_header(&bytes, INDEX_MAGIC, INDEX_VERSION, path)?;
+    let invalid = || stream_error(format!("{} is cut short", path.display()));
+
+    let (regions, at) = read_regions(&bytes).ok_or_else(invalid)?;
+    let count = match bytes.get(at..at + 8) {
+        Some(count) => u64::from_le_bytes(count.try_into().unwrap()) as usize,
+        None => return Err(invalid()),
+    };
+    let at = at + 8;
+    if bytes.len() != at + count * INDEX_ENTRY_LEN {
+        return Err(invalid());
+    }
+    let entries = bytes[at..]
+        .chunks_exact(INDEX_ENTRY_LEN)
+        .map(|entry| {
+            let u64_at = |at: usize| u64::from_le_bytes(entry[at..at + 8].try_into().unwrap());
+            IndexEntry {
+                sequence: u64_at(0),
+                number: u32::from_le_bytes(entry[8..12].try_into().unwrap()),
+                timestamp: match u64_at(16) as i64 {
+                    NO_TIMESTAMP => None,
+                    timestamp => Some(timestamp),
+                },
+                exposure: Duration::from_nanos(u64_at(24)),
+                offset: u64_at(32),
+            }
+        })
+        .collect();
+
+    Ok((regions, entries))
+}
+
+fn frame_len(regions: &[Region]) -> usize {
+    regions
+        .iter()
+        .map(|r| r.width() * r.height())
+        .sum::<usize>()
+        * 2
+}
+
+// Reads as much of bytes as the input has left
+fn read_up_to(input: &mut impl Read, bytes: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < bytes.len() {
+        match input.read(&mut bytes[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+// Rebuilds the index of a recording whose recorder never finished, from the frames' own
+// headers, checking every frame against its CRC. A header of zeros is taken for where the
+// recorder stopped; frames that fail their check are skipped and reported.
+pub fn recover<P: AsRef<Path>>(path: P) -> Result<Recovery> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| read_error(path, e))?;
+    let file_len = file.metadata().map_err(|e| read_error(path, e))?.len();
+    let mut input = BufReader::with_capacity(1 << 20, file);
+
+    // the magic, version and region count, then the regions
+    let mut header = vec![0; 16];
+    let len = read_up_to(&mut input, &mut header).map_err(|e| read_error(path, e))?;
+    check_header(&header[..len], MAGIC, VERSION, path)?;
+    let count = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+    header.resize(16 + 12 * count, 0);
+    let len = read_up_to(&mut input, &mut header[16..]).map_err(|e| read_error(path, e))?;
+    if len < 12 * count {
+        return Err(stream_error(format!(
+            "{} ends within its header",
+            path.display()
+        )));
+    }
+    let (regions, at) = read_regions(&header).unwrap_or_default();
+    let mut at = at as u64;
+
+    let mut recovery = Recovery {
+        entries: vec![],
+        missing: vec![],
+        damaged: vec![],
+        trailing: 0,
+    };
+    let mut end = at;
+    let mut record = vec![0; FRAME_HEADER_LEN + frame_len(&regions)];
+    loop {
+        let len = read_up_to(&mut input, &mut record).map_err(|e| read_error(path, e))?;
+        if len < record.len() || record[..FRAME_HEADER_LEN].iter().all(|b| *b == 0) {
+            break;
+        }
+        let (header, pixels) = record.split_at(FRAME_HEADER_LEN);
+        match check_record(at, header, pixels) {
+            Some(entry) => {
+                recovery.entries.push(entry);
+                end = at + record.len() as u64;
+            }
+            None => recovery.damaged.push(at),
+        }
+        at += record.len() as u64;
+    }
+    recovery.trailing = file_len - end;
+
+    let mut expected = 0;
+    for entry in recovery.entries.iter() {
+        recovery.missing.extend(expected..entry.sequence);
+        expected = entry.sequence + 1;
+    }
+    write_index(&index_path(path), &regions, &recovery.entries)?;
+
+    Ok(recovery)
+}
+
+// A finished or recovered recording, read through its index
+pub struct StreamReader {
+    path: PathBuf,
+    file: File,
+    regions: Vec<Region>,
+    entries: Vec<IndexEntry>,
+}
+
+impl StreamReader {
+    // Opens a recording with its index; a recording without one needs recover first
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let (regions, entries) = read_index(&index_path(&path))?;
+        let file = File::open(&path).map_err(|e| read_error(&path, e))?;
+
+        Ok(StreamReader {
+            path,
+            file,
+            regions,
+            entries,
+        })
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The index'th frame of the index, not FRAME_INFO.FrameNr; checked against its CRC
+    pub fn read_frame(&mut self, index: usize) -> Result<Frame> {
+        let entry = match self.entries.get(index) {
+            Some(entry) => *entry,
+            None => {
+                return Err(stream_error(format!(
+                    "the recording has {} frames, not {}",
+                    self.entries.len(),
+                    index + 1
+                )))
+            }
+        };
+
+        let mut record = vec![0; FRAME_HEADER_LEN + frame_len(&self.regions)];
+        self.file
+            .seek(SeekFrom::Start(entry.offset))
+            .and_then(|_| self.file.read_exact(&mut record))
+            .map_err(|e| read_error(&self.path, e))?;
+        let (header, pixels) = record.split_at(FRAME_HEADER_LEN);
+        if check_record(entry.offset, header, pixels) != Some(entry) {
+            return Err(stream_error(format!(
+                "frame {} of {} is damaged",
+                entry.sequence,
+                self.path.display()
+            )));
+        }
+
+        Ok(Frame {
+            number: entry.number,
+            exposure: entry.exposure,
+            timestamp: entry.timestamp,
+            regions: self.regions.clone(),
+            data: pixels
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect(),
+        })
+    }
+
+    pub fn read_sequence(&mut self) -> Result<Sequence> {
+        let frames = (0..self.entries.len())
+            .map(|index| self.read_frame(index))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Sequence {
+            regions: self.regions.clone(),
+            frames,
+        })
+    }
+}
@@ -0,0 +1,353 @@
+// Single and multi-page 16 bit grayscale TIFF:
+//
+//   let mut tiff = TiffWriter::create("stack.tif", Metadata::read(&camera)?)?;
+//   for frame in sequence.frames.iter() {
+//       tiff.write_frame(frame)?;
+//   }
+//   tiff.finish()?;
+//
+// Every region of every frame is a page. A page's pixels are written as soon as its frame is,
+// the page directories only on finish, once it is known whether the file passes 4 GB and has to
+// be a BigTIFF. A file that is not finished cannot be read.
+//
+// The camera and the frame are in each page's ImageDescription as key=value lines, e.g.
+// "frame=3", "exposure_ms=10", "roi=0,511,0,511", "binning=2x2", "temperature=-10";
+// vendor and model are in Make and Model as well.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::{io_error, Metadata};
+use crate::pvcam::{Error, ErrorKind, Frame, Region, Result, Sequence};
+
+// room for a BigTIFF header, so pixels land at the same offsets whichever the file turns out
+const HEADER_LEN: u64 = 16;
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_LONG8: u16 = 16;
+
+enum Value {
+    Short(Vec<u16>),
+    Long(u32),
+    // LONG in a TIFF, LONG8 in a BigTIFF
+    Offset(u64),
+    Ascii(String),
+}
+
+// A page whose pixels are on disk and whose directory is still to be written
+struct Page {
+    width: u32,
+    height: u32,
+    offset: u64,
+    description: String,
+}
+
+pub struct TiffWriter<W: Write + Seek> {
+    out: W,
+    // for error messages
+    path: Option<PathBuf>,
+    metadata: Metadata,
+    pages: Vec<Page>,
+    // where the next page's pixels go
+    end: u64,
+    big: bool,
+}
+
+fn tiff_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message,
+    }
+}
+
+impl TiffWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, metadata: Metadata) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| io_error(Some(path), e))?;
+        let mut writer = Self::new(BufWriter::with_capacity(1 << 20, file), metadata)?;
+        writer.path = Some(path.to_owned());
+
+        Ok(writer)
+    }
+}
+
+impl<W: Write + Seek> TiffWriter<W> {
+    pub fn new(mut out: W, metadata: Metadata) -> Result<Self> {
+        // the header is only written on finish
+        out.seek(SeekFrom::Start(0))
+            .and_then(|_| out.write_all(&[0; HEADER_LEN as usize]))
+            .map_err(|e| io_error(None, e))?;
+
+        Ok(TiffWriter {
+            out,
+            path: None,
+            metadata,
+            pages: vec![],
+            end: HEADER_LEN,
+            big: false,
+        })
+    }
+
+    // A BigTIFF however small the file, e.g. to test a reader with one
+    pub fn big_tiff(mut self, big: bool) -> Self {
+        self.big = big;
+        self
+    }
+
+    fn io_error(&self, e: std::io::Error) -> Error {
+        io_error(self.path.as_deref(), e)
+    }
+
+    fn description(&self, frame: &Frame, index: usize, region: &Region) -> String {
+        let metadata = &self.metadata;
+        let mut lines = vec![];
+        let mut line = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                lines.push(format!("{}={}", key, value));
+            }
+        };
+        line("vendor", metadata.vendor.clone());
+        line("model", metadata.model.clone());
+        line("serial", metadata.serial.clone());
+        line("chip", metadata.chip.clone());
+        line("readout_port", metadata.readout_port.clone());
+        line("speed", metadata.speed.map(|v| v.to_string()));
+        line("gain", metadata.gain.map(|v| v.to_string()));
+        line("bit_depth", metadata.bit_depth.map(|v| v.to_string()));
+        line("temperature", metadata.temperature.map(|v| v.to_string()));
+        line("frame", Some(frame.number.to_string()));
+        line("timestamp", frame.timestamp.map(|v| v.to_string()));
+        line(
+            "exposure_ms",
+            Some((frame.exposure.as_secs_f64() * 1000.0).to_string()),
+        );
+        if frame.regions.len() > 1 {
+            line("region", Some(index.to_string()));
+        }
+        line(
+            "roi",
+            Some(format!(
+                "{},{},{},{}",
+                region.s1, region.s2, region.p1, region.p2
+            )),
+        );
+        line("binning", Some(format!("{}x{}", region.sbin, region.pbin)));
+
+        lines.join("\n")
+    }
+
+    // Writes every region of the frame as a page of its own
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        let mut start = 0;
+        for (index, region) in frame.regions.iter().enumerate() {
+            let len = region.width() * region.height();
+            let pixels = match frame.data.get(start..start + len) {
+                Some(pixels) => pixels,
+                None => {
+                    return Err(tiff_error(format!(
+                        "frame {} has {} pixels, fewer than its regions",
+                        frame.number,
+                        frame.data.len()
+                    )))
+                }
+            };
+            start += len;
+
+            let mut bytes = Vec::with_capacity(len * 2);
+            for pixel in pixels.iter() {
+                bytes.extend_from_slice(&pixel.to_le_bytes());
+            }
+            if let Err(e) = self.out.write_all(&bytes) {
+                return Err(self.io_error(e));
+            }
+
+            self.pages.push(Page {
+                width: region.width() as u32,
+                height: region.height() as u32,
+                offset: self.end,
+                description: self.description(frame, index, region),
+            });
+            self.end += bytes.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_sequence(&mut self, sequence: &Sequence) -> Result<()> {
+        for frame in sequence.frames.iter() {
+            self.write_frame(frame)?;
+        }
+
+        Ok(())
+    }
+
+    fn entries(&self, page: &Page, number: usize) -> Vec<(u16, Value)> {
+        let ascii = |v: &str| Value::Ascii(v.to_owned());
+        let mut entries = vec![
+            (256, Value::Long(page.width)),
+            (257, Value::Long(page.height)),
+            // bits per sample, uncompressed, black is zero
+            (258, Value::Short(vec![16])),
+            (259, Value::Short(vec![1])),
+            (262, Value::Short(vec![1])),
+            (270, ascii(&page.description)),
+        ];
+        if let Some(vendor) = &self.metadata.vendor {
+            entries.push((271, ascii(vendor)));
+        }
+        if let Some(model) = &self.metadata.model {
+            entries.push((272, ascii(model)));
+        }
+        entries.extend(vec![
+            // one strip holding the whole page
+            (273, Value::Offset(page.offset)),
+            (277, Value::Short(vec![1])),
+            (278, Value::Long(page.height)),
+            (279, Value::Long(page.width * page.height * 2)),
+            (284, Value::Short(vec![1])),
+            (
+                297,
+                Value::Short(vec![
+                    number as u16,
+                    self.pages.len().min(u16::MAX as usize) as u16,
+                ]),
+            ),
+            (
+                305,
+                ascii(concat!("libpvcam-sys ", env!("CARGO_PKG_VERSION"))),
+            ),
+            // unsigned integers
+            (339, Value::Short(vec![1])),
+        ]);
+
+        entries
+    }
+
+    // The page directories laid out from `self.end`, None if a classic TIFF cannot address them
+    fn directories(&self, big: bool) -> Option<Vec<u8>> {
+        let (count_len, entry_len, next_len, inline_len) = match big {
+            true => (8, 20, 8, 8),
+            false => (2, 12, 4, 4),
+        };
+        let offset = |v: u64| -> Option<Vec<u8>> {
+            match big {
+                true => Some(v.to_le_bytes().to_vec()),
+                false => u32::try_from(v).ok().map(|v| v.to_le_bytes().to_vec()),
+            }
+        };
+
+        let mut bytes = vec![];
+        for (number, page) in self.pages.iter().enumerate() {
+            let entries = self.entries(page, number);
+            let at = self.end + bytes.len() as u64;
+            let mut extra_at = at + count_len + entries.len() as u64 * entry_len + next_len;
+            let mut extra = vec![];
+
+            bytes.extend(
+                offset(entries.len() as u64)?
+                    .iter()
+                    .take(count_len as usize),
+            );
+            for (tag, value) in entries.iter() {
+                let (kind, count, data) = match value {
+                    Value::Short(v) => (
+                        TYPE_SHORT,
+                        v.len(),
+                        v.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect(),
+                    ),
+                    Value::Long(v) => (TYPE_LONG, 1, v.to_le_bytes().to_vec()),
+                    Value::Offset(v) => (if big { TYPE_LONG8 } else { TYPE_LONG }, 1, offset(*v)?),
+                    Value::Ascii(v) => {
+                        // 7 bit with a terminator
+                        let mut data: Vec<u8> = v
+                            .chars()
+                            .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+                            .collect();
+                        data.push(0);
+                        (TYPE_ASCII, data.len(), data)
+                    }
+                };
+
+                bytes.extend_from_slice(&tag.to_le_bytes());
+                bytes.extend_from_slice(&kind.to_le_bytes());
+                bytes.extend(offset(count as u64)?);
+                if data.len() <= inline_len {
+                    let mut inline = data;
+                    inline.resize(inline_len, 0);
+                    bytes.extend(inline);
+                } else {
+                    bytes.extend(offset(extra_at)?);
+                    extra_at += data.len() as u64 + data.len() as u64 % 2;
+                    extra.extend_from_slice(&data);
+                    if data.len() % 2 == 1 {
+                        extra.push(0);
+                    }
+                }
+            }
+
+            let next = match number + 1 == self.pages.len() {
+                true => 0,
+                false => extra_at,
+            };
+            bytes.extend(offset(next)?);
+            bytes.extend(extra);
+        }
+
+        Some(bytes)
+    }
+
+    // finish with the first page described by `description` instead, e.g. OME-XML
+    pub(super) fn finish_with_description(mut self, description: String) -> Result<W> {
+        if let Some(page) = self.pages.first_mut() {
+            page.description = description;
+        }
+        self.finish()
+    }
+
+    // Writes the page directories and the header; returns the writer for in-memory output
+    pub fn finish(mut self) -> Result<W> {
+        if self.pages.is_empty() {
+            return Err(tiff_error("a TIFF needs at least one page".to_owned()));
+        }
+
+        let (big, directories) = match (self.big, self.directories(false)) {
+            (false, Some(directories)) => (false, directories),
+            _ => match self.directories(true) {
+                Some(directories) => (true, directories),
+                None => return Err(tiff_error("the pages cannot be addressed".to_owned())),
+            },
+        };
+        let mut header = b"II".to_vec();
+        match big {
+            true => {
+                header.extend_from_slice(&43u16.to_le_bytes());
+                header.extend_from_slice(&8u16.to_le_bytes());
+                header.extend_from_slice(&0u16.to_le_bytes());
+                header.extend_from_slice(&self.end.to_le_bytes());
+            }
+            false => {
+                header.extend_from_slice(&42u16.to_le_bytes());
+                header.extend_from_slice(&(self.end as u32).to_le_bytes());
+            }
+        }
+
+        let end = self.end;
+        let out = &mut self.out;
+        let result = out
+            .seek(SeekFrom::Start(end))
+            .and_then(|_| out.write_all(&directories))
+            .and_then(|_| out.seek(SeekFrom::Start(0)))
+            .and_then(|_| out.write_all(&header))
+            .and_then(|_| out.flush());
+        if let Err(e) = result {
+            return Err(self.io_error(e));
+        }
+
+        Ok(self.out)
+    }
+}
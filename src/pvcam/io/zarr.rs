@@ -0,0 +1,628 @@
+// OME-Zarr (OME-NGFF), for acquisitions too long for one file: a directory of chunks, written
+// as the frames come in:
+//
+//   let mut zarr = ZarrWriter::create("run.zarr", Metadata::read(&camera)?)?
+//       .chunks(16, 512, 512)
+//       .compression(Compression::Blosc { codec: BloscCodec::Zstd, shuffle: true });
+//   camera.start_cont(&config)?;
+//   while running() {
+//       if let Some(frame) = camera.latest_frame()? {
+//           zarr.write_frame(&frame)?;
+//       }
+//   }
+//   camera.stop_cont()?;
+//   zarr.finish()?;
+//
+// The store is a multiscale image of one resolution, the array "0", with the axes t, c, y and
+// x: frames along t and regions along c, so every region has to have the size and binning of the
+// first. It is Zarr v2 with OME-NGFF 0.4 or Zarr v3 with OME-NGFF 0.5, chunks keyed with "/".
+//
+// The chunks along t are written once all of their frames are in, the last, partial one on
+// flush and finish, padded with zeros. The array's shape is rewritten with every chunk along t,
+// so the store of a writer that died reads up to its last chunk. The camera's Metadata, the
+// regions and every frame's number, timestamp and exposure are in the group's attributes under
+// "pvcam"; as they grow with the acquisition, they are only written on flush and finish.
+
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use super::{io_error, Metadata};
+use crate::pvcam::{Error, ErrorKind, Frame, Region, Result, Sequence};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZarrVersion {
+    // .zgroup, .zattrs and .zarray; OME-NGFF 0.4
+    V2,
+    // zarr.json; OME-NGFF 0.5
+    V3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BloscCodec {
+    Lz4,
+    Zstd,
+}
+
+// How chunks are compressed, all of it done here rather than by c-blosc or libzstd. Lz4 is
+// numcodecs' LZ4, which Zarr v3 has no codec for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Zstd,
+    Lz4,
+    // Blosc 1 frames, the pixels' low bytes ahead of their high ones if shuffled
+    Blosc { codec: BloscCodec, shuffle: bool },
+}
+
+// ruzstd's fastest, about zstd's level 1
+const ZSTD_LEVEL: i32 = 1;
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+// what the metadata says; Blosc's level picks no other compression here
+const BLOSC_LEVEL: u8 = 5;
+const BLOSC_BLOCK: usize = 1 << 18;
+const BLOSC_SHUFFLE: u8 = 0x1;
+const BLOSC_MEMCPYED: u8 = 0x2;
+const BLOSC_DONT_SPLIT: u8 = 0x10;
+
+// What the attributes say about a frame
+struct Record {
+    number: u32,
+    timestamp: Option<i64>,
+    exposure: Duration,
+}
+
+pub struct ZarrWriter {
+    root: PathBuf,
+    metadata: Metadata,
+    version: ZarrVersion,
+    // t, y and x; a frame of whole regions if None
+    chunks: Option<(usize, usize, usize)>,
+    compression: Compression,
+    timestamp_resolution: Duration,
+    // of the first frame
+    regions: Vec<Region>,
+    records: Vec<Record>,
+    // the pixels of the frames of the chunk along t being filled
+    pending: Vec<Vec<u16>>,
+}
+
+fn zarr_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message,
+    }
+}
+
+// A zstd frame that gives its content size, which ruzstd leaves out and numcodecs sizes its
+// buffer by. The frame header ruzstd wrote is read field by field and written again with an 8 byte
+// content size in place of whatever it had.
+fn zstd(bytes: &[u8]) -> Result<Vec<u8>> {
+    let frame =
+        ruzstd::encoding::compress_to_vec(bytes, ruzstd::encoding::CompressionLevel::Fastest);
+    let unreadable = || zarr_error("ruzstd wrote a zstd frame header this cannot read".to_owned());
+    if frame.get(..4) != Some(&ZSTD_MAGIC[..]) {
+        return Err(unreadable());
+    }
+    let descriptor = *frame.get(4).ok_or_else(unreadable)?;
+    if descriptor & 0x08 != 0 {
+        return Err(unreadable());
+    }
+
+    // after the descriptor: a window descriptor unless the frame is a single segment, the
+    // dictionary id, then the content size, whose length the descriptor's top two bits give
+    let single_segment = descriptor & 0x20 != 0;
+    let window = usize::from(!single_segment);
+    let dictionary = [0, 1, 2, 4][(descriptor & 0x03) as usize];
+    let content_size = match descriptor >> 6 {
+        0 => usize::from(single_segment),
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let kept = 5 + window + dictionary;
+    if frame.len() < kept + content_size {
+        return Err(unreadable());
+    }
+
+    let mut out = Vec::with_capacity(frame.len() - content_size + 8);
+    out.extend_from_slice(&frame[..4]);
+    out.push(descriptor | 0xc0);
+    out.extend_from_slice(&frame[5..kept]);
+    out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    out.extend_from_slice(&frame[kept + content_size..]);
+    Ok(out)
+}
+
+// Blosc's byte shuffle of u16s: the first byte of every pixel, then the second
+fn shuffle(block: &[u8]) -> Vec<u8> {
+    let pixels = block.len() / 2;
+    let mut shuffled = vec![0; block.len()];
+    for (i, pixel) in block.chunks_exact(2).enumerate() {
+        shuffled[i] = pixel[0];
+        shuffled[pixels + i] = pixel[1];
+    }
+    shuffled
+}
+
+// A Blosc 1 frame: a 16 byte header, where each block starts, then each block as one stream, its
+// compressed length ahead of it, stored as is if it does not get smaller. If the whole does not,
+// the header is followed by the bytes as they are.
+fn blosc(bytes: &[u8], codec: BloscCodec, shuffled: bool) -> Result<Vec<u8>> {
+    let nbytes = i32::try_from(bytes.len())
+        .map_err(|_| zarr_error("Blosc compresses chunks of up to 2 GB".to_owned()))?;
+    let block_len = bytes.len().min(BLOSC_BLOCK);
+    let blocks = bytes.len().div_ceil(block_len);
+    let compcode = match codec {
+        BloscCodec::Lz4 => 1,
+        BloscCodec::Zstd => 4,
+    };
+    let header = |flags: u8, compressed: usize| {
+        let mut header = vec![2, 1, flags | compcode << 5, 2];
+        header.extend_from_slice(&nbytes.to_le_bytes());
+        header.extend_from_slice(&(block_len as i32).to_le_bytes());
+        header.extend_from_slice(&(compressed as i32).to_le_bytes());
+        header
+    };
+
+    let mut out = vec![0; 16 + 4 * blocks];
+    for (i, block) in bytes.chunks(block_len).enumerate() {
+        let start = out.len() as i32;
+        out[16 + 4 * i..20 + 4 * i].copy_from_slice(&start.to_le_bytes());
+        let block = match shuffled {
+            true => shuffle(block),
+            false => block.to_vec(),
+        };
+        let compressed = match codec {
+            BloscCodec::Lz4 => lz4_flex::block::compress(&block),
+            BloscCodec::Zstd => zstd(&block)?,
+        };
+        let stream = match compressed.len() < block.len() {
+            true => compressed,
+            false => block,
+        };
+        out.extend_from_slice(&(stream.len() as i32).to_le_bytes());
+        out.extend(stream);
+    }
+
+    if out.len() > 16 + bytes.len() {
+        let mut out = header(BLOSC_MEMCPYED | BLOSC_DONT_SPLIT, 16 + bytes.len());
+        out.extend_from_slice(bytes);
+        return Ok(out);
+    }
+    let flags = match shuffled {
+        true => BLOSC_SHUFFLE | BLOSC_DONT_SPLIT,
+        false => BLOSC_DONT_SPLIT,
+    };
+    let len = out.len();
+    out[..16].copy_from_slice(&header(flags, len));
+
+    Ok(out)
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| io_error(Some(parent), e))?;
+    }
+    let mut file = File::create(path).map_err(|e| io_error(Some(path), e))?;
+    file.write_all(bytes).map_err(|e| io_error(Some(path), e))
+}
+
+fn write_json(path: &Path, value: &Value) -> Result<()> {
+    let mut bytes = serde_json::to_vec(value)
+        .map_err(|e| zarr_error(format!("unable to write {}: {}", path.display(), e)))?;
+    bytes.push(b'\n');
+    write_file(path, &bytes)
+}
+
+impl ZarrWriter {
+    // Creates the store's directory, which has to be empty if it exists
+    pub fn create<P: AsRef<Path>>(path: P, metadata: Metadata) -> Result<Self> {
+        let root = path.as_ref();
+        if let Ok(mut entries) = fs::read_dir(root) {
+            if entries.next().is_some() {
+                return Err(zarr_error(format!("{} is not empty", root.display())));
+            }
+        }
+        fs::create_dir_all(root).map_err(|e| io_error(Some(root), e))?;
+
+        Ok(ZarrWriter {
+            root: root.to_owned(),
+            metadata,
+            version: ZarrVersion::V2,
+            chunks: None,
+            compression: Compression::None,
+            timestamp_resolution: Duration::from_micros(1),
+            regions: vec![],
+            records: vec![],
+            pending: vec![],
+        })
+    }
+
+    // Zarr v2 by default
+    pub fn version(mut self, version: ZarrVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    // Frames, rows and columns of a chunk, the latter two at most a region's; one frame of whole
+    // regions by default
+    pub fn chunks(mut self, frames: usize, rows: usize, columns: usize) -> Self {
+        self.chunks = Some((frames, rows, columns));
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    // The unit of FRAME_INFO.TimeStamp, for the time axis' scale; a microsecond by default
+    pub fn timestamp_resolution(mut self, resolution: Duration) -> Self {
+        self.timestamp_resolution = resolution;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    fn region_len(&self) -> usize {
+        self.regions[0].width() * self.regions[0].height()
+    }
+
+    fn chunk_shape(&self) -> (usize, usize, usize) {
+        let region = &self.regions[0];
+        let (frames, rows, columns) = self.chunks.unwrap_or((1, region.height(), region.width()));
+        (
+            frames,
+            rows.min(region.height()),
+            columns.min(region.width()),
+        )
+    }
+
+    // Takes the first frame's regions and writes the metadata of an empty array
+    fn start(&mut self, frame: &Frame) -> Result<()> {
+        let first = match frame.regions.first() {
+            Some(first) => first,
+            None => return Err(zarr_error(format!("frame {} has no regions", frame.number))),
+        };
+        if frame.regions.iter().any(|r| {
+            r.width() != first.width()
+                || r.height() != first.height()
+                || (r.sbin, r.pbin) != (first.sbin, first.pbin)
+        }) {
+            return Err(zarr_error(
+                "the regions of an OME-Zarr have to have one size and binning".to_owned(),
+            ));
+        }
+        if let Some((0, _, _)) | Some((_, 0, _)) | Some((_, _, 0)) = self.chunks {
+            return Err(zarr_error("chunks cannot be empty".to_owned()));
+        }
+        if (self.version, self.compression) == (ZarrVersion::V3, Compression::Lz4) {
+            return Err(zarr_error(
+                "Zarr v3 has no LZ4 codec; use Blosc with LZ4".to_owned(),
+            ));
+        }
+
+        self.regions = frame.regions.clone();
+        self.write_group()?;
+        self.write_array()
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        if self.records.is_empty() {
+            self.start(frame)?;
+        } else if frame.regions != self.regions {
+            return Err(zarr_error(format!(
+                "frame {} has regions other than the first frame's",
+                frame.number
+            )));
+        }
+        let len = self.regions.len() * self.region_len();
+        let pixels = match frame.data.get(..len) {
+            Some(pixels) => pixels,
+            None => {
+                return Err(zarr_error(format!(
+                    "frame {} has {} pixels, fewer than its regions",
+                    frame.number,
+                    frame.data.len()
+                )))
+            }
+        };
+
+        self.pending.push(pixels.to_vec());
+        self.records.push(Record {
+            number: frame.number,
+            timestamp: frame.timestamp,
+            exposure: frame.exposure,
+        });
+        if self.pending.len() == self.chunk_shape().0 {
+            self.write_chunks()?;
+            self.pending.clear();
+            self.write_array()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_sequence(&mut self, sequence: &Sequence) -> Result<()> {
+        for frame in sequence.frames.iter() {
+            self.write_frame(frame)?;
+        }
+
+        Ok(())
+    }
+
+    // Writes the chunk along t being filled as far as it is, and the attributes, so the store
+    // has every frame so far. The chunk is written again once it fills.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        if !self.pending.is_empty() {
+            self.write_chunks()?;
+        }
+        self.write_array()?;
+        self.write_group()
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        if self.records.is_empty() {
+            return Err(zarr_error(
+                "an OME-Zarr needs at least one frame".to_owned(),
+            ));
+        }
+        self.flush()
+    }
+
+    fn chunk_path(&self, key: [usize; 4]) -> PathBuf {
+        let mut path = self.root.join("0");
+        if self.version == ZarrVersion::V3 {
+            path.push("c");
+        }
+        for index in key.iter() {
+            path.push(index.to_string());
+        }
+        path
+    }
+
+    fn encode(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(match self.compression {
+            Compression::None => bytes,
+            Compression::Zstd => zstd(&bytes)?,
+            Compression::Lz4 => lz4_flex::compress_prepend_size(&bytes),
+            Compression::Blosc { codec, shuffle } => blosc(&bytes, codec, shuffle)?,
+        })
+    }
+
+    // Every chunk of the pending frames, in C order and little endian
+    fn write_chunks(&self) -> Result<()> {
+        let (frames, rows, columns) = self.chunk_shape();
+        let (width, height) = (self.regions[0].width(), self.regions[0].height());
+        let len = self.region_len();
+        let t = (self.records.len() - 1) / frames;
+
+        for c in 0..self.regions.len() {
+            for y in (0..height).step_by(rows) {
+                for x in (0..width).step_by(columns) {
+                    let mut chunk = vec![0; frames * rows * columns * 2];
+                    for (i, pixels) in self.pending.iter().enumerate() {
+                        let pixels = &pixels[c * len..(c + 1) * len];
+                        for row in y..(y + rows).min(height) {
+                            let start = row * width + x;
+                            let end = row * width + (x + columns).min(width);
+                            let at = ((i * rows + row - y) * columns) * 2;
+                            for (j, pixel) in pixels[start..end].iter().enumerate() {
+                                chunk[at + 2 * j..at + 2 * j + 2]
+                                    .copy_from_slice(&pixel.to_le_bytes());
+                            }
+                        }
+                    }
+
+                    let path = self.chunk_path([t, c, y / rows, x / columns]);
+                    write_file(&path, &self.encode(chunk)?)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn shape(&self) -> Value {
+        let region = &self.regions[0];
+        json!([
+            self.records.len(),
+            self.regions.len(),
+            region.height(),
+            region.width()
+        ])
+    }
+
+    fn write_array(&self) -> Result<()> {
+        let (frames, rows, columns) = self.chunk_shape();
+        let chunks = json!([frames, 1, rows, columns]);
+
+        match self.version {
+            ZarrVersion::V2 => {
+                let compressor = match self.compression {
+                    Compression::None => Value::Null,
+                    Compression::Zstd => json!({ "id": "zstd", "level": ZSTD_LEVEL }),
+                    Compression::Lz4 => json!({ "id": "lz4", "acceleration": 1 }),
+                    Compression::Blosc { codec, shuffle } => json!({
+                        "id": "blosc",
+                        "cname": blosc_name(codec),
+                        "clevel": BLOSC_LEVEL,
+                        "shuffle": shuffle as u8,
+                        "blocksize": 0,
+                    }),
+                };
+                let array = json!({
+                    "zarr_format": 2,
+                    "shape": self.shape(),
+                    "chunks": chunks,
+                    "dtype": "<u2",
+                    "compressor": compressor,
+                    "fill_value": 0,
+                    "order": "C",
+                    "filters": null,
+                    "dimension_separator": "/",
+                });
+                write_json(&self.root.join("0").join(".zarray"), &array)
+            }
+            ZarrVersion::V3 => {
+                let mut codecs = vec![json!({
+                    "name": "bytes",
+                    "configuration": { "endian": "little" },
+                })];
+                match self.compression {
+                    Compression::None | Compression::Lz4 => {}
+                    Compression::Zstd => codecs.push(json!({
+                        "name": "zstd",
+                        "configuration": { "level": ZSTD_LEVEL, "checksum": false },
+                    })),
+                    Compression::Blosc { codec, shuffle } => codecs.push(json!({
+                        "name": "blosc",
+                        "configuration": {
+                            "cname": blosc_name(codec),
+                            "clevel": BLOSC_LEVEL,
+                            "shuffle": if shuffle { "shuffle" } else { "noshuffle" },
+                            "typesize": 2,
+                            "blocksize": 0,
+                        },
+                    })),
+                }
+                let array = json!({
+                    "zarr_format": 3,
+                    "node_type": "array",
+                    "shape": self.shape(),
+                    "data_type": "uint16",
+                    "chunk_grid": {
+                        "name": "regular",
+                        "configuration": { "chunk_shape": chunks },
+                    },
+                    "chunk_key_encoding": {
+                        "name": "default",
+                        "configuration": { "separator": "/" },
+                    },
+                    "fill_value": 0,
+                    "codecs": codecs,
+                    "dimension_names": ["t", "c", "y", "x"],
+                    "attributes": {},
+                });
+                write_json(&self.root.join("0").join("zarr.json"), &array)
+            }
+        }
+    }
+
+    // Seconds between frames: the mean interval between the timestamps, otherwise the
+    // exposure
+    fn interval(&self) -> f64 {
+        let first = self.records.first().and_then(|r| r.timestamp);
+        let last = self.records.last().and_then(|r| r.timestamp);
+        let interval = match (first, last) {
+            (Some(first), Some(last)) if self.records.len() > 1 => {
+                (last - first) as f64 * self.timestamp_resolution.as_secs_f64()
+                    / (self.records.len() - 1) as f64
+            }
+            _ => self
+                .records
+                .first()
+                .map_or(0.0, |r| r.exposure.as_secs_f64()),
+        };
+        match interval > 0.0 {
+            true => interval,
+            false => 1.0,
+        }
+    }
+
+    fn multiscale(&self) -> Value {
+        let region = &self.regions[0];
+        let (space, y, x) = match self.metadata.pixel_size.filter(|(s, p)| *s > 0 && *p > 0) {
+            Some((serial, parallel)) => (
+                json!({ "type": "space", "unit": "micrometer" }),
+                parallel as f64 * region.pbin as f64 / 1000.0,
+                serial as f64 * region.sbin as f64 / 1000.0,
+            ),
+            None => (json!({ "type": "space" }), 1.0, 1.0),
+        };
+        let axis = |name: &str, kind: &Value| {
+            let mut axis = kind.clone();
+            axis["name"] = json!(name);
+            axis
+        };
+
+        let mut multiscale = json!({
+            "name": self.metadata.model.as_deref().unwrap_or("pvcam"),
+            "axes": [
+                { "name": "t", "type": "time", "unit": "second" },
+                { "name": "c", "type": "channel" },
+                axis("y", &space),
+                axis("x", &space),
+            ],
+            "datasets": [{
+                "path": "0",
+                "coordinateTransformations": [
+                    { "type": "scale", "scale": [self.interval(), 1.0, y, x] },
+                ],
+            }],
+        });
+        if self.version == ZarrVersion::V2 {
+            multiscale["version"] = json!("0.4");
+        }
+        multiscale
+    }
+
+    fn pvcam_attributes(&self) -> Value {
+        json!({
+            "camera": self.metadata,
+            "regions": self.regions,
+            "timestamp_resolution": self.timestamp_resolution.as_secs_f64(),
+            "frames": {
+                "number": self.records.iter().map(|r| r.number).collect::<Vec<_>>(),
+                "timestamp": self.records.iter().map(|r| r.timestamp).collect::<Vec<_>>(),
+                "exposure": self
+                    .records
+                    .iter()
+                    .map(|r| r.exposure.as_secs_f64())
+                    .collect::<Vec<_>>(),
+            },
+        })
+    }
+
+    fn write_group(&self) -> Result<()> {
+        match self.version {
+            ZarrVersion::V2 => {
+                write_json(&self.root.join(".zgroup"), &json!({ "zarr_format": 2 }))?;
+                let attributes = json!({
+                    "multiscales": [self.multiscale()],
+                    "pvcam": self.pvcam_attributes(),
+                });
+                write_json(&self.root.join(".zattrs"), &attributes)
+            }
+            ZarrVersion::V3 => {
+                let group = json!({
+                    "zarr_format": 3,
+                    "node_type": "group",
+                    "attributes": {
+                        "ome": { "version": "0.5", "multiscales": [self.multiscale()] },
+                        "pvcam": self.pvcam_attributes(),
+                    },
+                });
+                write_json(&self.root.join("zarr.json"), &group)
+            }
+        }
+    }
+}
+
+fn blosc_name(codec: BloscCodec) -> &'static str {
+    match codec {
+        BloscCodec::Lz4 => "lz4",
+        BloscCodec::Zstd => "zstd",
+    }
+}
@@ -0,0 +1,547 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::backend::CameraBackend;
+use super::state;
+use super::{
+    is_available, read_int, read_param, write_param, Camera, Error, ErrorKind, ExposureMode,
+    ParamAttrKind, Parameter, ParameterValue, PostProcessingValue, Region, Result, SequenceConfig,
+    PARAMETERS,
+};
+
+// Sensor coordinates of a region, inclusive like rgn_type
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Roi {
+    pub s1: u16,
+    pub s2: u16,
+    pub p1: u16,
+    pub p2: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Binning {
+    pub serial: u16,
+    pub parallel: u16,
+}
+
+// A per-assay camera setup, usually kept in a TOML file:
+//
+//   readout_port = "Sensitivity"
+//   speed = 0
+//   gain = 1
+//   trigger = "ExtTrigEdgeRising"
+//   exposure_ms = 20
+//   binning = { serial = 2, parallel = 2 }
+//
+//   [[roi]]
+//   s1 = 0
+//   s2 = 1023
+//   p1 = 0
+//   p2 = 1023
+//
+//   [post_processing."DESPECKLE BRIGHT HIGH"]
+//   ENABLED = 1
+//
+// Anything left out is not touched. readout_port and expose_out_mode are matched by name
+// against the camera's options; trigger is an ExposureMode.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub readout_port: Option<String>,
+    // PARAM_SPDTAB_INDEX
+    pub speed: Option<i32>,
+    // PARAM_GAIN_INDEX
+    pub gain: Option<i32>,
+    pub expose_out_mode: Option<String>,
+    pub trigger: Option<ExposureMode>,
+    pub exposure_ms: Option<u32>,
+    #[serde(default)]
+    pub roi: Vec<Roi>,
+    pub binning: Option<Binning>,
+    // feature name => parameter name => value
+    #[serde(default)]
+    pub post_processing: BTreeMap<String, BTreeMap<String, u32>>,
+}
+
+// One setting apply_profile changed on the camera
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub setting: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.setting, self.before, self.after)
+    }
+}
+
+fn binding_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message,
+    }
+}
+
+impl Profile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                return Err(binding_error(format!(
+                    "unable to read {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        match toml::from_str(text) {
+            Ok(profile) => Ok(profile),
+            Err(e) => Err(binding_error(format!("invalid profile: {}", e))),
+        }
+    }
+
+    // The regions to acquire: every roi binned by binning, or the whole sensor if no roi is set
+    pub fn regions(&self, camera: &Camera) -> Result<Vec<Region>> {
+        let binning = self.binning.unwrap_or(Binning {
+            serial: 1,
+            parallel: 1,
+        });
+        if binning.serial == 0 || binning.parallel == 0 {
+            return Err(binding_error(format!(
+                "binning {}x{} is not at least 1x1",
+                binning.serial, binning.parallel
+            )));
+        }
+        if let Some(roi) = self
+            .roi
+            .iter()
+            .find(|roi| roi.s1 > roi.s2 || roi.p1 > roi.p2)
+        {
+            return Err(binding_error(format!("{:?} ends before it starts", roi)));
+        }
+        let rois = match self.roi.is_empty() {
+            false => self.roi.clone(),
+            true => {
+                let (serial, parallel) = sensor_size(camera.backend())?;
+                if serial == 0 || parallel == 0 {
+                    return Err(binding_error(format!(
+                        "camera reports a {}x{} sensor",
+                        serial, parallel
+                    )));
+                }
+                vec![Roi {
+                    s1: 0,
+                    s2: serial - 1,
+                    p1: 0,
+                    p2: parallel - 1,
+                }]
+            }
+        };
+
+        Ok(rois
+            .iter()
+            .map(|roi| {
+                Region::new(
+                    (binning.serial, roi.s1..roi.s2),
+                    (binning.parallel, roi.p1..roi.p2),
+                )
+            })
+            .collect())
+    }
+
+    pub fn sequence_config(&self, camera: &Camera, frames: u16) -> Result<SequenceConfig> {
+        let exposure_ms = match self.exposure_ms {
+            Some(v) => v,
+            None => {
+                return Err(binding_error(
+                    "profile has no exposure_ms to acquire with".to_owned(),
+                ))
+            }
+        };
+        let config = SequenceConfig::new(
+            self.regions(camera)?,
+            frames,
+            std::time::Duration::from_millis(exposure_ms as u64),
+        );
+
+        Ok(match self.trigger {
+            Some(mode) => config.exp_mode(mode),
+            None => config,
+        })
+    }
+}
+
+fn sensor_size(backend: &dyn CameraBackend) -> Result<(u16, u16)> {
+    let size = |parameter| -> Result<u16> {
+        Ok(read_int(backend, parameter as u32, ParamAttrKind::Current)? as u16)
+    };
+
+    Ok((
+        size(Parameter::SensorSerialSize)?,
+        size(Parameter::SensorParallelSize)?,
+    ))
+}
+
+fn enum_by_name(
+    backend: &dyn CameraBackend,
+    parameter: Parameter,
+    name: &str,
+) -> Result<ParameterValue> {
+    let enums = backend.get_enums(parameter as u32)?;
+    match enums.iter().position(|e| e.name == name) {
+        Some(idx) => Ok(ParameterValue::Enum(idx as u32, enums)),
+        None => Err(binding_error(format!(
+            "{:?} is not one of the {:?} options: {}",
+            name,
+            parameter,
+            enums
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+fn has_enum_value(backend: &dyn CameraBackend, parameter: Parameter, value: i32) -> Result<bool> {
+    // cameras without the parameter accept whatever the SDK does
+    if !is_available(backend, parameter as u32)? {
+        return Ok(true);
+    }
+
+    Ok(backend
+        .get_enums(parameter as u32)?
+        .iter()
+        .any(|e| e.value == value))
+}
+
+fn int_in_range(
+    backend: &dyn CameraBackend,
+    parameter: Parameter,
+    value: i32,
+) -> Result<ParameterValue> {
+    let bound = |attr| read_int(backend, parameter as u32, attr);
+    let (min, max) = (bound(ParamAttrKind::Min)?, bound(ParamAttrKind::Max)?);
+    if (value as i64) < min || (value as i64) > max {
+        return Err(binding_error(format!(
+            "{} is outside {:?} range {}..={}",
+            value, parameter, min, max
+        )));
+    }
+
+    Ok(ParameterValue::Int(value))
+}
+
+struct Plan {
+    // in PARAMETERS order
+    params: Vec<(Parameter, ParameterValue)>,
+    // speed and gain whose range is that of a port or speed the plan selects; they are checked
+    // once it is selected, before they are written
+    deferred: Vec<Parameter>,
+    post_processing: Vec<(PostProcessingValue, (i16, i16))>,
+}
+
+// The speed and gain ranges a camera reports are those of the selected port and speed. Against
+// another port or speed they are only known once it is selected, which checking does not do, so
+// the check of a speed or gain behind one the profile changes is left to write_plan.
+fn speed_and_gain(
+    backend: &dyn CameraBackend,
+    profile: &Profile,
+    port: Option<&ParameterValue>,
+    deferred: &mut Vec<Parameter>,
+    problems: &mut Vec<String>,
+) -> Result<Vec<(Parameter, ParameterValue)>> {
+    let mut params = vec![];
+    let mut selects = match port {
+        Some(port) => {
+            read_param(
+                backend,
+                Parameter::ReadoutPort as u32,
+                ParamAttrKind::Current,
+            )?
+            .to_string()
+                != port.to_string()
+        }
+        None => false,
+    };
+
+    for (parameter, value) in [
+        (Parameter::SpeedTableIndex, profile.speed),
+        (Parameter::GainIndex, profile.gain),
+    ]
+    .iter()
+    {
+        let value = match value {
+            Some(value) => *value,
+            None => continue,
+        };
+        if selects {
+            deferred.push(*parameter);
+            params.push((*parameter, ParameterValue::Int(value)));
+        } else {
+            match int_in_range(backend, *parameter, value) {
+                Ok(value) => params.push((*parameter, value)),
+                Err(e) => problems.push(e.message),
+            }
+        }
+        // the gains offered depend on the speed
+        if *parameter == Parameter::SpeedTableIndex
+            && !selects
+            && profile.gain.is_some()
+            && is_available(backend, *parameter as u32)?
+        {
+            selects = read_int(backend, *parameter as u32, ParamAttrKind::Current)? != value as i64;
+        }
+    }
+
+    Ok(params)
+}
+
+// Checks every setting in the profile against the camera without changing anything, collecting
+// all problems rather than stopping at the first
+fn plan(backend: &dyn CameraBackend, profile: &Profile) -> Result<Plan> {
+    let mut problems = vec![];
+    let mut params = vec![];
+
+    // speed and gain are checked against the current port if the profile's does not fit
+    let port = match &profile.readout_port {
+        Some(name) => match enum_by_name(backend, Parameter::ReadoutPort, name) {
+            Ok(port) => Some(port),
+            Err(e) => {
+                problems.push(e.message);
+                None
+            }
+        },
+        None => None,
+    };
+    let mut deferred = vec![];
+    params.extend(speed_and_gain(
+        backend,
+        profile,
+        port.as_ref(),
+        &mut deferred,
+        &mut problems,
+    )?);
+    if let Some(port) = port {
+        params.push((Parameter::ReadoutPort, port));
+    }
+
+    if let Some(name) = &profile.expose_out_mode {
+        match enum_by_name(backend, Parameter::ExposeOutMode, name) {
+            Ok(value) => params.push((Parameter::ExposeOutMode, value)),
+            Err(e) => problems.push(e.message),
+        }
+    }
+
+    if let Some(mode) = profile.trigger {
+        if !has_enum_value(backend, Parameter::ExposureMode, mode as i32)? {
+            problems.push(format!("camera does not support {:?}", mode));
+        }
+    }
+
+    if !profile.roi.is_empty() {
+        let (serial, parallel) = sensor_size(backend)?;
+        for roi in profile.roi.iter() {
+            if roi.s1 > roi.s2 || roi.s2 >= serial || roi.p1 > roi.p2 || roi.p2 >= parallel {
+                problems.push(format!(
+                    "{:?} does not fit the {}x{} sensor",
+                    roi, serial, parallel
+                ));
+            }
+        }
+    }
+
+    if let Some(binning) = profile.binning {
+        for (parameter, factor) in [
+            (Parameter::BinningSerial, binning.serial),
+            (Parameter::BinningParallel, binning.parallel),
+        ]
+        .iter()
+        {
+            if *factor == 0 || !has_enum_value(backend, *parameter, *factor as i32)? {
+                problems.push(format!("{:?} cannot be {}", parameter, factor));
+            }
+        }
+    }
+
+    let mut post_processing = vec![];
+    if !profile.post_processing.is_empty() {
+        let indices = state::pp_indices(backend)?;
+        for (feature, values) in profile.post_processing.iter() {
+            for (param, value) in values.iter() {
+                match indices.get(&(feature.clone(), param.clone())) {
+                    Some(index) => post_processing.push((
+                        PostProcessingValue {
+                            feature: feature.clone(),
+                            param: param.clone(),
+                            value: *value,
+                        },
+                        *index,
+                    )),
+                    None => problems.push(format!(
+                        "camera has no post-processing parameter {}/{}",
+                        feature, param
+                    )),
+                }
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(binding_error(format!(
+            "profile does not fit this camera: {}",
+            problems.join("; ")
+        )));
+    }
+
+    params.sort_by_key(|(parameter, _)| PARAMETERS.iter().position(|p| p == parameter));
+
+    Ok(Plan {
+        params,
+        deferred,
+        post_processing,
+    })
+}
+
+// What to write back to undo one write of apply
+enum Undo {
+    Param(Parameter, ParameterValue),
+    PostProcessing((i16, i16), u32),
+}
+
+fn undo(backend: &dyn CameraBackend, undo: Vec<Undo>) -> Result<()> {
+    let mut result = Ok(());
+    for step in undo.into_iter().rev() {
+        let put_back = match step {
+            Undo::Param(parameter, value) => write_param(backend, parameter as u32, value),
+            Undo::PostProcessing(index, value) => state::set_pp_param(backend, index, value),
+        };
+        result = result.and(put_back);
+    }
+
+    result
+}
+
+fn write_plan(
+    backend: &dyn CameraBackend,
+    plan: Plan,
+    undo: &mut Vec<Undo>,
+) -> Result<Vec<Change>> {
+    let mut changes = vec![];
+
+    for (parameter, value) in plan.params.into_iter() {
+        if plan.deferred.contains(&parameter) {
+            if let ParameterValue::Int(value) = value {
+                int_in_range(backend, parameter, value).map_err(|e| {
+                    binding_error(format!("profile does not fit this camera: {}", e.message))
+                })?;
+            }
+        }
+
+        // selecting a port or speed can move the speed and gain, which are put back with it
+        let moved: &[Parameter] = match parameter {
+            Parameter::ReadoutPort => &[Parameter::GainIndex, Parameter::SpeedTableIndex],
+            Parameter::SpeedTableIndex => &[Parameter::GainIndex],
+            _ => &[],
+        };
+        let mut put_back = vec![];
+        for moved in moved.iter() {
+            if is_available(backend, *moved as u32)? {
+                let current = read_param(backend, *moved as u32, ParamAttrKind::Current)?;
+                put_back.push(Undo::Param(*moved, current));
+            }
+        }
+
+        let before = read_param(backend, parameter as u32, ParamAttrKind::Current)?;
+        write_param(backend, parameter as u32, value)?;
+        undo.extend(put_back);
+        undo.push(Undo::Param(parameter, before.clone()));
+        let after = read_param(backend, parameter as u32, ParamAttrKind::Current)?;
+
+        let (before, after) = (before.to_string(), after.to_string());
+        if before != after {
+            changes.push(Change {
+                setting: format!("{:?}", parameter),
+                before,
+                after,
+            });
+        }
+    }
+
+    if plan.post_processing.is_empty() {
+        return Ok(changes);
+    }
+
+    let find = |values: &[PostProcessingValue], planned: &PostProcessingValue| {
+        values
+            .iter()
+            .find(|v| v.feature == planned.feature && v.param == planned.param)
+            .map(|v| v.value)
+    };
+    let before = state::get_post_processing(backend)?;
+    for (value, index) in plan.post_processing.iter() {
+        state::set_pp_param(backend, *index, value.value)?;
+        if let Some(previous) = find(&before, value) {
+            undo.push(Undo::PostProcessing(*index, previous));
+        }
+    }
+    let after = state::get_post_processing(backend)?;
+
+    for (planned, _) in plan.post_processing.iter() {
+        let value = |values| {
+            find(values, planned)
+                .map(|v| v.to_string())
+                .unwrap_or_default()
+        };
+        let (before, after) = (value(&before), value(&after));
+        if before != after {
+            changes.push(Change {
+                setting: format!("{}/{}", planned.feature, planned.param),
+                before,
+                after,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+// Writes the camera settings of a profile, returning what changed. If a write fails the ones
+// before it are undone, so the camera is left as it was. The acquisition settings (roi, binning,
+// exposure and trigger) are only checked here; Camera keeps them for sequence_config.
+pub(super) fn apply(backend: &dyn CameraBackend, profile: &Profile) -> Result<Vec<Change>> {
+    let plan = plan(backend, profile)?;
+    let mut written = vec![];
+
+    match write_plan(backend, plan, &mut written) {
+        Ok(changes) => Ok(changes),
+        Err(mut e) => {
+            e.message = match undo(backend, written) {
+                Ok(()) => format!(
+                    "{}; the settings written before it were put back",
+                    e.message
+                ),
+                Err(undo) => format!(
+                    "{}; putting back the settings written before it failed too: {}",
+                    e.message, undo.message
+                ),
+            };
+            Err(e)
+        }
+    }
+}
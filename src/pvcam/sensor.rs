@@ -0,0 +1,286 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::Region;
+
+// Where each pixel's signal comes from, in photoelectrons per second, by sensor column and row
+type Scene = Arc<dyn Fn(u16, u16) -> f64 + Send + Sync>;
+
+// What the camera is set to when a frame is exposed; SimulatedCamera fills this in from
+// PARAM_GAIN_INDEX, PARAM_TEMP and PARAM_BIT_DEPTH
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SensorConditions {
+    pub exposure: Duration,
+    // 1 based, like PARAM_GAIN_INDEX
+    pub gain_index: i32,
+    // degrees C; PARAM_TEMP is in hundredths of that
+    pub temperature: f64,
+    pub bit_depth: u16,
+    // frames with the same number and seed get the same noise
+    pub frame: u32,
+}
+
+// A model of how a sensor turns light into ADUs, for frames with the noise and defects analysis
+// code has to cope with:
+//
+//   photoelectrons  Poisson(scene * exposure), clipped to the full well
+//   dark current    Poisson(dark rate * exposure), the rate doubling every `doubling` degrees
+//                   and far higher on hot pixels
+//   binning         the charge of every binned pixel summed before one read
+//   readout         bias + (charge + Normal(0, read noise)) / conversion gain of the gain index
+//
+// Noise comes from the seed, the frame number and the pixel's place on the sensor only, so a
+// frame renders the same for a given seed whatever Regions it is read through.
+#[derive(Clone)]
+pub struct SensorModel {
+    seed: u64,
+    full_well: f64,
+    bias: f64,
+    // e-/ADU for each gain index, the first being index 1
+    gains: Vec<f64>,
+    read_noise: f64,
+    // e-/pixel/s at `reference` degrees C
+    dark_current: f64,
+    reference: f64,
+    doubling: f64,
+    hot_fraction: f64,
+    hot_current: f64,
+    scene: Scene,
+}
+
+impl fmt::Debug for SensorModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SensorModel")
+            .field("seed", &self.seed)
+            .field("full_well", &self.full_well)
+            .field("bias", &self.bias)
+            .field("gains", &self.gains)
+            .field("read_noise", &self.read_noise)
+            .field("dark_current", &self.dark_current)
+            .field("reference", &self.reference)
+            .field("doubling", &self.doubling)
+            .field("hot_fraction", &self.hot_fraction)
+            .field("hot_current", &self.hot_current)
+            .finish()
+    }
+}
+
+impl Default for SensorModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SensorModel {
+    // A cooled scientific CMOS sensor lit evenly with 100 e-/pixel/s
+    pub fn new() -> Self {
+        SensorModel {
+            seed: 0,
+            full_well: 30_000.0,
+            bias: 100.0,
+            gains: vec![1.0, 0.5],
+            read_noise: 1.5,
+            dark_current: 0.5,
+            reference: 25.0,
+            doubling: 7.0,
+            hot_fraction: 1e-4,
+            hot_current: 50.0,
+            scene: Arc::new(|_, _| 100.0),
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // in electrons
+    pub fn full_well(mut self, full_well: f64) -> Self {
+        self.full_well = full_well;
+        self
+    }
+
+    // in ADU, what a pixel reads with no charge; PARAM_ADC_OFFSET on a camera
+    pub fn bias(mut self, bias: f64) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    // e-/ADU for gain index 1, 2, ...; an index past the end uses the last
+    pub fn gains(mut self, gains: Vec<f64>) -> Self {
+        self.gains = gains;
+        self
+    }
+
+    // in electrons rms
+    pub fn read_noise(mut self, read_noise: f64) -> Self {
+        self.read_noise = read_noise;
+        self
+    }
+
+    // `rate` e-/pixel/s at `reference` degrees C, doubling every `doubling` degrees warmer
+    pub fn dark_current(mut self, rate: f64, reference: f64, doubling: f64) -> Self {
+        self.dark_current = rate;
+        self.reference = reference;
+        self.doubling = doubling;
+        self
+    }
+
+    // `fraction` of the pixels gather `rate` e-/pixel/s at the reference temperature instead
+    pub fn hot_pixels(mut self, fraction: f64, rate: f64) -> Self {
+        self.hot_fraction = fraction;
+        self.hot_current = rate;
+        self
+    }
+
+    // the same photoelectrons per pixel per second everywhere
+    pub fn illumination(self, flux: f64) -> Self {
+        self.scene(move |_, _| flux)
+    }
+
+    // photoelectrons per second for the pixel at sensor column and row
+    pub fn scene<F>(mut self, scene: F) -> Self
+    where
+        F: Fn(u16, u16) -> f64 + Send + Sync + 'static,
+    {
+        self.scene = Arc::new(scene);
+        self
+    }
+
+    fn gain(&self, gain_index: i32) -> f64 {
+        let i = (gain_index.max(1) as usize - 1).min(self.gains.len().saturating_sub(1));
+        self.gains.get(i).copied().unwrap_or(1.0)
+    }
+
+    fn is_hot(&self, s: u16, p: u16) -> bool {
+        // fixed for the sensor, not per frame
+        let hash = mix(&[self.seed, HOT_PIXELS, s as u64, p as u64]);
+        unit(hash) < self.hot_fraction
+    }
+
+    // Charge the pixel at s, p gathers over the exposure
+    fn charge(&self, s: u16, p: u16, conditions: &SensorConditions) -> f64 {
+        let seconds = conditions.exposure.as_secs_f64();
+        let mut rng = Rng(mix(&[
+            self.seed,
+            CHARGE,
+            conditions.frame as u64,
+            s as u64,
+            p as u64,
+        ]));
+
+        let dark = match self.is_hot(s, p) {
+            true => self.hot_current,
+            false => self.dark_current,
+        } * 2f64.powf((conditions.temperature - self.reference) / self.doubling);
+        let light = (self.scene)(s, p).max(0.0);
+
+        let charge = rng.poisson(light * seconds) + rng.poisson(dark * seconds);
+        charge.min(self.full_well)
+    }
+
+    // Renders one frame read out through `regions`, one region after the other as a camera
+    // fills its buffer
+    pub fn render(&self, regions: &[Region], conditions: &SensorConditions) -> Vec<u16> {
+        let gain = self.gain(conditions.gain_index);
+        let max = ((1u32 << conditions.bit_depth.min(16)) - 1) as f64;
+
+        let mut frame = Vec::with_capacity(regions.iter().map(|r| r.width() * r.height()).sum());
+        for region in regions.iter() {
+            for row in 0..region.height() {
+                for col in 0..region.width() {
+                    // widened: a binned pixel ending at column or row 65535 ends past what u16 holds
+                    let s = region.s1 as u32 + col as u32 * region.sbin as u32;
+                    let p = region.p1 as u32 + row as u32 * region.pbin as u32;
+
+                    let mut charge = 0.0;
+                    for p in p..p + region.pbin as u32 {
+                        for s in s..s + region.sbin as u32 {
+                            charge += self.charge(s as u16, p as u16, conditions);
+                        }
+                    }
+
+                    // one read for the whole binned pixel
+                    let mut rng = Rng(mix(&[
+                        self.seed,
+                        READ,
+                        conditions.frame as u64,
+                        s as u64,
+                        p as u64,
+                        region.sbin as u64,
+                        region.pbin as u64,
+                    ]));
+                    let electrons = charge + rng.normal() * self.read_noise;
+                    let adu = (self.bias + electrons / gain).round();
+                    frame.push(adu.clamp(0.0, max) as u16);
+                }
+            }
+        }
+
+        frame
+    }
+}
+
+// keep the streams of the different uses of the seed apart
+const HOT_PIXELS: u64 = 1;
+const CHARGE: u64 = 2;
+const READ: u64 = 3;
+
+// splitmix64's finaliser
+fn scramble(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn mix(values: &[u64]) -> u64 {
+    values.iter().fold(0, |hash, v| {
+        scramble(hash ^ v.wrapping_add(0x9e37_79b9_7f4a_7c15))
+    })
+}
+
+// uniform in [0, 1) from the top 53 bits
+fn unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// splitmix64; small, and good enough for noise
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        scramble(self.0)
+    }
+
+    // uniform in (0, 1], so it can be passed to ln
+    fn uniform(&mut self) -> f64 {
+        1.0 - unit(self.next())
+    }
+
+    // standard normal, Box-Muller
+    fn normal(&mut self) -> f64 {
+        let (u1, u2) = (self.uniform(), self.uniform());
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+
+    // Knuth's method for small means, the normal approximation above that
+    fn poisson(&mut self, mean: f64) -> f64 {
+        if mean <= 0.0 {
+            return 0.0;
+        }
+        if mean > 30.0 {
+            return (mean + mean.sqrt() * self.normal()).round().max(0.0);
+        }
+
+        let limit = (-mean).exp();
+        let mut k = 0.0;
+        let mut product = self.uniform();
+        while product > limit {
+            k += 1.0;
+            product *= self.uniform();
+        }
+        k
+    }
+}
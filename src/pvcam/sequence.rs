@@ -0,0 +1,269 @@
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::backend::{CameraBackend, EofCallback, FrameInfo};
+use super::{
+    internal, Error, ErrorKind, ExposureMode, ParamAttrKind, ParameterValue, Region, Result,
+};
+
+// Describes a sequence acquisition: the regions to read out, the exposure mode and the
+// exposure time of every frame.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SequenceConfig {
+    regions: Vec<Region>,
+    exp_mode: ExposureMode,
+    exposures: Vec<Duration>,
+}
+
+impl SequenceConfig {
+    pub fn new(regions: Vec<Region>, frames: u16, exposure: Duration) -> Self {
+        SequenceConfig {
+            regions,
+            exp_mode: ExposureMode::Timed,
+            exposures: vec![exposure; frames as usize],
+        }
+    }
+
+    pub fn exp_mode(mut self, exp_mode: ExposureMode) -> Self {
+        self.exp_mode = exp_mode;
+        self
+    }
+
+    // Arms the sequence in VARIABLE_TIMED_MODE with one frame per exposure. PARAM_EXP_TIME is
+    // updated from the end-of-frame callback, so pl_exp_setup_seq only runs once. Exposures are
+    // whole milliseconds of at most 65535, as PARAM_EXP_TIME is, and there are at most 65535.
+    pub fn variable_exposures(mut self, exposures: Vec<Duration>) -> Self {
+        self.exp_mode = ExposureMode::VariableTimed;
+        self.exposures = exposures;
+        self
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn frames(&self) -> usize {
+        self.exposures.len()
+    }
+
+    pub fn exposure_mode(&self) -> ExposureMode {
+        self.exp_mode
+    }
+
+    pub fn exposures(&self) -> &[Duration] {
+        &self.exposures
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frame {
+    // FRAME_INFO.FrameNr, 1 based
+    pub number: u32,
+    // the PARAM_EXP_TIME read back from the camera for the frame; the configured exposure in
+    // modes other than VARIABLE_TIMED_MODE
+    pub exposure: Duration,
+    // FRAME_INFO.TimeStamp at end of frame; None if the callback did not report the frame
+    pub timestamp: Option<i64>,
+    pub regions: Vec<Region>,
+    // pixels of every region, one region after the other
+    pub data: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sequence {
+    pub regions: Vec<Region>,
+    pub frames: Vec<Frame>,
+}
+
+struct EofState {
+    // exposure the camera reported for each frame so far, index 0 is read before the sequence
+    // starts
+    applied: Vec<Duration>,
+    frames: Vec<Option<FrameInfo>>,
+}
+
+fn exposure_error(exposure: Duration, why: &str) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message: format!("{:?} {}", exposure, why),
+    }
+}
+
+// pl_exp_setup_seq takes the exposure in milliseconds, as a uns32; anything finer would be cut
+// off rather than rounded, so it is refused
+fn exposure_ms(exposure: Duration) -> Result<u32> {
+    let ms = u32::try_from(exposure.as_millis())
+        .map_err(|_| exposure_error(exposure, "does not fit in pl_exp_setup_seq's exposure"))?;
+    match Duration::from_millis(ms as u64) == exposure {
+        true => Ok(ms),
+        false => Err(exposure_error(
+            exposure,
+            "is not whole milliseconds, which is what an exposure is in",
+        )),
+    }
+}
+
+// VARIABLE_TIMED_MODE writes each exposure to PARAM_EXP_TIME, which is a uns16
+fn param_exposure_ms(exposure: Duration) -> Result<u16> {
+    u16::try_from(exposure_ms(exposure)?)
+        .map_err(|_| exposure_error(exposure, "does not fit in PARAM_EXP_TIME"))
+}
+
+// The PARAM_EXP_TIME the camera holds, which is what it exposes the next frame for
+fn read_exposure(backend: &dyn CameraBackend) -> Option<Duration> {
+    match backend.get_param(internal::PARAM_EXP_TIME, ParamAttrKind::Current) {
+        Ok(ParameterValue::Int(ms)) if ms >= 0 => Some(Duration::from_millis(ms as u64)),
+        Ok(ParameterValue::Long(ms)) if ms >= 0 => Some(Duration::from_millis(ms as u64)),
+        _ => None,
+    }
+}
+
+// Records every frame and, in VARIABLE_TIMED_MODE, queues the exposure of the next one and
+// reads back what the camera made of it. A refused exposure leaves the last one in effect.
+fn on_eof(state: Arc<Mutex<EofState>>, variable: bool, exposures: Vec<Duration>) -> EofCallback {
+    Box::new(move |info, backend| {
+        let mut state = match state.lock() {
+            Ok(s) => s,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        state.frames.push(info);
+
+        if !variable {
+            return;
+        }
+
+        let next = state.frames.len();
+        if let Some(&exposure) = exposures.get(next) {
+            let set = match param_exposure_ms(exposure) {
+                Ok(ms) => backend
+                    .set_param(internal::PARAM_EXP_TIME, ParameterValue::Int(ms as i32))
+                    .is_ok(),
+                Err(_) => false,
+            };
+            let current = state.applied.last().copied().unwrap_or(exposure);
+            let applied = match (read_exposure(backend), set) {
+                (Some(read), _) => read,
+                (None, true) => exposure,
+                (None, false) => current,
+            };
+            state.applied.push(applied);
+        }
+    })
+}
+
+// A sequence that has been set up on a camera along with the buffer it reads out into
+pub(super) struct Acquisition {
+    config: SequenceConfig,
+    buffer: Vec<u16>,
+    state: Arc<Mutex<EofState>>,
+}
+
+impl Acquisition {
+    // Sets the sequence up and registers the end-of-frame callback; the camera deregisters it
+    // once the acquisition is finished or dropped
+    pub(super) fn setup(backend: &dyn CameraBackend, config: &SequenceConfig) -> Result<Self> {
+        if config.exposures.is_empty() {
+            return Err(Error {
+                kind: ErrorKind::Binding,
+                code: -1,
+                message: "a sequence needs at least one frame".to_owned(),
+            });
+        }
+
+        let frames = u16::try_from(config.frames()).map_err(|_| Error {
+            kind: ErrorKind::Binding,
+            code: -1,
+            message: format!(
+                "{} frames do not fit in a sequence, which has at most {}",
+                config.frames(),
+                u16::MAX
+            ),
+        })?;
+
+        // validate up front rather than from inside the callback
+        let variable = config.exp_mode == ExposureMode::VariableTimed;
+        for exposure in config.exposures.iter() {
+            match variable {
+                true => param_exposure_ms(*exposure).map(u32::from)?,
+                false => exposure_ms(*exposure)?,
+            };
+        }
+        let first = config.exposures[0];
+        let mut applied = first;
+        if variable {
+            backend.set_param(
+                internal::PARAM_EXP_TIME,
+                ParameterValue::Int(param_exposure_ms(first)? as i32),
+            )?;
+            applied = read_exposure(backend).unwrap_or(first);
+        }
+
+        let buf_size = backend.exp_setup_seq(
+            frames,
+            &config.regions,
+            config.exp_mode,
+            exposure_ms(first)?,
+        )?;
+
+        let state = Arc::new(Mutex::new(EofState {
+            applied: vec![applied],
+            frames: vec![],
+        }));
+        backend.register_eof(on_eof(state.clone(), variable, config.exposures.clone()))?;
+
+        Ok(Acquisition {
+            config: config.clone(),
+            buffer: vec![0; buf_size as usize / 2],
+            state,
+        })
+    }
+
+    pub(super) fn exp_mode(&self) -> ExposureMode {
+        self.config.exp_mode
+    }
+
+    pub(super) fn buffer_ptr(&mut self) -> *mut u16 {
+        self.buffer.as_mut_ptr()
+    }
+
+    // Splits the read out buffer into frames tagged with what the callback saw
+    pub(super) fn finish(mut self) -> Sequence {
+        let buffer = std::mem::take(&mut self.buffer);
+        let n_frames = self.config.frames();
+        // chunks_exact panics on 0, an empty buffer still yields no frames with 1
+        let frame_len = std::cmp::max(buffer.len() / n_frames, 1);
+
+        let state = match self.state.lock() {
+            Ok(s) => s,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let frames = buffer
+            .chunks_exact(frame_len)
+            .enumerate()
+            .map(|(i, data)| {
+                let info = state.frames.get(i).copied().flatten();
+                Frame {
+                    number: info.map(|f| f.number).unwrap_or(i as u32 + 1),
+                    exposure: match state.applied.get(i) {
+                        Some(exposure) => *exposure,
+                        None => self.config.exposures[i],
+                    },
+                    timestamp: info.map(|f| f.timestamp),
+                    regions: self.config.regions.clone(),
+                    data: data.to_vec(),
+                }
+            })
+            .collect();
+
+        Sequence {
+            regions: self.config.regions.clone(),
+            frames,
+        }
+    }
+}
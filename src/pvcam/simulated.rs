@@ -0,0 +1,1339 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::backend::{CameraBackend, EofCallback, FrameInfo};
+#[cfg(feature = "describe")]
+use super::describe::{self, CameraDescription};
+use super::sensor::{SensorConditions, SensorModel};
+#[cfg(pvcam_3_9)]
+use super::trigger_flags;
+use super::{
+    internal, resolve_enum, CaptureStatus, Error, ErrorKind, ExposureMode, PVEnum, ParamAttrKind,
+    Parameter, ParameterAccess, ParameterValue, Region, Result,
+};
+
+const SENSOR_SIZE: u16 = 1024;
+const READOUT_TIME: Duration = Duration::from_millis(10);
+// the cooler closes about two thirds of the gap to PARAM_TEMP_SETPOINT in this long
+const COOLING_TIME: Duration = Duration::from_secs(5);
+// PARAM_ROI_COUNT's maximum
+const MAX_REGIONS: usize = 15;
+
+// Stands in for what libpvcam would report through pl_error_code
+fn sim_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Pvcam,
+        code: -1,
+        message,
+    }
+}
+
+// what PvcamBackend refuses itself, before PVCam is called
+fn binding_error(message: String) -> Error {
+    Error::new(ErrorKind::Binding, message)
+}
+
+// The values an integer ATTR_TYPE holds; PvcamBackend refuses to write others
+fn type_range(code: u32) -> Option<(i64, i64)> {
+    match code {
+        internal::TYPE_INT8 => Some((i8::MIN as i64, i8::MAX as i64)),
+        internal::TYPE_UNS8 => Some((0, u8::MAX as i64)),
+        internal::TYPE_INT16 => Some((i16::MIN as i64, i16::MAX as i64)),
+        internal::TYPE_UNS16 => Some((0, u16::MAX as i64)),
+        internal::TYPE_INT32 => Some((i32::MIN as i64, i32::MAX as i64)),
+        internal::TYPE_UNS32 => Some((0, u32::MAX as i64)),
+        internal::TYPE_INT64 => Some((i64::MIN, i64::MAX)),
+        internal::TYPE_UNS64 => Some((0, i64::MAX)),
+        _ => None,
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+// A parameter of a SimulatedCamera: its value, its range or options, and its access
+#[derive(Debug, Clone)]
+pub struct SimParam {
+    value: SimValue,
+    access: ParameterAccess,
+    // the ATTR_TYPE reported instead of the one the value implies, e.g. a described camera's uns16
+    attr_type: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+enum SimValue {
+    // reported as an Int, or a Long like PVCam's uns32 and 64 bit parameters
+    Int {
+        value: i64,
+        min: i64,
+        max: i64,
+        long: bool,
+    },
+    Float {
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+    Bool(bool),
+    String(String),
+    // (value, name) options and the selected value
+    Enum {
+        options: Vec<(i32, String)>,
+        value: i32,
+    },
+}
+
+fn pick<T>(attr: ParamAttrKind, value: T, min: T, max: T) -> T {
+    match attr {
+        ParamAttrKind::Min => min,
+        ParamAttrKind::Max => max,
+        _ => value,
+    }
+}
+
+impl SimParam {
+    fn new(value: SimValue) -> Self {
+        SimParam {
+            value,
+            access: ParameterAccess::ReadWrite,
+            attr_type: None,
+        }
+    }
+
+    pub fn int(value: i32, min: i32, max: i32) -> Self {
+        Self::new(SimValue::Int {
+            value: value as i64,
+            min: min as i64,
+            max: max as i64,
+            long: false,
+        })
+    }
+
+    pub fn long(value: i64, min: i64, max: i64) -> Self {
+        Self::new(SimValue::Int {
+            value,
+            min,
+            max,
+            long: true,
+        })
+    }
+
+    pub fn float(value: f64, min: f64, max: f64) -> Self {
+        Self::new(SimValue::Float { value, min, max })
+    }
+
+    pub fn boolean(value: bool) -> Self {
+        Self::new(SimValue::Bool(value))
+    }
+
+    // read only, as PVCam's string parameters are
+    pub fn string(value: &str) -> Self {
+        Self::new(SimValue::String(value.to_owned())).read_only()
+    }
+
+    // `options` are (value, name) pairs in the order the camera lists them
+    pub fn enumeration(options: &[(i32, &str)], value: i32) -> Self {
+        Self::new(SimValue::Enum {
+            options: options
+                .iter()
+                .map(|(value, name)| (*value, name.to_string()))
+                .collect(),
+            value,
+        })
+    }
+
+    pub fn access(mut self, access: ParameterAccess) -> Self {
+        self.access = access;
+        self
+    }
+
+    pub fn read_only(self) -> Self {
+        self.access(ParameterAccess::ReadOnly)
+    }
+
+    #[cfg(feature = "describe")]
+    pub(super) fn attr_type(mut self, code: u32) -> Self {
+        self.attr_type = Some(code);
+        self
+    }
+
+    fn type_code(&self) -> u32 {
+        if let Some(code) = self.attr_type {
+            return code;
+        }
+        match self.value {
+            SimValue::Int { long: false, .. } => internal::TYPE_INT32,
+            SimValue::Int { long: true, .. } => internal::TYPE_INT64,
+            SimValue::Float { .. } => internal::TYPE_FLT64,
+            SimValue::Bool(_) => internal::TYPE_BOOLEAN,
+            SimValue::String(_) => internal::TYPE_CHAR_PTR,
+            SimValue::Enum { .. } => internal::TYPE_ENUM,
+        }
+    }
+
+    fn enums(&self, param_id: u32) -> Result<Vec<PVEnum>> {
+        match &self.value {
+            SimValue::Enum { options, .. } => Ok(options
+                .iter()
+                .enumerate()
+                .map(|(idx, (value, name))| PVEnum {
+                    idx: idx as u32,
+                    value: *value,
+                    name: name.clone(),
+                })
+                .collect()),
+            _ => Err(sim_error(format!("parameter {} is not an enum", param_id))),
+        }
+    }
+
+    fn get(&self, param_id: u32, attr: ParamAttrKind) -> Result<ParameterValue> {
+        let readable = matches!(
+            self.access,
+            ParameterAccess::ReadOnly | ParameterAccess::ReadWrite
+        );
+
+        match attr {
+            ParamAttrKind::Available => Ok(ParameterValue::Bool(true)),
+            ParamAttrKind::Access => Ok(ParameterValue::Int(self.access as i32)),
+            ParamAttrKind::AttrType => Ok(ParameterValue::Long(self.type_code() as i64)),
+            ParamAttrKind::Count => Ok(ParameterValue::Long(match &self.value {
+                SimValue::Int { min, max, .. } => max - min + 1,
+                SimValue::Float { .. } => 0,
+                SimValue::Bool(_) => 2,
+                // the buffer length including the terminator
+                SimValue::String(v) => v.len() as i64 + 1,
+                SimValue::Enum { options, .. } => options.len() as i64,
+            })),
+            ParamAttrKind::Current if !readable => {
+                Err(sim_error(format!("parameter {} cannot be read", param_id)))
+            }
+            ParamAttrKind::Current | ParamAttrKind::Min | ParamAttrKind::Max => {
+                Ok(match &self.value {
+                    SimValue::Int {
+                        value,
+                        min,
+                        max,
+                        long,
+                    } => {
+                        let v = pick(attr, *value, *min, *max);
+                        match long {
+                            true => ParameterValue::Long(v),
+                            false => ParameterValue::Int(v as i32),
+                        }
+                    }
+                    SimValue::Float { value, min, max } => {
+                        ParameterValue::Float(pick(attr, *value, *min, *max))
+                    }
+                    SimValue::Bool(v) => ParameterValue::Bool(pick(attr, *v, false, true)),
+                    SimValue::String(v) => ParameterValue::String(v.clone()),
+                    SimValue::Enum { options, value } => {
+                        let values = options.iter().map(|(v, _)| *v);
+                        let selected = pick(
+                            attr,
+                            Some(*value),
+                            values.clone().min(),
+                            values.clone().max(),
+                        );
+                        let idx = options
+                            .iter()
+                            .position(|(v, _)| Some(*v) == selected)
+                            .unwrap_or(0);
+                        ParameterValue::Enum(idx as u32, self.enums(param_id)?)
+                    }
+                })
+            }
+        }
+    }
+
+    // whether PvcamBackend would write `value` to a parameter of this type at all
+    fn accepts(&self, value: &ParameterValue) -> bool {
+        matches!(
+            (&self.value, value),
+            (SimValue::Int { .. }, ParameterValue::Int(_))
+                | (SimValue::Int { .. }, ParameterValue::Long(_))
+                | (SimValue::Float { .. }, ParameterValue::Float(_))
+                | (SimValue::Bool(_), ParameterValue::Bool(_))
+                | (SimValue::Enum { .. }, ParameterValue::Enum(..))
+        )
+    }
+
+    // Fails with the ErrorKind PvcamBackend would: Binding for what it refuses itself, a value
+    // of the wrong type or too wide for the parameter, and Pvcam for what PVCam refuses
+    fn set(&mut self, param_id: u32, new: ParameterValue) -> Result<()> {
+        if !self.accepts(&new) {
+            return Err(binding_error(format!(
+                "parameter {} cannot be set to {}",
+                param_id, new
+            )));
+        }
+        let new = match new {
+            ParameterValue::Int(v) => ParameterValue::Long(v as i64),
+            new => new,
+        };
+        if let (Some((lowest, highest)), ParameterValue::Long(v)) =
+            (type_range(self.type_code()), &new)
+        {
+            if v < &lowest || v > &highest {
+                return Err(binding_error(format!(
+                    "{} cannot fit in parameter {}, whose type holds {}..={}",
+                    v, param_id, lowest, highest
+                )));
+            }
+        }
+
+        if !matches!(
+            self.access,
+            ParameterAccess::ReadWrite | ParameterAccess::WriteOnly
+        ) {
+            return Err(sim_error(format!("parameter {} is read only", param_id)));
+        }
+
+        let out_of_range = |v: &dyn std::fmt::Display, min: &dyn std::fmt::Display, max| {
+            sim_error(format!(
+                "{} is outside parameter {} range {}..={}",
+                v, param_id, min, max
+            ))
+        };
+
+        match (&mut self.value, new) {
+            (
+                SimValue::Int {
+                    value, min, max, ..
+                },
+                ParameterValue::Long(v),
+            ) => {
+                if v < *min || v > *max {
+                    return Err(out_of_range(&v, min, max as &dyn std::fmt::Display));
+                }
+                *value = v;
+            }
+            (SimValue::Float { value, min, max }, ParameterValue::Float(v)) => {
+                if v < *min || v > *max {
+                    return Err(out_of_range(&v, min, max as &dyn std::fmt::Display));
+                }
+                *value = v;
+            }
+            (SimValue::Bool(value), ParameterValue::Bool(v)) => *value = v,
+            (SimValue::Enum { options, value }, ParameterValue::Enum(idx, enums)) => {
+                // like PVCam, the option's value is what gets written
+                let v = match enums.get(idx as usize) {
+                    Some(e) => e.value,
+                    None => idx as i32,
+                };
+                if !options.iter().any(|(option, _)| *option == v) {
+                    return Err(sim_error(format!(
+                        "{} is not an option of parameter {}",
+                        v, param_id
+                    )));
+                }
+                *value = v;
+            }
+            (_, other) => {
+                return Err(binding_error(format!(
+                    "parameter {} cannot be set to {}",
+                    param_id, other
+                )))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(not(pvcam_3_9), allow(unused_mut))]
+fn default_params() -> BTreeMap<u32, SimParam> {
+    let mut exposure_modes = vec![
+        (ExposureMode::Timed as i32, "Timed"),
+        (ExposureMode::VariableTimed as i32, "Variable Timed"),
+        (ExposureMode::ExtTrigInternal as i32, "Internal Trigger"),
+        (ExposureMode::ExtTrigTrigFirst as i32, "Trigger First"),
+        (ExposureMode::ExtTrigEdgeRising as i32, "Edge Trigger"),
+    ];
+    #[cfg(pvcam_3_9)]
+    exposure_modes.extend_from_slice(&[
+        (
+            ExposureMode::ExtTrigSoftwareFirst as i32,
+            "Software Trigger First",
+        ),
+        (
+            ExposureMode::ExtTrigSoftwareEdge as i32,
+            "Software Trigger Edge",
+        ),
+    ]);
+    let binning = [(1, "1"), (2, "2"), (4, "4")];
+    let size = SENSOR_SIZE as i32;
+
+    vec![
+        (Parameter::ChipName, SimParam::string("SimSensor")),
+        (Parameter::ProductName, SimParam::string("SimulatedCamera")),
+        (Parameter::VendorName, SimParam::string("libpvcam-sys")),
+        (Parameter::CameraSerial, SimParam::string("SIM0001")),
+        (Parameter::CameraPartNumber, SimParam::string("SIM-1")),
+        (
+            Parameter::FirmwareVersion,
+            SimParam::int(0x0100, 0, 0xffff).read_only(),
+        ),
+        (
+            Parameter::SensorSerialSize,
+            SimParam::int(size, size, size).read_only(),
+        ),
+        (
+            Parameter::SensorParallelSize,
+            SimParam::int(size, size, size).read_only(),
+        ),
+        (
+            Parameter::PixelSerialSize,
+            SimParam::int(6500, 6500, 6500).read_only(),
+        ),
+        (
+            Parameter::PixelParallelSize,
+            SimParam::int(6500, 6500, 6500).read_only(),
+        ),
+        (Parameter::BitDepth, SimParam::int(16, 16, 16).read_only()),
+        (
+            Parameter::ReadoutPort,
+            SimParam::enumeration(&[(0, "Sensitivity"), (1, "Speed")], 0),
+        ),
+        (Parameter::SpeedTableIndex, SimParam::int(0, 0, 1)),
+        (Parameter::GainIndex, SimParam::int(1, 1, 2)),
+        (
+            Parameter::AdcOffset,
+            SimParam::int(100, 100, 100).read_only(),
+        ),
+        (
+            Parameter::PMode,
+            SimParam::enumeration(&[(0, "Normal"), (1, "Frame Transfer")], 0),
+        ),
+        (
+            Parameter::ClearMode,
+            SimParam::enumeration(&[(0, "Never"), (1, "Pre-Exposure"), (2, "Pre-Sequence")], 1),
+        ),
+        (Parameter::ClearCycles, SimParam::int(2, 0, 16)),
+        (
+            Parameter::ShutterOpenMode,
+            SimParam::enumeration(&[(0, "Never"), (1, "Pre-Exposure"), (2, "Pre-Sequence")], 1),
+        ),
+        (
+            Parameter::ExposureResolution,
+            SimParam::enumeration(&[(0, "One Millisecond")], 0),
+        ),
+        (Parameter::ExposureResolutionIndex, SimParam::int(0, 0, 0)),
+        (
+            Parameter::ExposureMode,
+            SimParam::enumeration(&exposure_modes, ExposureMode::Timed as i32).read_only(),
+        ),
+        (
+            Parameter::ExposeOutMode,
+            SimParam::enumeration(
+                &[
+                    (0, "First Row"),
+                    (1, "All Rows"),
+                    (2, "Any Row"),
+                    (3, "Rolling Shutter"),
+                ],
+                0,
+            ),
+        ),
+        (Parameter::ExposureTime, SimParam::long(10, 0, 3_600_000)),
+        (Parameter::BinningSerial, SimParam::enumeration(&binning, 1)),
+        (
+            Parameter::BinningParallel,
+            SimParam::enumeration(&binning, 1),
+        ),
+        (Parameter::MetadataEnabled, SimParam::boolean(false)),
+        (Parameter::CircBuffer, SimParam::boolean(true).read_only()),
+        (
+            Parameter::Temperature,
+            SimParam::int(-1000, -2500, 2500).read_only(),
+        ),
+        (
+            Parameter::TemperatureSetpoint,
+            SimParam::int(-1000, -2500, 2500),
+        ),
+        (
+            Parameter::FanSpeedSetpoint,
+            SimParam::enumeration(&[(0, "High"), (1, "Medium"), (2, "Low"), (3, "Off")], 0),
+        ),
+        (Parameter::PixelTime, SimParam::int(10, 10, 10).read_only()),
+        (Parameter::ReadoutTime, readout_time_param(READOUT_TIME)),
+        (Parameter::ClearingTime, SimParam::long(0, 0, 0).read_only()),
+        (
+            Parameter::RoiCount,
+            SimParam::int(1, 1, MAX_REGIONS as i32).read_only(),
+        ),
+    ]
+    .into_iter()
+    .map(|(parameter, param)| (parameter as u32, param))
+    .collect()
+}
+
+// PARAM_READOUT_TIME is in microseconds
+fn readout_time_param(readout: Duration) -> SimParam {
+    let us = readout.as_micros() as i64;
+    SimParam::long(us, us, us).read_only()
+}
+
+// What selecting one PARAM_SPDTAB_INDEX of a port sets, see SimulatedCamera::speed_table
+#[derive(Debug, Clone)]
+pub(super) struct SimSpeed {
+    pub(super) bit_depth: Option<i64>,
+    pub(super) pixel_time: Option<i64>,
+    // the PARAM_GAIN_INDEX range
+    pub(super) gains: Option<(i64, i64)>,
+}
+
+// A post-processing feature: its name and its parameters as (name, value, max), in
+// PARAM_PP_PARAM_INDEX order
+type SimFeature = (String, Vec<(String, u32, u32)>);
+
+// The acquisition exp_setup_seq or exp_setup_cont prepared
+#[derive(Debug, Clone)]
+struct SimSetup {
+    regions: Vec<Region>,
+    exp_mode: ExposureMode,
+    exposure_ms: u32,
+    // None for a continuous acquisition
+    frames: Option<u32>,
+    // pixels of one frame
+    frame_len: usize,
+}
+
+// The buffer the worker writes frames into; the caller of exp_start_seq/cont keeps it valid
+#[derive(Debug, Clone, Copy)]
+struct BufferPtr(*mut u16);
+
+unsafe impl Send for BufferPtr {}
+
+struct SimState {
+    params: BTreeMap<u32, SimParam>,
+    readout: Duration,
+    time_scale: f64,
+    // renders the frames when set, otherwise they are a ramp
+    model: Option<SensorModel>,
+    // PARAM_READOUT_PORT value => its speeds; the ranges are left alone for ports not in it
+    speed_table: BTreeMap<i32, Vec<SimSpeed>>,
+    // in PARAM_PP_INDEX order
+    post_processing: Vec<SimFeature>,
+    setup: Option<SimSetup>,
+    buffer: Option<BufferPtr>,
+    running: bool,
+    stop: bool,
+    status: CaptureStatus,
+    bytes_arrived: u32,
+    buffer_cnt: u32,
+    // pixel offset and info of the newest frame
+    latest: Option<(usize, FrameInfo)>,
+    // set while the camera is ready for exp_trigger; triggers at any other time are ignored
+    awaiting_trigger: bool,
+    // accepted triggers the worker has yet to act on
+    triggers: u32,
+    eof: Option<EofCallback>,
+    // bumped whenever eof changes so a callback in flight is not put back over its replacement
+    eof_generation: u64,
+    // PARAM_TEMP unrounded and when it was last brought up to date, once the cooler has run
+    cooler: Option<(f64, Instant)>,
+}
+
+struct Shared {
+    state: Mutex<SimState>,
+    changed: Condvar,
+    created: Instant,
+}
+
+// A camera simulated in memory, for running everything built on Camera without PVCam:
+//
+//   let mut camera = Camera::new(SimulatedCamera::new().time_scale(0.0));
+//
+// It starts out with the parameters most PVCam cameras have, which param and without_param
+// change. Frames are produced on a thread of their own after their exposure and readout time,
+// like a real camera's, and software triggers are honoured.
+pub struct SimulatedCamera {
+    shared: Arc<Shared>,
+    // None in the copies handed to EOF callbacks
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Default for SimulatedCamera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedCamera {
+    // A 1024x1024 sensor; small enough that simulated acquisitions stay cheap
+    pub fn new() -> Self {
+        SimulatedCamera {
+            shared: Arc::new(Shared {
+                state: Mutex::new(SimState {
+                    params: default_params(),
+                    readout: READOUT_TIME,
+                    time_scale: 1.0,
+                    model: None,
+                    speed_table: BTreeMap::new(),
+                    post_processing: vec![],
+                    setup: None,
+                    buffer: None,
+                    running: false,
+                    stop: false,
+                    status: CaptureStatus::ReadoutNotActive,
+                    bytes_arrived: 0,
+                    buffer_cnt: 0,
+                    latest: None,
+                    awaiting_trigger: false,
+                    triggers: 0,
+                    eof: None,
+                    eof_generation: 0,
+                    cooler: None,
+                }),
+                changed: Condvar::new(),
+                created: Instant::now(),
+            }),
+            worker: Mutex::new(None),
+        }
+    }
+
+    // A camera with the parameters, access, ranges, options and speed table of a described model
+    // in place of the defaults; what the model does not have is unavailable
+    #[cfg(feature = "describe")]
+    pub fn from_description(description: &CameraDescription) -> Result<Self> {
+        describe::simulate(description)
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        lock(&self.shared.state)
+    }
+
+    // Adds or replaces a parameter
+    pub fn param(self, param_id: u32, param: SimParam) -> Self {
+        let mut state = self.state();
+        state.params.insert(param_id, param);
+        if param_id == Parameter::Temperature as u32 {
+            state.cooler = None;
+        }
+        drop(state);
+        self
+    }
+
+    pub fn without_param(self, param_id: u32) -> Self {
+        self.state().params.remove(&param_id);
+        self
+    }
+
+    pub fn sensor(self, serial: u16, parallel: u16) -> Self {
+        let (serial, parallel) = (serial as i32, parallel as i32);
+        self.param(
+            Parameter::SensorSerialSize as u32,
+            SimParam::int(serial, serial, serial).read_only(),
+        )
+        .param(
+            Parameter::SensorParallelSize as u32,
+            SimParam::int(parallel, parallel, parallel).read_only(),
+        )
+    }
+
+    // The time every frame takes to read out after its exposure
+    pub fn readout_time(self, readout: Duration) -> Self {
+        {
+            let mut state = self.state();
+            state.readout = readout;
+            state
+                .params
+                .insert(Parameter::ReadoutTime as u32, readout_time_param(readout));
+        }
+        self
+    }
+
+    // Renders frames with `model` instead of the ramp, from the PARAM_GAIN_INDEX, PARAM_TEMP and
+    // PARAM_BIT_DEPTH at the time and the frame's exposure
+    pub fn sensor_model(self, model: SensorModel) -> Self {
+        self.state().model = Some(model);
+        self
+    }
+
+    // Has writing PARAM_READOUT_PORT or PARAM_SPDTAB_INDEX change the speed range, the gain
+    // range, the bit depth and the pixel time, as on a camera
+    #[cfg(feature = "describe")]
+    pub(super) fn speed_table(self, table: BTreeMap<i32, Vec<SimSpeed>>) -> Self {
+        {
+            let mut state = self.state();
+            state.speed_table = table;
+            select_speed(&mut state);
+        }
+        self
+    }
+
+    // Adds a post-processing feature with its parameters as (name, value, max), reached through
+    // PARAM_PP_INDEX and PARAM_PP_PARAM_INDEX as on a camera
+    pub fn post_processing(self, feature: &str, params: &[(&str, u32, u32)]) -> Self {
+        {
+            let mut state = self.state();
+            let params = params
+                .iter()
+                .map(|(name, value, max)| (name.to_string(), *value, *max))
+                .collect();
+            state.post_processing.push((feature.to_owned(), params));
+            let features = state.post_processing.len() as i32;
+            state
+                .params
+                .insert(internal::PARAM_PP_INDEX, SimParam::int(0, 0, features - 1));
+            state
+                .params
+                .insert(internal::PARAM_PP_PARAM_INDEX, SimParam::int(0, 0, 0));
+            select_pp_param(&mut state);
+        }
+        self
+    }
+
+    // Scales every simulated delay; 0 produces frames as fast as they can be written
+    pub fn time_scale(self, scale: f64) -> Self {
+        let mut state = self.state();
+        cool(&mut state);
+        state.time_scale = scale.max(0.0);
+        drop(state);
+        self
+    }
+
+    fn sensor_size(state: &SimState) -> (u16, u16) {
+        let size = |parameter: Parameter| match state.params.get(&(parameter as u32)) {
+            Some(SimParam {
+                value: SimValue::Int { value, .. },
+                ..
+            }) => *value as u16,
+            _ => SENSOR_SIZE,
+        };
+
+        (
+            size(Parameter::SensorSerialSize),
+            size(Parameter::SensorParallelSize),
+        )
+    }
+
+    fn setup(
+        &self,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+        frames: Option<u32>,
+    ) -> Result<SimSetup> {
+        self.join_worker();
+        let mut state = self.state();
+        if state.running {
+            return Err(sim_error("an acquisition is in progress".to_owned()));
+        }
+
+        if regions.is_empty() || regions.len() > MAX_REGIONS {
+            return Err(sim_error(format!(
+                "{} regions given, 1 to {} are supported",
+                regions.len(),
+                MAX_REGIONS
+            )));
+        }
+        let (serial, parallel) = Self::sensor_size(&state);
+        for region in regions.iter() {
+            let fits = region.s1 <= region.s2
+                && region.s2 < serial
+                && region.p1 <= region.p2
+                && region.p2 < parallel
+                && region.sbin > 0
+                && region.pbin > 0
+                && region.sbin <= region.s2 - region.s1 + 1
+                && region.pbin <= region.p2 - region.p1 + 1;
+            if !fits {
+                return Err(sim_error(format!(
+                    "{:?} does not fit the {}x{} sensor",
+                    region, serial, parallel
+                )));
+            }
+        }
+
+        if let Some(param) = state.params.get(&(Parameter::ExposureMode as u32)) {
+            let supported = param
+                .enums(Parameter::ExposureMode as u32)?
+                .iter()
+                .any(|e| e.value == exp_mode as i32);
+            if !supported {
+                return Err(sim_error(format!("{:?} is not supported", exp_mode)));
+            }
+        }
+
+        let frame_len: usize = regions.iter().map(|r| r.width() * r.height()).sum();
+        let setup = SimSetup {
+            regions: regions.to_vec(),
+            exp_mode,
+            exposure_ms,
+            frames,
+            frame_len,
+        };
+        state.setup = Some(setup.clone());
+        state.status = CaptureStatus::ReadoutNotActive;
+
+        Ok(setup)
+    }
+
+    fn start(&self, buffer: *mut u16, slots: usize, continuous: bool) -> Result<()> {
+        self.join_worker();
+        let setup = {
+            let mut state = self.state();
+            let setup = match &state.setup {
+                Some(setup) if setup.frames.is_none() == continuous => setup.clone(),
+                _ => return Err(sim_error("the acquisition has not been set up".to_owned())),
+            };
+            if state.running {
+                return Err(sim_error("an acquisition is in progress".to_owned()));
+            }
+
+            state.buffer = Some(BufferPtr(buffer));
+            state.running = true;
+            state.stop = false;
+            state.status = CaptureStatus::ExposureInProgress;
+            state.bytes_arrived = 0;
+            state.buffer_cnt = 0;
+            state.latest = None;
+            // ready for the first trigger as soon as this returns, like a camera
+            state.awaiting_trigger = waits_for_trigger(setup.exp_mode, 0);
+            state.triggers = 0;
+            setup
+        };
+
+        let shared = self.shared.clone();
+        let buffer = BufferPtr(buffer);
+        *lock(&self.worker) = Some(thread::spawn(move || run(shared, setup, buffer, slots)));
+
+        Ok(())
+    }
+
+    // Stops the worker, if any, and waits for it to be gone
+    fn join_worker(&self) {
+        let worker = match lock(&self.worker).take() {
+            Some(worker) => worker,
+            None => return,
+        };
+
+        self.state().stop = true;
+        self.shared.changed.notify_all();
+        let _ = worker.join();
+
+        let mut state = self.state();
+        state.stop = false;
+        if state.running {
+            state.running = false;
+            state.status = CaptureStatus::ReadoutNotActive;
+        }
+        state.buffer = None;
+    }
+}
+
+// Whether frame `number`, 0 based, waits for exp_trigger
+#[cfg_attr(not(pvcam_3_9), allow(unused_variables))]
+fn waits_for_trigger(exp_mode: ExposureMode, number: u32) -> bool {
+    match exp_mode {
+        #[cfg(pvcam_3_9)]
+        ExposureMode::ExtTrigSoftwareFirst => number == 0,
+        #[cfg(pvcam_3_9)]
+        ExposureMode::ExtTrigSoftwareEdge => true,
+        _ => false,
+    }
+}
+
+// Sleeps for `period` unless told to stop first; false if it was
+fn wait_unless_stopped(shared: &Shared, period: Duration) -> bool {
+    let deadline = Instant::now() + period;
+    let mut state = lock(&shared.state);
+    loop {
+        if state.stop {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        state = match shared.changed.wait_timeout(state, deadline - now) {
+            Ok((state, _)) => state,
+            Err(poisoned) => poisoned.into_inner().0,
+        };
+    }
+}
+
+// Blocks until a software trigger arrives; false if told to stop first
+fn wait_for_trigger(shared: &Shared) -> bool {
+    let mut state = lock(&shared.state);
+    if state.triggers == 0 {
+        state.awaiting_trigger = true;
+    }
+    while state.triggers == 0 && !state.stop {
+        state = match shared.changed.wait(state) {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+    }
+    if state.stop {
+        return false;
+    }
+    state.triggers -= 1;
+
+    true
+}
+
+fn int_value(state: &SimState, param_id: u32) -> Option<i64> {
+    match state.params.get(&param_id) {
+        Some(SimParam {
+            value: SimValue::Int { value, .. },
+            ..
+        }) => Some(*value),
+        _ => None,
+    }
+}
+
+fn int_param(state: &SimState, parameter: Parameter) -> Option<i64> {
+    int_value(state, parameter as u32)
+}
+
+fn set_int_value(state: &mut SimState, param_id: u32, current: i64, range: (i64, i64)) {
+    if let Some(SimParam {
+        value: SimValue::Int {
+            value, min, max, ..
+        },
+        ..
+    }) = state.params.get_mut(&param_id)
+    {
+        *value = current;
+        *min = range.0;
+        *max = range.1;
+    }
+}
+
+fn set_int_param(state: &mut SimState, parameter: Parameter, current: i64, range: (i64, i64)) {
+    set_int_value(state, parameter as u32, current, range)
+}
+
+// Brings PARAM_TEMP up to date: it approaches PARAM_TEMP_SETPOINT exponentially over the time
+// passed since, scaled like every other delay
+fn cool(state: &mut SimState) {
+    let (temperature, setpoint) = match (
+        int_param(state, Parameter::Temperature),
+        int_param(state, Parameter::TemperatureSetpoint),
+    ) {
+        (Some(temperature), Some(setpoint)) => (temperature, setpoint as f64),
+        _ => return,
+    };
+    let now = Instant::now();
+    let (current, since) = state.cooler.unwrap_or((temperature as f64, now));
+    let constant = COOLING_TIME.as_secs_f64() * state.time_scale;
+    let current = match constant > 0.0 {
+        true => {
+            let elapsed = now.duration_since(since).as_secs_f64();
+            setpoint + (current - setpoint) * (-elapsed / constant).exp()
+        }
+        false => setpoint,
+    };
+    state.cooler = Some((current, now));
+    if let Some(SimParam {
+        value: SimValue::Int { value, .. },
+        ..
+    }) = state.params.get_mut(&(Parameter::Temperature as u32))
+    {
+        *value = current.round() as i64;
+    }
+}
+
+// Brings the speed and gain ranges, the bit depth and the pixel time in line with the selected
+// port and speed; the speed and gain stay where they are if the new ranges have them
+fn select_speed(state: &mut SimState) {
+    let port = match state.params.get(&(Parameter::ReadoutPort as u32)) {
+        Some(SimParam {
+            value: SimValue::Enum { value, .. },
+            ..
+        }) => *value,
+        _ => return,
+    };
+    let speeds = match state.speed_table.get(&port) {
+        Some(speeds) if !speeds.is_empty() => speeds.clone(),
+        _ => return,
+    };
+
+    let last = speeds.len() as i64 - 1;
+    let index = int_param(state, Parameter::SpeedTableIndex)
+        .unwrap_or(0)
+        .clamp(0, last);
+    set_int_param(state, Parameter::SpeedTableIndex, index, (0, last));
+
+    let speed = &speeds[index as usize];
+    if let Some((min, max)) = speed.gains {
+        let gain = int_param(state, Parameter::GainIndex)
+            .unwrap_or(min)
+            .clamp(min, max);
+        set_int_param(state, Parameter::GainIndex, gain, (min, max));
+    }
+    if let Some(bit_depth) = speed.bit_depth {
+        set_int_param(
+            state,
+            Parameter::BitDepth,
+            bit_depth,
+            (bit_depth, bit_depth),
+        );
+    }
+    if let Some(pixel_time) = speed.pixel_time {
+        set_int_param(
+            state,
+            Parameter::PixelTime,
+            pixel_time,
+            (pixel_time, pixel_time),
+        );
+    }
+}
+
+// Brings PARAM_PP_FEAT_NAME, PARAM_PP_PARAM_NAME, PARAM_PP_PARAM and the PARAM_PP_PARAM_INDEX
+// range in line with the selected post-processing feature and parameter
+fn select_pp_param(state: &mut SimState) {
+    let features = state.post_processing.len() as i64;
+    if features == 0 {
+        return;
+    }
+    let feature = int_value(state, internal::PARAM_PP_INDEX)
+        .unwrap_or(0)
+        .clamp(0, features - 1);
+    let (name, params) = state.post_processing[feature as usize].clone();
+    let last = (params.len() as i64 - 1).max(0);
+    let param = int_value(state, internal::PARAM_PP_PARAM_INDEX)
+        .unwrap_or(0)
+        .clamp(0, last);
+    set_int_value(state, internal::PARAM_PP_INDEX, feature, (0, features - 1));
+    set_int_value(state, internal::PARAM_PP_PARAM_INDEX, param, (0, last));
+
+    state
+        .params
+        .insert(internal::PARAM_PP_FEAT_NAME, SimParam::string(&name));
+    match params.get(param as usize) {
+        Some((name, value, max)) => {
+            state
+                .params
+                .insert(internal::PARAM_PP_PARAM_NAME, SimParam::string(name));
+            state.params.insert(
+                internal::PARAM_PP_PARAM,
+                SimParam::long(*value as i64, 0, *max as i64),
+            );
+        }
+        None => {
+            state.params.remove(&internal::PARAM_PP_PARAM_NAME);
+            state.params.remove(&internal::PARAM_PP_PARAM);
+        }
+    }
+}
+
+// Keeps a PARAM_PP_PARAM written to with the feature and parameter it was written for
+fn store_pp_param(state: &mut SimState) {
+    let (feature, param) = match (
+        int_value(state, internal::PARAM_PP_INDEX),
+        int_value(state, internal::PARAM_PP_PARAM_INDEX),
+    ) {
+        (Some(feature), Some(param)) => (feature as usize, param as usize),
+        _ => return,
+    };
+    let value = int_value(state, internal::PARAM_PP_PARAM).unwrap_or(0) as u32;
+    if let Some((_, params)) = state.post_processing.get_mut(feature) {
+        if let Some(stored) = params.get_mut(param) {
+            stored.1 = value;
+        }
+    }
+}
+
+// A ramp across the sensor that moves with every frame, so frames and regions can be told apart
+unsafe fn fill(frame: *mut u16, regions: &[Region], number: u32) {
+    let mut i = 0;
+    for region in regions.iter() {
+        for row in 0..region.height() {
+            for col in 0..region.width() {
+                let s = region.s1 as usize + col * region.sbin as usize;
+                let p = region.p1 as usize + row * region.pbin as usize;
+                *frame.add(i) = ((s + p + number as usize) % 4096) as u16;
+                i += 1;
+            }
+        }
+    }
+}
+
+// The acquisition thread: exposes, reads out into the buffer and calls back, frame by frame
+fn run(shared: Arc<Shared>, setup: SimSetup, buffer: BufferPtr, slots: usize) {
+    let mut number: u32 = 0;
+    loop {
+        if let Some(frames) = setup.frames {
+            if number >= frames {
+                break;
+            }
+        }
+        if waits_for_trigger(setup.exp_mode, number) && !wait_for_trigger(&shared) {
+            return;
+        }
+
+        let (period, model) = {
+            let mut state = lock(&shared.state);
+            cool(&mut state);
+            // VARIABLE_TIMED_MODE takes each frame's exposure from PARAM_EXP_TIME
+            let exposure_ms = match setup.exp_mode {
+                ExposureMode::VariableTimed => int_param(&state, Parameter::ExposureTime),
+                _ => None,
+            };
+            let exposure =
+                Duration::from_millis(exposure_ms.unwrap_or(setup.exposure_ms as i64) as u64);
+            let model = state.model.clone().map(|model| {
+                let conditions = SensorConditions {
+                    exposure,
+                    gain_index: int_param(&state, Parameter::GainIndex).unwrap_or(1) as i32,
+                    temperature: int_param(&state, Parameter::Temperature).unwrap_or(0) as f64
+                        / 100.0,
+                    bit_depth: int_param(&state, Parameter::BitDepth).unwrap_or(16) as u16,
+                    frame: number + 1,
+                };
+                (model, conditions)
+            });
+            ((exposure + state.readout).mul_f64(state.time_scale), model)
+        };
+        if !wait_unless_stopped(&shared, period) {
+            return;
+        }
+
+        let offset = (number as usize % slots) * setup.frame_len;
+        match model {
+            Some((model, conditions)) => {
+                let frame = model.render(&setup.regions, &conditions);
+                unsafe {
+                    std::ptr::copy_nonoverlapping(frame.as_ptr(), buffer.0.add(offset), frame.len())
+                };
+            }
+            None => unsafe { fill(buffer.0.add(offset), &setup.regions, number) },
+        }
+        number += 1;
+        let info = FrameInfo {
+            number,
+            timestamp: shared.created.elapsed().as_micros() as i64,
+        };
+
+        let (callback, generation) = {
+            let mut state = lock(&shared.state);
+            state.bytes_arrived = (offset + setup.frame_len) as u32 * 2;
+            state.latest = Some((offset, info));
+            if number as usize % slots == 0 {
+                state.buffer_cnt += 1;
+            }
+            (state.eof.take(), state.eof_generation)
+        };
+
+        // called without the lock, the callback may use the camera
+        if let Some(mut callback) = callback {
+            let camera = SimulatedCamera {
+                shared: shared.clone(),
+                worker: Mutex::new(None),
+            };
+            callback(Some(info), &camera);
+            let mut state = lock(&shared.state);
+            if state.eof_generation == generation {
+                state.eof = Some(callback);
+            }
+        }
+
+        let mut state = lock(&shared.state);
+        state.status = match setup.frames {
+            Some(frames) if number >= frames => CaptureStatus::ReadoutComplete,
+            Some(_) => CaptureStatus::ReadoutInProgress,
+            // FRAME_AVAILABLE
+            None => CaptureStatus::ReadoutComplete,
+        };
+        shared.changed.notify_all();
+    }
+
+    let mut state = lock(&shared.state);
+    state.running = false;
+    shared.changed.notify_all();
+}
+
+impl CameraBackend for SimulatedCamera {
+    fn get_param(&self, param_id: u32, attr: ParamAttrKind) -> Result<ParameterValue> {
+        let mut state = self.state();
+        if param_id == Parameter::Temperature as u32 {
+            cool(&mut state);
+        }
+        match state.params.get(&param_id) {
+            Some(param) => param.get(param_id, attr),
+            None if attr == ParamAttrKind::Available => Ok(ParameterValue::Bool(false)),
+            None => Err(sim_error(format!(
+                "parameter {} is not available",
+                param_id
+            ))),
+        }
+    }
+
+    fn set_param(&self, param_id: u32, value: ParameterValue) -> Result<()> {
+        let value = resolve_enum(self, param_id, value)?;
+        let mut state = self.state();
+        if param_id == Parameter::TemperatureSetpoint as u32 {
+            // cooled toward the old setpoint until now
+            cool(&mut state);
+        }
+        match state.params.get_mut(&param_id) {
+            Some(param) => param.set(param_id, value)?,
+            None => {
+                return Err(sim_error(format!(
+                    "parameter {} is not available",
+                    param_id
+                )))
+            }
+        }
+        if param_id == Parameter::ReadoutPort as u32
+            || param_id == Parameter::SpeedTableIndex as u32
+        {
+            select_speed(&mut state);
+        }
+        if param_id == internal::PARAM_PP_INDEX || param_id == internal::PARAM_PP_PARAM_INDEX {
+            select_pp_param(&mut state);
+        }
+        if param_id == internal::PARAM_PP_PARAM {
+            store_pp_param(&mut state);
+        }
+
+        Ok(())
+    }
+
+    fn get_enums(&self, param_id: u32) -> Result<Vec<PVEnum>> {
+        match self.state().params.get(&param_id) {
+            Some(param) => param.enums(param_id),
+            None => Err(sim_error(format!(
+                "parameter {} is not available",
+                param_id
+            ))),
+        }
+    }
+
+    fn exp_setup_seq(
+        &self,
+        exp_total: u16,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        if exp_total == 0 {
+            return Err(sim_error("a sequence needs at least one frame".to_owned()));
+        }
+        let setup = self.setup(regions, exp_mode, exposure_ms, Some(exp_total as u32))?;
+        let bytes = setup.frame_len * 2 * exp_total as usize;
+        if bytes > u32::MAX as usize {
+            return Err(sim_error(format!("{} bytes do not fit a sequence", bytes)));
+        }
+
+        Ok(bytes as u32)
+    }
+
+    unsafe fn exp_start_seq(&self, buffer: *mut u16) -> Result<()> {
+        let frames = match self.state().setup.as_ref().and_then(|s| s.frames) {
+            Some(frames) => frames as usize,
+            None => return Err(sim_error("no sequence has been set up".to_owned())),
+        };
+        self.start(buffer, frames, false)
+    }
+
+    fn exp_check_status(&self) -> Result<(CaptureStatus, u32)> {
+        let state = self.state();
+        Ok((state.status, state.bytes_arrived))
+    }
+
+    fn exp_abort(&self) -> Result<()> {
+        self.join_worker();
+        Ok(())
+    }
+
+    #[cfg(pvcam_3_9)]
+    fn exp_trigger(&self, _flags: u32, _value: u32) -> Result<u32> {
+        let accepted = {
+            let mut state = self.state();
+            let waiting = state.awaiting_trigger;
+            if waiting {
+                state.awaiting_trigger = false;
+                state.triggers += 1;
+            }
+            waiting
+        };
+        self.shared.changed.notify_all();
+
+        trigger_flags(match accepted {
+            true => internal::PL_SW_TRIG_STATUSES_PL_SW_TRIG_STATUS_TRIGGERED,
+            false => internal::PL_SW_TRIG_STATUSES_PL_SW_TRIG_STATUS_IGNORED,
+        })
+    }
+
+    fn exp_setup_cont(
+        &self,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        let setup = self.setup(regions, exp_mode, exposure_ms, None)?;
+        Ok((setup.frame_len * 2) as u32)
+    }
+
+    unsafe fn exp_start_cont(&self, buffer: *mut u16, size: u32) -> Result<()> {
+        let frame_len = match self.state().setup.as_ref() {
+            Some(setup) if setup.frames.is_none() => setup.frame_len,
+            _ => {
+                return Err(sim_error(
+                    "no continuous acquisition has been set up".to_owned(),
+                ))
+            }
+        };
+        let slots = size as usize / 2 / frame_len.max(1);
+        if slots == 0 {
+            return Err(sim_error(format!(
+                "a {} byte buffer cannot hold a frame",
+                size
+            )));
+        }
+
+        self.start(buffer, slots, true)
+    }
+
+    fn exp_check_cont_status(&self) -> Result<(CaptureStatus, u32, u32)> {
+        let state = self.state();
+        Ok((state.status, state.bytes_arrived, state.buffer_cnt))
+    }
+
+    fn exp_get_latest_frame(&self) -> Result<(*const u16, Option<FrameInfo>)> {
+        let state = self.state();
+        match (state.buffer, state.latest) {
+            (Some(buffer), Some((offset, info))) => {
+                Ok((unsafe { buffer.0.add(offset) } as *const u16, Some(info)))
+            }
+            _ => Err(sim_error("no frame has arrived".to_owned())),
+        }
+    }
+
+    fn exp_stop_cont(&self) -> Result<()> {
+        self.join_worker();
+        Ok(())
+    }
+
+    fn register_eof(&self, callback: EofCallback) -> Result<()> {
+        let mut state = self.state();
+        state.eof = Some(callback);
+        state.eof_generation += 1;
+
+        Ok(())
+    }
+
+    fn deregister_eof(&self) -> Result<()> {
+        let mut state = self.state();
+        state.eof = None;
+        state.eof_generation += 1;
+
+        Ok(())
+    }
+}
+
+impl Drop for SimulatedCamera {
+    fn drop(&mut self) {
+        self.join_worker();
+    }
+}
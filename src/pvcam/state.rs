@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use super::backend::CameraBackend;
+use super::{
+    internal, is_available, read_access, read_int, read_param, read_string, write_param, Error,
+    ErrorKind, ParamAttrKind, Parameter, ParameterAccess, ParameterValue, Result, PARAMETERS,
+};
+
+// One PARAM_PP_PARAM value, addressed by the names of its feature and parameter since their
+// indices are not guaranteed to be stable between cameras or firmware versions
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PostProcessingValue {
+    pub feature: String,
+    pub param: String,
+    pub value: u32,
+}
+
+// Everything a camera was set to when Camera::snapshot ran
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraState {
+    // in PARAMETERS order
+    pub params: Vec<(Parameter, ParameterValue)>,
+    pub post_processing: Vec<PostProcessingValue>,
+    // what the camera has but could not be read, left out of the above; not serialized
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub failed: Vec<(Parameter, Error)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub failed_post_processing: Vec<Error>,
+}
+
+impl CameraState {
+    pub fn get(&self, parameter: Parameter) -> Option<&ParameterValue> {
+        self.params
+            .iter()
+            .find(|(p, _)| *p == parameter)
+            .map(|(_, value)| value)
+    }
+
+    // whether every available, readable parameter made it in
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.failed_post_processing.is_empty()
+    }
+}
+
+// The outcome of Camera::restore; a failure to write one parameter does not stop the rest
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    pub restored: Vec<Parameter>,
+    // read only, or not writable for another reason
+    pub skipped: Vec<Parameter>,
+    pub failed: Vec<(Parameter, Error)>,
+    pub failed_post_processing: Vec<(PostProcessingValue, Error)>,
+}
+
+impl RestoreReport {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty() && self.failed_post_processing.is_empty()
+    }
+}
+
+pub(super) fn is_readable(access: ParameterAccess) -> bool {
+    matches!(
+        access,
+        ParameterAccess::ReadOnly | ParameterAccess::ReadWrite
+    )
+}
+
+pub(super) fn is_writable(access: ParameterAccess) -> bool {
+    matches!(
+        access,
+        ParameterAccess::ReadWrite | ParameterAccess::WriteOnly
+    )
+}
+
+// The parameter's current value, or None if the camera does not have it or it cannot be read
+fn snapshot_param(
+    backend: &dyn CameraBackend,
+    parameter: Parameter,
+) -> Result<Option<ParameterValue>> {
+    let param_id = parameter as u32;
+    if !is_available(backend, param_id)? || !is_readable(read_access(backend, param_id)?) {
+        return Ok(None);
+    }
+
+    read_param(backend, param_id, ParamAttrKind::Current).map(Some)
+}
+
+pub(super) fn snapshot(backend: &dyn CameraBackend) -> CameraState {
+    let mut state = CameraState {
+        params: vec![],
+        post_processing: vec![],
+        failed: vec![],
+        failed_post_processing: vec![],
+    };
+    for parameter in PARAMETERS.iter() {
+        match snapshot_param(backend, *parameter) {
+            Ok(Some(value)) => state.params.push((*parameter, value)),
+            Ok(None) => {}
+            Err(e) => state.failed.push((*parameter, e)),
+        }
+    }
+
+    let walked = for_each_pp_param(backend, |feature, param, _, _| {
+        match read_int(backend, internal::PARAM_PP_PARAM, ParamAttrKind::Current) {
+            Ok(value) => state.post_processing.push(PostProcessingValue {
+                feature: feature.to_owned(),
+                param: param.to_owned(),
+                value: value as u32,
+            }),
+            Err(e) => state.failed_post_processing.push(Error {
+                message: format!("{}/{}: {}", feature, param, e.message),
+                ..e
+            }),
+        }
+        Ok(())
+    });
+    if let Err(e) = walked {
+        state.failed_post_processing.push(e);
+    }
+
+    state
+}
+
+fn set_index(backend: &dyn CameraBackend, param_id: u32, index: i16) -> Result<()> {
+    backend.set_param(param_id, ParameterValue::Int(index as i32))
+}
+
+// Walks PARAM_PP_INDEX and PARAM_PP_PARAM_INDEX, calling f with the names and indices of
+// every post-processing parameter. The selected feature and parameter are put back after; a
+// failure to put them back is only reported if the walk itself went through.
+fn for_each_pp_param<F>(backend: &dyn CameraBackend, mut f: F) -> Result<()>
+where
+    F: FnMut(&str, &str, i16, i16) -> Result<()>,
+{
+    if !is_available(backend, internal::PARAM_PP_INDEX)? {
+        return Ok(());
+    }
+
+    let selected_feature = read_int(backend, internal::PARAM_PP_INDEX, ParamAttrKind::Current)?;
+    let selected_param = read_int(
+        backend,
+        internal::PARAM_PP_PARAM_INDEX,
+        ParamAttrKind::Current,
+    )?;
+
+    let mut walk = || -> Result<()> {
+        let n_features = read_int(backend, internal::PARAM_PP_INDEX, ParamAttrKind::Count)?;
+        for i_feature in 0..n_features as i16 {
+            set_index(backend, internal::PARAM_PP_INDEX, i_feature)?;
+            let feature = read_string(
+                backend,
+                internal::PARAM_PP_FEAT_NAME,
+                ParamAttrKind::Current,
+            )?;
+
+            let n_params = read_int(
+                backend,
+                internal::PARAM_PP_PARAM_INDEX,
+                ParamAttrKind::Count,
+            )?;
+            for i_param in 0..n_params as i16 {
+                set_index(backend, internal::PARAM_PP_PARAM_INDEX, i_param)?;
+                let param = read_string(
+                    backend,
+                    internal::PARAM_PP_PARAM_NAME,
+                    ParamAttrKind::Current,
+                )?;
+                f(&feature, &param, i_feature, i_param)?;
+            }
+        }
+
+        Ok(())
+    };
+    let result = walk();
+
+    let put_back =
+        set_index(backend, internal::PARAM_PP_INDEX, selected_feature as i16).and_then(|_| {
+            set_index(
+                backend,
+                internal::PARAM_PP_PARAM_INDEX,
+                selected_param as i16,
+            )
+        });
+
+    result.and(put_back)
+}
+
+// (feature name, parameter name) => (PARAM_PP_INDEX, PARAM_PP_PARAM_INDEX)
+pub(super) type PPIndices = HashMap<(String, String), (i16, i16)>;
+
+pub(super) fn pp_indices(backend: &dyn CameraBackend) -> Result<PPIndices> {
+    let mut indices = HashMap::new();
+    for_each_pp_param(backend, |feature, param, i_feature, i_param| {
+        indices.insert((feature.to_owned(), param.to_owned()), (i_feature, i_param));
+        Ok(())
+    })?;
+
+    Ok(indices)
+}
+
+pub(super) fn set_pp_param(
+    backend: &dyn CameraBackend,
+    index: (i16, i16),
+    value: u32,
+) -> Result<()> {
+    set_index(backend, internal::PARAM_PP_INDEX, index.0)?;
+    set_index(backend, internal::PARAM_PP_PARAM_INDEX, index.1)?;
+    backend.set_param(internal::PARAM_PP_PARAM, ParameterValue::Long(value as i64))
+}
+
+#[cfg(feature = "profile")]
+pub(super) fn get_post_processing(backend: &dyn CameraBackend) -> Result<Vec<PostProcessingValue>> {
+    let mut values = vec![];
+    for_each_pp_param(backend, |feature, param, _, _| {
+        values.push(PostProcessingValue {
+            feature: feature.to_owned(),
+            param: param.to_owned(),
+            value: read_int(backend, internal::PARAM_PP_PARAM, ParamAttrKind::Current)? as u32,
+        });
+        Ok(())
+    })?;
+
+    Ok(values)
+}
+
+fn restore_param(
+    backend: &dyn CameraBackend,
+    parameter: Parameter,
+    value: &ParameterValue,
+) -> Result<bool> {
+    if !is_writable(read_access(backend, parameter as u32)?) {
+        return Ok(false);
+    }
+    // enums are matched by name, so a snapshot from another firmware still lands on the same option
+    write_param(backend, parameter as u32, value.clone())?;
+
+    Ok(true)
+}
+
+pub(super) fn restore(backend: &dyn CameraBackend, state: &CameraState) -> RestoreReport {
+    let mut report = RestoreReport::default();
+
+    // PARAMETERS order rather than the snapshot's, in case it was built by hand
+    for parameter in PARAMETERS.iter() {
+        let value = match state.get(*parameter) {
+            Some(value) => value,
+            None => continue,
+        };
+        match restore_param(backend, *parameter, value) {
+            Ok(true) => report.restored.push(*parameter),
+            Ok(false) => report.skipped.push(*parameter),
+            Err(e) => report.failed.push((*parameter, e)),
+        }
+    }
+
+    if state.post_processing.is_empty() {
+        return report;
+    }
+
+    let indices = match pp_indices(backend) {
+        Ok(indices) => indices,
+        Err(e) => {
+            for value in state.post_processing.iter() {
+                report
+                    .failed_post_processing
+                    .push((value.clone(), e.clone()));
+            }
+            return report;
+        }
+    };
+
+    for value in state.post_processing.iter() {
+        let result = match indices.get(&(value.feature.clone(), value.param.clone())) {
+            Some(index) => set_pp_param(backend, *index, value.value),
+            None => Err(Error {
+                kind: ErrorKind::Binding,
+                code: -1,
+                message: format!(
+                    "camera has no post-processing parameter {}/{}",
+                    value.feature, value.param
+                ),
+            }),
+        };
+        if let Err(e) = result {
+            report.failed_post_processing.push((value.clone(), e));
+        }
+    }
+
+    report
+}
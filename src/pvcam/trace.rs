@@ -0,0 +1,1445 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::backend::{CameraBackend, EofCallback, FrameInfo};
+use super::{
+    CaptureStatus, Error, ErrorKind, ExposureMode, PVEnum, ParamAttrKind, ParameterValue, Region,
+    Result,
+};
+
+// "PVTRACE" and the format version
+const MAGIC: &[u8; 8] = b"PVTRACE\x01";
+
+// One call made on a camera backend as a RecordingBackend saw it: the arguments, what came back
+// and, where the call hands out frames, the pixels it left in the buffer
+#[derive(Debug, Clone)]
+pub enum Call {
+    GetParam {
+        param_id: u32,
+        attr: ParamAttrKind,
+        result: Result<ParameterValue>,
+    },
+    SetParam {
+        param_id: u32,
+        value: ParameterValue,
+        result: Result<()>,
+    },
+    GetEnums {
+        param_id: u32,
+        result: Result<Vec<PVEnum>>,
+    },
+    ExpSetupSeq {
+        exp_total: u16,
+        regions: Vec<Region>,
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+        result: Result<u32>,
+    },
+    ExpStartSeq {
+        result: Result<()>,
+    },
+    // `data` is the whole sequence buffer the first time the sequence is seen complete, and
+    // empty otherwise
+    ExpCheckStatus {
+        result: Result<(CaptureStatus, u32)>,
+        data: Vec<u16>,
+    },
+    ExpAbort {
+        result: Result<()>,
+    },
+    ExpTrigger {
+        flags: u32,
+        value: u32,
+        result: Result<u32>,
+    },
+    ExpSetupCont {
+        regions: Vec<Region>,
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+        result: Result<u32>,
+    },
+    ExpStartCont {
+        size: u32,
+        result: Result<()>,
+    },
+    ExpCheckContStatus {
+        result: Result<(CaptureStatus, u32, u32)>,
+    },
+    // the frame's offset into the continuous buffer, in pixels; `data` is the frame, or empty
+    // when it is the one the call before returned
+    ExpGetLatestFrame {
+        result: Result<(usize, Option<FrameInfo>)>,
+        data: Vec<u16>,
+    },
+    ExpStopCont {
+        result: Result<()>,
+    },
+    RegisterEof {
+        result: Result<()>,
+    },
+    DeregisterEof {
+        result: Result<()>,
+    },
+    // the EOF callback ran; the calls it made follow it
+    Eof {
+        info: Option<FrameInfo>,
+    },
+}
+
+impl Call {
+    // the pl_* function the call stands for
+    pub fn function(&self) -> &'static str {
+        match self {
+            Call::GetParam { .. } => "pl_get_param",
+            Call::SetParam { .. } => "pl_set_param",
+            Call::GetEnums { .. } => "pl_get_enum_param",
+            Call::ExpSetupSeq { .. } => "pl_exp_setup_seq",
+            Call::ExpStartSeq { .. } => "pl_exp_start_seq",
+            Call::ExpCheckStatus { .. } => "pl_exp_check_status",
+            Call::ExpAbort { .. } => "pl_exp_abort",
+            Call::ExpTrigger { .. } => "pl_exp_trigger",
+            Call::ExpSetupCont { .. } => "pl_exp_setup_cont",
+            Call::ExpStartCont { .. } => "pl_exp_start_cont",
+            Call::ExpCheckContStatus { .. } => "pl_exp_check_cont_status",
+            Call::ExpGetLatestFrame { .. } => "pl_exp_get_latest_frame_ex",
+            Call::ExpStopCont { .. } => "pl_exp_stop_cont",
+            Call::RegisterEof { .. } => "pl_cam_register_callback_ex3",
+            Call::DeregisterEof { .. } => "pl_cam_deregister_callback",
+            Call::Eof { .. } => "EOF callback",
+        }
+    }
+
+    // The function and its arguments, encoded; two calls asking for the same thing have the
+    // same request
+    fn request(&self) -> Vec<u8> {
+        let mut out = Encoder(vec![]);
+        out.request(self);
+        out.0
+    }
+}
+
+fn trace_error(message: String) -> Error {
+    Error {
+        kind: ErrorKind::Binding,
+        code: -1,
+        message,
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+// Every ExposureMode, to decode them by value
+const EXPOSURE_MODES: &[ExposureMode] = &[
+    ExposureMode::Timed,
+    ExposureMode::Strobed,
+    ExposureMode::Bulb,
+    ExposureMode::TriggerFirst,
+    ExposureMode::Flash,
+    ExposureMode::VariableTimed,
+    ExposureMode::IntStrobe,
+    ExposureMode::ExtTrigInternal,
+    ExposureMode::ExtTrigTrigFirst,
+    ExposureMode::ExtTrigEdgeRising,
+    ExposureMode::ExtTrigLevel,
+    #[cfg(pvcam_3_9)]
+    ExposureMode::ExtTrigSoftwareFirst,
+    #[cfg(pvcam_3_9)]
+    ExposureMode::ExtTrigSoftwareEdge,
+    ExposureMode::ExtTrigLevelOverlap,
+    ExposureMode::ExtTrigLevelPulsed,
+];
+
+const ATTRS: &[ParamAttrKind] = &[
+    ParamAttrKind::Current,
+    ParamAttrKind::Count,
+    ParamAttrKind::AttrType,
+    ParamAttrKind::Min,
+    ParamAttrKind::Max,
+    ParamAttrKind::Access,
+    ParamAttrKind::Available,
+];
+
+const ERROR_KINDS: &[ErrorKind] = &[
+    ErrorKind::Pvcam,
+    ErrorKind::Binding,
+    ErrorKind::TriggerNotAccepted,
+    ErrorKind::NotArmed,
+    ErrorKind::LibraryNotAvailable,
+];
+
+// The trace format: MAGIC, then one call after the other, each a tag byte, the arguments and
+// the result. Numbers are little endian, strings and pixel buffers length prefixed.
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i16(&mut self, v: i16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i32(&mut self, v: i32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i64(&mut self, v: i64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f64(&mut self, v: f64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn str(&mut self, v: &str) {
+        self.u32(v.len() as u32);
+        self.0.extend_from_slice(v.as_bytes());
+    }
+
+    fn pixels(&mut self, v: &[u16]) {
+        self.u32(v.len() as u32);
+        for pixel in v.iter() {
+            self.u16(*pixel);
+        }
+    }
+
+    fn regions(&mut self, regions: &[Region]) {
+        self.u16(regions.len() as u16);
+        for r in regions.iter() {
+            for v in [r.s1, r.s2, r.sbin, r.p1, r.p2, r.pbin].iter() {
+                self.u16(*v);
+            }
+        }
+    }
+
+    fn info(&mut self, info: &Option<FrameInfo>) {
+        match info {
+            Some(info) => {
+                self.u8(1);
+                self.u32(info.number);
+                self.i64(info.timestamp);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn enums(&mut self, enums: &[PVEnum]) {
+        self.u32(enums.len() as u32);
+        for e in enums.iter() {
+            self.u32(e.idx);
+            self.i32(e.value);
+            self.str(&e.name);
+        }
+    }
+
+    fn value(&mut self, value: &ParameterValue) {
+        match value {
+            ParameterValue::Enum(idx, enums) => {
+                self.u8(0);
+                self.u32(*idx);
+                self.enums(enums);
+            }
+            ParameterValue::Int(v) => {
+                self.u8(1);
+                self.i32(*v);
+            }
+            ParameterValue::Long(v) => {
+                self.u8(2);
+                self.i64(*v);
+            }
+            ParameterValue::Float(v) => {
+                self.u8(3);
+                self.f64(*v);
+            }
+            ParameterValue::Bool(v) => {
+                self.u8(4);
+                self.u8(*v as u8);
+            }
+            ParameterValue::String(v) => {
+                self.u8(5);
+                self.str(v);
+            }
+        }
+    }
+
+    // an Ok is 0 and the value, an Err 1 and the error
+    fn result<T>(&mut self, result: &Result<T>, ok: impl FnOnce(&mut Self, &T)) {
+        match result {
+            Ok(v) => {
+                self.u8(0);
+                ok(self, v);
+            }
+            Err(e) => {
+                self.u8(1);
+                let kind = ERROR_KINDS.iter().position(|k| *k == e.kind).unwrap_or(0);
+                self.u8(kind as u8);
+                self.i16(e.code);
+                self.str(&e.message);
+            }
+        }
+    }
+
+    fn request(&mut self, call: &Call) {
+        match call {
+            Call::GetParam { param_id, attr, .. } => {
+                self.u8(0);
+                self.u32(*param_id);
+                self.i16(*attr as i16);
+            }
+            Call::SetParam {
+                param_id, value, ..
+            } => {
+                self.u8(1);
+                self.u32(*param_id);
+                self.value(value);
+            }
+            Call::GetEnums { param_id, .. } => {
+                self.u8(2);
+                self.u32(*param_id);
+            }
+            Call::ExpSetupSeq {
+                exp_total,
+                regions,
+                exp_mode,
+                exposure_ms,
+                ..
+            } => {
+                self.u8(3);
+                self.u16(*exp_total);
+                self.regions(regions);
+                self.i16(*exp_mode as i16);
+                self.u32(*exposure_ms);
+            }
+            Call::ExpStartSeq { .. } => self.u8(4),
+            Call::ExpCheckStatus { .. } => self.u8(5),
+            Call::ExpAbort { .. } => self.u8(6),
+            Call::ExpTrigger { flags, value, .. } => {
+                self.u8(7);
+                self.u32(*flags);
+                self.u32(*value);
+            }
+            Call::ExpSetupCont {
+                regions,
+                exp_mode,
+                exposure_ms,
+                ..
+            } => {
+                self.u8(8);
+                self.regions(regions);
+                self.i16(*exp_mode as i16);
+                self.u32(*exposure_ms);
+            }
+            Call::ExpStartCont { size, .. } => {
+                self.u8(9);
+                self.u32(*size);
+            }
+            Call::ExpCheckContStatus { .. } => self.u8(10),
+            Call::ExpGetLatestFrame { .. } => self.u8(11),
+            Call::ExpStopCont { .. } => self.u8(12),
+            Call::RegisterEof { .. } => self.u8(13),
+            Call::DeregisterEof { .. } => self.u8(14),
+            Call::Eof { info } => {
+                self.u8(15);
+                self.info(info);
+            }
+        }
+    }
+
+    fn call(&mut self, call: &Call) {
+        self.request(call);
+        match call {
+            Call::GetParam { result, .. } => self.result(result, |out, v| out.value(v)),
+            Call::GetEnums { result, .. } => self.result(result, |out, v| out.enums(v)),
+            Call::ExpSetupSeq { result, .. } | Call::ExpSetupCont { result, .. } => {
+                self.result(result, |out, v| out.u32(*v))
+            }
+            Call::ExpTrigger { result, .. } => self.result(result, |out, v| out.u32(*v)),
+            Call::ExpCheckStatus { result, data } => {
+                self.result(result, |out, (status, bytes)| {
+                    out.i16(*status as i16);
+                    out.u32(*bytes);
+                });
+                self.pixels(data);
+            }
+            Call::ExpCheckContStatus { result } => {
+                self.result(result, |out, (status, bytes, count)| {
+                    out.i16(*status as i16);
+                    out.u32(*bytes);
+                    out.u32(*count);
+                })
+            }
+            Call::ExpGetLatestFrame { result, data } => {
+                self.result(result, |out, (offset, info)| {
+                    out.u32(*offset as u32);
+                    out.info(info);
+                });
+                self.pixels(data);
+            }
+            Call::SetParam { result, .. }
+            | Call::ExpStartSeq { result }
+            | Call::ExpAbort { result }
+            | Call::ExpStartCont { result, .. }
+            | Call::ExpStopCont { result }
+            | Call::RegisterEof { result }
+            | Call::DeregisterEof { result } => self.result(result, |_, _| {}),
+            Call::Eof { .. } => {}
+        }
+    }
+}
+
+// Reads what Encoder wrote; None once the bytes run out or make no sense
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.bytes.len() {
+            return None;
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let mut b = [0; 2];
+        b.copy_from_slice(self.take(2)?);
+        Some(u16::from_le_bytes(b))
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.take(4)?);
+        Some(u32::from_le_bytes(b))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(self.u32()? as i32)
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Some(i64::from_le_bytes(b))
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_bits(self.i64()? as u64))
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn pixels(&mut self) -> Option<Vec<u16>> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len.checked_mul(2)?)?;
+        Some(
+            bytes
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect(),
+        )
+    }
+
+    fn regions(&mut self) -> Option<Vec<Region>> {
+        let n = self.u16()?;
+        (0..n)
+            .map(|_| {
+                Some(Region {
+                    s1: self.u16()?,
+                    s2: self.u16()?,
+                    sbin: self.u16()?,
+                    p1: self.u16()?,
+                    p2: self.u16()?,
+                    pbin: self.u16()?,
+                })
+            })
+            .collect()
+    }
+
+    fn info(&mut self) -> Option<Option<FrameInfo>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => Some(Some(FrameInfo {
+                number: self.u32()?,
+                timestamp: self.i64()?,
+            })),
+            _ => None,
+        }
+    }
+
+    fn enums(&mut self) -> Option<Vec<PVEnum>> {
+        let n = self.u32()?;
+        (0..n)
+            .map(|_| {
+                Some(PVEnum {
+                    idx: self.u32()?,
+                    value: self.i32()?,
+                    name: self.str()?,
+                })
+            })
+            .collect()
+    }
+
+    fn value(&mut self) -> Option<ParameterValue> {
+        Some(match self.u8()? {
+            0 => ParameterValue::Enum(self.u32()?, self.enums()?),
+            1 => ParameterValue::Int(self.i32()?),
+            2 => ParameterValue::Long(self.i64()?),
+            3 => ParameterValue::Float(self.f64()?),
+            4 => ParameterValue::Bool(self.u8()? != 0),
+            5 => ParameterValue::String(self.str()?),
+            _ => return None,
+        })
+    }
+
+    fn exp_mode(&mut self) -> Option<ExposureMode> {
+        let v = self.i16()?;
+        EXPOSURE_MODES
+            .iter()
+            .copied()
+            .find(|mode| *mode as i16 == v)
+    }
+
+    fn attr(&mut self) -> Option<ParamAttrKind> {
+        let v = self.i16()?;
+        ATTRS.iter().copied().find(|attr| *attr as i16 == v)
+    }
+
+    fn result<T>(&mut self, ok: impl FnOnce(&mut Self) -> Option<T>) -> Option<Result<T>> {
+        match self.u8()? {
+            0 => Some(Ok(ok(self)?)),
+            1 => Some(Err(Error {
+                kind: *ERROR_KINDS.get(self.u8()? as usize)?,
+                code: self.i16()?,
+                message: self.str()?,
+            })),
+            _ => None,
+        }
+    }
+
+    fn unit(&mut self) -> Option<Result<()>> {
+        self.result(|_| Some(()))
+    }
+
+    fn call(&mut self) -> Option<Call> {
+        Some(match self.u8()? {
+            0 => Call::GetParam {
+                param_id: self.u32()?,
+                attr: self.attr()?,
+                result: self.result(|d| d.value())?,
+            },
+            1 => Call::SetParam {
+                param_id: self.u32()?,
+                value: self.value()?,
+                result: self.unit()?,
+            },
+            2 => Call::GetEnums {
+                param_id: self.u32()?,
+                result: self.result(|d| d.enums())?,
+            },
+            3 => Call::ExpSetupSeq {
+                exp_total: self.u16()?,
+                regions: self.regions()?,
+                exp_mode: self.exp_mode()?,
+                exposure_ms: self.u32()?,
+                result: self.result(|d| d.u32())?,
+            },
+            4 => Call::ExpStartSeq {
+                result: self.unit()?,
+            },
+            5 => Call::ExpCheckStatus {
+                result: self.result(|d| Some((CaptureStatus::from_i16(d.i16()?), d.u32()?)))?,
+                data: self.pixels()?,
+            },
+            6 => Call::ExpAbort {
+                result: self.unit()?,
+            },
+            7 => Call::ExpTrigger {
+                flags: self.u32()?,
+                value: self.u32()?,
+                result: self.result(|d| d.u32())?,
+            },
+            8 => Call::ExpSetupCont {
+                regions: self.regions()?,
+                exp_mode: self.exp_mode()?,
+                exposure_ms: self.u32()?,
+                result: self.result(|d| d.u32())?,
+            },
+            9 => Call::ExpStartCont {
+                size: self.u32()?,
+                result: self.unit()?,
+            },
+            10 => Call::ExpCheckContStatus {
+                result: self
+                    .result(|d| Some((CaptureStatus::from_i16(d.i16()?), d.u32()?, d.u32()?)))?,
+            },
+            11 => Call::ExpGetLatestFrame {
+                result: self.result(|d| Some((d.u32()? as usize, d.info()?)))?,
+                data: self.pixels()?,
+            },
+            12 => Call::ExpStopCont {
+                result: self.unit()?,
+            },
+            13 => Call::RegisterEof {
+                result: self.unit()?,
+            },
+            14 => Call::DeregisterEof {
+                result: self.unit()?,
+            },
+            15 => Call::Eof { info: self.info()? },
+            _ => return None,
+        })
+    }
+}
+
+// The calls of a recording, in the order they were made
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    calls: Vec<Call>,
+}
+
+impl Trace {
+    pub fn new(calls: Vec<Call>) -> Self {
+        Trace { calls }
+    }
+
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    // A trace cut short, say by the recording process crashing, ends at its last whole call
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let mut bytes = vec![];
+        if let Err(e) = reader.read_to_end(&mut bytes) {
+            return Err(trace_error(format!("trace could not be read: {}", e)));
+        }
+        if !bytes.starts_with(MAGIC) {
+            return Err(trace_error("not a PVCam call trace".to_owned()));
+        }
+
+        let mut decoder = Decoder {
+            bytes: &bytes[MAGIC.len()..],
+        };
+        let mut calls = vec![];
+        while let Some(call) = decoder.call() {
+            calls.push(call);
+        }
+
+        Ok(Trace { calls })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match File::open(path.as_ref()) {
+            Ok(file) => Self::read_from(file),
+            Err(e) => Err(trace_error(format!(
+                "{} could not be opened: {}",
+                path.as_ref().display(),
+                e
+            ))),
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut out = Encoder(MAGIC.to_vec());
+        for call in self.calls.iter() {
+            out.call(call);
+        }
+        writer
+            .write_all(&out.0)
+            .map_err(|e| trace_error(format!("trace could not be written: {}", e)))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match File::create(path.as_ref()) {
+            Ok(file) => self.write_to(BufWriter::new(file)),
+            Err(e) => Err(trace_error(format!(
+                "{} could not be created: {}",
+                path.as_ref().display(),
+                e
+            ))),
+        }
+    }
+}
+
+// A buffer handed to exp_start_seq/cont; its owner keeps it valid while the camera writes to it
+#[derive(Debug, Clone, Copy)]
+struct Buffer {
+    ptr: *mut u16,
+    // in pixels
+    len: usize,
+}
+
+unsafe impl Send for Buffer {}
+
+struct LogState {
+    out: Box<dyn Write + Send>,
+    // pixels exp_setup_seq asked for, and the buffer they are read out into
+    seq_len: usize,
+    seq: Option<Buffer>,
+    // set once the completed sequence's pixels are in the trace
+    seq_recorded: bool,
+    // pixels of one continuous frame, and the circular buffer
+    frame_len: usize,
+    cont: Option<Buffer>,
+    // what exp_get_latest_frame returned last
+    latest: Option<(usize, Option<FrameInfo>)>,
+}
+
+// Where a recording's calls go, shared with the EOF callback
+struct Log {
+    state: Mutex<LogState>,
+}
+
+impl Log {
+    fn write(&self, calls: &[Call]) -> Result<()> {
+        let mut out = Encoder(vec![]);
+        for call in calls.iter() {
+            out.call(call);
+        }
+
+        let mut state = lock(&self.state);
+        // whole calls only, flushed at once, so a crash loses at most the call in flight
+        state
+            .out
+            .write_all(&out.0)
+            .and_then(|_| state.out.flush())
+            .map_err(|e| trace_error(format!("trace could not be written: {}", e)))
+    }
+}
+
+// Records the calls made on `backend` into `log`. In an EOF callback `held` collects them, to
+// be written straight after the Eof so a replay makes them from the callback too.
+struct Recorder<'a> {
+    backend: &'a dyn CameraBackend,
+    log: &'a Arc<Log>,
+    held: Option<&'a RefCell<Vec<Call>>>,
+}
+
+// a Recorder lives only on the stack of the thread that made it
+unsafe impl Send for Recorder<'_> {}
+
+impl Recorder<'_> {
+    // `result` is returned as the backend gave it, unless the trace could not be written
+    fn record<T>(&self, call: Call, result: Result<T>) -> Result<T> {
+        match self.held {
+            Some(held) => held.borrow_mut().push(call),
+            None => self.log.write(&[call])?,
+        }
+        result
+    }
+
+    // Where the latest frame is in the continuous buffer, and its pixels unless the last call
+    // recorded them already
+    fn latest_frame(
+        &self,
+        frame: *const u16,
+        info: Option<FrameInfo>,
+    ) -> Result<(usize, Vec<u16>)> {
+        let mut state = lock(&self.log.state);
+        let cont = match state.cont {
+            Some(cont) => cont,
+            None => {
+                return Err(trace_error(
+                    "exp_get_latest_frame before exp_start_cont".to_owned(),
+                ))
+            }
+        };
+        let offset = (frame as usize).wrapping_sub(cont.ptr as usize) / 2;
+        if (frame as usize) < cont.ptr as usize || offset + state.frame_len > cont.len {
+            return Err(trace_error(
+                "latest frame is outside the continuous buffer".to_owned(),
+            ));
+        }
+
+        let data = match state.latest == Some((offset, info)) {
+            true => vec![],
+            false => unsafe { std::slice::from_raw_parts(frame, state.frame_len) }.to_vec(),
+        };
+        state.latest = Some((offset, info));
+        Ok((offset, data))
+    }
+}
+
+impl CameraBackend for Recorder<'_> {
+    fn get_param(&self, param_id: u32, attr: ParamAttrKind) -> Result<ParameterValue> {
+        let result = self.backend.get_param(param_id, attr);
+        let call = Call::GetParam {
+            param_id,
+            attr,
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn set_param(&self, param_id: u32, value: ParameterValue) -> Result<()> {
+        let result = self.backend.set_param(param_id, value.clone());
+        let call = Call::SetParam {
+            param_id,
+            value,
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn get_enums(&self, param_id: u32) -> Result<Vec<PVEnum>> {
+        let result = self.backend.get_enums(param_id);
+        let call = Call::GetEnums {
+            param_id,
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn exp_setup_seq(
+        &self,
+        exp_total: u16,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        let result = self
+            .backend
+            .exp_setup_seq(exp_total, regions, exp_mode, exposure_ms);
+        if let Ok(bytes) = result {
+            lock(&self.log.state).seq_len = bytes as usize / 2;
+        }
+        let call = Call::ExpSetupSeq {
+            exp_total,
+            regions: regions.to_vec(),
+            exp_mode,
+            exposure_ms,
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    unsafe fn exp_start_seq(&self, buffer: *mut u16) -> Result<()> {
+        let result = self.backend.exp_start_seq(buffer);
+        // a start that failed leaves the camera without the buffer
+        if result.is_ok() {
+            let mut state = lock(&self.log.state);
+            state.seq = Some(Buffer {
+                ptr: buffer,
+                len: state.seq_len,
+            });
+            state.seq_recorded = false;
+        }
+        let call = Call::ExpStartSeq {
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn exp_check_status(&self) -> Result<(CaptureStatus, u32)> {
+        let result = self.backend.exp_check_status();
+        let mut data = vec![];
+        if let Ok((CaptureStatus::ReadoutComplete, _)) = result {
+            let mut state = lock(&self.log.state);
+            if let (Some(seq), false) = (state.seq, state.seq_recorded) {
+                // the camera is done with the buffer
+                data = unsafe { std::slice::from_raw_parts(seq.ptr, seq.len) }.to_vec();
+                state.seq_recorded = true;
+            }
+        }
+        let call = Call::ExpCheckStatus {
+            result: result.clone(),
+            data,
+        };
+        self.record(call, result)
+    }
+
+    fn exp_abort(&self) -> Result<()> {
+        let result = self.backend.exp_abort();
+        let call = Call::ExpAbort {
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn exp_trigger(&self, flags: u32, value: u32) -> Result<u32> {
+        let result = self.backend.exp_trigger(flags, value);
+        let call = Call::ExpTrigger {
+            flags,
+            value,
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn exp_setup_cont(
+        &self,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        let result = self.backend.exp_setup_cont(regions, exp_mode, exposure_ms);
+        if let Ok(bytes) = result {
+            lock(&self.log.state).frame_len = bytes as usize / 2;
+        }
+        let call = Call::ExpSetupCont {
+            regions: regions.to_vec(),
+            exp_mode,
+            exposure_ms,
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    unsafe fn exp_start_cont(&self, buffer: *mut u16, size: u32) -> Result<()> {
+        let result = self.backend.exp_start_cont(buffer, size);
+        if result.is_ok() {
+            let mut state = lock(&self.log.state);
+            state.cont = Some(Buffer {
+                ptr: buffer,
+                len: size as usize / 2,
+            });
+            state.latest = None;
+        }
+        let call = Call::ExpStartCont {
+            size,
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn exp_check_cont_status(&self) -> Result<(CaptureStatus, u32, u32)> {
+        let result = self.backend.exp_check_cont_status();
+        let call = Call::ExpCheckContStatus {
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn exp_get_latest_frame(&self) -> Result<(*const u16, Option<FrameInfo>)> {
+        // a frame the trace cannot place is recorded as the error the caller gets, so a replay
+        // fails at the same call
+        let (result, recorded, data) = match self.backend.exp_get_latest_frame() {
+            Ok((frame, info)) => match self.latest_frame(frame, info) {
+                Ok((offset, data)) => (Ok((frame, info)), Ok((offset, info)), data),
+                Err(e) => (Err(e.clone()), Err(e), vec![]),
+            },
+            Err(e) => (Err(e.clone()), Err(e), vec![]),
+        };
+        let call = Call::ExpGetLatestFrame {
+            result: recorded,
+            data,
+        };
+        self.record(call, result)
+    }
+
+    fn exp_stop_cont(&self) -> Result<()> {
+        let result = self.backend.exp_stop_cont();
+        let call = Call::ExpStopCont {
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn register_eof(&self, mut callback: EofCallback) -> Result<()> {
+        let log = self.log.clone();
+        let result = self.backend.register_eof(Box::new(move |info, backend| {
+            let held = RefCell::new(vec![Call::Eof { info }]);
+            let recorder = Recorder {
+                backend,
+                log: &log,
+                held: Some(&held),
+            };
+            callback(info, &recorder);
+            // there is no one to report a failed write to on this thread; the next call on
+            // the camera's own thread will fail the same way
+            let _ = log.write(&held.into_inner());
+        }));
+        let call = Call::RegisterEof {
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+
+    fn deregister_eof(&self) -> Result<()> {
+        let result = self.backend.deregister_eof();
+        let call = Call::DeregisterEof {
+            result: result.clone(),
+        };
+        self.record(call, result)
+    }
+}
+
+// Passes every call on to another backend and writes it, with its arguments, results, error
+// codes and the frames it returns, to a trace a ReplayBackend can play back:
+//
+//   let backend = RecordingBackend::create(PvcamBackend::open(&name)?, "rig.pvtrace")?;
+//   let mut camera = Camera::new(backend);
+//
+// Each call is written and flushed as it returns. A trace that cannot be written fails the
+// call that was to be written.
+pub struct RecordingBackend<B> {
+    inner: B,
+    log: Arc<Log>,
+}
+
+impl<B: CameraBackend> RecordingBackend<B> {
+    pub fn new<W: Write + Send + 'static>(inner: B, mut out: W) -> Result<Self> {
+        out.write_all(MAGIC)
+            .and_then(|_| out.flush())
+            .map_err(|e| trace_error(format!("trace could not be written: {}", e)))?;
+
+        Ok(RecordingBackend {
+            inner,
+            log: Arc::new(Log {
+                state: Mutex::new(LogState {
+                    out: Box::new(out),
+                    seq_len: 0,
+                    seq: None,
+                    seq_recorded: false,
+                    frame_len: 0,
+                    cont: None,
+                    latest: None,
+                }),
+            }),
+        })
+    }
+
+    pub fn create<P: AsRef<Path>>(inner: B, path: P) -> Result<Self> {
+        match File::create(path.as_ref()) {
+            Ok(file) => Self::new(inner, BufWriter::new(file)),
+            Err(e) => Err(trace_error(format!(
+                "{} could not be created: {}",
+                path.as_ref().display(),
+                e
+            ))),
+        }
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn recorder(&self) -> Recorder<'_> {
+        Recorder {
+            backend: &self.inner,
+            log: &self.log,
+            held: None,
+        }
+    }
+}
+
+impl<B: CameraBackend> CameraBackend for RecordingBackend<B> {
+    fn handle(&self) -> Option<i16> {
+        self.inner.handle()
+    }
+
+    fn get_param(&self, param_id: u32, attr: ParamAttrKind) -> Result<ParameterValue> {
+        self.recorder().get_param(param_id, attr)
+    }
+
+    fn set_param(&self, param_id: u32, value: ParameterValue) -> Result<()> {
+        self.recorder().set_param(param_id, value)
+    }
+
+    fn get_enums(&self, param_id: u32) -> Result<Vec<PVEnum>> {
+        self.recorder().get_enums(param_id)
+    }
+
+    fn exp_setup_seq(
+        &self,
+        exp_total: u16,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        self.recorder()
+            .exp_setup_seq(exp_total, regions, exp_mode, exposure_ms)
+    }
+
+    unsafe fn exp_start_seq(&self, buffer: *mut u16) -> Result<()> {
+        self.recorder().exp_start_seq(buffer)
+    }
+
+    fn exp_check_status(&self) -> Result<(CaptureStatus, u32)> {
+        self.recorder().exp_check_status()
+    }
+
+    fn exp_abort(&self) -> Result<()> {
+        self.recorder().exp_abort()
+    }
+
+    fn exp_trigger(&self, flags: u32, value: u32) -> Result<u32> {
+        self.recorder().exp_trigger(flags, value)
+    }
+
+    fn exp_setup_cont(
+        &self,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        self.recorder()
+            .exp_setup_cont(regions, exp_mode, exposure_ms)
+    }
+
+    unsafe fn exp_start_cont(&self, buffer: *mut u16, size: u32) -> Result<()> {
+        self.recorder().exp_start_cont(buffer, size)
+    }
+
+    fn exp_check_cont_status(&self) -> Result<(CaptureStatus, u32, u32)> {
+        self.recorder().exp_check_cont_status()
+    }
+
+    fn exp_get_latest_frame(&self) -> Result<(*const u16, Option<FrameInfo>)> {
+        self.recorder().exp_get_latest_frame()
+    }
+
+    fn exp_stop_cont(&self) -> Result<()> {
+        self.recorder().exp_stop_cont()
+    }
+
+    fn register_eof(&self, callback: EofCallback) -> Result<()> {
+        self.recorder().register_eof(callback)
+    }
+
+    fn deregister_eof(&self) -> Result<()> {
+        self.recorder().deregister_eof()
+    }
+}
+
+struct ReplayState {
+    calls: Vec<Call>,
+    next: usize,
+    seq: Option<Buffer>,
+    cont: Option<Buffer>,
+    eof: Option<EofCallback>,
+}
+
+// Plays a Trace back: every call must be the one the trace has next, with the same arguments,
+// and gets the result recorded for it. Frames are written into the caller's buffers where the
+// recording saw them arrive, and the EOF callback is run wherever the recording ran it, on the
+// thread of the call that follows. A call the trace does not have next fails with
+// ErrorKind::Binding.
+pub struct ReplayBackend {
+    state: Mutex<ReplayState>,
+}
+
+impl ReplayBackend {
+    pub fn new(trace: Trace) -> Self {
+        ReplayBackend {
+            state: Mutex::new(ReplayState {
+                calls: trace.calls,
+                next: 0,
+                seq: None,
+                cont: None,
+                eof: None,
+            }),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(Trace::load(path)?))
+    }
+
+    // calls of the trace not yet replayed
+    pub fn remaining(&self) -> usize {
+        let state = lock(&self.state);
+        state.calls.len() - state.next
+    }
+
+    // Runs the callback for the Eof entries due before the next call
+    fn deliver_eofs(&self) {
+        loop {
+            let (info, callback) = {
+                let mut state = lock(&self.state);
+                let info = match state.calls.get(state.next) {
+                    Some(Call::Eof { info }) => *info,
+                    _ => return,
+                };
+                state.next += 1;
+                (info, state.eof.take())
+            };
+
+            // without the lock, as the callback makes calls of its own
+            if let Some(mut callback) = callback {
+                callback(info, self);
+                let mut state = lock(&self.state);
+                if state.eof.is_none() {
+                    state.eof = Some(callback);
+                }
+            }
+        }
+    }
+
+    // The recorded call matching `request`, which is consumed
+    fn replay(&self, request: Call) -> Result<Call> {
+        self.deliver_eofs();
+
+        let mut state = lock(&self.state);
+        let recorded = match state.calls.get(state.next) {
+            Some(recorded) => recorded,
+            None => {
+                return Err(trace_error(format!(
+                    "the trace has ended, {} was not recorded",
+                    request.function()
+                )))
+            }
+        };
+        if recorded.request() != request.request() {
+            let message = match recorded.function() == request.function() {
+                true => format!(
+                    "{} was called with other arguments than in the trace: {:?}",
+                    request.function(),
+                    request
+                ),
+                false => format!(
+                    "the trace has {} next, not {}",
+                    recorded.function(),
+                    request.function()
+                ),
+            };
+            return Err(trace_error(message));
+        }
+
+        let recorded = recorded.clone();
+        state.next += 1;
+        Ok(recorded)
+    }
+}
+
+fn mismatch<T>() -> Result<T> {
+    Err(trace_error(
+        "trace entry does not match its request".to_owned(),
+    ))
+}
+
+impl CameraBackend for ReplayBackend {
+    fn get_param(&self, param_id: u32, attr: ParamAttrKind) -> Result<ParameterValue> {
+        match self.replay(Call::GetParam {
+            param_id,
+            attr,
+            result: mismatch(),
+        })? {
+            Call::GetParam { result, .. } => result,
+            _ => mismatch(),
+        }
+    }
+
+    fn set_param(&self, param_id: u32, value: ParameterValue) -> Result<()> {
+        match self.replay(Call::SetParam {
+            param_id,
+            value,
+            result: mismatch(),
+        })? {
+            Call::SetParam { result, .. } => result,
+            _ => mismatch(),
+        }
+    }
+
+    fn get_enums(&self, param_id: u32) -> Result<Vec<PVEnum>> {
+        match self.replay(Call::GetEnums {
+            param_id,
+            result: mismatch(),
+        })? {
+            Call::GetEnums { result, .. } => result,
+            _ => mismatch(),
+        }
+    }
+
+    fn exp_setup_seq(
+        &self,
+        exp_total: u16,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        let result = match self.replay(Call::ExpSetupSeq {
+            exp_total,
+            regions: regions.to_vec(),
+            exp_mode,
+            exposure_ms,
+            result: mismatch(),
+        })? {
+            Call::ExpSetupSeq { result, .. } => result,
+            _ => mismatch(),
+        };
+        if let Ok(bytes) = result {
+            lock(&self.state).seq = Some(Buffer {
+                ptr: std::ptr::null_mut(),
+                len: bytes as usize / 2,
+            });
+        }
+        result
+    }
+
+    unsafe fn exp_start_seq(&self, buffer: *mut u16) -> Result<()> {
+        let result = match self.replay(Call::ExpStartSeq { result: mismatch() })? {
+            Call::ExpStartSeq { result } => result,
+            _ => mismatch(),
+        };
+        if let (Ok(()), Some(seq)) = (&result, lock(&self.state).seq.as_mut()) {
+            seq.ptr = buffer;
+        }
+        result
+    }
+
+    fn exp_check_status(&self) -> Result<(CaptureStatus, u32)> {
+        let (result, data) = match self.replay(Call::ExpCheckStatus {
+            result: mismatch(),
+            data: vec![],
+        })? {
+            Call::ExpCheckStatus { result, data } => (result, data),
+            _ => return mismatch(),
+        };
+        if !data.is_empty() {
+            match lock(&self.state).seq {
+                Some(seq) if !seq.ptr.is_null() && data.len() <= seq.len => unsafe {
+                    std::ptr::copy_nonoverlapping(data.as_ptr(), seq.ptr, data.len())
+                },
+                _ => {
+                    return Err(trace_error(
+                        "the trace has more pixels than the sequence buffer holds".to_owned(),
+                    ))
+                }
+            }
+        }
+        result
+    }
+
+    fn exp_abort(&self) -> Result<()> {
+        match self.replay(Call::ExpAbort { result: mismatch() })? {
+            Call::ExpAbort { result } => result,
+            _ => mismatch(),
+        }
+    }
+
+    fn exp_trigger(&self, flags: u32, value: u32) -> Result<u32> {
+        match self.replay(Call::ExpTrigger {
+            flags,
+            value,
+            result: mismatch(),
+        })? {
+            Call::ExpTrigger { result, .. } => result,
+            _ => mismatch(),
+        }
+    }
+
+    fn exp_setup_cont(
+        &self,
+        regions: &[Region],
+        exp_mode: ExposureMode,
+        exposure_ms: u32,
+    ) -> Result<u32> {
+        match self.replay(Call::ExpSetupCont {
+            regions: regions.to_vec(),
+            exp_mode,
+            exposure_ms,
+            result: mismatch(),
+        })? {
+            Call::ExpSetupCont { result, .. } => result,
+            _ => mismatch(),
+        }
+    }
+
+    unsafe fn exp_start_cont(&self, buffer: *mut u16, size: u32) -> Result<()> {
+        let result = match self.replay(Call::ExpStartCont {
+            size,
+            result: mismatch(),
+        })? {
+            Call::ExpStartCont { result, .. } => result,
+            _ => mismatch(),
+        };
+        if result.is_ok() {
+            lock(&self.state).cont = Some(Buffer {
+                ptr: buffer,
+                len: size as usize / 2,
+            });
+        }
+        result
+    }
+
+    fn exp_check_cont_status(&self) -> Result<(CaptureStatus, u32, u32)> {
+        match self.replay(Call::ExpCheckContStatus { result: mismatch() })? {
+            Call::ExpCheckContStatus { result } => result,
+            _ => mismatch(),
+        }
+    }
+
+    fn exp_get_latest_frame(&self) -> Result<(*const u16, Option<FrameInfo>)> {
+        let (result, data) = match self.replay(Call::ExpGetLatestFrame {
+            result: mismatch(),
+            data: vec![],
+        })? {
+            Call::ExpGetLatestFrame { result, data } => (result, data),
+            _ => return mismatch(),
+        };
+        let (offset, info) = result?;
+
+        let cont = match lock(&self.state).cont {
+            Some(cont) if offset + data.len() <= cont.len => cont,
+            _ => {
+                return Err(trace_error(
+                    "the recorded frame is outside the continuous buffer".to_owned(),
+                ))
+            }
+        };
+        unsafe {
+            let frame = cont.ptr.add(offset);
+            std::ptr::copy_nonoverlapping(data.as_ptr(), frame, data.len());
+            Ok((frame as *const u16, info))
+        }
+    }
+
+    fn exp_stop_cont(&self) -> Result<()> {
+        match self.replay(Call::ExpStopCont { result: mismatch() })? {
+            Call::ExpStopCont { result } => result,
+            _ => mismatch(),
+        }
+    }
+
+    fn register_eof(&self, callback: EofCallback) -> Result<()> {
+        let result = match self.replay(Call::RegisterEof { result: mismatch() })? {
+            Call::RegisterEof { result } => result,
+            _ => mismatch(),
+        };
+        if result.is_ok() {
+            lock(&self.state).eof = Some(callback);
+        }
+        result
+    }
+
+    fn deregister_eof(&self) -> Result<()> {
+        let result = match self.replay(Call::DeregisterEof { result: mismatch() })? {
+            Call::DeregisterEof { result } => result,
+            _ => mismatch(),
+        };
+        if result.is_ok() {
+            lock(&self.state).eof = None;
+        }
+        result
+    }
+}
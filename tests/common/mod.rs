@@ -0,0 +1,36 @@
+// What the tests writing acquisitions to disk share; each of them uses only some of it.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::io::Metadata;
+use libpvcam_sys::pvcam::{Camera, Region, Sequence, SequenceConfig, SimulatedCamera};
+
+// A sequence from a simulated 64x32 camera, and the metadata read from the camera before it
+pub fn acquire(regions: Vec<Region>, frames: u16, exposure: Duration) -> (Metadata, Sequence) {
+    let mut camera = Camera::new(SimulatedCamera::new().sensor(64, 32));
+    let metadata = Metadata::read(&camera).unwrap();
+    let config = SequenceConfig::new(regions, frames, exposure);
+    let sequence = camera.acquire_seq(&config).unwrap();
+
+    (metadata, sequence)
+}
+
+// little endian u16s back from bytes
+pub fn pixels(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+// A path in the temporary directory unique to this process, cleared of whatever a run before
+// with the same process id left there
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("pvcam-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&path);
+    let _ = fs::remove_file(&path);
+    path
+}
@@ -0,0 +1,193 @@
+// Describes simulated cameras and loads the descriptions back into the simulator, the way a
+// model's JSON from pvcam-describe is turned into a CI camera.
+
+#![cfg(feature = "describe")]
+
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::{
+    Camera, CameraBackend, CameraDescription, ExposureMode, ParamAttrKind, Parameter,
+    ParameterValue, Region, SequenceConfig, SimulatedCamera,
+};
+
+// Two ports whose speeds differ in bit depth and gains; leaves out everything else
+fn model() -> CameraDescription {
+    CameraDescription::parse(&format!(
+        r#"{{
+          "sensor_size": [640, 480],
+          "params": [
+            {{ "parameter": "ReadoutPort", "available": true, "access": "ReadWrite", "type": "enum",
+               "current": {{ "Enum": "Low Noise" }},
+               "options": [{{ "value": 0, "name": "Low Noise" }}, {{ "value": 2, "name": "High Speed" }}] }},
+            {{ "parameter": "SpeedTableIndex", "available": true, "access": "ReadWrite", "type": "int16",
+               "current": {{ "Int": 0 }}, "min": {{ "Int": 0 }}, "max": {{ "Int": 0 }} }},
+            {{ "parameter": "GainIndex", "available": true, "access": "ReadWrite", "type": "int16",
+               "current": {{ "Int": 1 }}, "min": {{ "Int": 1 }}, "max": {{ "Int": 3 }} }},
+            {{ "parameter": "BitDepth", "available": true, "access": "ReadOnly", "type": "int16",
+               "current": {{ "Int": 16 }}, "min": {{ "Int": 16 }}, "max": {{ "Int": 16 }} }},
+            {{ "parameter": "PixelTime", "available": true, "access": "ReadOnly", "type": "uns16",
+               "current": {{ "Int": 100 }}, "min": {{ "Int": 100 }}, "max": {{ "Int": 100 }} }},
+            {{ "parameter": "ExposureMode", "available": true, "access": "ReadOnly", "type": "enum",
+               "current": {{ "Enum": "Timed" }}, "options": [{{ "value": {}, "name": "Timed" }}] }},
+            {{ "parameter": "ExposureTime", "available": true, "access": "ReadWrite", "type": "uns32",
+               "current": {{ "Long": 10 }}, "min": {{ "Long": 1 }}, "max": {{ "Long": 10000 }} }},
+            {{ "parameter": "GainMultFactor", "available": false }}
+          ],
+          "speed_table": [
+            {{ "name": "Low Noise", "value": 0, "speeds": [
+              {{ "index": 0, "bit_depth": 16, "pixel_time": 100, "gains": [1, 2, 3] }} ] }},
+            {{ "name": "High Speed", "value": 2, "speeds": [
+              {{ "index": 0, "bit_depth": 12, "pixel_time": 10, "gains": [1] }},
+              {{ "index": 1, "bit_depth": 11, "pixel_time": 5, "gains": [1, 2] }} ] }}
+          ]
+        }}"#,
+        ExposureMode::Timed as i32
+    ))
+    .unwrap()
+}
+
+fn current(backend: &dyn CameraBackend, parameter: Parameter) -> ParameterValue {
+    backend
+        .get_param(parameter as u32, ParamAttrKind::Current)
+        .unwrap()
+}
+
+fn int(value: ParameterValue) -> i64 {
+    match value {
+        ParameterValue::Int(v) => v as i64,
+        ParameterValue::Long(v) => v,
+        other => panic!("{:?}", other),
+    }
+}
+
+fn available(backend: &dyn CameraBackend, parameter: Parameter) -> bool {
+    matches!(
+        backend.get_param(parameter as u32, ParamAttrKind::Available),
+        Ok(ParameterValue::Bool(true))
+    )
+}
+
+fn select_port(backend: &dyn CameraBackend, name: &str) {
+    let options = backend.get_enums(Parameter::ReadoutPort as u32).unwrap();
+    let idx = options.iter().position(|e| e.name == name).unwrap();
+    backend
+        .set_param(
+            Parameter::ReadoutPort as u32,
+            ParameterValue::Enum(idx as u32, options),
+        )
+        .unwrap();
+}
+
+#[test]
+fn descriptions_survive_the_simulator() {
+    let mut camera = Camera::new(
+        SimulatedCamera::new()
+            .sensor(256, 128)
+            .without_param(Parameter::FanSpeedSetpoint as u32),
+    );
+    let description = camera.describe().unwrap();
+    let json = description.to_json().unwrap();
+
+    // through a file, as pvcam-describe leaves it
+    let path = std::env::temp_dir().join(format!(
+        "libpvcam-sys-description-{}.json",
+        std::process::id()
+    ));
+    description.save(&path).unwrap();
+    let loaded = CameraDescription::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let loaded = loaded.unwrap();
+    let fan = loaded.get(Parameter::FanSpeedSetpoint).unwrap();
+    assert!(!fan.available);
+    assert_eq!(loaded.sensor_size, (256, 128));
+    assert_eq!(loaded.speed_table.len(), 2);
+
+    let mut camera = Camera::new(SimulatedCamera::from_description(&loaded).unwrap());
+    assert!(!available(camera.backend(), Parameter::FanSpeedSetpoint));
+    assert_eq!(camera.describe().unwrap().to_json().unwrap(), json);
+}
+
+#[test]
+fn simulated_models_follow_their_description() {
+    let mut camera = Camera::new(SimulatedCamera::from_description(&model()).unwrap());
+    let backend = camera.backend();
+
+    // what the description leaves out or marks unavailable the model does not have
+    assert!(!available(backend, Parameter::GainMultFactor));
+    assert!(!available(backend, Parameter::ChipName));
+    assert!(backend
+        .get_param(Parameter::ChipName as u32, ParamAttrKind::Current)
+        .is_err());
+
+    // only the described options exist
+    let ports: Vec<i32> = backend
+        .get_enums(Parameter::ReadoutPort as u32)
+        .unwrap()
+        .iter()
+        .map(|e| e.value)
+        .collect();
+    assert_eq!(ports, [0, 2]);
+    let region = Region::new((1, 0..639), (1, 0..479));
+    assert!(backend
+        .exp_setup_seq(1, &[region], ExposureMode::VariableTimed, 10)
+        .is_err());
+    assert!(backend
+        .exp_setup_seq(
+            1,
+            &[Region::new((1, 0..640), (1, 0..479))],
+            ExposureMode::Timed,
+            10
+        )
+        .is_err());
+    assert!(backend
+        .exp_setup_seq(1, &[region], ExposureMode::Timed, 10)
+        .is_ok());
+
+    // the port and speed decide the speed range, the gains and the bit depth
+    assert!(backend
+        .set_param(Parameter::GainIndex as u32, ParameterValue::Int(3))
+        .is_ok());
+    select_port(backend, "High Speed");
+    assert_eq!(
+        int(backend
+            .get_param(Parameter::SpeedTableIndex as u32, ParamAttrKind::Max)
+            .unwrap()),
+        1
+    );
+    assert_eq!(int(current(backend, Parameter::BitDepth)), 12);
+    assert_eq!(int(current(backend, Parameter::GainIndex)), 1);
+    backend
+        .set_param(Parameter::SpeedTableIndex as u32, ParameterValue::Int(1))
+        .unwrap();
+    assert_eq!(int(current(backend, Parameter::BitDepth)), 11);
+    assert_eq!(int(current(backend, Parameter::PixelTime)), 5);
+    backend
+        .set_param(Parameter::GainIndex as u32, ParameterValue::Int(2))
+        .unwrap();
+    assert!(backend
+        .set_param(Parameter::GainIndex as u32, ParameterValue::Int(3))
+        .is_err());
+
+    // describing walks every port and speed, then puts them back
+    let description = camera.describe().unwrap();
+    let backend = camera.backend();
+    assert_eq!(
+        current(backend, Parameter::ReadoutPort).to_string(),
+        "High Speed"
+    );
+    assert_eq!(int(current(backend, Parameter::SpeedTableIndex)), 1);
+    assert_eq!(int(current(backend, Parameter::GainIndex)), 2);
+    assert_eq!(description.speed_table, model().speed_table);
+    let gain = description.get(Parameter::GainIndex).unwrap();
+    assert_eq!(gain.kind.as_deref(), Some("int16"));
+    let exposure = description.get(Parameter::ExposureTime).unwrap();
+    assert!(matches!(exposure.max, Some(ParameterValue::Long(10000))));
+
+    // and the model still acquires
+    let config = SequenceConfig::new(
+        vec![Region::new((2, 0..63), (2, 0..31))],
+        2,
+        Duration::from_millis(1),
+    );
+    assert_eq!(camera.acquire_seq(&config).unwrap().frames.len(), 2);
+}
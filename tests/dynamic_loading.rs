@@ -0,0 +1,27 @@
+// A dynamic-loading build on a machine without libpvcam: every call fails with
+// LibraryNotAvailable rather than the process failing to start. The library is looked for once
+// per process, so this file holds a single test.
+#![cfg(feature = "dynamic-loading")]
+
+use std::env;
+
+use libpvcam_sys::pvcam::{self, ErrorKind};
+
+#[test]
+fn a_missing_library_is_reported_by_every_call() {
+    env::set_var("PVCAM_LIBRARY", "/nonexistent/libpvcam.so");
+
+    let e = pvcam::init().unwrap_err();
+    assert_eq!(e.kind, ErrorKind::LibraryNotAvailable);
+    assert!(e.message.contains("PVCAM_LIBRARY"), "{}", e.message);
+    assert!(
+        e.message.contains("/nonexistent/libpvcam.so"),
+        "{}",
+        e.message
+    );
+
+    let e = pvcam::cam_get_total().unwrap_err();
+    assert_eq!(e.kind, ErrorKind::LibraryNotAvailable);
+    let e = pvcam::uninit().unwrap_err();
+    assert_eq!(e.kind, ErrorKind::LibraryNotAvailable);
+}
@@ -0,0 +1,267 @@
+// End to end tests of the FFI layer against the fake libpvcam in tools/fake-pvcam. They only
+// build when the crate was built against the fake SDK:
+//
+//   cargo run -p fake-pvcam --bin fake-sdk -- target/fake-sdk
+//   PVCAM_SDK_PATH=$PWD/target/fake-sdk cargo test --test fake_pvcam
+//
+// The fake writes every value at its type's exact width and every string exactly as long as it
+// says, so a read at the wrong width or into too small a buffer shows up as a wrong value here.
+
+#![cfg(all(pvcam_fake, not(feature = "dynamic-loading")))]
+
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libpvcam_sys::pvcam::{
+    self, Camera, ContinuousConfig, ErrorKind, ParamAttrKind, Parameter, ParameterValue, Region,
+    SequenceConfig,
+};
+
+// scripting, exported by the fake only
+extern "C" {
+    fn fake_pvcam_reset();
+    fn fake_pvcam_fail_next(function: *const c_char, code: i16);
+    fn fake_pvcam_calls(function: *const c_char) -> u32;
+    fn fake_pvcam_set_string(param_id: u32, value: *const c_char);
+    fn fake_pvcam_set_int(param_id: u32, current: i64, min: i64, max: i64);
+}
+
+// the library is one global camera
+static FAKE: Mutex<()> = Mutex::new(());
+
+// A fresh, initialised library; hold on to it for the whole test
+fn fake() -> MutexGuard<'static, ()> {
+    let guard = FAKE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    unsafe { fake_pvcam_reset() };
+    pvcam::init().unwrap();
+    guard
+}
+
+fn open() -> Camera {
+    Camera::open(&pvcam::cam_get_name(0).unwrap()).unwrap()
+}
+
+fn handle(camera: &Camera) -> i16 {
+    camera.handle().unwrap()
+}
+
+fn current(camera: &Camera, parameter: Parameter) -> ParameterValue {
+    pvcam::get_param(handle(camera), parameter, ParamAttrKind::Current).unwrap()
+}
+
+fn calls(function: &str) -> u32 {
+    let function = CString::new(function).unwrap();
+    unsafe { fake_pvcam_calls(function.as_ptr()) }
+}
+
+fn fail_next(function: &str, code: i16) {
+    let function = CString::new(function).unwrap();
+    unsafe { fake_pvcam_fail_next(function.as_ptr(), code) };
+}
+
+// what the fake writes into pixel `index` of frame `number`, see tools/fake-pvcam/src/camera.rs
+fn pixel(number: u32, index: usize) -> u16 {
+    (number as usize * 1000 + index) as u16
+}
+
+#[test]
+fn values_are_read_at_their_width() {
+    let _fake = fake();
+    let camera = open();
+
+    // int16, uns16, int64 and flt64 parameters, each with a value a wrong width gets wrong
+    assert!(matches!(
+        current(&camera, Parameter::Temperature),
+        ParameterValue::Int(-1234)
+    ));
+    assert!(matches!(
+        current(&camera, Parameter::AdcOffset),
+        ParameterValue::Int(-300)
+    ));
+    assert!(matches!(
+        current(&camera, Parameter::FirmwareVersion),
+        ParameterValue::Int(0xfe01)
+    ));
+    assert!(matches!(
+        current(&camera, Parameter::ClearingTime),
+        ParameterValue::Long(0x1_2345_6789)
+    ));
+    match current(&camera, Parameter::ReadoutTime) {
+        ParameterValue::Float(v) => assert_eq!(v, 1234.5),
+        other => panic!("{:?}", other),
+    }
+
+    let access = pvcam::get_param_access(handle(&camera), Parameter::Temperature).unwrap();
+    assert_eq!(access, pvcam::ParameterAccess::ReadOnly);
+}
+
+#[test]
+fn strings_are_read_whole() {
+    let _fake = fake();
+    let camera = open();
+
+    // as long as MAX_ALPHA_SER_NUM_LEN allows
+    assert!(matches!(
+        current(&camera, Parameter::CameraSerial),
+        ParameterValue::String(ref s) if s == "FAKE-0123456789-0123456789-0123"
+    ));
+
+    // longer than any of the *_LEN constants
+    let long = "a chip name far longer than the thirty two bytes of MAX_PP_NAME_LEN".repeat(4);
+    let value = CString::new(long.clone()).unwrap();
+    unsafe { fake_pvcam_set_string(Parameter::ChipName as u32, value.as_ptr()) };
+    assert!(matches!(
+        current(&camera, Parameter::ChipName),
+        ParameterValue::String(ref s) if *s == long
+    ));
+}
+
+#[test]
+fn enums_round_trip() {
+    let _fake = fake();
+    let camera = open();
+
+    let enums = match current(&camera, Parameter::ClearMode) {
+        ParameterValue::Enum(idx, enums) => {
+            assert_eq!(enums[idx as usize].name, "Pre-Exposure");
+            enums
+        }
+        other => panic!("{:?}", other),
+    };
+    let names: Vec<&str> = enums.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["Never", "Pre-Exposure", "Pre-Sequence"]);
+
+    pvcam::set_param(
+        handle(&camera),
+        Parameter::ClearMode,
+        ParameterValue::Enum(2, enums),
+    )
+    .unwrap();
+    match current(&camera, Parameter::ClearMode) {
+        ParameterValue::Enum(idx, enums) => assert_eq!(enums[idx as usize].value, 2),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn ints_are_written_at_their_width() {
+    let _fake = fake();
+    let camera = open();
+
+    // int16, so -2500 only survives being written as one
+    pvcam::set_param(
+        handle(&camera),
+        Parameter::TemperatureSetpoint,
+        ParameterValue::Int(-2500),
+    )
+    .unwrap();
+    assert!(matches!(
+        current(&camera, Parameter::TemperatureSetpoint),
+        ParameterValue::Int(-2500)
+    ));
+
+    // the fake refuses what is outside ATTR_MIN..=ATTR_MAX
+    unsafe { fake_pvcam_set_int(Parameter::ClearCycles as u32, 2, 0, 16) };
+    let e = pvcam::set_param(
+        handle(&camera),
+        Parameter::ClearCycles,
+        ParameterValue::Int(17),
+    )
+    .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Pvcam);
+    assert!(matches!(
+        current(&camera, Parameter::ClearCycles),
+        ParameterValue::Int(2)
+    ));
+}
+
+#[test]
+fn failures_carry_the_pvcam_error() {
+    let _fake = fake();
+    let camera = open();
+
+    fail_next("pl_get_param", 42);
+    let e = pvcam::get_param(
+        handle(&camera),
+        Parameter::Temperature,
+        ParamAttrKind::Current,
+    )
+    .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Pvcam);
+    assert_eq!(e.code, 42);
+    assert_eq!(e.message, "pl_get_param failed as scripted");
+
+    // only the next call fails
+    assert!(matches!(
+        current(&camera, Parameter::Temperature),
+        ParameterValue::Int(-1234)
+    ));
+}
+
+#[test]
+fn sequences_read_out_every_pixel() {
+    let _fake = fake();
+    let mut camera = open();
+
+    let regions = vec![
+        Region::new((1, 0..15), (1, 0..7)),
+        Region::new((2, 16..31), (2, 8..15)),
+    ];
+    let config = SequenceConfig::new(regions, 3, Duration::from_millis(1));
+    let sequence = camera.acquire_seq(&config).unwrap();
+
+    assert_eq!(sequence.frames.len(), 3);
+    for frame in sequence.frames.iter() {
+        assert_eq!(frame.data.len(), 16 * 8 + 8 * 4);
+        for (i, value) in frame.data.iter().enumerate() {
+            assert_eq!(*value, pixel(frame.number, i));
+        }
+    }
+    let numbers: Vec<u32> = sequence.frames.iter().map(|f| f.number).collect();
+    assert_eq!(numbers, [1, 2, 3]);
+}
+
+#[test]
+fn continuous_frames_arrive() {
+    let _fake = fake();
+    let mut camera = open();
+
+    let region = Region::new((1, 0..31), (1, 0..31));
+    let config = ContinuousConfig::new(vec![region], Duration::from_millis(1)).buffer_frames(4);
+    camera.start_cont(&config).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let frame = loop {
+        if let Some(frame) = camera.latest_frame().unwrap() {
+            break frame;
+        }
+        assert!(Instant::now() < deadline, "no frame arrived");
+        thread::sleep(Duration::from_millis(1));
+    };
+    camera.stop_cont().unwrap();
+
+    assert_eq!(frame.data.len(), 32 * 32);
+    for (i, value) in frame.data.iter().enumerate() {
+        assert_eq!(*value, pixel(frame.number, i));
+    }
+}
+
+#[test]
+fn cameras_close_once() {
+    let _fake = fake();
+    drop(open());
+
+    assert_eq!(calls("pl_cam_open"), 1);
+    assert_eq!(calls("pl_cam_close"), 1);
+}
+
+#[test]
+fn library_version_matches_the_headers() {
+    let _fake = fake();
+
+    let version = pvcam::library_version().unwrap();
+    assert!(version.same_release(pvcam::SDK_HEADER_VERSION));
+}
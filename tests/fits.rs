@@ -0,0 +1,103 @@
+// Writes FITS files and reads them back, checking the blocks, keywords and pixels.
+
+use std::io::Cursor;
+use std::time::{Duration, UNIX_EPOCH};
+
+use libpvcam_sys::pvcam::io::fits::{self, FitsWriter};
+use libpvcam_sys::pvcam::io::Metadata;
+use libpvcam_sys::pvcam::{Camera, Frame, Region, SequenceConfig, SimulatedCamera};
+
+#[test]
+fn sequences_survive_a_round_trip() {
+    let mut camera = Camera::new(SimulatedCamera::new().sensor(64, 32));
+    let metadata = Metadata::read(&camera).unwrap();
+    let config = SequenceConfig::new(
+        vec![
+            Region::new((2, 0..15), (1, 0..7)),
+            Region::new((1, 32..39), (4, 8..23)),
+        ],
+        3,
+        Duration::from_millis(5),
+    );
+    let sequence = camera.acquire_seq(&config).unwrap();
+
+    // 2024-02-29T12:34:56.5
+    let start = UNIX_EPOCH + Duration::from_millis(1_709_210_096_500);
+    let mut fits = FitsWriter::new(Cursor::new(vec![]), metadata.clone())
+        .unwrap()
+        .start_time(start);
+    fits.write_sequence(&sequence).unwrap();
+    let bytes = fits.finish().unwrap().into_inner();
+    assert_eq!(bytes.len() % 2880, 0);
+    assert!(bytes.starts_with(b"SIMPLE  =                    T"));
+
+    let hdus = fits::read(bytes.as_slice()).unwrap();
+    assert_eq!(hdus.len(), 1 + 3 * 2);
+    let primary = &hdus[0];
+    assert_eq!(primary.get("NAXIS"), Some("0"));
+    assert_eq!(primary.get("INSTRUME"), metadata.model.as_deref());
+    assert!(primary.data.is_empty());
+
+    let images = &hdus[1..];
+    for (frame, hdus) in sequence.frames.iter().zip(images.chunks(2)) {
+        let mut start = 0;
+        let binning = [("2", "1"), ("1", "4")];
+        for ((region, hdu), (x, y)) in frame.regions.iter().zip(hdus.iter()).zip(binning) {
+            let len = region.width() * region.height();
+            assert_eq!((hdu.width, hdu.height), (region.width(), region.height()));
+            assert_eq!(hdu.data, frame.data[start..start + len]);
+            start += len;
+
+            assert_eq!(hdu.get("XTENSION"), Some("IMAGE"));
+            assert_eq!(hdu.get("FRAMENUM"), Some(frame.number.to_string().as_str()));
+            assert_eq!(hdu.get("EXPTIME"), Some("0.005"));
+            assert_eq!(hdu.get("XBINNING"), Some(x));
+            assert_eq!(hdu.get("YBINNING"), Some(y));
+            assert_eq!(hdu.get("SERIALNO"), Some("SIM0001"));
+            assert_eq!(hdu.get("GAIN"), Some("1"));
+            let temperature: f64 = hdu.get("CCD-TEMP").unwrap().parse().unwrap();
+            assert_eq!(Some(temperature), metadata.temperature);
+        }
+    }
+    assert_eq!(
+        images[0].get("DATE-OBS"),
+        Some("2024-02-29T12:34:56.500000")
+    );
+    let first = sequence.frames[0].timestamp.unwrap();
+    let last = sequence.frames[2].timestamp.unwrap();
+    let expected = format!(
+        "2024-02-29T12:34:{:09.6}",
+        56.5 + (last - first) as f64 / 1e6
+    );
+    assert_eq!(images[5].get("DATE-OBS"), Some(expected.as_str()));
+    assert_eq!(images[5].get("EXTVER"), Some("2"));
+}
+
+#[test]
+fn the_whole_u16_range_survives() {
+    let data = vec![
+        0, 1, 255, 256, 32767, 32768, 32769, 65534, 65535, 4096, 12, 40000,
+    ];
+    let frame = Frame {
+        number: 1,
+        exposure: Duration::from_micros(250),
+        timestamp: None,
+        regions: vec![Region::new((1, 0..3), (1, 0..2))],
+        data: data.clone(),
+    };
+    let metadata = Metadata {
+        model: Some("Spectrometer 'A'".to_owned()),
+        ..Metadata::default()
+    };
+
+    let mut fits = FitsWriter::new(Cursor::new(vec![]), metadata).unwrap();
+    fits.write_frame(&frame).unwrap();
+    let hdus = fits::read(fits.finish().unwrap().get_ref().as_slice()).unwrap();
+
+    assert_eq!(hdus[1].data, data);
+    assert_eq!(hdus[1].get("EXPTIME"), Some("0.00025"));
+    assert_eq!(hdus[1].get("INSTRUME"), Some("Spectrometer 'A'"));
+    // no timestamp, no date
+    assert_eq!(hdus[1].get("DATE-OBS"), None);
+    assert_eq!(hdus[1].get("CCD-TEMP"), None);
+}
@@ -0,0 +1,44 @@
+// Views frames and sequences as ndarray arrays and checks them against the flat pixels.
+#![cfg(feature = "ndarray")]
+
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::{Camera, Region, SequenceConfig, SimulatedCamera};
+
+#[test]
+fn views_borrow_each_region_binned() {
+    let mut camera = Camera::new(SimulatedCamera::new().sensor(64, 32));
+    let regions = vec![
+        Region::new((1, 0..15), (1, 0..7)),
+        Region::new((2, 16..31), (4, 8..23)),
+    ];
+    let config = SequenceConfig::new(regions, 3, Duration::from_millis(1));
+    let sequence = camera.acquire_seq(&config).unwrap();
+
+    let frame = &sequence.frames[1];
+    let view = frame.view();
+    assert_eq!(view.dim(), (8, 16));
+    assert_eq!(view.as_ptr(), frame.data.as_ptr(), "no copy");
+    assert_eq!(view[[2, 5]], frame.data[2 * 16 + 5]);
+
+    let views = frame.region_views();
+    assert_eq!(views.len(), 2);
+    // 16 columns binned by 2, 16 rows by 4
+    assert_eq!(views[1].dim(), (4, 8));
+    assert_eq!(views[1][[3, 7]], frame.data[128 + 3 * 8 + 7]);
+    assert!(frame.region_view(2).is_none());
+
+    let stack = sequence.as_array3();
+    assert_eq!(stack.dim(), (3, 8, 16));
+    for (i, frame) in sequence.frames.iter().enumerate() {
+        assert_eq!(stack.index_axis(ndarray::Axis(0), i), frame.view());
+    }
+    let second = sequence.region_array3(1).unwrap();
+    assert_eq!(second.dim(), (3, 4, 8));
+    assert_eq!(second[[2, 1, 0]], sequence.frames[2].data[128 + 8]);
+
+    let mut short = frame.clone();
+    short.data.truncate(100);
+    assert!(short.region_view(0).is_none());
+    assert_eq!(short.region_views().len(), 0);
+}
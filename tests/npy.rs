@@ -0,0 +1,156 @@
+// Writes sequences as .npy and .npz and checks them byte for byte against what numpy.save and
+// numpy.savez write, reading the zip back with a minimal reader of its Zip64 directory.
+
+use std::convert::TryInto;
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::io::npy;
+use libpvcam_sys::pvcam::{Region, Sequence};
+
+mod common;
+
+use common::pixels;
+
+fn acquire(regions: Vec<Region>) -> Sequence {
+    common::acquire(regions, 3, Duration::from_millis(1)).1
+}
+
+// A header as numpy.save writes it: the dict padded to 128 bytes
+fn header(len: u16, dict: &str, padding: usize) -> Vec<u8> {
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&len.to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header.extend(vec![b' '; padding]);
+    header.push(b'\n');
+    header
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// Name and data of every entry, checked against their CRC
+fn unzip(zip: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let u16_at = |at: usize| u16::from_le_bytes(zip[at..at + 2].try_into().unwrap()) as usize;
+    let u32_at = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(zip[at..at + 8].try_into().unwrap()) as usize;
+
+    // the classic end, the Zip64 locator before it, and the Zip64 end it points at
+    let end = zip.len() - 22;
+    assert_eq!(u32_at(end), 0x0605_4b50);
+    assert_eq!(u32_at(end - 20), 0x0706_4b50);
+    let end64 = u64_at(end - 20 + 8);
+    assert_eq!(u32_at(end64), 0x0606_4b50);
+    let count = u64_at(end64 + 32);
+    let mut at = u64_at(end64 + 48);
+
+    let mut entries = vec![];
+    for _ in 0..count {
+        assert_eq!(u32_at(at), 0x0201_4b50);
+        let crc = u32_at(at + 16);
+        let name_len = u16_at(at + 28);
+        let extra_len = u16_at(at + 30);
+        let name = String::from_utf8(zip[at + 46..at + 46 + name_len].to_vec()).unwrap();
+        let extra = at + 46 + name_len;
+        assert_eq!(u16_at(extra), 1);
+        let (len, offset) = (u64_at(extra + 4), u64_at(extra + 20));
+
+        assert_eq!(u32_at(offset), 0x0403_4b50);
+        let data = offset + 30 + u16_at(offset + 26) + u16_at(offset + 28);
+        let data = zip[data..data + len].to_vec();
+        assert_eq!(crc32(&data), crc, "{}", name);
+        entries.push((name, data));
+        at = extra + extra_len;
+    }
+
+    entries
+}
+
+#[test]
+fn npy_is_laid_out_as_numpy_writes_it() {
+    let sequence = acquire(vec![Region::new((2, 0..31), (1, 0..7))]);
+    let mut bytes = vec![];
+    npy::write(&mut bytes, &sequence).unwrap();
+
+    let dict = "{'descr': '<u2', 'fortran_order': False, 'shape': (3, 8, 16), }";
+    assert_eq!(bytes[..128], header(118, dict, 54)[..]);
+    assert_eq!(bytes.len(), 128 + 3 * 8 * 16 * 2);
+    for (i, frame) in sequence.frames.iter().enumerate() {
+        let at = 128 + i * 8 * 16 * 2;
+        assert_eq!(pixels(&bytes[at..at + 8 * 16 * 2]), frame.data);
+    }
+
+    // one region per .npy
+    let regions = vec![
+        Region::new((1, 0..7), (1, 0..3)),
+        Region::new((1, 8..11), (1, 0..1)),
+    ];
+    let sequence = acquire(regions);
+    assert!(npy::write(&mut vec![], &sequence).is_err());
+    let mut bytes = vec![];
+    npy::write_region(&mut bytes, &sequence, 1).unwrap();
+    let dict = "{'descr': '<u2', 'fortran_order': False, 'shape': (3, 2, 4), }";
+    assert_eq!(bytes[..128], header(118, dict, 55)[..]);
+    assert_eq!(pixels(&bytes[128..144]), sequence.frames[0].data[32..]);
+}
+
+#[test]
+fn npz_carries_frames_numbers_and_timestamps() {
+    let regions = vec![
+        Region::new((1, 0..7), (1, 0..3)),
+        Region::new((1, 8..11), (1, 0..1)),
+    ];
+    let sequence = acquire(regions);
+    let zip = npy::write_npz(vec![], &sequence).unwrap();
+
+    let entries = unzip(&zip);
+    let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "frames_0.npy",
+            "frames_1.npy",
+            "numbers.npy",
+            "timestamps.npy"
+        ]
+    );
+
+    let frames = &entries[0].1;
+    let dict = "{'descr': '<u2', 'fortran_order': False, 'shape': (3, 4, 8), }";
+    assert_eq!(frames[..128], header(118, dict, 55)[..]);
+    assert_eq!(pixels(&frames[128..192]), sequence.frames[0].data[..32]);
+
+    let numbers = &entries[2].1;
+    let dict = "{'descr': '<u4', 'fortran_order': False, 'shape': (3,), }";
+    assert_eq!(numbers[..128], header(118, dict, 60)[..]);
+    let numbers: Vec<u32> = numbers[128..]
+        .chunks(4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .collect();
+    assert_eq!(numbers, [1, 2, 3]);
+
+    let timestamps = &entries[3].1;
+    let dict = "{'descr': '<i8', 'fortran_order': False, 'shape': (3,), }";
+    assert_eq!(timestamps[..128], header(118, dict, 60)[..]);
+    let timestamps: Vec<i64> = timestamps[128..]
+        .chunks(8)
+        .map(|b| i64::from_le_bytes(b.try_into().unwrap()))
+        .collect();
+    let expected: Vec<i64> = sequence
+        .frames
+        .iter()
+        .map(|f| f.timestamp.unwrap_or(-1))
+        .collect();
+    assert_eq!(timestamps, expected);
+}
@@ -0,0 +1,31 @@
+// A dynamic-loading build against the 3.9 headers running on a 3.8 libpvcam, which has no
+// pl_exp_trigger: the library loads and everything else works, only a software trigger fails.
+// The fake libpvcam stands in for the older library:
+//
+//   cargo build -p fake-pvcam --features fake-pvcam/pvcam-3-8
+//   PVCAM_LIBRARY=$PWD/target/debug/libpvcam.so cargo test --features dynamic-loading \
+//       --test older_library -- --ignored
+#![cfg(all(feature = "dynamic-loading", pvcam_3_9))]
+
+use libpvcam_sys::pvcam::{self, Camera, ErrorKind, ExposureMode, Region};
+
+#[test]
+#[ignore = "needs PVCAM_LIBRARY set to fake-pvcam built with the pvcam-3-8 feature"]
+fn a_library_without_pl_exp_trigger_loads() {
+    pvcam::init().unwrap();
+    let mut camera = Camera::open(&pvcam::cam_get_name(0).unwrap()).unwrap();
+
+    let regions = vec![Region::new((1, 0..15), (1, 0..7))];
+    let bytes = camera
+        .exp_setup_seq(1, regions, ExposureMode::ExtTrigSoftwareFirst, 1)
+        .unwrap();
+    let mut buffer = vec![0u16; bytes as usize / 2];
+    unsafe { camera.exp_start_seq(buffer.as_mut_ptr()).unwrap() };
+
+    let e = camera.trigger(0, 0).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::LibraryNotAvailable);
+    assert!(e.message.contains("pl_exp_trigger"), "{}", e.message);
+
+    drop(camera);
+    pvcam::uninit().unwrap();
+}
@@ -0,0 +1,361 @@
+// Profiles parsed from TOML, checked against a SimulatedCamera and applied to it.
+#![cfg(feature = "profile")]
+
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::{
+    Binning, Call, Camera, ExposureMode, ParamAttrKind, Parameter, Profile, RecordingBackend,
+    Region, Roi, SimParam, SimulatedCamera, Trace,
+};
+
+fn simulated() -> SimulatedCamera {
+    SimulatedCamera::new()
+        .sensor(64, 32)
+        .post_processing("Denoise", &[("Enabled", 0, 1), ("Strength", 5, 10)])
+}
+
+fn current(camera: &Camera, parameter: Parameter) -> String {
+    camera
+        .backend()
+        .get_param(parameter as u32, ParamAttrKind::Current)
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn profiles_parse_from_toml() {
+    let profile = Profile::parse(
+        r#"
+        readout_port = "Speed"
+        speed = 1
+        gain = 2
+        trigger = "ExtTrigEdgeRising"
+        exposure_ms = 20
+        binning = { serial = 2, parallel = 2 }
+
+        [[roi]]
+        s1 = 0
+        s2 = 31
+        p1 = 8
+        p2 = 15
+
+        [post_processing.Denoise]
+        Strength = 7
+        "#,
+    )
+    .unwrap();
+    assert_eq!(profile.readout_port.as_deref(), Some("Speed"));
+    assert_eq!((profile.speed, profile.gain), (Some(1), Some(2)));
+    assert_eq!(profile.trigger, Some(ExposureMode::ExtTrigEdgeRising));
+    assert_eq!(profile.exposure_ms, Some(20));
+    assert_eq!(
+        profile.binning,
+        Some(Binning {
+            serial: 2,
+            parallel: 2
+        })
+    );
+    assert_eq!(
+        profile.roi,
+        [Roi {
+            s1: 0,
+            s2: 31,
+            p1: 8,
+            p2: 15
+        }]
+    );
+    assert_eq!(profile.post_processing["Denoise"]["Strength"], 7);
+    assert_eq!(Profile::parse("").unwrap(), Profile::default());
+
+    for text in [
+        "readout_prot = \"Speed\"",
+        "trigger = \"Sometimes\"",
+        "binning = { serial = 2 }",
+        "speed = \"fast\"",
+    ]
+    .iter()
+    {
+        let e = Profile::parse(text).unwrap_err();
+        assert!(e.message.starts_with("invalid profile"), "{}", e.message);
+    }
+}
+
+#[test]
+fn every_problem_is_reported_and_nothing_written() {
+    let mut camera = Camera::new(simulated());
+    let profile = Profile {
+        // on the current port, as the profile selects none
+        gain: Some(9),
+        expose_out_mode: Some("Global Shutter".to_owned()),
+        roi: vec![Roi {
+            s1: 0,
+            s2: 64,
+            p1: 0,
+            p2: 31,
+        }],
+        binning: Some(Binning {
+            serial: 3,
+            parallel: 1,
+        }),
+        post_processing: [(
+            "Denoise".to_owned(),
+            [("Radius".to_owned(), 2)].iter().cloned().collect(),
+        )]
+        .iter()
+        .cloned()
+        .collect(),
+        ..Profile::default()
+    };
+
+    let e = camera.apply_profile(&profile).unwrap_err();
+    for problem in [
+        "9 is outside GainIndex",
+        "\"Global Shutter\" is not one of the ExposeOutMode options",
+        "does not fit the 64x32 sensor",
+        "BinningSerial cannot be 3",
+        "no post-processing parameter Denoise/Radius",
+    ]
+    .iter()
+    {
+        assert!(
+            e.message.contains(problem),
+            "{:?} in {}",
+            problem,
+            e.message
+        );
+    }
+    assert_eq!(current(&camera, Parameter::ReadoutPort), "Sensitivity");
+    assert_eq!(current(&camera, Parameter::SpeedTableIndex), "0");
+    assert_eq!(current(&camera, Parameter::GainIndex), "1");
+    assert!(camera.sequence_config(1).is_err());
+}
+
+#[test]
+fn applying_reports_the_changes_and_keeps_the_acquisition_settings() {
+    let mut camera = Camera::new(simulated());
+    let profile = Profile {
+        readout_port: Some("Speed".to_owned()),
+        gain: Some(1),
+        expose_out_mode: Some("All Rows".to_owned()),
+        trigger: Some(ExposureMode::ExtTrigEdgeRising),
+        exposure_ms: Some(20),
+        roi: vec![Roi {
+            s1: 0,
+            s2: 31,
+            p1: 8,
+            p2: 15,
+        }],
+        binning: Some(Binning {
+            serial: 2,
+            parallel: 2,
+        }),
+        post_processing: [(
+            "Denoise".to_owned(),
+            [("Strength".to_owned(), 7)].iter().cloned().collect(),
+        )]
+        .iter()
+        .cloned()
+        .collect(),
+        ..Profile::default()
+    };
+
+    let changes: Vec<String> = camera
+        .apply_profile(&profile)
+        .unwrap()
+        .iter()
+        .map(|change| change.to_string())
+        .collect();
+    // the gain was 1 already
+    assert_eq!(
+        changes,
+        [
+            "ReadoutPort: Sensitivity -> Speed",
+            "ExposeOutMode: First Row -> All Rows",
+            "Denoise/Strength: 5 -> 7",
+        ]
+    );
+
+    let config = camera.sequence_config(3).unwrap();
+    assert_eq!(config.regions(), [Region::new((2, 0..31), (2, 8..15))]);
+    assert_eq!(config.frames(), 3);
+    assert_eq!(config.exposure_mode(), ExposureMode::ExtTrigEdgeRising);
+    assert_eq!(config.exposures(), [Duration::from_millis(20); 3]);
+
+    // applying it again changes nothing
+    assert!(camera.apply_profile(&profile).unwrap().is_empty());
+}
+
+#[test]
+fn a_failed_write_puts_back_the_ones_before_it() {
+    // nothing short of writing it tells that this one cannot be written
+    let mut camera = Camera::new(simulated().param(
+        Parameter::ExposeOutMode as u32,
+        SimParam::enumeration(&[(0, "First Row"), (1, "All Rows")], 0).read_only(),
+    ));
+    let profile = Profile {
+        readout_port: Some("Speed".to_owned()),
+        gain: Some(2),
+        expose_out_mode: Some("All Rows".to_owned()),
+        ..Profile::default()
+    };
+
+    let e = camera.apply_profile(&profile).unwrap_err();
+    assert!(
+        e.message
+            .ends_with("the settings written before it were put back"),
+        "{}",
+        e.message
+    );
+    assert_eq!(current(&camera, Parameter::ReadoutPort), "Sensitivity");
+    assert_eq!(current(&camera, Parameter::GainIndex), "1");
+    assert_eq!(current(&camera, Parameter::ExposeOutMode), "First Row");
+}
+
+#[test]
+fn the_whole_sensor_is_acquired_without_a_roi() {
+    let camera = Camera::new(simulated());
+    let profile = Profile {
+        binning: Some(Binning {
+            serial: 2,
+            parallel: 1,
+        }),
+        ..Profile::default()
+    };
+    assert_eq!(
+        profile.regions(&camera).unwrap(),
+        [Region::new((2, 0..63), (1, 0..31))]
+    );
+    assert!(profile.sequence_config(&camera, 1).is_err(), "no exposure");
+
+    let empty = Camera::new(SimulatedCamera::new().sensor(0, 32));
+    let e = profile.regions(&empty).unwrap_err();
+    assert!(e.message.contains("0x32 sensor"), "{}", e.message);
+}
+
+#[test]
+fn regions_new_would_refuse_are_errors() {
+    let camera = Camera::new(simulated());
+    let unbinned = Profile {
+        binning: Some(Binning {
+            serial: 0,
+            parallel: 1,
+        }),
+        ..Profile::default()
+    };
+    let e = unbinned.regions(&camera).unwrap_err();
+    assert!(e.message.contains("binning 0x1"), "{}", e.message);
+
+    let reversed = Profile {
+        roi: vec![Roi {
+            s1: 10,
+            s2: 5,
+            p1: 0,
+            p2: 7,
+        }],
+        ..Profile::default()
+    };
+    let e = reversed.regions(&camera).unwrap_err();
+    assert!(e.message.contains("ends before it starts"), "{}", e.message);
+}
+
+// Speed and gain are checked against the port the profile selects, not the current one
+#[cfg(feature = "describe")]
+#[test]
+fn speed_and_gain_are_checked_against_the_profiles_port() {
+    use libpvcam_sys::pvcam::CameraDescription;
+
+    let description = CameraDescription::parse(
+        r#"{
+          "sensor_size": [64, 32],
+          "params": [
+            { "parameter": "ReadoutPort", "available": true, "access": "ReadWrite", "type": "enum",
+              "current": { "Enum": "Low Noise" },
+              "options": [{ "value": 0, "name": "Low Noise" }, { "value": 2, "name": "High Speed" }] },
+            { "parameter": "SpeedTableIndex", "available": true, "access": "ReadWrite", "type": "int16",
+              "current": { "Int": 0 }, "min": { "Int": 0 }, "max": { "Int": 0 } },
+            { "parameter": "GainIndex", "available": true, "access": "ReadWrite", "type": "int16",
+              "current": { "Int": 3 }, "min": { "Int": 1 }, "max": { "Int": 3 } },
+            { "parameter": "BitDepth", "available": true, "access": "ReadOnly", "type": "int16",
+              "current": { "Int": 16 }, "min": { "Int": 16 }, "max": { "Int": 16 } }
+          ],
+          "speed_table": [
+            { "name": "Low Noise", "value": 0, "speeds": [
+              { "index": 0, "bit_depth": 16, "gains": [1, 2, 3] } ] },
+            { "name": "High Speed", "value": 2, "speeds": [
+              { "index": 0, "bit_depth": 12, "gains": [1] },
+              { "index": 1, "bit_depth": 11, "gains": [1, 2] } ] }
+          ]
+        }"#,
+    )
+    .unwrap();
+    let mut camera = Camera::new(SimulatedCamera::from_description(&description).unwrap());
+    let high_speed = |speed, gain| Profile {
+        readout_port: Some("High Speed".to_owned()),
+        speed: Some(speed),
+        gain: Some(gain),
+        ..Profile::default()
+    };
+
+    // the current port has neither speed 1 nor, at speed 0 of High Speed, gain 3
+    let e = camera.apply_profile(&high_speed(0, 3)).unwrap_err();
+    assert!(
+        e.message.contains("3 is outside GainIndex range 1..=1"),
+        "{}",
+        e.message
+    );
+    assert_eq!(current(&camera, Parameter::ReadoutPort), "Low Noise");
+    assert_eq!(current(&camera, Parameter::GainIndex), "3");
+
+    let changes = camera.apply_profile(&high_speed(1, 2)).unwrap();
+    assert_eq!(changes.len(), 3, "{:?}", changes);
+    assert_eq!(current(&camera, Parameter::ReadoutPort), "High Speed");
+    assert_eq!(current(&camera, Parameter::SpeedTableIndex), "1");
+    assert_eq!(current(&camera, Parameter::GainIndex), "2");
+    assert_eq!(current(&camera, Parameter::BitDepth), "11");
+}
+
+// Checking writes nothing; a speed or gain behind a port the profile selects is checked once
+// apply has selected it, and the port is put back with the rest if it does not fit
+#[test]
+fn checking_a_profile_writes_nothing() {
+    let path = std::env::temp_dir().join(format!("pvcam-{}-profile.pvtrace", std::process::id()));
+    let mut camera = Camera::new(RecordingBackend::create(simulated(), &path).unwrap());
+    let profile = Profile {
+        readout_port: Some("Speed".to_owned()),
+        gain: Some(9),
+        expose_out_mode: Some("Global Shutter".to_owned()),
+        ..Profile::default()
+    };
+    let e = camera.apply_profile(&profile).unwrap_err();
+    assert!(!e.message.contains("GainIndex"), "{}", e.message);
+    drop(camera);
+    let trace = Trace::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let writes = trace
+        .unwrap()
+        .calls()
+        .iter()
+        .filter(|call| matches!(call, Call::SetParam { .. }))
+        .count();
+    assert_eq!(writes, 0);
+
+    let mut camera = Camera::new(simulated());
+    let profile = Profile {
+        expose_out_mode: None,
+        ..profile
+    };
+    let e = camera.apply_profile(&profile).unwrap_err();
+    assert!(
+        e.message.contains("9 is outside GainIndex range"),
+        "{}",
+        e.message
+    );
+    assert!(
+        e.message
+            .ends_with("the settings written before it were put back"),
+        "{}",
+        e.message
+    );
+    assert_eq!(current(&camera, Parameter::ReadoutPort), "Sensitivity");
+    assert_eq!(current(&camera, Parameter::GainIndex), "1");
+}
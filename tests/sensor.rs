@@ -0,0 +1,205 @@
+// SensorModel frames against the statistics the model promises: noise fixed by seed and frame,
+// dark and lit means and variances, the full well, binning and where the hot pixels are.
+
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::{Region, SensorConditions, SensorModel};
+
+const SIDE: u16 = 128;
+
+fn conditions(frame: u32) -> SensorConditions {
+    SensorConditions {
+        exposure: Duration::from_secs(1),
+        gain_index: 1,
+        temperature: 25.0,
+        bit_depth: 16,
+        frame,
+    }
+}
+
+fn whole() -> Region {
+    Region::new((1, 0..SIDE - 1), (1, 0..SIDE - 1))
+}
+
+// neither light nor hot pixels
+fn dark() -> SensorModel {
+    SensorModel::new().illumination(0.0).hot_pixels(0.0, 0.0)
+}
+
+fn mean_and_variance(frame: &[u16]) -> (f64, f64) {
+    let n = frame.len() as f64;
+    let mean = frame.iter().map(|&v| v as f64).sum::<f64>() / n;
+    let variance = frame
+        .iter()
+        .map(|&v| (v as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    (mean, variance)
+}
+
+fn assert_near(value: f64, expected: f64, tolerance: f64, what: &str) {
+    assert!(
+        (value - expected).abs() <= tolerance,
+        "{} is {}, expected {} +/- {}",
+        what,
+        value,
+        expected,
+        tolerance
+    );
+}
+
+#[test]
+fn frames_are_fixed_by_seed_and_frame_number() {
+    let model = SensorModel::new().seed(7);
+    let frame = model.render(&[whole()], &conditions(1));
+    assert_eq!(frame.len(), SIDE as usize * SIDE as usize);
+    assert_eq!(frame, model.render(&[whole()], &conditions(1)));
+    assert_ne!(frame, model.render(&[whole()], &conditions(2)));
+    assert_ne!(
+        frame,
+        model.clone().seed(8).render(&[whole()], &conditions(1))
+    );
+
+    // read through a smaller region the pixels are the same
+    let part = model.render(&[Region::new((1, 8..15), (1, 4..7))], &conditions(1));
+    let expected: Vec<u16> = (4..8)
+        .flat_map(|p| (8..16).map(move |s| p * SIDE as usize + s))
+        .map(|i| frame[i])
+        .collect();
+    assert_eq!(part, expected);
+}
+
+#[test]
+fn dark_frames_have_the_bias_dark_current_and_read_noise() {
+    let model = dark().dark_current(10.0, 25.0, 7.0).read_noise(2.0);
+
+    // bias + D t, with variance D t + r^2 and the rounding to whole ADUs
+    let (mean, variance) = mean_and_variance(&model.render(&[whole()], &conditions(1)));
+    assert_near(mean, 110.0, 0.2, "the mean");
+    assert_near(variance, 14.0 + 1.0 / 12.0, 1.0, "the variance");
+
+    // 7 degrees warmer the dark current doubles
+    let warm = SensorConditions {
+        temperature: 32.0,
+        ..conditions(1)
+    };
+    let (mean, _) = mean_and_variance(&model.render(&[whole()], &warm));
+    assert_near(mean, 120.0, 0.2, "the mean 7 degrees warmer");
+}
+
+#[test]
+fn lit_frames_follow_the_conversion_gain() {
+    let model = dark()
+        .illumination(1000.0)
+        .dark_current(0.0, 25.0, 7.0)
+        .read_noise(0.0)
+        .gains(vec![1.0, 0.5]);
+
+    // 1000 e- with a shot noise variance of 1000, at 0.5 e-/ADU
+    let high_gain = SensorConditions {
+        gain_index: 2,
+        ..conditions(1)
+    };
+    let (mean, variance) = mean_and_variance(&model.render(&[whole()], &high_gain));
+    assert_near(mean, 100.0 + 2000.0, 3.0, "the mean");
+    assert_near(variance, 4000.0, 200.0, "the variance");
+
+    let (mean, variance) = mean_and_variance(&model.render(&[whole()], &conditions(1)));
+    assert_near(mean, 100.0 + 1000.0, 1.5, "the mean at gain index 1");
+    assert_near(variance, 1000.0, 50.0, "the variance at gain index 1");
+}
+
+#[test]
+fn charge_is_clipped_to_the_full_well_and_values_to_the_bit_depth() {
+    let model = dark()
+        .illumination(1e6)
+        .full_well(1000.0)
+        .read_noise(0.0)
+        .gains(vec![1.0]);
+
+    let frame = model.render(&[whole()], &conditions(1));
+    assert!(frame.iter().all(|&v| v == 1100), "{:?}", &frame[..8]);
+
+    // binning sums the clipped charge of each pixel
+    let binned = model.render(&[Region::new((2, 0..7), (2, 0..7))], &conditions(1));
+    assert_eq!(binned.len(), 16);
+    assert!(binned.iter().all(|&v| v == 4100), "{:?}", binned);
+
+    let ten_bit = SensorConditions {
+        bit_depth: 10,
+        ..conditions(1)
+    };
+    let binned = model.render(&[Region::new((2, 0..7), (2, 0..7))], &ten_bit);
+    assert!(binned.iter().all(|&v| v == 1023), "{:?}", binned);
+}
+
+#[test]
+fn binned_pixels_hold_the_sum_of_their_charges() {
+    let model = SensorModel::new()
+        .seed(3)
+        .illumination(5.0)
+        .read_noise(0.0)
+        .gains(vec![1.0]);
+
+    let pixels = model.render(&[Region::new((1, 0..15), (1, 0..15))], &conditions(1));
+    let binned = model.render(&[Region::new((2, 0..15), (2, 0..15))], &conditions(1));
+    assert_eq!(binned.len(), 64);
+    for (i, &value) in binned.iter().enumerate() {
+        let (s, p) = (i % 8 * 2, i / 8 * 2);
+        let charge: i64 = [(s, p), (s + 1, p), (s, p + 1), (s + 1, p + 1)]
+            .iter()
+            .map(|&(s, p)| pixels[p * 16 + s] as i64 - 100)
+            .sum();
+        assert_eq!(value as i64 - 100, charge, "binned pixel {}", i);
+    }
+
+    // a binned pixel ending at the last column and row the coordinates can hold
+    let edge = model.render(
+        &[Region::new((2, 65534..65535), (2, 65534..65535))],
+        &conditions(1),
+    );
+    assert_eq!(edge.len(), 1);
+}
+
+#[test]
+fn hot_pixels_stay_where_they_are() {
+    let model = dark()
+        .seed(11)
+        .hot_pixels(0.01, 1000.0)
+        .dark_current(0.0, 25.0, 7.0)
+        .read_noise(0.0);
+    let hot = |model: &SensorModel, frame: u32| -> Vec<usize> {
+        let pixels = model.render(&[whole()], &conditions(frame));
+        (0..pixels.len()).filter(|&i| pixels[i] > 500).collect()
+    };
+
+    // about 1% of the pixels, every frame the same ones
+    let first = hot(&model, 1);
+    let expected = SIDE as f64 * SIDE as f64 * 0.01;
+    assert_near(
+        first.len() as f64,
+        expected,
+        50.0,
+        "the number of hot pixels",
+    );
+    assert_eq!(first, hot(&model, 2));
+
+    // the same ones read through a region that starts elsewhere
+    let region = Region::new((1, 32..95), (1, 16..79));
+    let pixels = model.render(&[region], &conditions(3));
+    let through_region: Vec<usize> = (0..pixels.len())
+        .filter(|&i| pixels[i] > 500)
+        .map(|i| (i / 64 + 16) * SIDE as usize + i % 64 + 32)
+        .collect();
+    let inside: Vec<usize> = first
+        .iter()
+        .copied()
+        .filter(|&i| {
+            (32..96).contains(&(i % SIDE as usize)) && (16..80).contains(&(i / SIDE as usize))
+        })
+        .collect();
+    assert_eq!(through_region, inside);
+
+    // another sensor has them elsewhere
+    assert_ne!(first, hot(&model.clone().seed(12), 1));
+}
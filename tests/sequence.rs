@@ -0,0 +1,84 @@
+// Variable timed sequences on a SimulatedCamera, whose frames carry the exposure the camera
+// reports rather than the one asked for.
+
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::{
+    Camera, ErrorKind, ExposureMode, Parameter, Region, SequenceConfig, SimParam, SimulatedCamera,
+};
+
+fn variable(exposures: &[u64]) -> SequenceConfig {
+    let region = Region::new((1, 0..15), (1, 0..7));
+    let exposures = exposures.iter().map(|ms| Duration::from_millis(*ms));
+    SequenceConfig::new(vec![region], 1, Duration::from_millis(1))
+        .variable_exposures(exposures.collect())
+}
+
+fn exposures_ms(camera: &mut Camera, config: &SequenceConfig) -> Vec<u128> {
+    let sequence = camera.acquire_seq(config).unwrap();
+    sequence
+        .frames
+        .iter()
+        .map(|f| f.exposure.as_millis())
+        .collect()
+}
+
+#[test]
+fn frames_carry_their_exposure() {
+    let mut camera = Camera::new(SimulatedCamera::new().sensor(64, 32).time_scale(0.0));
+    let config = variable(&[2, 4, 6]);
+    assert_eq!(config.exposure_mode(), ExposureMode::VariableTimed);
+    assert_eq!(exposures_ms(&mut camera, &config), [2, 4, 6]);
+}
+
+#[test]
+fn a_refused_exposure_leaves_the_last_one() {
+    // a camera that exposes for at most 4 ms and keeps the 4 ms when asked for 6
+    let camera = SimulatedCamera::new()
+        .sensor(64, 32)
+        .time_scale(0.0)
+        .param(Parameter::ExposureTime as u32, SimParam::long(1, 0, 4));
+    let mut camera = Camera::new(camera);
+    assert_eq!(exposures_ms(&mut camera, &variable(&[2, 4, 6])), [2, 4, 4]);
+}
+
+#[test]
+fn exposures_finer_than_milliseconds_are_refused() {
+    let mut camera = Camera::new(SimulatedCamera::new().sensor(64, 32).time_scale(0.0));
+    let region = Region::new((1, 0..15), (1, 0..7));
+    for exposure in [Duration::from_micros(500), Duration::from_micros(1500)] {
+        let timed = SequenceConfig::new(vec![region], 1, exposure);
+        let variable = timed.clone().variable_exposures(vec![exposure]);
+        for config in [timed, variable] {
+            let e = camera.acquire_seq(&config).unwrap_err();
+            assert_eq!(e.kind, ErrorKind::Binding, "{:?}", exposure);
+        }
+    }
+}
+
+#[test]
+fn timed_exposures_are_not_held_to_param_exp_time() {
+    let mut camera = Camera::new(SimulatedCamera::new().sensor(64, 32).time_scale(0.0));
+    let region = Region::new((1, 0..15), (1, 0..7));
+
+    // pl_exp_setup_seq takes a uns32, PARAM_EXP_TIME a uns16
+    let long = Duration::from_secs(70);
+    let timed = SequenceConfig::new(vec![region], 1, long);
+    let sequence = camera.acquire_seq(&timed).unwrap();
+    assert_eq!(sequence.frames[0].exposure, long);
+
+    let e = camera
+        .acquire_seq(&timed.variable_exposures(vec![long]))
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Binding);
+    assert!(e.message.contains("PARAM_EXP_TIME"), "{}", e.message);
+}
+
+#[test]
+fn more_frames_than_a_sequence_holds_are_refused() {
+    let mut camera = Camera::new(SimulatedCamera::new().sensor(64, 32).time_scale(0.0));
+    let config = variable(&vec![1; u16::MAX as usize + 1]);
+    let e = camera.setup_seq(&config).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Binding);
+    assert!(e.message.starts_with("65536 frames"), "{}", e.message);
+}
@@ -0,0 +1,135 @@
+// The public types through JSON and back. Enums travel as the selected option's name, which the
+// camera the value ends up on resolves against its own options.
+#![cfg(all(feature = "serde", feature = "serde_json"))]
+
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::{
+    Camera, CameraState, ErrorKind, ExposureMode, ParamAttrKind, Parameter, ParameterValue, Region,
+    SequenceConfig, SimParam, SimulatedCamera,
+};
+
+fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+    let json = serde_json::to_string(value).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+fn current(camera: &Camera, parameter: Parameter) -> ParameterValue {
+    camera
+        .backend()
+        .get_param(parameter as u32, ParamAttrKind::Current)
+        .unwrap()
+}
+
+// the clear modes in another order and with other values than the default camera's
+fn reordered() -> SimulatedCamera {
+    SimulatedCamera::new().param(
+        Parameter::ClearMode as u32,
+        SimParam::enumeration(&[(5, "Pre-Sequence"), (7, "Pre-Exposure"), (9, "Never")], 9),
+    )
+}
+
+#[test]
+fn parameter_values_round_trip() {
+    let values = [
+        ParameterValue::Int(-3),
+        ParameterValue::Long(1 << 40),
+        ParameterValue::Float(0.25),
+        ParameterValue::Bool(true),
+        ParameterValue::String("Prime BSI".to_owned()),
+    ];
+    for value in &values {
+        assert_eq!(round_trip(value).to_string(), value.to_string());
+    }
+
+    let camera = Camera::new(SimulatedCamera::new());
+    let mode = current(&camera, Parameter::ClearMode);
+    assert_eq!(
+        serde_json::to_string(&mode).unwrap(),
+        r#"{"Enum":"Pre-Exposure"}"#
+    );
+    assert_eq!(round_trip(&mode).to_string(), "Pre-Exposure");
+    assert!(serde_json::from_str::<ParameterValue>(r#"{"Enum":""}"#).is_err());
+}
+
+#[test]
+fn an_enum_by_name_selects_the_option_with_that_name() {
+    let camera = Camera::new(reordered());
+    let value: ParameterValue = serde_json::from_str(r#"{"Enum":"Pre-Exposure"}"#).unwrap();
+    camera
+        .backend()
+        .set_param(Parameter::ClearMode as u32, value)
+        .unwrap();
+    match current(&camera, Parameter::ClearMode) {
+        ParameterValue::Enum(idx, options) => {
+            assert_eq!(idx, 1);
+            assert_eq!(options[1].value, 7);
+            assert_eq!(options[1].name, "Pre-Exposure");
+        }
+        value => panic!("{:?}", value),
+    }
+
+    let unknown: ParameterValue = serde_json::from_str(r#"{"Enum":"Always"}"#).unwrap();
+    let e = camera
+        .backend()
+        .set_param(Parameter::ClearMode as u32, unknown)
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Binding);
+    assert_eq!(
+        current(&camera, Parameter::ClearMode).to_string(),
+        "Pre-Exposure"
+    );
+}
+
+#[test]
+fn a_deserialized_snapshot_restores_onto_another_camera() {
+    let camera = Camera::new(SimulatedCamera::new());
+    camera
+        .backend()
+        .set_param(Parameter::ClearCycles as u32, ParameterValue::Int(7))
+        .unwrap();
+    let state: CameraState = round_trip(&camera.snapshot());
+    assert_eq!(
+        state.get(Parameter::ClearMode).unwrap().to_string(),
+        "Pre-Exposure"
+    );
+
+    let mut other = Camera::new(reordered());
+    let report = other.restore(&state);
+    assert!(report.is_ok(), "{:?}", report);
+    assert_eq!(
+        current(&other, Parameter::ClearMode).to_string(),
+        "Pre-Exposure"
+    );
+    assert_eq!(current(&other, Parameter::ClearCycles).to_string(), "7");
+}
+
+#[test]
+fn sequence_configs_round_trip() {
+    let config = SequenceConfig::new(
+        vec![Region::new((2, 0..63), (2, 10..41))],
+        3,
+        Duration::from_millis(5),
+    )
+    .exp_mode(ExposureMode::VariableTimed)
+    .variable_exposures(vec![Duration::from_millis(5), Duration::from_millis(8)]);
+
+    let back = round_trip(&config);
+    assert_eq!(back.regions(), config.regions());
+    assert_eq!(back.frames(), config.frames());
+    assert_eq!(back.exposure_mode(), ExposureMode::VariableTimed);
+    assert_eq!(back.exposures(), config.exposures());
+}
+
+// Deserializing skips Region::new's checks, so such a region has to come out empty rather than
+// dividing by zero or wrapping around
+#[test]
+fn a_deserialized_region_new_would_refuse_has_no_pixels() {
+    let region = |json: &str| -> Region { serde_json::from_str(json).unwrap() };
+
+    let unbinned = region(r#"{"s1":0,"s2":7,"sbin":0,"p1":0,"p2":7,"pbin":1}"#);
+    assert_eq!((unbinned.width(), unbinned.height()), (0, 8));
+
+    let reversed = region(r#"{"s1":0,"s2":7,"sbin":1,"p1":7,"p2":0,"pbin":1}"#);
+    assert_eq!((reversed.width(), reversed.height()), (8, 0));
+}
@@ -0,0 +1,281 @@
+// SimulatedCamera as a stand-in for PvcamBackend: parameter access, ranges and refusals fail
+// with the ErrorKind a real camera's would, and continuous acquisition wraps its buffer.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libpvcam_sys::pvcam::{
+    CameraBackend, ErrorKind, ExposureMode, ParamAttrKind, Parameter, ParameterAccess,
+    ParameterValue, Region, SimParam, SimulatedCamera,
+};
+
+fn set(camera: &SimulatedCamera, parameter: Parameter, value: ParameterValue) -> Option<ErrorKind> {
+    camera
+        .set_param(parameter as u32, value)
+        .err()
+        .map(|e| e.kind)
+}
+
+fn get(camera: &SimulatedCamera, parameter: Parameter, attr: ParamAttrKind) -> String {
+    camera
+        .get_param(parameter as u32, attr)
+        .unwrap()
+        .to_string()
+}
+
+fn current(camera: &SimulatedCamera, parameter: Parameter) -> String {
+    get(camera, parameter, ParamAttrKind::Current)
+}
+
+#[test]
+fn parameters_report_their_attributes() {
+    let camera = SimulatedCamera::new();
+
+    assert_eq!(
+        get(&camera, Parameter::ClearCycles, ParamAttrKind::Available),
+        "true"
+    );
+    assert_eq!(
+        get(&camera, Parameter::ClearCycles, ParamAttrKind::Min),
+        "0"
+    );
+    assert_eq!(
+        get(&camera, Parameter::ClearCycles, ParamAttrKind::Max),
+        "16"
+    );
+    assert_eq!(
+        get(&camera, Parameter::ClearCycles, ParamAttrKind::Count),
+        "17"
+    );
+    assert_eq!(
+        get(&camera, Parameter::ClearCycles, ParamAttrKind::Access),
+        (ParameterAccess::ReadWrite as i32).to_string()
+    );
+    assert_eq!(
+        get(&camera, Parameter::ChipName, ParamAttrKind::Access),
+        (ParameterAccess::ReadOnly as i32).to_string()
+    );
+    assert_eq!(
+        get(&camera, Parameter::ReadoutPort, ParamAttrKind::Count),
+        "2"
+    );
+    let ports: Vec<String> = camera
+        .get_enums(Parameter::ReadoutPort as u32)
+        .unwrap()
+        .iter()
+        .map(|e| e.name.clone())
+        .collect();
+    assert_eq!(ports, ["Sensitivity", "Speed"]);
+
+    // one the camera does not have is unavailable, and nothing else can be asked of it
+    let camera = camera.without_param(Parameter::ClearCycles as u32);
+    assert_eq!(
+        get(&camera, Parameter::ClearCycles, ParamAttrKind::Available),
+        "false"
+    );
+    let e = camera
+        .get_param(Parameter::ClearCycles as u32, ParamAttrKind::Current)
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Pvcam);
+    assert_eq!(
+        set(&camera, Parameter::ClearCycles, ParameterValue::Int(1)),
+        Some(ErrorKind::Pvcam)
+    );
+}
+
+#[test]
+fn refusals_have_the_kind_pvcam_backend_gives_them() {
+    let camera = SimulatedCamera::new().param(
+        Parameter::MetadataEnabled as u32,
+        SimParam::boolean(false).access(ParameterAccess::WriteOnly),
+    );
+
+    // PVCam refuses these
+    assert_eq!(
+        set(&camera, Parameter::AdcOffset, ParameterValue::Int(100)),
+        Some(ErrorKind::Pvcam),
+        "read only"
+    );
+    assert_eq!(
+        set(&camera, Parameter::ClearCycles, ParameterValue::Int(17)),
+        Some(ErrorKind::Pvcam),
+        "above the maximum"
+    );
+    assert_eq!(
+        set(&camera, Parameter::ClearCycles, ParameterValue::Long(-1)),
+        Some(ErrorKind::Pvcam),
+        "below the minimum"
+    );
+    assert_eq!(
+        set(
+            &camera,
+            Parameter::ClearMode,
+            ParameterValue::Enum(7, vec![])
+        ),
+        Some(ErrorKind::Pvcam),
+        "an option value the camera does not have"
+    );
+    let e = camera
+        .get_param(Parameter::MetadataEnabled as u32, ParamAttrKind::Current)
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Pvcam, "write only");
+
+    // PvcamBackend refuses these before calling PVCam
+    assert_eq!(
+        set(
+            &camera,
+            Parameter::ChipName,
+            ParameterValue::String("x".into())
+        ),
+        Some(ErrorKind::Binding),
+        "strings are not written at all"
+    );
+    let mut modes = camera.get_enums(Parameter::ClearMode as u32).unwrap();
+    modes[0].name = "Always".to_owned();
+    assert_eq!(
+        set(
+            &camera,
+            Parameter::ClearMode,
+            ParameterValue::Enum(0, modes)
+        ),
+        Some(ErrorKind::Binding),
+        "an option by a name the camera does not have"
+    );
+    assert_eq!(
+        set(&camera, Parameter::ClearCycles, ParameterValue::Bool(true)),
+        Some(ErrorKind::Binding),
+        "the wrong type"
+    );
+    assert_eq!(
+        set(&camera, Parameter::MetadataEnabled, ParameterValue::Int(1)),
+        Some(ErrorKind::Binding),
+        "a number for a boolean"
+    );
+    assert_eq!(
+        set(
+            &camera,
+            Parameter::ClearCycles,
+            ParameterValue::Long(1 << 40)
+        ),
+        Some(ErrorKind::Binding),
+        "too wide for an int32"
+    );
+    assert_eq!(current(&camera, Parameter::ClearCycles), "2");
+
+    assert_eq!(
+        set(&camera, Parameter::ClearCycles, ParameterValue::Long(16)),
+        None
+    );
+    assert_eq!(current(&camera, Parameter::ClearCycles), "16");
+    assert_eq!(
+        set(
+            &camera,
+            Parameter::MetadataEnabled,
+            ParameterValue::Bool(true)
+        ),
+        None
+    );
+}
+
+#[test]
+fn continuous_acquisition_wraps_its_buffer() {
+    const SLOTS: usize = 3;
+    let camera = SimulatedCamera::new().sensor(16, 8).time_scale(0.0);
+    let regions = [Region::new((1, 0..3), (1, 0..1))];
+    let frame_bytes = camera
+        .exp_setup_cont(&regions, ExposureMode::Timed, 1)
+        .unwrap() as usize;
+    let frame_len = frame_bytes / 2;
+    assert_eq!(frame_len, 8);
+    let mut buffer = vec![0u16; frame_len * SLOTS];
+    let start = buffer.as_ptr() as usize;
+
+    // (frame number, slot, bytes arrived, times filled) as each frame arrives; the next frame
+    // waits for the callback, so the status matches the frame
+    let seen = Arc::new(Mutex::new(vec![]));
+    let record = seen.clone();
+    camera
+        .register_eof(Box::new(move |info, backend| {
+            let (_, bytes_arrived, buffer_cnt) = backend.exp_check_cont_status().unwrap();
+            let (frame, _) = backend.exp_get_latest_frame().unwrap();
+            let slot = (frame as usize - start) / frame_bytes;
+            record.lock().unwrap().push((
+                info.unwrap().number as usize,
+                slot,
+                bytes_arrived as usize,
+                buffer_cnt as usize,
+            ));
+        }))
+        .unwrap();
+    unsafe {
+        camera
+            .exp_start_cont(buffer.as_mut_ptr(), (frame_bytes * SLOTS) as u32)
+            .unwrap()
+    };
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while seen.lock().unwrap().len() < 3 * SLOTS {
+        assert!(
+            Instant::now() < deadline,
+            "the buffer never filled three times"
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+    camera.exp_stop_cont().unwrap();
+
+    let seen = seen.lock().unwrap().clone();
+    for (i, (number, slot, bytes_arrived, buffer_cnt)) in seen.iter().copied().enumerate() {
+        assert_eq!(number, i + 1);
+        assert_eq!(slot, i % SLOTS, "frame {}", number);
+        assert_eq!(bytes_arrived, (slot + 1) * frame_bytes, "frame {}", number);
+        assert_eq!(buffer_cnt, number / SLOTS, "frame {}", number);
+    }
+
+    // every slot holds the last frame written to it; the ramp starts at the frame's index, mod 4096
+    let written = seen.len();
+    for slot in 0..SLOTS {
+        let newest = (0..written).rev().find(|n| n % SLOTS == slot).unwrap();
+        assert_eq!(
+            buffer[slot * frame_len] as usize,
+            newest % 4096,
+            "slot {}",
+            slot
+        );
+    }
+}
+
+#[test]
+fn the_temperature_approaches_the_setpoint() {
+    let camera = SimulatedCamera::new();
+    assert_eq!(current(&camera, Parameter::Temperature), "-1000");
+    assert_eq!(
+        set(
+            &camera,
+            Parameter::TemperatureSetpoint,
+            ParameterValue::Int(-2000)
+        ),
+        None
+    );
+
+    // seconds to get there at the real pace, a fraction of a degree in the meantime
+    thread::sleep(Duration::from_millis(50));
+    let temperature: i64 = current(&camera, Parameter::Temperature).parse().unwrap();
+    assert!(
+        (-1500..-1000).contains(&temperature),
+        "{} after 50 ms",
+        temperature
+    );
+
+    // and there in a blink a thousand times faster
+    let camera = SimulatedCamera::new().time_scale(0.001);
+    assert_eq!(
+        set(
+            &camera,
+            Parameter::TemperatureSetpoint,
+            ParameterValue::Int(2000)
+        ),
+        None
+    );
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(current(&camera, Parameter::Temperature), "2000");
+}
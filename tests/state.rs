@@ -0,0 +1,108 @@
+// Snapshots of a SimulatedCamera restored onto it after changes, or onto another camera that
+// lists its post-processing features in another order.
+
+use libpvcam_sys::pvcam::{Camera, ParamAttrKind, Parameter, ParameterValue, SimulatedCamera};
+
+fn simulated() -> SimulatedCamera {
+    SimulatedCamera::new()
+        .post_processing("Denoise", &[("Enabled", 0, 1), ("Strength", 5, 10)])
+        .post_processing("Despeckle", &[("Threshold", 100, 1000)])
+}
+
+fn current(camera: &Camera, parameter: Parameter) -> String {
+    camera
+        .backend()
+        .get_param(parameter as u32, ParamAttrKind::Current)
+        .unwrap()
+        .to_string()
+}
+
+fn set(camera: &Camera, parameter: Parameter, value: ParameterValue) {
+    camera.backend().set_param(parameter as u32, value).unwrap();
+}
+
+fn post_processing(camera: &Camera) -> Vec<(String, String, u32)> {
+    let mut values: Vec<_> = camera
+        .snapshot()
+        .post_processing
+        .into_iter()
+        .map(|v| (v.feature, v.param, v.value))
+        .collect();
+    values.sort();
+    values
+}
+
+#[test]
+fn restore_undoes_changes_since_the_snapshot() {
+    let mut camera = Camera::new(simulated());
+    let state = camera.snapshot();
+    assert!(state.is_complete(), "{:?}", state.failed);
+    assert_eq!(state.post_processing.len(), 3);
+    assert_eq!(current(&camera, Parameter::ReadoutPort), "Sensitivity");
+
+    set(&camera, Parameter::ClearCycles, ParameterValue::Int(9));
+    set(&camera, Parameter::ExposureTime, ParameterValue::Long(250));
+    let ports = camera
+        .backend()
+        .get_enums(Parameter::ReadoutPort as u32)
+        .unwrap();
+    set(
+        &camera,
+        Parameter::ReadoutPort,
+        ParameterValue::Enum(1, ports),
+    );
+    let changed = camera.snapshot();
+    assert_eq!(current(&camera, Parameter::ReadoutPort), "Speed");
+    assert_eq!(
+        changed.get(Parameter::ClearCycles).unwrap().to_string(),
+        "9"
+    );
+
+    let report = camera.restore(&state);
+    assert!(report.is_ok(), "{:?}", report);
+    assert_eq!(current(&camera, Parameter::ReadoutPort), "Sensitivity");
+    assert_eq!(current(&camera, Parameter::ClearCycles), "2");
+    assert_eq!(current(&camera, Parameter::ExposureTime), "10");
+    assert!(report.restored.contains(&Parameter::ClearCycles));
+    // read only, so there is nothing to put back
+    for parameter in [Parameter::Temperature, Parameter::ChipName] {
+        assert!(report.skipped.contains(&parameter), "{:?}", parameter);
+        assert!(!report.restored.contains(&parameter), "{:?}", parameter);
+    }
+    // the camera has no such parameter, so the snapshot has none either
+    assert!(state.get(Parameter::ScanMode).is_none());
+}
+
+#[test]
+fn post_processing_is_restored_by_name() {
+    let camera = Camera::new(simulated());
+    let state = camera.snapshot();
+    for (i, value) in state.post_processing.iter().enumerate() {
+        assert_eq!(value.value, [0, 5, 100][i], "{:?}", value);
+    }
+
+    // the same features, listed the other way round and set to something else
+    let mut other = Camera::new(
+        SimulatedCamera::new()
+            .post_processing("Despeckle", &[("Threshold", 20, 1000)])
+            .post_processing("Denoise", &[("Strength", 1, 10), ("Enabled", 1, 1)]),
+    );
+    let report = other.restore(&state);
+    assert!(report.is_ok(), "{:?}", report.failed_post_processing);
+    assert_eq!(post_processing(&other), post_processing(&camera));
+
+    // one the camera does not have is reported, the others still land
+    let mut fewer =
+        Camera::new(SimulatedCamera::new().post_processing("Denoise", &[("Strength", 1, 10)]));
+    let report = fewer.restore(&state);
+    let failed: Vec<&str> = report
+        .failed_post_processing
+        .iter()
+        .map(|(value, _)| value.param.as_str())
+        .collect();
+    assert_eq!(failed, ["Enabled", "Threshold"]);
+    assert_eq!(
+        post_processing(&fewer),
+        [("Denoise".to_owned(), "Strength".to_owned(), 5)]
+    );
+}
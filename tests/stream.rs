@@ -0,0 +1,138 @@
+// Records frames through a StreamRecorder and reads the recording back through its index, or
+// recovers the index of a recording cut short.
+
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::io::stream::{self, StreamConfig, StreamReader, StreamRecorder};
+use libpvcam_sys::pvcam::{Region, Sequence};
+
+mod common;
+
+use common::temp_path;
+
+fn acquire(frames: u16) -> Sequence {
+    let regions = vec![
+        Region::new((1, 0..31), (1, 0..15)),
+        Region::new((2, 32..47), (2, 16..27)),
+    ];
+    common::acquire(regions, frames, Duration::from_millis(1)).1
+}
+
+fn remove(path: &PathBuf) {
+    let _ = fs::remove_file(path);
+    let _ = fs::remove_file(stream::index_path(path));
+}
+
+#[test]
+fn recordings_read_back_frame_for_frame() {
+    let sequence = acquire(20);
+    let path = temp_path("round-trip.raw");
+    for direct in [false, true] {
+        // a frame is 1,120 bytes, so frames straddle the 4,096 byte writes
+        let config = StreamConfig::new()
+            .write_size(4000)
+            .preallocate(1 << 20)
+            .direct(direct)
+            .block(true);
+        let mut recorder = match StreamRecorder::create(&path, &config) {
+            Ok(recorder) => recorder,
+            // O_DIRECT is up to the file system, tmpfs refuses it
+            Err(_) if direct => continue,
+            Err(e) => panic!("{}", e),
+        };
+        for frame in sequence.frames.iter() {
+            recorder.record(frame.clone()).unwrap();
+        }
+        let mut other = sequence.frames[0].clone();
+        other.regions.pop();
+        assert!(recorder.record(other).is_err(), "regions differ");
+        let recorded = recorder.finish().unwrap();
+        assert_eq!((recorded.frames, recorded.dropped), (20, 0));
+        // the recording's header with two regions, then a 48 byte header per frame
+        assert_eq!(recorded.bytes, 40 + 20 * (48 + 1120));
+        assert_eq!(fs::metadata(&path).unwrap().len(), recorded.bytes);
+
+        let mut reader = StreamReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 20);
+        assert_eq!(reader.entries()[3].offset, 40 + 3 * 1168);
+        let read = reader.read_sequence().unwrap();
+        assert_eq!(read.regions, sequence.regions);
+        for (read, frame) in read.frames.iter().zip(sequence.frames.iter()) {
+            assert_eq!(read.number, frame.number);
+            assert_eq!(read.timestamp, frame.timestamp);
+            assert_eq!(read.exposure, frame.exposure);
+            assert_eq!(read.data, frame.data);
+        }
+        assert!(reader.read_frame(20).is_err());
+        remove(&path);
+    }
+}
+
+#[test]
+fn a_full_queue_drops_frames_rather_than_wait() {
+    let sequence = acquire(10);
+    let path = temp_path("back-pressure.raw");
+    let config = StreamConfig::new().write_size(4096).queue_frames(1);
+    let mut recorder = StreamRecorder::create(&path, &config).unwrap();
+
+    let mut sent = 0;
+    for _ in 0..50 {
+        for frame in sequence.frames.iter() {
+            let backlog = recorder.record(frame.clone()).unwrap();
+            assert_eq!(backlog.capacity, 1);
+            sent += 1;
+        }
+    }
+    let dropped = recorder.backlog().dropped;
+    let recorded = recorder.finish().unwrap();
+    assert_eq!(recorded.dropped, dropped);
+    assert_eq!(recorded.frames + recorded.dropped, sent);
+
+    let reader = StreamReader::open(&path).unwrap();
+    assert_eq!(reader.len() as u64, recorded.frames);
+    remove(&path);
+}
+
+#[test]
+fn recover_rebuilds_the_index_of_what_survived() {
+    let sequence = acquire(10);
+    let path = temp_path("recover.raw");
+    let config = StreamConfig::new().write_size(4096).block(true);
+    let mut recorder = StreamRecorder::create(&path, &config).unwrap();
+    for frame in sequence.frames.iter() {
+        recorder.record(frame.clone()).unwrap();
+    }
+    // the recorder dies before it writes the index
+    drop(recorder);
+    assert!(StreamReader::open(&path).is_err());
+
+    // frame 3 takes a hit, and the power goes halfway through frame 8
+    let record = |sequence: u64| 40 + sequence * 1168;
+    let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+    file.seek(SeekFrom::Start(record(3) + 48 + 100)).unwrap();
+    file.write_all(&[0xff, 0xfe]).unwrap();
+    file.set_len(record(8) + 600).unwrap();
+    drop(file);
+
+    let recovery = stream::recover(&path).unwrap();
+    let survived: Vec<u64> = recovery.entries.iter().map(|e| e.sequence).collect();
+    assert_eq!(survived, [0, 1, 2, 4, 5, 6, 7]);
+    assert_eq!(recovery.missing, [3]);
+    assert_eq!(recovery.damaged, [record(3)]);
+    assert_eq!(recovery.trailing, 600);
+
+    let read = StreamReader::open(&path).unwrap().read_sequence().unwrap();
+    assert_eq!(read.frames.len(), 7);
+    for (read, index) in read.frames.iter().zip(survived) {
+        let frame = &sequence.frames[index as usize];
+        assert_eq!(
+            (read.number, read.timestamp),
+            (frame.number, frame.timestamp)
+        );
+        assert_eq!(read.data, frame.data);
+    }
+    remove(&path);
+}
@@ -0,0 +1,335 @@
+// Writes simulated acquisitions as TIFF and OME-TIFF and reads them back with a minimal reader
+// of both layouts, checking the tags and pixels every page ends up with and the OME-XML.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::Cursor;
+use std::process::Command;
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::io::ome_tiff::OmeTiffWriter;
+use libpvcam_sys::pvcam::io::tiff::TiffWriter;
+use libpvcam_sys::pvcam::io::Metadata;
+use libpvcam_sys::pvcam::{Region, Sequence};
+
+mod common;
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Numbers(Vec<u64>),
+    Ascii(String),
+}
+
+// The tags of every page, and whether the file is a BigTIFF
+fn read_tiff(bytes: &[u8]) -> (bool, Vec<BTreeMap<u16, Value>>) {
+    let u16_at = |at: usize| u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap());
+    let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    assert_eq!(&bytes[..2], b"II");
+    let big = match u16_at(2) {
+        42 => false,
+        43 => true,
+        other => panic!("not a TIFF: {}", other),
+    };
+    let offset = |at: usize| match big {
+        true => u64_at(at) as usize,
+        false => u32_at(at) as usize,
+    };
+
+    let mut pages = vec![];
+    let mut at = offset(if big { 8 } else { 4 });
+    while at != 0 {
+        let (count, entry_len, header) = match big {
+            true => (u64_at(at) as usize, 20, 8),
+            false => (u16_at(at) as usize, 12, 2),
+        };
+        let mut tags = BTreeMap::new();
+        for i in 0..count {
+            let entry = at + header + i * entry_len;
+            let (tag, kind) = (u16_at(entry), u16_at(entry + 2));
+            let n = offset(entry + 4);
+            let size = match kind {
+                2 => 1,
+                3 => 2,
+                4 => 4,
+                16 => 8,
+                other => panic!("unexpected type {}", other),
+            };
+            let field = entry + if big { 12 } else { 8 };
+            let data = match n * size <= if big { 8 } else { 4 } {
+                true => field,
+                false => offset(field),
+            };
+            let value = match kind {
+                2 => Value::Ascii(String::from_utf8(bytes[data..data + n - 1].to_vec()).unwrap()),
+                _ => Value::Numbers(
+                    (0..n)
+                        .map(|i| match size {
+                            2 => u16_at(data + i * 2) as u64,
+                            4 => u32_at(data + i * 4) as u64,
+                            _ => u64_at(data + i * 8),
+                        })
+                        .collect(),
+                ),
+            };
+            assert!(tags.insert(tag, value).is_none());
+        }
+        pages.push(tags);
+        at = offset(at + header + count * entry_len);
+    }
+
+    (big, pages)
+}
+
+fn number(page: &BTreeMap<u16, Value>, tag: u16) -> u64 {
+    match &page[&tag] {
+        Value::Numbers(v) => v[0],
+        other => panic!("{:?}", other),
+    }
+}
+
+fn description(page: &BTreeMap<u16, Value>) -> BTreeMap<String, String> {
+    match &page[&270] {
+        Value::Ascii(v) => v
+            .lines()
+            .map(|line| {
+                let (key, value) = line.split_once('=').unwrap();
+                (key.to_owned(), value.to_owned())
+            })
+            .collect(),
+        other => panic!("{:?}", other),
+    }
+}
+
+fn acquire(regions: Vec<Region>, frames: u16) -> (Metadata, Sequence) {
+    common::acquire(regions, frames, Duration::from_millis(3))
+}
+
+fn write(metadata: &Metadata, sequence: &Sequence, big: bool) -> Vec<u8> {
+    let mut tiff = TiffWriter::new(Cursor::new(vec![]), metadata.clone())
+        .unwrap()
+        .big_tiff(big);
+    for frame in sequence.frames.iter() {
+        tiff.write_frame(frame).unwrap();
+    }
+    tiff.finish().unwrap().into_inner()
+}
+
+#[test]
+fn pages_carry_pixels_and_metadata() {
+    let (metadata, sequence) = acquire(vec![Region::new((2, 0..15), (1, 4..11))], 3);
+    assert!(metadata.serial.is_some());
+    let bytes = write(&metadata, &sequence, false);
+
+    let (big, pages) = read_tiff(&bytes);
+    assert!(!big);
+    assert_eq!(pages.len(), 3);
+    for (i, (page, frame)) in pages.iter().zip(sequence.frames.iter()).enumerate() {
+        assert_eq!(number(page, 256), 8);
+        assert_eq!(number(page, 257), 8);
+        assert_eq!(number(page, 258), 16);
+        assert_eq!(number(page, 279), 8 * 8 * 2);
+        assert_eq!(page[&297], Value::Numbers(vec![i as u64, 3]));
+
+        let start = number(page, 273) as usize;
+        let pixels: Vec<u16> = bytes[start..start + 128]
+            .chunks(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(pixels, frame.data);
+
+        let description = description(page);
+        assert_eq!(description["serial"], *metadata.serial.as_ref().unwrap());
+        assert_eq!(description["frame"], frame.number.to_string());
+        assert_eq!(description["exposure_ms"], "3");
+        assert_eq!(description["roi"], "0,15,4,11");
+        assert_eq!(description["binning"], "2x1");
+        assert_eq!(
+            description.get("timestamp"),
+            frame.timestamp.map(|t| t.to_string()).as_ref()
+        );
+        assert!(!description.contains_key("region"));
+    }
+}
+
+#[test]
+fn big_tiff_holds_the_same_pages() {
+    let regions = vec![
+        Region::new((1, 0..7), (1, 0..3)),
+        Region::new((1, 32..35), (1, 16..31)),
+    ];
+    let (metadata, sequence) = acquire(regions, 2);
+    let classic = write(&metadata, &sequence, false);
+    let big = write(&metadata, &sequence, true);
+
+    let (_, classic_pages) = read_tiff(&classic);
+    let (is_big, big_pages) = read_tiff(&big);
+    assert!(is_big);
+    // every region of every frame is a page
+    assert_eq!(big_pages.len(), 4);
+    assert_eq!(classic_pages, big_pages);
+    assert_eq!(number(&big_pages[1], 256), 4);
+    assert_eq!(number(&big_pages[1], 257), 16);
+    assert_eq!(description(&big_pages[3])["region"], "1");
+    // pixels are where they would be in either
+    assert_eq!(classic[16..16 + 2 * 8 * 4], big[16..16 + 2 * 8 * 4]);
+
+    let empty = TiffWriter::new(Cursor::new(vec![]), metadata).unwrap();
+    assert!(empty.finish().is_err());
+}
+
+fn write_ome(metadata: &Metadata, sequence: &Sequence) -> (Vec<BTreeMap<u16, Value>>, String) {
+    let mut tiff = OmeTiffWriter::new(Cursor::new(vec![]), metadata.clone()).unwrap();
+    tiff.write_sequence(sequence).unwrap();
+    let (_, pages) = read_tiff(&tiff.finish().unwrap().into_inner());
+    let xml = match &pages[0][&270] {
+        Value::Ascii(xml) => xml.clone(),
+        other => panic!("{:?}", other),
+    };
+
+    (pages, xml)
+}
+
+fn attribute<'a>(node: &roxmltree::Node<'a, '_>, name: &str) -> &'a str {
+    node.attribute(name)
+        .unwrap_or_else(|| panic!("{:?} has no {}", node.tag_name(), name))
+}
+
+fn elements<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &'static str,
+) -> Vec<roxmltree::Node<'a, 'input>> {
+    node.children().filter(|n| n.has_tag_name(name)).collect()
+}
+
+#[test]
+fn ome_xml_has_regions_as_channels_and_frames_as_time() {
+    let regions = vec![
+        Region::new((2, 0..15), (2, 0..15)),
+        Region::new((2, 32..47), (2, 16..31)),
+    ];
+    let (metadata, sequence) = acquire(regions, 3);
+    let (pages, xml) = write_ome(&metadata, &sequence);
+    assert_eq!(pages.len(), 6);
+
+    let document = roxmltree::Document::parse(&xml).unwrap();
+    let ome = document.root_element();
+    assert_eq!(
+        ome.tag_name().namespace(),
+        Some("http://www.openmicroscopy.org/Schemas/OME/2016-06")
+    );
+    let detector = ome
+        .descendants()
+        .find(|n| n.has_tag_name("Detector"))
+        .unwrap();
+    assert_eq!(attribute(&detector, "SerialNumber"), "SIM0001");
+    assert_eq!(attribute(&detector, "Model"), "SimulatedCamera");
+
+    let images = elements(ome, "Image");
+    assert_eq!(images.len(), 1);
+    let pixels = elements(images[0], "Pixels")[0];
+    assert_eq!(attribute(&pixels, "DimensionOrder"), "XYCZT");
+    assert_eq!(attribute(&pixels, "SizeX"), "8");
+    assert_eq!(attribute(&pixels, "SizeY"), "8");
+    assert_eq!(attribute(&pixels, "SizeC"), "2");
+    assert_eq!(attribute(&pixels, "SizeT"), "3");
+    // 6500 nm pixels binned 2x2
+    assert_eq!(attribute(&pixels, "PhysicalSizeX"), "13");
+    assert_eq!(attribute(&pixels, "PhysicalSizeY"), "13");
+
+    let channels = elements(pixels, "Channel");
+    assert_eq!(channels.len(), 2);
+    let settings = elements(channels[1], "DetectorSettings")[0];
+    assert_eq!(attribute(&settings, "Binning"), "2x2");
+
+    let ifds: Vec<(&str, &str, &str)> = elements(pixels, "TiffData")
+        .iter()
+        .map(|n| {
+            (
+                attribute(n, "IFD"),
+                attribute(n, "FirstC"),
+                attribute(n, "FirstT"),
+            )
+        })
+        .collect();
+    assert_eq!(
+        ifds,
+        [
+            ("0", "0", "0"),
+            ("1", "1", "0"),
+            ("2", "0", "1"),
+            ("3", "1", "1"),
+            ("4", "0", "2"),
+            ("5", "1", "2")
+        ]
+    );
+
+    let planes = elements(pixels, "Plane");
+    assert_eq!(planes.len(), 6);
+    assert_eq!(attribute(&planes[0], "DeltaT"), "0");
+    assert_eq!(attribute(&planes[5], "ExposureTime"), "3");
+    let first = sequence.frames[0].timestamp.unwrap();
+    let last = sequence.frames[2].timestamp.unwrap();
+    let delta: f64 = attribute(&planes[5], "DeltaT").parse().unwrap();
+    assert!((delta - (last - first) as f64 / 1e6).abs() < 1e-9);
+
+    let chip = ome
+        .descendants()
+        .find(|n| n.has_tag_name("M") && n.attribute("K") == Some("chip"))
+        .unwrap();
+    assert_eq!(chip.text(), Some("SimSensor"));
+}
+
+#[test]
+fn regions_of_different_sizes_are_images_of_their_own() {
+    let regions = vec![
+        Region::new((1, 0..7), (1, 0..3)),
+        Region::new((1, 32..35), (1, 16..31)),
+    ];
+    let (metadata, sequence) = acquire(regions, 2);
+    let (_, xml) = write_ome(&metadata, &sequence);
+
+    let document = roxmltree::Document::parse(&xml).unwrap();
+    let images = elements(document.root_element(), "Image");
+    assert_eq!(images.len(), 2);
+    let pixels = elements(images[1], "Pixels")[0];
+    assert_eq!(attribute(&pixels, "SizeX"), "4");
+    assert_eq!(attribute(&pixels, "SizeY"), "16");
+    assert_eq!(attribute(&pixels, "SizeC"), "1");
+    assert_eq!(attribute(&pixels, "SizeT"), "2");
+    let ifds: Vec<&str> = elements(pixels, "TiffData")
+        .iter()
+        .map(|n| attribute(n, "IFD"))
+        .collect();
+    assert_eq!(ifds, ["1", "3"]);
+}
+
+// Needs xmllint and the 2016-06 schema, which is not bundled:
+//   OME_XSD=ome.xsd cargo test --test tiff -- --ignored
+#[test]
+#[ignore = "needs OME_XSD set to the path of ome.xsd and xmllint"]
+fn ome_xml_validates_against_the_schema() {
+    let schema = std::env::var("OME_XSD").expect("OME_XSD is not set");
+    let regions = vec![
+        Region::new((2, 0..15), (2, 0..15)),
+        Region::new((1, 32..35), (1, 16..31)),
+    ];
+    let (metadata, sequence) = acquire(regions, 2);
+    let (_, xml) = write_ome(&metadata, &sequence);
+
+    let path = std::env::temp_dir().join(format!("pvcam-ome-{}.xml", std::process::id()));
+    std::fs::write(&path, xml).unwrap();
+    let output = Command::new("xmllint")
+        .arg("--noout")
+        .arg("--schema")
+        .arg(&schema)
+        .arg(&path)
+        .output()
+        .expect("xmllint is not installed");
+    let _ = std::fs::remove_file(&path);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,198 @@
+// Records a session on a SimulatedCamera and plays it back through a ReplayBackend, the way a
+// trace from a rig is turned into a test.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::{
+    Call, Camera, CameraBackend, CaptureStatus, ContinuousConfig, ErrorKind, ExposureMode, Frame,
+    ParamAttrKind, Parameter, ParameterValue, RecordingBackend, Region, ReplayBackend, SensorModel,
+    SequenceConfig, SimulatedCamera, Trace,
+};
+
+fn trace_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "libpvcam-sys-{}-{}.pvtrace",
+        name,
+        std::process::id()
+    ))
+}
+
+// What the session saw, to compare the replay against
+#[derive(Debug, PartialEq)]
+struct Session {
+    temperature: String,
+    sequence: Vec<(u32, Duration, Vec<u16>)>,
+    continuous: Vec<(u32, Vec<u16>)>,
+}
+
+fn frames(frames: &[Frame]) -> Vec<(u32, Duration, Vec<u16>)> {
+    frames
+        .iter()
+        .map(|f| (f.number, f.exposure, f.data.clone()))
+        .collect()
+}
+
+fn session(camera: &mut Camera) -> Session {
+    let temperature = camera
+        .backend()
+        .get_param(Parameter::Temperature as u32, ParamAttrKind::Current)
+        .unwrap()
+        .to_string();
+
+    let region = Region::new((2, 0..63), (2, 0..31));
+    let config = SequenceConfig::new(vec![region], 3, Duration::from_millis(2))
+        .exp_mode(ExposureMode::VariableTimed)
+        .variable_exposures(vec![
+            Duration::from_millis(2),
+            Duration::from_millis(4),
+            Duration::from_millis(6),
+        ]);
+    let sequence = frames(&camera.acquire_seq(&config).unwrap().frames);
+
+    let config = ContinuousConfig::new(vec![region], Duration::from_millis(1)).buffer_frames(3);
+    camera.start_cont(&config).unwrap();
+    let mut continuous = vec![];
+    while continuous.len() < 3 {
+        if let Some(frame) = camera.latest_frame().unwrap() {
+            continuous.push((frame.number, frame.data));
+        }
+    }
+    camera.stop_cont().unwrap();
+
+    Session {
+        temperature,
+        sequence,
+        continuous,
+    }
+}
+
+#[test]
+fn replays_reproduce_the_recording() {
+    let path = trace_path("replay");
+    let sim = SimulatedCamera::new()
+        .time_scale(0.0)
+        .sensor_model(SensorModel::new().seed(7));
+
+    let mut camera = Camera::new(RecordingBackend::create(sim, &path).unwrap());
+    let recorded = session(&mut camera);
+    drop(camera);
+
+    let trace = Trace::load(&path).unwrap();
+    let calls: Vec<&str> = trace.calls().iter().map(|c| c.function()).collect();
+    assert!(calls.contains(&"pl_get_param"));
+    assert!(calls.contains(&"pl_exp_check_status"));
+    // every frame of the sequence went through the EOF callback, each variable exposure after
+    // the first set from it
+    let eofs = trace
+        .calls()
+        .windows(2)
+        .filter(|w| matches!(w, [Call::Eof { .. }, Call::SetParam { .. }]))
+        .count();
+    assert_eq!(eofs, 2);
+
+    let mut camera = Camera::new(ReplayBackend::new(trace));
+    assert_eq!(session(&mut camera), recorded);
+    drop(camera);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replays_refuse_other_calls() {
+    let path = trace_path("refuse");
+    let recording =
+        RecordingBackend::create(SimulatedCamera::new().time_scale(0.0), &path).unwrap();
+    recording
+        .get_param(Parameter::Temperature as u32, ParamAttrKind::Current)
+        .unwrap();
+    recording
+        .set_param(Parameter::ClearCycles as u32, ParameterValue::Int(2))
+        .unwrap();
+    drop(recording);
+
+    let replay = ReplayBackend::load(&path).unwrap();
+    let e = replay.get_enums(Parameter::ClearMode as u32).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Binding);
+
+    // the refused call consumed nothing
+    replay
+        .get_param(Parameter::Temperature as u32, ParamAttrKind::Current)
+        .unwrap();
+    let e = replay
+        .set_param(Parameter::ClearCycles as u32, ParameterValue::Int(3))
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Binding);
+    replay
+        .set_param(Parameter::ClearCycles as u32, ParameterValue::Int(2))
+        .unwrap();
+    assert_eq!(replay.remaining(), 0);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn traces_cut_short_keep_their_whole_calls() {
+    let path = trace_path("cut");
+    let recording =
+        RecordingBackend::create(SimulatedCamera::new().time_scale(0.0), &path).unwrap();
+    for _ in 0..3 {
+        recording
+            .get_param(Parameter::Temperature as u32, ParamAttrKind::Current)
+            .unwrap();
+    }
+    drop(recording);
+
+    let bytes = std::fs::read(&path).unwrap();
+    let trace = Trace::read_from(&bytes[..bytes.len() - 1]).unwrap();
+    assert_eq!(trace.calls().len(), 2);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+// A start that fails and is retried, then a latest frame outside any continuous buffer, which the
+// recording refuses, each seen the same way by the replay. Every call's result is compared.
+fn failed_start(backend: &dyn CameraBackend, buffer: &mut [u16]) -> Vec<String> {
+    let mut results = vec![];
+    let e = unsafe { backend.exp_start_seq(buffer.as_mut_ptr()) }.unwrap_err();
+    results.push(format!("{:?}", e));
+
+    let region = Region::new((2, 0..63), (2, 0..31));
+    let bytes = backend
+        .exp_setup_seq(2, &[region], ExposureMode::Timed, 1)
+        .unwrap();
+    assert_eq!(bytes as usize, buffer.len() * 2);
+    unsafe { backend.exp_start_seq(buffer.as_mut_ptr()) }.unwrap();
+    loop {
+        let status = backend.exp_check_status().unwrap();
+        results.push(format!("{:?}", status));
+        if let (CaptureStatus::ReadoutComplete, _) = status {
+            break;
+        }
+    }
+
+    let e = backend.exp_get_latest_frame().unwrap_err();
+    results.push(format!("{:?}", e));
+    results.push(format!("{:?}", backend.exp_abort()));
+    results
+}
+
+#[test]
+fn replays_follow_a_failed_start_call_for_call() {
+    let path = trace_path("failed-start");
+    let sim = SimulatedCamera::new()
+        .time_scale(0.0)
+        .sensor_model(SensorModel::new().seed(3));
+    let recording = RecordingBackend::create(sim, &path).unwrap();
+    let mut recorded_frames = vec![0u16; 2 * 32 * 16];
+    let recorded = failed_start(&recording, &mut recorded_frames);
+    drop(recording);
+
+    let replay = ReplayBackend::load(&path).unwrap();
+    let mut replayed_frames = vec![0u16; recorded_frames.len()];
+    assert_eq!(failed_start(&replay, &mut replayed_frames), recorded);
+    assert_eq!(replayed_frames, recorded_frames);
+    assert_eq!(replay.remaining(), 0);
+
+    std::fs::remove_file(&path).unwrap();
+}
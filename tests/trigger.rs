@@ -0,0 +1,96 @@
+// Software triggers on a SimulatedCamera: refused unless a software trigger acquisition is
+// running, and ignored while the camera is still busy with the frame before.
+#![cfg(pvcam_3_9)]
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libpvcam_sys::pvcam::{
+    Camera, CaptureStatus, ErrorKind, ExposureMode, Region, SequenceConfig, SimulatedCamera,
+};
+
+fn config(exp_mode: ExposureMode, frames: u16, exposure: Duration) -> SequenceConfig {
+    let region = Region::new((1, 0..15), (1, 0..7));
+    SequenceConfig::new(vec![region], frames, exposure).exp_mode(exp_mode)
+}
+
+fn camera() -> Camera {
+    Camera::new(SimulatedCamera::new().sensor(64, 32))
+}
+
+// Triggers until the camera takes one, as it does once it is ready for the next frame
+fn trigger_when_ready(camera: &mut Camera) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match camera.trigger(0, 0) {
+            Ok(_) => return,
+            Err(e) if e.kind == ErrorKind::TriggerNotAccepted && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(1))
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+#[test]
+fn triggers_need_a_started_software_trigger_acquisition() {
+    let mut camera = camera();
+    assert_eq!(camera.trigger(0, 0).unwrap_err().kind, ErrorKind::NotArmed);
+
+    let software = config(
+        ExposureMode::ExtTrigSoftwareEdge,
+        1,
+        Duration::from_millis(1),
+    );
+    camera.setup_seq(&software).unwrap();
+    let e = camera.trigger(0, 0).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::NotArmed, "set up but not started");
+    camera.exp_abort().unwrap();
+
+    let timed = config(ExposureMode::Timed, 2, Duration::from_millis(50));
+    camera.setup_seq(&timed).unwrap();
+    camera.start_seq().unwrap();
+    let e = camera.trigger(0, 0).unwrap_err();
+    assert_eq!(
+        e.kind,
+        ErrorKind::NotArmed,
+        "timed sequences start themselves"
+    );
+    camera.exp_abort().unwrap();
+}
+
+#[test]
+fn every_frame_waits_for_an_accepted_trigger() {
+    let mut camera = camera();
+    let software = config(
+        ExposureMode::ExtTrigSoftwareEdge,
+        2,
+        Duration::from_millis(200),
+    );
+    camera.setup_seq(&software).unwrap();
+    camera.start_seq().unwrap();
+
+    camera.trigger(0, 0).unwrap();
+    // the first frame is still being exposed
+    let e = camera.trigger(0, 0).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::TriggerNotAccepted);
+    assert!(matches!(
+        camera.exp_check_status().unwrap().0,
+        CaptureStatus::ExposureInProgress
+    ));
+
+    trigger_when_ready(&mut camera);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !matches!(
+        camera.exp_check_status().unwrap().0,
+        CaptureStatus::ReadoutComplete
+    ) {
+        assert!(Instant::now() < deadline, "the second frame never arrived");
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    let sequence = camera.finish_seq().unwrap();
+    let numbers: Vec<u32> = sequence.frames.iter().map(|f| f.number).collect();
+    assert_eq!(numbers, [1, 2]);
+    assert_eq!(camera.trigger(0, 0).unwrap_err().kind, ErrorKind::NotArmed);
+}
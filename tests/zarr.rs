@@ -0,0 +1,285 @@
+// Writes OME-Zarr stores and reads them back, decoding the chunks and checking the metadata.
+#![cfg(feature = "zarr")]
+
+use std::convert::TryInto;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use libpvcam_sys::pvcam::io::zarr::{BloscCodec, Compression, ZarrVersion, ZarrWriter};
+use libpvcam_sys::pvcam::io::Metadata;
+use libpvcam_sys::pvcam::{Region, Sequence};
+use serde_json::Value;
+
+mod common;
+
+use common::{pixels, temp_path};
+
+fn acquire(frames: u16) -> (Sequence, Metadata) {
+    let regions = vec![
+        Region::new((1, 0..7), (1, 0..3)),
+        Region::new((1, 8..15), (1, 4..7)),
+    ];
+    let (metadata, sequence) = common::acquire(regions, frames, Duration::from_millis(2));
+    (sequence, metadata)
+}
+
+fn json(path: &Path) -> Value {
+    serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+}
+
+fn i32_at(bytes: &[u8], at: usize) -> usize {
+    i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize
+}
+
+fn zstd(bytes: &[u8]) -> Vec<u8> {
+    let mut decoded = vec![];
+    ruzstd::decoding::StreamingDecoder::new(bytes)
+        .unwrap()
+        .read_to_end(&mut decoded)
+        .unwrap();
+    decoded
+}
+
+// The Blosc 1 frames c-blosc reads, as far as the writer uses them
+fn blosc(bytes: &[u8]) -> Vec<u8> {
+    assert_eq!(bytes[..2], [2, 1]);
+    let (flags, typesize) = (bytes[2], bytes[3] as usize);
+    let (len, block_len) = (i32_at(bytes, 4), i32_at(bytes, 8));
+    assert_eq!(i32_at(bytes, 12), bytes.len());
+    if flags & 0x2 != 0 {
+        return bytes[16..].to_vec();
+    }
+    assert_ne!(flags & 0x10, 0, "blocks are not split");
+
+    let mut decoded = vec![];
+    for (i, start) in (0..len).step_by(block_len).enumerate() {
+        let block_len = block_len.min(len - start);
+        let at = i32_at(bytes, 16 + 4 * i);
+        let stream = &bytes[at + 4..at + 4 + i32_at(bytes, at)];
+        let block = match (stream.len() == block_len, flags >> 5) {
+            (true, _) => stream.to_vec(),
+            (false, 1) => lz4_flex::block::decompress(stream, block_len).unwrap(),
+            (false, 4) => zstd(stream),
+            (false, code) => panic!("compressor {}", code),
+        };
+        assert_eq!(block.len(), block_len);
+        if flags & 0x1 == 0 {
+            decoded.extend(block);
+            continue;
+        }
+        let n = block_len / typesize;
+        for j in 0..n {
+            decoded.extend((0..typesize).map(|byte| block[byte * n + j]));
+        }
+    }
+    decoded
+}
+
+// The array as a flat t, c, y, x Vec, from chunks of `shape` at 0/<prefix>t/c/y/x
+fn read_array(root: &Path, prefix: &str, shape: [usize; 4], chunk: [usize; 4]) -> Vec<u16> {
+    let [frames, channels, height, width] = shape;
+    let mut array = vec![0; frames * channels * height * width];
+    let count = |i: usize| shape[i].div_ceil(chunk[i]);
+
+    for t in 0..count(0) {
+        for c in 0..count(1) {
+            for y in 0..count(2) {
+                for x in 0..count(3) {
+                    let key = format!("0/{}{}/{}/{}/{}", prefix, t, c, y, x);
+                    let bytes = fs::read(root.join(&key)).unwrap();
+                    let decoded = pixels(&match bytes[..4] {
+                        [0x28, 0xb5, 0x2f, 0xfd] => zstd(&bytes),
+                        [2, 1, _, 2] => blosc(&bytes),
+                        _ => bytes,
+                    });
+                    assert_eq!(decoded.len(), chunk.iter().product::<usize>(), "{}", key);
+
+                    for (i, pixel) in decoded.into_iter().enumerate() {
+                        let (ct, cy, cx) = (
+                            i / (chunk[2] * chunk[3]),
+                            i / chunk[3] % chunk[2],
+                            i % chunk[3],
+                        );
+                        let at = [t * chunk[0] + ct, c, y * chunk[2] + cy, x * chunk[3] + cx];
+                        if at[0] < frames && at[2] < height && at[3] < width {
+                            let index = ((at[0] * channels + c) * height + at[2]) * width + at[3];
+                            array[index] = pixel;
+                        } else {
+                            assert_eq!(pixel, 0, "{} pads with zeros", key);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    array
+}
+
+fn frames(sequence: &Sequence, count: usize) -> Vec<u16> {
+    sequence.frames[..count]
+        .iter()
+        .flat_map(|f| f.data.iter().copied())
+        .collect()
+}
+
+#[test]
+fn v2_stores_hold_every_frame_in_blosc_chunks() {
+    let (sequence, metadata) = acquire(5);
+    let root = temp_path("v2.zarr");
+    for (shuffle, codec) in [(true, BloscCodec::Lz4), (false, BloscCodec::Zstd)] {
+        let _ = fs::remove_dir_all(&root);
+        let mut zarr = ZarrWriter::create(&root, metadata.clone())
+            .unwrap()
+            .chunks(2, 3, 5)
+            .compression(Compression::Blosc { codec, shuffle });
+        zarr.write_sequence(&sequence).unwrap();
+        zarr.finish().unwrap();
+
+        let array = json(&root.join("0/.zarray"));
+        assert_eq!(array["shape"], serde_json::json!([5, 2, 4, 8]));
+        assert_eq!(array["chunks"], serde_json::json!([2, 1, 3, 5]));
+        assert_eq!(array["dtype"], "<u2");
+        assert_eq!(array["compressor"]["id"], "blosc");
+        assert_eq!(array["compressor"]["shuffle"], shuffle as u8);
+        let read = read_array(&root, "", [5, 2, 4, 8], [2, 1, 3, 5]);
+        assert_eq!(read, frames(&sequence, 5));
+    }
+    assert!(ZarrWriter::create(&root, metadata).is_err(), "not empty");
+
+    assert_eq!(json(&root.join(".zgroup"))["zarr_format"], 2);
+    let attributes = json(&root.join(".zattrs"));
+    let multiscale = &attributes["multiscales"][0];
+    assert_eq!(multiscale["version"], "0.4");
+    let axes: Vec<&str> = (0..4)
+        .map(|i| multiscale["axes"][i]["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(axes, ["t", "c", "y", "x"]);
+    let scale = &multiscale["datasets"][0]["coordinateTransformations"][0]["scale"];
+    assert_eq!(scale[2], 6.5);
+    assert_eq!(scale[3], 6.5);
+
+    let pvcam = &attributes["pvcam"];
+    assert_eq!(pvcam["camera"]["serial"], "SIM0001");
+    assert_eq!(
+        pvcam["frames"]["number"],
+        serde_json::json!([1, 2, 3, 4, 5])
+    );
+    let timestamps: Vec<Option<i64>> = sequence.frames.iter().map(|f| f.timestamp).collect();
+    assert_eq!(pvcam["frames"]["timestamp"], serde_json::json!(timestamps));
+    assert_eq!(pvcam["frames"]["exposure"][0], 0.002);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn v3_stores_flush_the_partial_chunk() {
+    let (sequence, metadata) = acquire(3);
+    let root = temp_path("v3.zarr");
+    let mut lz4 = ZarrWriter::create(&root, metadata.clone())
+        .unwrap()
+        .version(ZarrVersion::V3)
+        .compression(Compression::Lz4);
+    assert!(
+        lz4.write_frame(&sequence.frames[0]).is_err(),
+        "v3 has no lz4"
+    );
+    fs::remove_dir_all(&root).unwrap();
+
+    let mut zarr = ZarrWriter::create(&root, metadata)
+        .unwrap()
+        .version(ZarrVersion::V3)
+        .chunks(2, 4, 8)
+        .compression(Compression::Zstd);
+    zarr.write_frame(&sequence.frames[0]).unwrap();
+    zarr.flush().unwrap();
+    let array = json(&root.join("0/zarr.json"));
+    assert_eq!(array["shape"], serde_json::json!([1, 2, 4, 8]));
+    let read = read_array(&root, "c/", [1, 2, 4, 8], [2, 1, 4, 8]);
+    assert_eq!(read, frames(&sequence, 1));
+    let group = json(&root.join("zarr.json"));
+    assert_eq!(group["attributes"]["pvcam"]["frames"]["number"][0], 1);
+
+    zarr.write_frame(&sequence.frames[1]).unwrap();
+    zarr.write_frame(&sequence.frames[2]).unwrap();
+    zarr.finish().unwrap();
+
+    let array = json(&root.join("0/zarr.json"));
+    assert_eq!(array["node_type"], "array");
+    assert_eq!(array["shape"], serde_json::json!([3, 2, 4, 8]));
+    assert_eq!(array["data_type"], "uint16");
+    assert_eq!(array["codecs"][0]["name"], "bytes");
+    assert_eq!(array["codecs"][1]["name"], "zstd");
+    let read = read_array(&root, "c/", [3, 2, 4, 8], [2, 1, 4, 8]);
+    assert_eq!(read, frames(&sequence, 3));
+
+    let group = json(&root.join("zarr.json"));
+    assert_eq!(group["node_type"], "group");
+    let ome = &group["attributes"]["ome"];
+    assert_eq!(ome["version"], "0.5");
+    assert!(ome["multiscales"][0].get("version").is_none());
+    assert_eq!(group["attributes"]["pvcam"]["regions"][1]["p1"], 4);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+// The zstd command line tool as the independent decoder: every chunk decodes to its full size,
+// which the frame header gives.
+//   cargo test --features zarr --test zarr -- --ignored
+#[test]
+#[ignore = "needs the zstd command line tool"]
+fn zstd_chunks_decode_with_the_reference_decoder() {
+    let (sequence, metadata) = acquire(3);
+    let root = temp_path("zstd.zarr");
+    let mut zarr = ZarrWriter::create(&root, metadata)
+        .unwrap()
+        .version(ZarrVersion::V3)
+        .chunks(2, 4, 8)
+        .compression(Compression::Zstd);
+    zarr.write_sequence(&sequence).unwrap();
+    zarr.finish().unwrap();
+
+    let chunk_len = 2 * 4 * 8 * 2;
+    let mut chunks = 0;
+    for t in 0..2 {
+        for c in 0..2 {
+            let path = root.join(format!("0/c/{}/{}/0/0", t, c));
+            let output = Command::new("zstd")
+                .arg("-d")
+                .arg("-c")
+                .arg(&path)
+                .output()
+                .expect("zstd is not installed");
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            assert_eq!(output.stdout.len(), chunk_len, "{}", path.display());
+            assert_eq!(output.stdout, zstd(&fs::read(&path).unwrap()));
+
+            let listing = Command::new("zstd")
+                .arg("-l")
+                .arg("-v")
+                .arg(&path)
+                .output()
+                .unwrap();
+            let listing = String::from_utf8_lossy(&listing.stdout);
+            assert!(
+                listing.contains(&format!("Decompressed Size: {} B", chunk_len)),
+                "{}",
+                listing
+            );
+            chunks += 1;
+        }
+    }
+    assert_eq!(chunks, 4);
+    assert_eq!(
+        read_array(&root, "c/", [3, 2, 4, 8], [2, 1, 4, 8]),
+        frames(&sequence, 3)
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
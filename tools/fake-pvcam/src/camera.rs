@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+use std::os::raw::c_void;
+
+use crate::sdk::*;
+
+pub const CAMERA_NAME: &str = "FakeCam0";
+pub const SENSOR_SIZE: u16 = 256;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    // every integer type, bool included, kept wide and written at the parameter's width
+    Int {
+        current: i64,
+        min: i64,
+        max: i64,
+    },
+    Float {
+        current: f64,
+        min: f64,
+        max: f64,
+    },
+    String(String),
+    Enum {
+        current: i32,
+        options: Vec<(i32, String)>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub access: i32,
+    pub value: Value,
+}
+
+impl Param {
+    fn int(current: i64, min: i64, max: i64) -> Self {
+        Param {
+            access: ACC_READ_WRITE,
+            value: Value::Int { current, min, max },
+        }
+    }
+
+    fn read_only(mut self) -> Self {
+        self.access = ACC_READ_ONLY;
+        self
+    }
+
+    fn string(value: &str) -> Self {
+        Param {
+            access: ACC_READ_ONLY,
+            value: Value::String(value.to_owned()),
+        }
+    }
+
+    fn enumeration(options: &[(i32, &str)], current: i32) -> Self {
+        Param {
+            access: ACC_READ_WRITE,
+            value: Value::Enum {
+                current,
+                options: options
+                    .iter()
+                    .map(|(value, name)| (*value, name.to_string()))
+                    .collect(),
+            },
+        }
+    }
+}
+
+// the TYPE_* a parameter id carries in its top byte
+pub fn param_type(param_id: u32) -> u32 {
+    param_id >> 24
+}
+
+// Defaults picked to catch a read of the wrong width: negative values where the type is signed,
+// values past 16 or 32 bits where it is that wide
+pub fn default_params() -> BTreeMap<u32, Param> {
+    let size = SENSOR_SIZE as i64;
+    let binning = [(1, "1x1"), (2, "2x2"), (4, "4x4")];
+
+    vec![
+        (PARAM_CHIP_NAME, Param::string("FakeSensor")),
+        // MAX_ALPHA_SER_NUM_LEN - 1 characters, the most that fits
+        (
+            PARAM_HEAD_SER_NUM_ALPHA,
+            Param::string("FAKE-0123456789-0123456789-0123"),
+        ),
+        (PARAM_CAMERA_PART_NUMBER, Param::string("FK-1")),
+        (PARAM_VENDOR_NAME, Param::string("libpvcam-sys")),
+        (PARAM_PRODUCT_NAME, Param::string("FakeCamera")),
+        (
+            PARAM_CAM_FW_VERSION,
+            Param::int(0xfe01, 0, 0xffff).read_only(),
+        ),
+        (PARAM_SER_SIZE, Param::int(size, size, size).read_only()),
+        (PARAM_PAR_SIZE, Param::int(size, size, size).read_only()),
+        (PARAM_PIX_SER_SIZE, Param::int(6500, 6500, 6500).read_only()),
+        (PARAM_PIX_PAR_SIZE, Param::int(6500, 6500, 6500).read_only()),
+        (PARAM_BIT_DEPTH, Param::int(16, 16, 16).read_only()),
+        (PARAM_GAIN_INDEX, Param::int(1, 1, 2)),
+        (PARAM_SPDTAB_INDEX, Param::int(0, 0, 1)),
+        (
+            PARAM_READOUT_PORT,
+            Param::enumeration(&[(0, "Sensitivity"), (1, "Speed")], 0),
+        ),
+        (PARAM_PIX_TIME, Param::int(10, 10, 10).read_only()),
+        (PARAM_ADC_OFFSET, Param::int(-300, -300, -300).read_only()),
+        (
+            PARAM_PMODE,
+            Param::enumeration(&[(0, "Normal"), (1, "Frame Transfer")], 0),
+        ),
+        (
+            PARAM_CLEAR_MODE,
+            Param::enumeration(&[(0, "Never"), (1, "Pre-Exposure"), (2, "Pre-Sequence")], 1),
+        ),
+        (PARAM_CLEAR_CYCLES, Param::int(2, 0, 16)),
+        (
+            PARAM_SHTR_OPEN_MODE,
+            Param::enumeration(&[(0, "Never"), (1, "Pre-Exposure")], 1),
+        ),
+        (PARAM_TEMP, Param::int(-1234, -1234, -1234).read_only()),
+        (PARAM_TEMP_SETPOINT, Param::int(-1000, -2500, 2500)),
+        (
+            PARAM_FAN_SPEED_SETPOINT,
+            Param::enumeration(&[(0, "High"), (1, "Medium"), (2, "Low"), (3, "Off")], 0),
+        ),
+        (PARAM_BINNING_SER, Param::enumeration(&binning, 1)),
+        (PARAM_BINNING_PAR, Param::enumeration(&binning, 1)),
+        (PARAM_METADATA_ENABLED, Param::int(0, 0, 1)),
+        (PARAM_CIRC_BUFFER, Param::int(1, 0, 1).read_only()),
+        (PARAM_ROI_COUNT, Param::int(1, 1, 15).read_only()),
+        (
+            PARAM_READOUT_TIME,
+            Param {
+                access: ACC_READ_ONLY,
+                value: Value::Float {
+                    current: 1234.5,
+                    min: 0.0,
+                    max: 1e9,
+                },
+            },
+        ),
+        (
+            PARAM_CLEARING_TIME,
+            Param::int(0x1_2345_6789, 0, i64::MAX).read_only(),
+        ),
+        (PARAM_PP_INDEX, Param::int(0, 0, 0)),
+        (PARAM_PP_FEAT_NAME, Param::string("FakeFeature")),
+        (PARAM_PP_PARAM_INDEX, Param::int(0, 0, 0)),
+        (PARAM_PP_PARAM_NAME, Param::string("FakeLevel")),
+        (PARAM_PP_PARAM, Param::int(0x8000_0001, 0, u32::MAX as i64)),
+        (PARAM_EXP_TIME, Param::int(10, 0, 0xffff)),
+        (
+            PARAM_EXP_RES,
+            Param::enumeration(&[(0, "One Millisecond")], 0),
+        ),
+        (PARAM_EXP_RES_INDEX, Param::int(0, 0, 0)),
+        (
+            PARAM_EXPOSURE_MODE,
+            Param {
+                access: ACC_READ_ONLY,
+                ..Param::enumeration(
+                    &[
+                        (TIMED_MODE, "Timed"),
+                        (VARIABLE_TIMED_MODE, "Variable Timed"),
+                        (EXT_TRIG_INTERNAL, "Internal Trigger"),
+                        (EXT_TRIG_SOFTWARE_FIRST, "Software Trigger First"),
+                        (EXT_TRIG_SOFTWARE_EDGE, "Software Trigger Edge"),
+                    ],
+                    TIMED_MODE,
+                )
+            },
+        ),
+        (
+            PARAM_EXPOSE_OUT_MODE,
+            Param::enumeration(&[(0, "First Row"), (1, "All Rows")], 0),
+        ),
+    ]
+    .into_iter()
+    .collect()
+}
+
+// Writes `value` through `ptr` at exactly the width of `ty`, as libpvcam does
+pub unsafe fn write_int(ptr: *mut c_void, ty: u32, value: i64) {
+    match ty {
+        TYPE_INT8 => *(ptr as *mut i8) = value as i8,
+        TYPE_UNS8 => *(ptr as *mut u8) = value as u8,
+        TYPE_INT16 => *(ptr as *mut i16) = value as i16,
+        TYPE_UNS16 | TYPE_BOOLEAN => *(ptr as *mut u16) = value as u16,
+        TYPE_INT32 | TYPE_ENUM => *(ptr as *mut i32) = value as i32,
+        TYPE_UNS32 => *(ptr as *mut u32) = value as u32,
+        TYPE_INT64 | TYPE_UNS64 => *(ptr as *mut i64) = value,
+        _ => unreachable!("not an integer type: {}", ty),
+    }
+}
+
+// Reads a value of exactly the width of `ty` from `ptr`
+pub unsafe fn read_int(ptr: *const c_void, ty: u32) -> i64 {
+    match ty {
+        TYPE_INT8 => *(ptr as *const i8) as i64,
+        TYPE_UNS8 => *(ptr as *const u8) as i64,
+        TYPE_INT16 => *(ptr as *const i16) as i64,
+        TYPE_UNS16 | TYPE_BOOLEAN => *(ptr as *const u16) as i64,
+        TYPE_INT32 | TYPE_ENUM => *(ptr as *const i32) as i64,
+        TYPE_UNS32 => *(ptr as *const u32) as i64,
+        TYPE_INT64 | TYPE_UNS64 => *(ptr as *const i64),
+        _ => unreachable!("not an integer type: {}", ty),
+    }
+}
+
+// pixels of one frame read out through `regions`
+pub fn frame_len(regions: &[rgn_type]) -> usize {
+    regions
+        .iter()
+        .map(|r| ((r.s2 - r.s1 + 1) / r.sbin) as usize * ((r.p2 - r.p1 + 1) / r.pbin) as usize)
+        .sum()
+}
+
+// What pixel `index` of frame `number`, 1 based, holds; tests check frames against it
+pub fn pixel(number: u32, index: usize) -> u16 {
+    (number as usize * 1000 + index) as u16
+}
@@ -0,0 +1,1004 @@
+// A libpvcam stand-in with one scripted camera, for testing libpvcam-sys's FFI paths end to end.
+// It exports the pl_* functions the crate calls with PVCam's C ABI and behaves the way the SDK
+// documents them: values are written at exactly their type's width, strings exactly as long as
+// the lengths it reports, and frames exactly as large as pl_exp_setup_* said. Calls out of order,
+// such as anything before pl_pvcam_init, fail like they would on the real library.
+//
+// Tests script the camera with the fake_pvcam_* functions, which the real library does not have.
+// The fake-sdk binary lays out an SDK directory with headers for this library, see src/main.rs.
+
+// the exports take pointers from C callers, and are exactly as unsafe as the library they stand
+// in for
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+mod camera;
+pub mod sdk;
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use camera::{frame_len, param_type, pixel, read_int, write_int, Param, Value, CAMERA_NAME};
+use sdk::*;
+
+// error codes pl_error_code hands out; the real library's are different
+const ERR_NONE: i16 = 0;
+const ERR_NOT_INITIALIZED: i16 = 1;
+const ERR_BAD_HANDLE: i16 = 2;
+const ERR_NO_CAMERA: i16 = 3;
+const ERR_BAD_PARAM: i16 = 4;
+const ERR_BAD_VALUE: i16 = 5;
+const ERR_NOT_ALLOWED: i16 = 6;
+const ERR_BUFFER: i16 = 7;
+const ERR_STATE: i16 = 8;
+
+const HANDLE: i16 = 1;
+
+type Failure = (i16, String);
+
+type EofCallback = extern "C" fn(*mut FRAME_INFO, *mut c_void);
+
+// the buffer handed to pl_exp_start_*; the caller keeps it valid until the acquisition stops
+#[derive(Clone, Copy)]
+struct Buffer(*mut u16);
+
+unsafe impl Send for Buffer {}
+
+#[derive(Clone)]
+struct Setup {
+    exp_mode: i32,
+    exposure_ms: u32,
+    // None for a continuous acquisition
+    frames: Option<u32>,
+    frame_len: usize,
+}
+
+struct Acquisition {
+    status: i16,
+    bytes_arrived: u32,
+    buffer_cnt: u32,
+    latest: Option<(*mut u16, FRAME_INFO)>,
+    awaiting_trigger: bool,
+    triggers: u32,
+    stop: bool,
+}
+
+unsafe impl Send for Acquisition {}
+
+struct Fake {
+    initialized: bool,
+    open: bool,
+    name: String,
+    params: BTreeMap<u32, Param>,
+    error: Failure,
+    // function => error code its next call fails with
+    fail_next: HashMap<String, i16>,
+    calls: HashMap<String, u32>,
+    eof: Option<(usize, usize)>,
+    setup: Option<Setup>,
+    acquisition: Option<Acquisition>,
+    worker: Option<JoinHandle<()>>,
+    created: Instant,
+}
+
+impl Fake {
+    fn new() -> Self {
+        Fake {
+            initialized: false,
+            open: false,
+            name: CAMERA_NAME.to_owned(),
+            params: camera::default_params(),
+            error: (ERR_NONE, "no error".to_owned()),
+            fail_next: HashMap::new(),
+            calls: HashMap::new(),
+            eof: None,
+            setup: None,
+            acquisition: None,
+            worker: None,
+            created: Instant::now(),
+        }
+    }
+
+    fn camera(&self, hcam: int16) -> Result<(), Failure> {
+        match hcam == HANDLE && self.open {
+            true => Ok(()),
+            false => Err((ERR_BAD_HANDLE, format!("{} is not an open camera", hcam))),
+        }
+    }
+
+    fn param(&self, param_id: uns32) -> Result<&Param, Failure> {
+        self.params.get(&param_id).ok_or_else(|| {
+            (
+                ERR_BAD_PARAM,
+                format!("parameter {:#x} is not available", param_id),
+            )
+        })
+    }
+
+    fn param_int(&self, param_id: uns32) -> i64 {
+        match self.params.get(&param_id).map(|p| &p.value) {
+            Some(Value::Int { current, .. }) => *current,
+            _ => 0,
+        }
+    }
+}
+
+static FAKE: Mutex<Option<Fake>> = Mutex::new(None);
+static CHANGED: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, Option<Fake>> {
+    match FAKE.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn fake(state: &mut Option<Fake>) -> &mut Fake {
+    state.get_or_insert_with(Fake::new)
+}
+
+// Runs a pl_* call the way libpvcam does: counted, refused before pl_pvcam_init, failing if
+// scripted to, and leaving its error for pl_error_code
+fn call(function: &str, body: impl FnOnce(&mut Fake) -> Result<(), Failure>) -> rs_bool {
+    let mut guard = lock();
+    let fake = fake(&mut guard);
+    *fake.calls.entry(function.to_owned()).or_insert(0) += 1;
+
+    let result = match fake.fail_next.remove(function) {
+        Some(code) => Err((code, format!("{} failed as scripted", function))),
+        None if !fake.initialized && function != "pl_pvcam_init" => Err((
+            ERR_NOT_INITIALIZED,
+            format!("{} called before pl_pvcam_init", function),
+        )),
+        None => body(fake),
+    };
+
+    match result {
+        Ok(()) => PV_OK as rs_bool,
+        Err(error) => {
+            fake.error = error;
+            PV_FAIL as rs_bool
+        }
+    }
+}
+
+// Copies `value` and its terminator into `dst`, which must hold `len` bytes
+unsafe fn write_str(dst: *mut c_char, len: usize, value: &str) -> Result<(), Failure> {
+    if dst.is_null() || value.len() + 1 > len {
+        return Err((
+            ERR_BUFFER,
+            format!("{:?} needs {} bytes, have {}", value, value.len() + 1, len),
+        ));
+    }
+    ptr::copy_nonoverlapping(value.as_ptr() as *const c_char, dst, value.len());
+    *dst.add(value.len()) = 0;
+
+    Ok(())
+}
+
+fn checked<'a, T>(ptr: *mut T) -> Result<&'a mut T, Failure> {
+    unsafe { ptr.as_mut() }.ok_or_else(|| (ERR_BUFFER, "null pointer".to_owned()))
+}
+
+#[no_mangle]
+pub extern "C" fn pl_pvcam_get_ver(pvcam_version: *mut uns16) -> rs_bool {
+    call("pl_pvcam_get_ver", |_| {
+        *checked(pvcam_version)? = (VERSION.0 << 8) | (VERSION.1 << 4) | VERSION.2;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_pvcam_init() -> rs_bool {
+    call("pl_pvcam_init", |fake| match fake.initialized {
+        true => Err((ERR_STATE, "pl_pvcam_init called twice".to_owned())),
+        false => {
+            fake.initialized = true;
+            Ok(())
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_pvcam_uninit() -> rs_bool {
+    call("pl_pvcam_uninit", |fake| {
+        fake.initialized = false;
+        fake.open = false;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_cam_get_total(totl_cams: *mut int16) -> rs_bool {
+    call("pl_cam_get_total", |_| {
+        *checked(totl_cams)? = 1;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_cam_get_name(cam_num: int16, camera_name: *mut c_char) -> rs_bool {
+    call("pl_cam_get_name", |fake| match cam_num {
+        0 => unsafe { write_str(camera_name, CAM_NAME_LEN as usize, &fake.name) },
+        _ => Err((ERR_NO_CAMERA, format!("no camera number {}", cam_num))),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_cam_open(
+    camera_name: *mut c_char,
+    hcam: *mut int16,
+    o_mode: int16,
+) -> rs_bool {
+    call("pl_cam_open", |fake| {
+        let name = unsafe { CStr::from_ptr(checked(camera_name)?) }.to_string_lossy();
+        if name != fake.name {
+            return Err((ERR_NO_CAMERA, format!("no camera named {:?}", name)));
+        }
+        if o_mode as i32 != OPEN_EXCLUSIVE || fake.open {
+            return Err((ERR_NOT_ALLOWED, format!("{} is already open", name)));
+        }
+        fake.open = true;
+        *checked(hcam)? = HANDLE;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_cam_close(hcam: int16) -> rs_bool {
+    let result = call("pl_cam_close", |fake| {
+        fake.camera(hcam)?;
+        fake.open = false;
+        fake.eof = None;
+        Ok(())
+    });
+    stop_worker();
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn pl_cam_register_callback_ex3(
+    hcam: int16,
+    callback_event: int32,
+    callback: *mut c_void,
+    context: *mut c_void,
+) -> rs_bool {
+    call("pl_cam_register_callback_ex3", |fake| {
+        fake.camera(hcam)?;
+        if callback_event != PL_CALLBACK_EOF || callback.is_null() {
+            return Err((
+                ERR_BAD_VALUE,
+                format!("cannot register event {}", callback_event),
+            ));
+        }
+        if fake.eof.is_some() {
+            return Err((
+                ERR_NOT_ALLOWED,
+                "an EOF callback is already registered".to_owned(),
+            ));
+        }
+        fake.eof = Some((callback as usize, context as usize));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_cam_deregister_callback(hcam: int16, callback_event: int32) -> rs_bool {
+    call("pl_cam_deregister_callback", |fake| {
+        fake.camera(hcam)?;
+        match (callback_event, fake.eof.take()) {
+            (PL_CALLBACK_EOF, Some(_)) => Ok(()),
+            _ => Err((
+                ERR_NOT_ALLOWED,
+                format!("no callback for event {}", callback_event),
+            )),
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_error_code() -> int16 {
+    fake(&mut lock()).error.0
+}
+
+#[no_mangle]
+pub extern "C" fn pl_error_message(err_code: int16, msg: *mut c_char) -> rs_bool {
+    let mut guard = lock();
+    let fake = fake(&mut guard);
+    let message = match err_code == fake.error.0 {
+        true => fake.error.1.clone(),
+        false => format!("fake error {}", err_code),
+    };
+    // longer messages are cut to fit, the way PVCam's are
+    let mut end = message.len().min(ERROR_MSG_LEN as usize - 1);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    match unsafe { write_str(msg, ERROR_MSG_LEN as usize, &message[..end]) } {
+        Ok(()) => PV_OK as rs_bool,
+        Err(_) => PV_FAIL as rs_bool,
+    }
+}
+
+// which of a value's attributes ATTR_CURRENT, ATTR_MIN and so on ask for
+fn pick<T>(attr: i32, current: T, min: T, max: T, increment: T) -> T {
+    match attr {
+        ATTR_MIN => min,
+        ATTR_MAX => max,
+        ATTR_INCREMENT => increment,
+        _ => current,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pl_get_param(
+    hcam: int16,
+    param_id: uns32,
+    param_attribute: int16,
+    param_value: *mut c_void,
+) -> rs_bool {
+    call("pl_get_param", |fake| {
+        fake.camera(hcam)?;
+        checked(param_value)?;
+        let attr = param_attribute as i32;
+        if attr == ATTR_AVAIL {
+            let available = fake.params.contains_key(&param_id) as i64;
+            unsafe { write_int(param_value, TYPE_BOOLEAN, available) };
+            return Ok(());
+        }
+
+        let param = fake.param(param_id)?;
+        let ty = param_type(param_id);
+        match attr {
+            ATTR_ACCESS => unsafe { write_int(param_value, TYPE_UNS16, param.access as i64) },
+            ATTR_TYPE => unsafe { write_int(param_value, TYPE_UNS16, ty as i64) },
+            ATTR_COUNT => {
+                let count = match &param.value {
+                    Value::Int { min, max, .. } => max - min + 1,
+                    Value::Float { .. } => 0,
+                    // the buffer pl_get_param needs, terminator included
+                    Value::String(s) => s.len() as i64 + 1,
+                    Value::Enum { options, .. } => options.len() as i64,
+                };
+                unsafe { write_int(param_value, TYPE_UNS32, count) };
+            }
+            ATTR_CURRENT | ATTR_MIN | ATTR_MAX | ATTR_DEFAULT | ATTR_INCREMENT => {
+                if attr == ATTR_CURRENT && param.access == ACC_WRITE_ONLY {
+                    return Err((ERR_NOT_ALLOWED, format!("{:#x} is write only", param_id)));
+                }
+                match &param.value {
+                    Value::Int { current, min, max } => unsafe {
+                        write_int(param_value, ty, pick(attr, *current, *min, *max, 1))
+                    },
+                    Value::Float { current, min, max } => unsafe {
+                        *(param_value as *mut f64) = pick(attr, *current, *min, *max, 1.0)
+                    },
+                    Value::Enum { current, options } => {
+                        let min = options.iter().map(|o| o.0).min().unwrap_or(0);
+                        let max = options.iter().map(|o| o.0).max().unwrap_or(0);
+                        unsafe {
+                            write_int(
+                                param_value,
+                                TYPE_ENUM,
+                                pick(attr, *current, min, max, 1) as i64,
+                            )
+                        }
+                    }
+                    Value::String(s) => unsafe {
+                        let dst = param_value as *mut c_char;
+                        ptr::copy_nonoverlapping(s.as_ptr() as *const c_char, dst, s.len());
+                        *dst.add(s.len()) = 0;
+                    },
+                }
+            }
+            _ => return Err((ERR_BAD_VALUE, format!("no attribute {}", attr))),
+        }
+
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_set_param(hcam: int16, param_id: uns32, param_value: *mut c_void) -> rs_bool {
+    call("pl_set_param", |fake| {
+        fake.camera(hcam)?;
+        checked(param_value)?;
+        let ty = param_type(param_id);
+        let param = fake.params.get_mut(&param_id).ok_or_else(|| {
+            (
+                ERR_BAD_PARAM,
+                format!("parameter {:#x} is not available", param_id),
+            )
+        })?;
+        if param.access != ACC_READ_WRITE && param.access != ACC_WRITE_ONLY {
+            return Err((ERR_NOT_ALLOWED, format!("{:#x} is read only", param_id)));
+        }
+
+        match &mut param.value {
+            Value::Int { current, min, max } => {
+                let value = unsafe { read_int(param_value, ty) };
+                if value < *min || value > *max {
+                    return Err((
+                        ERR_BAD_VALUE,
+                        format!("{} is outside {}..={} for {:#x}", value, min, max, param_id),
+                    ));
+                }
+                *current = value;
+            }
+            Value::Float { current, min, max } => {
+                let value = unsafe { *(param_value as *const f64) };
+                if value < *min || value > *max {
+                    return Err((ERR_BAD_VALUE, format!("{} is out of range", value)));
+                }
+                *current = value;
+            }
+            Value::Enum { current, options } => {
+                let value = unsafe { read_int(param_value, TYPE_ENUM) } as i32;
+                if !options.iter().any(|o| o.0 == value) {
+                    return Err((
+                        ERR_BAD_VALUE,
+                        format!("{} is not an option of {:#x}", value, param_id),
+                    ));
+                }
+                *current = value;
+            }
+            Value::String(_) => {
+                return Err((ERR_NOT_ALLOWED, "strings cannot be set".to_owned()));
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn enum_option(fake: &Fake, param_id: uns32, index: uns32) -> Result<(i32, String), Failure> {
+    match &fake.param(param_id)?.value {
+        Value::Enum { options, .. } => options.get(index as usize).cloned().ok_or_else(|| {
+            (
+                ERR_BAD_VALUE,
+                format!("{:#x} has no option {}", param_id, index),
+            )
+        }),
+        _ => Err((ERR_BAD_PARAM, format!("{:#x} is not an enum", param_id))),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pl_enum_str_length(
+    hcam: int16,
+    param_id: uns32,
+    index: uns32,
+    length: *mut uns32,
+) -> rs_bool {
+    call("pl_enum_str_length", |fake| {
+        fake.camera(hcam)?;
+        let (_, name) = enum_option(fake, param_id, index)?;
+        *checked(length)? = name.len() as u32 + 1;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_get_enum_param(
+    hcam: int16,
+    param_id: uns32,
+    index: uns32,
+    value: *mut int32,
+    desc: *mut c_char,
+    length: uns32,
+) -> rs_bool {
+    call("pl_get_enum_param", |fake| {
+        fake.camera(hcam)?;
+        let (option, name) = enum_option(fake, param_id, index)?;
+        *checked(value)? = option;
+        unsafe { write_str(desc, length as usize, &name) }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_pp_reset(hcam: int16) -> rs_bool {
+    call("pl_pp_reset", |fake| fake.camera(hcam))
+}
+
+#[no_mangle]
+pub extern "C" fn pl_create_frame_info_struct(new_frame: *mut *mut FRAME_INFO) -> rs_bool {
+    call("pl_create_frame_info_struct", |_| {
+        *checked(new_frame)? = Box::into_raw(Box::default());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_release_frame_info_struct(frame_to_delete: *mut FRAME_INFO) -> rs_bool {
+    call("pl_release_frame_info_struct", |_| {
+        checked(frame_to_delete)?;
+        drop(unsafe { Box::from_raw(frame_to_delete) });
+        Ok(())
+    })
+}
+
+fn setup(
+    fake: &mut Fake,
+    rgn_total: uns16,
+    rgn_array: *const rgn_type,
+    exp_mode: int16,
+    exposure_time: uns32,
+    frames: Option<u32>,
+) -> Result<Setup, Failure> {
+    if rgn_total == 0 || rgn_array.is_null() {
+        return Err((ERR_BAD_VALUE, "no regions".to_owned()));
+    }
+
+    let regions = unsafe { std::slice::from_raw_parts(rgn_array, rgn_total as usize) }.to_vec();
+    let (serial, parallel) = (
+        fake.param_int(PARAM_SER_SIZE) as u16,
+        fake.param_int(PARAM_PAR_SIZE) as u16,
+    );
+    for r in regions.iter() {
+        if r.s1 > r.s2
+            || r.s2 >= serial
+            || r.p1 > r.p2
+            || r.p2 >= parallel
+            || r.sbin == 0
+            || r.pbin == 0
+        {
+            return Err((ERR_BAD_VALUE, format!("{:?} does not fit the sensor", r)));
+        }
+    }
+
+    let supported = match &fake.param(PARAM_EXPOSURE_MODE)?.value {
+        Value::Enum { options, .. } => options.iter().any(|o| o.0 == exp_mode as i32),
+        _ => false,
+    };
+    if !supported {
+        return Err((
+            ERR_BAD_VALUE,
+            format!("exposure mode {} is not supported", exp_mode),
+        ));
+    }
+
+    let setup = Setup {
+        frame_len: frame_len(&regions),
+        exp_mode: exp_mode as i32,
+        exposure_ms: exposure_time,
+        frames,
+    };
+    fake.setup = Some(setup.clone());
+    fake.acquisition = None;
+
+    Ok(setup)
+}
+
+#[no_mangle]
+pub extern "C" fn pl_exp_setup_seq(
+    hcam: int16,
+    exp_total: uns16,
+    rgn_total: uns16,
+    rgn_array: *const rgn_type,
+    exp_mode: int16,
+    exposure_time: uns32,
+    exp_bytes: *mut uns32,
+) -> rs_bool {
+    stop_worker();
+    call("pl_exp_setup_seq", |fake| {
+        fake.camera(hcam)?;
+        if exp_total == 0 {
+            return Err((ERR_BAD_VALUE, "no exposures".to_owned()));
+        }
+        let setup = setup(
+            fake,
+            rgn_total,
+            rgn_array,
+            exp_mode,
+            exposure_time,
+            Some(exp_total as u32),
+        )?;
+        *checked(exp_bytes)? = (setup.frame_len * 2 * exp_total as usize) as u32;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_exp_setup_cont(
+    hcam: int16,
+    rgn_total: uns16,
+    rgn_array: *const rgn_type,
+    exp_mode: int16,
+    exposure_time: uns32,
+    exp_bytes: *mut uns32,
+    buffer_mode: int16,
+) -> rs_bool {
+    stop_worker();
+    call("pl_exp_setup_cont", |fake| {
+        fake.camera(hcam)?;
+        if buffer_mode as i32 != CIRC_OVERWRITE && buffer_mode as i32 != CIRC_NO_OVERWRITE {
+            return Err((ERR_BAD_VALUE, format!("buffer mode {}", buffer_mode)));
+        }
+        let setup = setup(fake, rgn_total, rgn_array, exp_mode, exposure_time, None)?;
+        *checked(exp_bytes)? = (setup.frame_len * 2) as u32;
+        Ok(())
+    })
+}
+
+fn start(
+    fake: &mut Fake,
+    buffer: *mut c_void,
+    slots: usize,
+    continuous: bool,
+) -> Result<(), Failure> {
+    let setup = match &fake.setup {
+        Some(setup) if setup.frames.is_none() == continuous => setup.clone(),
+        _ => return Err((ERR_STATE, "not set up for this acquisition".to_owned())),
+    };
+    if buffer.is_null() || slots == 0 {
+        return Err((ERR_BUFFER, "no room for a frame".to_owned()));
+    }
+
+    fake.acquisition = Some(Acquisition {
+        status: EXPOSURE_IN_PROGRESS as i16,
+        bytes_arrived: 0,
+        buffer_cnt: 0,
+        latest: None,
+        awaiting_trigger: setup.exp_mode == EXT_TRIG_SOFTWARE_FIRST
+            || setup.exp_mode == EXT_TRIG_SOFTWARE_EDGE,
+        triggers: 0,
+        stop: false,
+    });
+    let buffer = Buffer(buffer as *mut u16);
+    let created = fake.created;
+    fake.worker = Some(thread::spawn(move || run(setup, buffer, slots, created)));
+
+    Ok(())
+}
+
+#[no_mangle]
+pub extern "C" fn pl_exp_start_seq(hcam: int16, pixel_stream: *mut c_void) -> rs_bool {
+    stop_worker();
+    call("pl_exp_start_seq", |fake| {
+        fake.camera(hcam)?;
+        let frames = fake.setup.as_ref().and_then(|s| s.frames).unwrap_or(0) as usize;
+        start(fake, pixel_stream, frames, false)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_exp_start_cont(
+    hcam: int16,
+    pixel_stream: *mut c_void,
+    size: uns32,
+) -> rs_bool {
+    stop_worker();
+    call("pl_exp_start_cont", |fake| {
+        fake.camera(hcam)?;
+        let frame_bytes = fake.setup.as_ref().map_or(0, |s| s.frame_len * 2);
+        if frame_bytes == 0 || size as usize % frame_bytes != 0 {
+            return Err((
+                ERR_BUFFER,
+                format!(
+                    "{} bytes is not a whole number of {} byte frames",
+                    size, frame_bytes
+                ),
+            ));
+        }
+        start(fake, pixel_stream, size as usize / frame_bytes, true)
+    })
+}
+
+// Stops the acquisition thread, if any, and waits for it
+fn stop_worker() {
+    let worker = {
+        let mut guard = lock();
+        let fake = fake(&mut guard);
+        if let Some(acquisition) = fake.acquisition.as_mut() {
+            acquisition.stop = true;
+        }
+        fake.worker.take()
+    };
+    CHANGED.notify_all();
+    if let Some(worker) = worker {
+        let _ = worker.join();
+    }
+}
+
+// Sleeps unless stopped first; false if it was
+fn wait(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    let mut guard = lock();
+    loop {
+        match fake(&mut guard).acquisition.as_ref() {
+            Some(acquisition) if !acquisition.stop => {}
+            _ => return false,
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        guard = match CHANGED.wait_timeout(guard, deadline - now) {
+            Ok((guard, _)) => guard,
+            Err(poisoned) => poisoned.into_inner().0,
+        };
+    }
+}
+
+fn wait_for_trigger() -> bool {
+    let mut guard = lock();
+    loop {
+        let acquisition = match fake(&mut guard).acquisition.as_mut() {
+            Some(acquisition) if !acquisition.stop => acquisition,
+            _ => return false,
+        };
+        if acquisition.triggers > 0 {
+            acquisition.triggers -= 1;
+            return true;
+        }
+        acquisition.awaiting_trigger = true;
+        guard = match CHANGED.wait(guard) {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+    }
+}
+
+// The acquisition thread: writes each frame into its slot, then calls the EOF callback
+fn run(setup: Setup, buffer: Buffer, slots: usize, created: Instant) {
+    let mut number = 0;
+    while !matches!(setup.frames, Some(frames) if number >= frames) {
+        let software = setup.exp_mode == EXT_TRIG_SOFTWARE_EDGE
+            || (setup.exp_mode == EXT_TRIG_SOFTWARE_FIRST && number == 0);
+        if software && !wait_for_trigger() {
+            return;
+        }
+
+        let exposure_ms = match setup.exp_mode {
+            VARIABLE_TIMED_MODE => fake(&mut lock()).param_int(PARAM_EXP_TIME) as u64,
+            _ => setup.exposure_ms as u64,
+        };
+        if !wait(Duration::from_millis(exposure_ms)) {
+            return;
+        }
+
+        let slot = number as usize % slots;
+        number += 1;
+        let frame = unsafe { buffer.0.add(slot * setup.frame_len) };
+        for i in 0..setup.frame_len {
+            unsafe { *frame.add(i) = pixel(number, i) };
+        }
+        let mut info = FRAME_INFO {
+            hCam: HANDLE,
+            FrameNr: number as i32,
+            TimeStamp: created.elapsed().as_micros() as i64,
+            ..FRAME_INFO::default()
+        };
+
+        let eof = {
+            let mut guard = lock();
+            let fake = fake(&mut guard);
+            if let Some(acquisition) = fake.acquisition.as_mut() {
+                acquisition.bytes_arrived = ((slot + 1) * setup.frame_len * 2) as u32;
+                acquisition.latest = Some((frame, info));
+                if slot + 1 == slots {
+                    acquisition.buffer_cnt += 1;
+                }
+            }
+            fake.eof
+        };
+        // without the lock, the callback calls back in
+        if let Some((callback, context)) = eof {
+            let callback: EofCallback = unsafe { std::mem::transmute(callback) };
+            callback(&mut info, context as *mut c_void);
+        }
+
+        let mut guard = lock();
+        if let Some(acquisition) = fake(&mut guard).acquisition.as_mut() {
+            acquisition.status = match setup.frames {
+                Some(frames) if number < frames => READOUT_IN_PROGRESS as i16,
+                _ => READOUT_COMPLETE as i16,
+            };
+        }
+        CHANGED.notify_all();
+    }
+}
+
+// a 3.8 libpvcam has no pl_exp_trigger
+#[cfg(not(feature = "pvcam-3-8"))]
+#[no_mangle]
+pub extern "C" fn pl_exp_trigger(hcam: int16, flags: *mut uns32, _value: uns32) -> rs_bool {
+    let result = call("pl_exp_trigger", |fake| {
+        fake.camera(hcam)?;
+        let flags = checked(flags)?;
+        let accepted = match fake.acquisition.as_mut() {
+            Some(acquisition) if acquisition.awaiting_trigger => {
+                acquisition.awaiting_trigger = false;
+                acquisition.triggers += 1;
+                true
+            }
+            _ => false,
+        };
+        *flags = match accepted {
+            true => PL_SW_TRIG_STATUS_TRIGGERED as u32,
+            false => PL_SW_TRIG_STATUS_IGNORED as u32,
+        };
+        Ok(())
+    });
+    CHANGED.notify_all();
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn pl_exp_check_status(
+    hcam: int16,
+    status: *mut int16,
+    bytes_arrived: *mut uns32,
+) -> rs_bool {
+    call("pl_exp_check_status", |fake| {
+        fake.camera(hcam)?;
+        let (s, bytes) = match &fake.acquisition {
+            Some(acquisition) => (acquisition.status, acquisition.bytes_arrived),
+            None => (READOUT_NOT_ACTIVE as i16, 0),
+        };
+        *checked(status)? = s;
+        *checked(bytes_arrived)? = bytes;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_exp_check_cont_status(
+    hcam: int16,
+    status: *mut int16,
+    bytes_arrived: *mut uns32,
+    buffer_cnt: *mut uns32,
+) -> rs_bool {
+    call("pl_exp_check_cont_status", |fake| {
+        fake.camera(hcam)?;
+        let (s, bytes, count) = match &fake.acquisition {
+            // FRAME_AVAILABLE once there is one
+            Some(acquisition) if acquisition.latest.is_some() => (
+                READOUT_COMPLETE as i16,
+                acquisition.bytes_arrived,
+                acquisition.buffer_cnt,
+            ),
+            Some(acquisition) => (acquisition.status, 0, 0),
+            None => (READOUT_NOT_ACTIVE as i16, 0, 0),
+        };
+        *checked(status)? = s;
+        *checked(bytes_arrived)? = bytes;
+        *checked(buffer_cnt)? = count;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pl_exp_get_latest_frame_ex(
+    hcam: int16,
+    frame: *mut *mut c_void,
+    frame_info: *mut FRAME_INFO,
+) -> rs_bool {
+    call("pl_exp_get_latest_frame_ex", |fake| {
+        fake.camera(hcam)?;
+        match fake.acquisition.as_ref().and_then(|a| a.latest) {
+            Some((latest, info)) => {
+                *checked(frame)? = latest as *mut c_void;
+                *checked(frame_info)? = info;
+                Ok(())
+            }
+            None => Err((ERR_STATE, "no frame has arrived".to_owned())),
+        }
+    })
+}
+
+fn halt(function: &str, hcam: int16, cam_state: int16) -> rs_bool {
+    let result = call(function, |fake| {
+        fake.camera(hcam)?;
+        match cam_state as i32 {
+            CCS_NO_CHANGE | CCS_HALT => Ok(()),
+            _ => Err((ERR_BAD_VALUE, format!("cam_state {}", cam_state))),
+        }
+    });
+    stop_worker();
+    let mut guard = lock();
+    if let Some(acquisition) = fake(&mut guard).acquisition.as_mut() {
+        acquisition.status = READOUT_NOT_ACTIVE as i16;
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn pl_exp_stop_cont(hcam: int16, cam_state: int16) -> rs_bool {
+    halt("pl_exp_stop_cont", hcam, cam_state)
+}
+
+#[no_mangle]
+pub extern "C" fn pl_exp_abort(hcam: int16, cam_state: int16) -> rs_bool {
+    halt("pl_exp_abort", hcam, cam_state)
+}
+
+// Scripting, for tests
+
+// Back to a fresh, uninitialised library with the default camera
+#[no_mangle]
+pub extern "C" fn fake_pvcam_reset() {
+    stop_worker();
+    *lock() = Some(Fake::new());
+}
+
+// The next call to `function` fails with `code`
+#[no_mangle]
+pub extern "C" fn fake_pvcam_fail_next(function: *const c_char, code: int16) {
+    let function = unsafe { CStr::from_ptr(function) }
+        .to_string_lossy()
+        .into_owned();
+    fake(&mut lock()).fail_next.insert(function, code);
+}
+
+// How often `function` has been called since the last reset
+#[no_mangle]
+pub extern "C" fn fake_pvcam_calls(function: *const c_char) -> uns32 {
+    let function = unsafe { CStr::from_ptr(function) }.to_string_lossy();
+    fake(&mut lock())
+        .calls
+        .get(function.as_ref())
+        .copied()
+        .unwrap_or(0)
+}
+
+// Renames the camera pl_cam_get_name reports
+#[no_mangle]
+pub extern "C" fn fake_pvcam_set_name(name: *const c_char) {
+    let name = unsafe { CStr::from_ptr(name) }
+        .to_string_lossy()
+        .into_owned();
+    fake(&mut lock()).name = name;
+}
+
+// Adds or replaces an integer, bool or enum parameter; enums take their current option value
+#[no_mangle]
+pub extern "C" fn fake_pvcam_set_int(param_id: uns32, current: long64, min: long64, max: long64) {
+    let value = match param_type(param_id) {
+        TYPE_ENUM => Value::Enum {
+            current: current as i32,
+            options: (min..=max)
+                .map(|v| (v as i32, format!("Option {}", v)))
+                .collect(),
+        },
+        _ => Value::Int { current, min, max },
+    };
+    fake(&mut lock()).params.insert(
+        param_id,
+        Param {
+            access: ACC_READ_WRITE,
+            value,
+        },
+    );
+}
+
+// Adds or replaces a read only string parameter
+#[no_mangle]
+pub extern "C" fn fake_pvcam_set_string(param_id: uns32, value: *const c_char) {
+    let value = unsafe { CStr::from_ptr(value) }
+        .to_string_lossy()
+        .into_owned();
+    fake(&mut lock()).params.insert(
+        param_id,
+        Param {
+            access: ACC_READ_ONLY,
+            value: Value::String(value),
+        },
+    );
+}
+
+#[no_mangle]
+pub extern "C" fn fake_pvcam_set_access(param_id: uns32, access: int32) {
+    if let Some(param) = fake(&mut lock()).params.get_mut(&param_id) {
+        param.access = access;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn fake_pvcam_remove_param(param_id: uns32) {
+    fake(&mut lock()).params.remove(&param_id);
+}
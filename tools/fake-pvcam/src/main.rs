@@ -0,0 +1,75 @@
+// Lays out a PVCam SDK directory around the fake libpvcam, for building and testing libpvcam-sys
+// against it without a camera or the real SDK:
+//
+//   cargo run -p fake-pvcam --bin fake-sdk -- target/fake-sdk
+//   PVCAM_SDK_PATH=$PWD/target/fake-sdk cargo test
+//
+// The directory gets include/master.h and include/pvcam.h written from src/sdk.rs, and
+// library/<arch>/libpvcam.so copied from next to this binary, where cargo built it.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// the name build.rs looks for under library/, see library_arch there
+fn library_arch() -> &'static str {
+    match env::consts::ARCH {
+        "x86" => "i686",
+        arch => arch,
+    }
+}
+
+fn write(path: &Path, contents: &[u8]) {
+    if let Err(e) = fs::write(path, contents) {
+        eprintln!("could not write {}: {}", path.display(), e);
+        process::exit(1);
+    }
+}
+
+fn create_dir(path: &Path) {
+    if let Err(e) = fs::create_dir_all(path) {
+        eprintln!("could not create {}: {}", path.display(), e);
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let sdk = match args.as_slice() {
+        [dir] => PathBuf::from(dir),
+        _ => {
+            eprintln!("usage: fake-sdk <dir>");
+            process::exit(2);
+        }
+    };
+
+    let library = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("libpvcam.so")))
+        .filter(|library| library.is_file());
+    let library = match library {
+        Some(library) => library,
+        None => {
+            eprintln!("libpvcam.so is not next to fake-sdk; run it with cargo run -p fake-pvcam");
+            process::exit(1);
+        }
+    };
+    let contents = match fs::read(&library) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("could not read {}: {}", library.display(), e);
+            process::exit(1);
+        }
+    };
+
+    let include = sdk.join("include");
+    let library_dir = sdk.join("library").join(library_arch());
+    create_dir(&include);
+    create_dir(&library_dir);
+    write(&include.join("master.h"), pvcam::sdk::master_h().as_bytes());
+    write(&include.join("pvcam.h"), pvcam::sdk::pvcam_h().as_bytes());
+    write(&library_dir.join("libpvcam.so"), &contents);
+
+    println!("fake PVCam SDK written to {}", sdk.display());
+}
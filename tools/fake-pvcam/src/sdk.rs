@@ -0,0 +1,349 @@
+// What the fake SDK headers declare. The library is built on these same constants and the
+// headers are written from them, so the two cannot disagree. Parameter ids are put together the
+// way PVCam's are, (class << 16) + (type << 24) + index, but the indexes are the fake's own.
+
+#![allow(non_camel_case_types, non_upper_case_globals)]
+
+pub type rs_bool = u16;
+pub type int16 = i16;
+pub type uns16 = u16;
+pub type int32 = i32;
+pub type uns32 = u32;
+pub type long64 = i64;
+
+// the release the headers claim to be; 3.9 so pl_exp_trigger is in the bindings
+pub const VERSION: (u16, u16, u16) = (3, 9, 4);
+
+macro_rules! defines {
+    ($($name:ident = $value:expr;)*) => {
+        $(pub const $name: u32 = $value;)*
+        const DEFINES: &[(&str, u32)] = &[$((stringify!($name), $name),)*];
+    };
+}
+
+macro_rules! enums {
+    ($($enum:ident { $($name:ident = $value:expr,)* })*) => {
+        $($(pub const $name: i32 = $value;)*)*
+        const ENUMS: &[(&str, &[(&str, i32)])] = &[
+            $((stringify!($enum), &[$((stringify!($name), $name),)*]),)*
+        ];
+    };
+}
+
+const fn param(class: u32, ty: u32, index: u32) -> u32 {
+    (class << 16) + (ty << 24) + index
+}
+
+defines! {
+    PV_FAIL = 0;
+    PV_OK = 1;
+
+    CAM_NAME_LEN = 32;
+    PARAM_NAME_LEN = 32;
+    ERROR_MSG_LEN = 255;
+    CCD_NAME_LEN = 17;
+    MAX_ALPHA_SER_NUM_LEN = 32;
+    MAX_PP_NAME_LEN = 32;
+    MAX_VENDOR_NAME_LEN = 32;
+    MAX_PRODUCT_NAME_LEN = 32;
+    MAX_CAM_PART_NUM_LEN = 32;
+
+    CLASS0 = 0;
+    CLASS2 = 2;
+    CLASS3 = 3;
+
+    TYPE_INT16 = 1;
+    TYPE_INT32 = 2;
+    TYPE_FLT64 = 4;
+    TYPE_UNS8 = 5;
+    TYPE_UNS16 = 6;
+    TYPE_UNS32 = 7;
+    TYPE_UNS64 = 8;
+    TYPE_ENUM = 9;
+    TYPE_BOOLEAN = 11;
+    TYPE_INT8 = 12;
+    TYPE_CHAR_PTR = 13;
+    TYPE_INT64 = 16;
+
+    PARAM_CHIP_NAME = param(CLASS2, TYPE_CHAR_PTR, 129);
+    PARAM_HEAD_SER_NUM_ALPHA = param(CLASS2, TYPE_CHAR_PTR, 533);
+    PARAM_CAMERA_PART_NUMBER = param(CLASS2, TYPE_CHAR_PTR, 534);
+    PARAM_VENDOR_NAME = param(CLASS2, TYPE_CHAR_PTR, 538);
+    PARAM_PRODUCT_NAME = param(CLASS2, TYPE_CHAR_PTR, 539);
+    PARAM_CAM_FW_VERSION = param(CLASS2, TYPE_UNS16, 532);
+    PARAM_SER_SIZE = param(CLASS2, TYPE_UNS16, 58);
+    PARAM_PAR_SIZE = param(CLASS2, TYPE_UNS16, 57);
+    PARAM_PIX_SER_SIZE = param(CLASS2, TYPE_UNS16, 62);
+    PARAM_PIX_PAR_SIZE = param(CLASS2, TYPE_UNS16, 63);
+    PARAM_BIT_DEPTH = param(CLASS2, TYPE_INT16, 511);
+    PARAM_GAIN_INDEX = param(CLASS2, TYPE_INT16, 512);
+    PARAM_SPDTAB_INDEX = param(CLASS2, TYPE_INT16, 513);
+    PARAM_READOUT_PORT = param(CLASS2, TYPE_ENUM, 247);
+    PARAM_PIX_TIME = param(CLASS2, TYPE_UNS16, 516);
+    PARAM_ADC_OFFSET = param(CLASS2, TYPE_INT16, 195);
+    PARAM_GAIN_MULT_ENABLE = param(CLASS2, TYPE_BOOLEAN, 541);
+    PARAM_GAIN_MULT_FACTOR = param(CLASS2, TYPE_UNS16, 537);
+    PARAM_PMODE = param(CLASS2, TYPE_ENUM, 524);
+    PARAM_CLEAR_MODE = param(CLASS2, TYPE_ENUM, 523);
+    PARAM_CLEAR_CYCLES = param(CLASS2, TYPE_UNS16, 97);
+    PARAM_SHTR_OPEN_MODE = param(CLASS2, TYPE_ENUM, 98);
+    PARAM_TEMP = param(CLASS2, TYPE_INT16, 525);
+    PARAM_TEMP_SETPOINT = param(CLASS2, TYPE_INT16, 526);
+    PARAM_FAN_SPEED_SETPOINT = param(CLASS2, TYPE_ENUM, 710);
+    PARAM_BINNING_SER = param(CLASS2, TYPE_ENUM, 165);
+    PARAM_BINNING_PAR = param(CLASS2, TYPE_ENUM, 166);
+    PARAM_METADATA_ENABLED = param(CLASS2, TYPE_BOOLEAN, 168);
+    PARAM_ROI_COUNT = param(CLASS2, TYPE_UNS16, 169);
+    PARAM_READOUT_TIME = param(CLASS2, TYPE_FLT64, 179);
+    PARAM_CLEARING_TIME = param(CLASS2, TYPE_INT64, 180);
+    PARAM_CIRC_BUFFER = param(CLASS2, TYPE_BOOLEAN, 299);
+    PARAM_PP_INDEX = param(CLASS2, TYPE_INT16, 543);
+    PARAM_PP_FEAT_NAME = param(CLASS2, TYPE_CHAR_PTR, 542);
+    PARAM_PP_PARAM_INDEX = param(CLASS2, TYPE_INT16, 544);
+    PARAM_PP_PARAM_NAME = param(CLASS2, TYPE_CHAR_PTR, 545);
+    PARAM_PP_PARAM = param(CLASS2, TYPE_UNS32, 546);
+    PARAM_CLN_WHILE_EXPO = param(CLASS2, TYPE_BOOLEAN, 507);
+    PARAM_SHTR_OPEN_DELAY = param(CLASS2, TYPE_UNS16, 100);
+    PARAM_SHTR_CLOSE_DELAY = param(CLASS2, TYPE_UNS16, 101);
+    PARAM_GAIN_NAME = param(CLASS2, TYPE_CHAR_PTR, 517);
+    PARAM_COOLING_MODE = param(CLASS2, TYPE_ENUM, 214);
+    PARAM_FRAME_CAPABLE = param(CLASS2, TYPE_BOOLEAN, 509);
+    PARAM_CAM_INTERFACE_TYPE = param(CLASS2, TYPE_ENUM, 10);
+    PARAM_CAM_INTERFACE_MODE = param(CLASS2, TYPE_ENUM, 11);
+    PARAM_SCAN_MODE = param(CLASS3, TYPE_ENUM, 260);
+    PARAM_SCAN_DIRECTION = param(CLASS3, TYPE_ENUM, 261);
+    PARAM_SCAN_DIRECTION_RESET = param(CLASS3, TYPE_BOOLEAN, 262);
+    PARAM_SCAN_LINE_DELAY = param(CLASS3, TYPE_UNS16, 263);
+    PARAM_SCAN_LINE_TIME = param(CLASS3, TYPE_INT64, 264);
+    PARAM_SCAN_WIDTH = param(CLASS3, TYPE_UNS16, 265);
+    PARAM_CENTROIDS_ENABLED = param(CLASS3, TYPE_BOOLEAN, 400);
+    PARAM_CENTROIDS_RADIUS = param(CLASS3, TYPE_UNS16, 401);
+    PARAM_CENTROIDS_COUNT = param(CLASS3, TYPE_UNS16, 402);
+    PARAM_SMART_STREAM_MODE_ENABLED = param(CLASS3, TYPE_BOOLEAN, 700);
+    PARAM_TRIGTAB_SIGNAL = param(CLASS3, TYPE_ENUM, 180);
+    PARAM_LAST_MUXED_SIGNAL = param(CLASS3, TYPE_UNS8, 181);
+    PARAM_EXP_TIME = param(CLASS3, TYPE_UNS16, 1);
+    PARAM_EXP_RES = param(CLASS3, TYPE_ENUM, 2);
+    PARAM_EXP_RES_INDEX = param(CLASS3, TYPE_UNS16, 4);
+    PARAM_EXPOSURE_MODE = param(CLASS3, TYPE_ENUM, 535);
+    PARAM_EXPOSE_OUT_MODE = param(CLASS3, TYPE_ENUM, 560);
+}
+
+const MAX_EXPOSE_MODE: i32 = 7;
+
+enums! {
+    PL_PARAM_ATTRIBUTES {
+        ATTR_CURRENT = 0,
+        ATTR_COUNT = 1,
+        ATTR_TYPE = 2,
+        ATTR_MIN = 3,
+        ATTR_MAX = 4,
+        ATTR_DEFAULT = 5,
+        ATTR_INCREMENT = 6,
+        ATTR_ACCESS = 7,
+        ATTR_AVAIL = 8,
+    }
+    PL_PARAM_ACCESS {
+        ACC_READ_ONLY = 1,
+        ACC_READ_WRITE = 2,
+        ACC_EXIST_CHECK_ONLY = 3,
+        ACC_WRITE_ONLY = 4,
+    }
+    PL_IMAGE_STATUSES {
+        READOUT_NOT_ACTIVE = 0,
+        EXPOSURE_IN_PROGRESS = 1,
+        READOUT_IN_PROGRESS = 2,
+        READOUT_COMPLETE = 3,
+        READOUT_FAILED = 4,
+    }
+    PL_EXPOSURE_MODES {
+        TIMED_MODE = 0,
+        STROBED_MODE = 1,
+        BULB_MODE = 2,
+        TRIGGER_FIRST_MODE = 3,
+        FLASH_MODE = 4,
+        VARIABLE_TIMED_MODE = 5,
+        INT_STROBE_MODE = 6,
+        EXT_TRIG_INTERNAL = MAX_EXPOSE_MODE << 8,
+        EXT_TRIG_TRIG_FIRST = (MAX_EXPOSE_MODE + 1) << 8,
+        EXT_TRIG_EDGE_RISING = (MAX_EXPOSE_MODE + 2) << 8,
+        EXT_TRIG_LEVEL = (MAX_EXPOSE_MODE + 3) << 8,
+        EXT_TRIG_SOFTWARE_FIRST = (MAX_EXPOSE_MODE + 4) << 8,
+        EXT_TRIG_SOFTWARE_EDGE = (MAX_EXPOSE_MODE + 5) << 8,
+        EXT_TRIG_LEVEL_OVERLAP = (MAX_EXPOSE_MODE + 6) << 8,
+        EXT_TRIG_LEVEL_PULSED = (MAX_EXPOSE_MODE + 7) << 8,
+    }
+    PL_CCS_ABORT_MODES {
+        CCS_NO_CHANGE = 0,
+        CCS_HALT = 1,
+    }
+    PL_CIRC_MODES {
+        CIRC_NONE = 0,
+        CIRC_OVERWRITE = 1,
+        CIRC_NO_OVERWRITE = 2,
+    }
+    PL_OPEN_MODES {
+        OPEN_EXCLUSIVE = 0,
+    }
+    PL_CALLBACK_EVENT {
+        PL_CALLBACK_BOF = 0,
+        PL_CALLBACK_EOF = 1,
+    }
+    PL_SW_TRIG_STATUSES {
+        PL_SW_TRIG_STATUS_TRIGGERED = 0,
+        PL_SW_TRIG_STATUS_IGNORED = 1,
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct rgn_type {
+    pub s1: uns16,
+    pub s2: uns16,
+    pub sbin: uns16,
+    pub p1: uns16,
+    pub p2: uns16,
+    pub pbin: uns16,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PVCAM_FRAME_INFO_GUID {
+    pub f1: uns32,
+    pub f2: uns16,
+    pub f3: uns16,
+    pub f4: [u8; 8],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+#[allow(non_snake_case)]
+pub struct FRAME_INFO {
+    pub FrameInfoGUID: PVCAM_FRAME_INFO_GUID,
+    pub hCam: int16,
+    pub FrameNr: int32,
+    pub TimeStamp: long64,
+    pub ReadoutTime: int32,
+    pub TimeStampBOF: long64,
+}
+
+const MASTER_H: &str = "\
+#ifndef _MASTER_H
+#define _MASTER_H
+
+typedef unsigned short rs_bool;
+typedef signed char int8;
+typedef unsigned char uns8;
+typedef short int16;
+typedef unsigned short uns16;
+typedef int int32;
+typedef unsigned int uns32;
+typedef float flt32;
+typedef double flt64;
+typedef long long long64;
+typedef unsigned long long ulong64;
+
+#define PV_DECL
+
+#endif
+";
+
+const TYPES: &str = "\
+typedef struct rgn_type
+{
+    uns16 s1;
+    uns16 s2;
+    uns16 sbin;
+    uns16 p1;
+    uns16 p2;
+    uns16 pbin;
+}
+rgn_type;
+
+typedef struct PVCAM_FRAME_INFO_GUID
+{
+    uns32 f1;
+    uns16 f2;
+    uns16 f3;
+    uns8 f4[8];
+}
+PVCAM_FRAME_INFO_GUID;
+
+typedef struct FRAME_INFO
+{
+    PVCAM_FRAME_INFO_GUID FrameInfoGUID;
+    int16 hCam;
+    int32 FrameNr;
+    long64 TimeStamp;
+    int32 ReadoutTime;
+    long64 TimeStampBOF;
+}
+FRAME_INFO;
+";
+
+// every function lib.rs exports, as PVCam declares them
+const FUNCTIONS: &str = "\
+rs_bool PV_DECL pl_pvcam_get_ver(uns16* pvcam_version);
+rs_bool PV_DECL pl_pvcam_init(void);
+rs_bool PV_DECL pl_pvcam_uninit(void);
+rs_bool PV_DECL pl_cam_close(int16 hcam);
+rs_bool PV_DECL pl_cam_get_name(int16 cam_num, char* camera_name);
+rs_bool PV_DECL pl_cam_get_total(int16* totl_cams);
+rs_bool PV_DECL pl_cam_open(char* camera_name, int16* hcam, int16 o_mode);
+rs_bool PV_DECL pl_cam_register_callback_ex3(int16 hcam, int32 callback_event, void* callback, void* context);
+rs_bool PV_DECL pl_cam_deregister_callback(int16 hcam, int32 callback_event);
+int16 PV_DECL pl_error_code(void);
+rs_bool PV_DECL pl_error_message(int16 err_code, char* msg);
+rs_bool PV_DECL pl_get_param(int16 hcam, uns32 param_id, int16 param_attribute, void* param_value);
+rs_bool PV_DECL pl_set_param(int16 hcam, uns32 param_id, void* param_value);
+rs_bool PV_DECL pl_get_enum_param(int16 hcam, uns32 param_id, uns32 index, int32* value, char* desc, uns32 length);
+rs_bool PV_DECL pl_enum_str_length(int16 hcam, uns32 param_id, uns32 index, uns32* length);
+rs_bool PV_DECL pl_pp_reset(int16 hcam);
+rs_bool PV_DECL pl_create_frame_info_struct(FRAME_INFO** new_frame);
+rs_bool PV_DECL pl_release_frame_info_struct(FRAME_INFO* frame_to_delete);
+rs_bool PV_DECL pl_exp_setup_seq(int16 hcam, uns16 exp_total, uns16 rgn_total, const rgn_type* rgn_array, int16 exp_mode, uns32 exposure_time, uns32* exp_bytes);
+rs_bool PV_DECL pl_exp_start_seq(int16 hcam, void* pixel_stream);
+rs_bool PV_DECL pl_exp_setup_cont(int16 hcam, uns16 rgn_total, const rgn_type* rgn_array, int16 exp_mode, uns32 exposure_time, uns32* exp_bytes, int16 buffer_mode);
+rs_bool PV_DECL pl_exp_start_cont(int16 hcam, void* pixel_stream, uns32 size);
+rs_bool PV_DECL pl_exp_trigger(int16 hcam, uns32* flags, uns32 value);
+rs_bool PV_DECL pl_exp_check_status(int16 hcam, int16* status, uns32* bytes_arrived);
+rs_bool PV_DECL pl_exp_check_cont_status(int16 hcam, int16* status, uns32* bytes_arrived, uns32* buffer_cnt);
+rs_bool PV_DECL pl_exp_get_latest_frame_ex(int16 hcam, void** frame, FRAME_INFO* frame_info);
+rs_bool PV_DECL pl_exp_stop_cont(int16 hcam, int16 cam_state);
+rs_bool PV_DECL pl_exp_abort(int16 hcam, int16 cam_state);
+";
+
+pub fn master_h() -> String {
+    MASTER_H.to_owned()
+}
+
+pub fn pvcam_h() -> String {
+    let mut out = String::new();
+    out.push_str("#ifndef _PVCAM_H\n#define _PVCAM_H\n\n#include \"master.h\"\n\n");
+    // build.rs sets cfg(pvcam_fake) when it sees this
+    out.push_str("#define PVCAM_FAKE 1\n");
+    out.push_str(&format!(
+        "#define PVCAM_VERSION_MAJOR {}\n#define PVCAM_VERSION_MINOR {}\n#define PVCAM_VERSION_BUILD {}\n\n",
+        VERSION.0, VERSION.1, VERSION.2
+    ));
+
+    for (name, value) in DEFINES.iter() {
+        out.push_str(&format!("#define {} {}\n", name, value));
+    }
+    out.push('\n');
+
+    for (name, values) in ENUMS.iter() {
+        out.push_str(&format!("typedef enum {}\n{{\n", name));
+        for (value_name, value) in values.iter() {
+            out.push_str(&format!("    {} = {},\n", value_name, value));
+        }
+        out.push_str(&format!("}}\n{};\n\n", name));
+    }
+
+    out.push_str(TYPES);
+    out.push('\n');
+    out.push_str(FUNCTIONS);
+    out.push_str("\n#endif\n");
+
+    out
+}
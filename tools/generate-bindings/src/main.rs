@@ -0,0 +1,76 @@
+// Maintainer tool: regenerates bindings/pvcam-<version>.rs from the SDK at PVCAM_SDK_PATH.
+//
+//   PVCAM_SDK_PATH=/opt/pvcam/sdk cargo run -p generate-bindings -- 3.9.4.0
+//   PVCAM_SDK_PATH=/opt/pvcam/sdk cargo run -p generate-bindings -- --check 3.9.4.0
+//
+// --check writes nothing and exits non-zero when the checked in file differs from what the
+// headers produce, so CI machines with the SDK installed can keep the bundled copies honest.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+include!("../../../bindings/builder.rs");
+
+// keep in step with BUNDLED_VERSIONS in build.rs
+const VERSIONS: &[&str] = &["3.8.4.3", "3.9.4.0"];
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: generate-bindings [--check] <version>\n  versions: {}",
+        VERSIONS.join(", ")
+    );
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (check, version) = match args.as_slice() {
+        [flag, version] if flag == "--check" => (true, version),
+        [version] => (false, version),
+        _ => usage(),
+    };
+    if !VERSIONS.contains(&version.as_str()) {
+        usage();
+    }
+
+    let sdk_path = match env::var("PVCAM_SDK_PATH") {
+        Ok(v) => v,
+        Err(_) => {
+            eprintln!("PVCAM_SDK_PATH must point at the PVCam {} SDK", version);
+            process::exit(2);
+        }
+    };
+
+    // bundled bindings carry the deprecated functions too; the deprecated-api feature decides
+    // whether pvcam::sys exposes them
+    let generated = bindings_builder(&format!("{}/include", sdk_path), true)
+        .generate()
+        .expect("Unable to generate bindings")
+        .to_string();
+
+    let bundled = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../../bindings")
+        .join(format!("pvcam-{}.rs", version));
+
+    if check {
+        match fs::read_to_string(&bundled) {
+            Ok(existing) if existing == generated => {
+                println!("{} matches the headers", bundled.display());
+            }
+            Ok(_) => {
+                eprintln!("{} differs from the headers", bundled.display());
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{} could not be read: {}", bundled.display(), e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    fs::write(&bundled, generated).expect("Couldn't write bindings!");
+    println!("wrote {}", bundled.display());
+}
@@ -0,0 +1,28 @@
+// Keeps the checked in bindings honest: regenerates them from an installed SDK and compares.
+//
+//   PVCAM_SDK_PATH=/opt/pvcam/sdk PVCAM_SDK_VERSION=3.9.4.0 \
+//       cargo test -p generate-bindings -- --ignored
+//
+// The headers do not say which release they are, so PVCAM_SDK_VERSION names it.
+
+use std::env;
+use std::process::Command;
+
+#[test]
+#[ignore = "needs PVCAM_SDK_PATH and PVCAM_SDK_VERSION set for an installed SDK, and libclang"]
+fn bundled_bindings_match_the_sdk() {
+    env::var("PVCAM_SDK_PATH").expect("PVCAM_SDK_PATH is not set");
+    let version = env::var("PVCAM_SDK_VERSION").expect("PVCAM_SDK_VERSION is not set");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_generate-bindings"))
+        .arg("--check")
+        .arg(&version)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}